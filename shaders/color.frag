@@ -0,0 +1,14 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} constants;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = constants.color;
+}
@@ -0,0 +1,162 @@
+#version 450
+
+const float PI = 3.14159265359;
+
+// Have to match the renderer
+const int MAX_SHADOW_LIGHTS = 4;
+const int MAX_POINT_LIGHTS = 32;
+
+// Keeps surfaces from shadowing themselves
+const float SHADOW_BIAS = 0.005;
+
+// Same as in pbr.frag
+const vec3 DIELECTRIC_F0 = vec3(0.04);
+const float MIN_ROUGHNESS = 0.045;
+
+struct ShadowLight {
+    vec3 position;
+    float radius;
+};
+
+struct PointLight {
+    vec3 position;
+    float radius;
+    vec3 color;
+    int shadowMap;
+};
+
+layout(binding = 1) uniform Lights {
+    vec3 sunDirection;
+    uint pointCount;
+    vec3 sunColor;
+    vec3 ambient;
+    ShadowLight shadows[MAX_SHADOW_LIGHTS];
+    PointLight points[MAX_POINT_LIGHTS];
+} lights;
+
+layout(binding = 2) uniform samplerCube shadowMaps[MAX_SHADOW_LIGHTS];
+
+// See GBUFFER_FORMATS in the renderer
+layout(set = 1, binding = 0) uniform sampler2D albedoMap;
+layout(set = 1, binding = 1) uniform sampler2D normalMap;
+layout(set = 1, binding = 2) uniform sampler2D depthMap;
+layout(set = 1, binding = 3) uniform sampler2D materialMap;
+
+layout(push_constant) uniform PushConstants {
+    mat4 invViewProj;
+    vec4 cameraPosition;
+} constants;
+
+layout(location = 0) out vec4 outColor;
+
+// Indexing sampler arrays with anything but constants needs an optional device feature
+float closestOccluder(int shadowMap, vec3 direction) {
+    switch (shadowMap) {
+    case 0:
+        return texture(shadowMaps[0], direction).r;
+    case 1:
+        return texture(shadowMaps[1], direction).r;
+    case 2:
+        return texture(shadowMaps[2], direction).r;
+    case 3:
+        return texture(shadowMaps[3], direction).r;
+    }
+
+    return 0.0;
+}
+
+float shadowFactor(int shadowMap, vec3 position) {
+    if (shadowMap < 0) {
+        return 1.0;
+    }
+
+    ShadowLight shadow = lights.shadows[shadowMap];
+    vec3 offset = position - shadow.position;
+
+    // Reversed like in shadow.frag, 1 at the light and 0 at its radius
+    float depth = 1.0 - length(offset) / shadow.radius;
+
+    return depth + SHADOW_BIAS >= closestOccluder(shadowMap, offset) ? 1.0 : 0.0;
+}
+
+// pbr.frag's, see there
+vec3 brdf(vec3 normal, vec3 view, vec3 light, vec3 baseColor, float metallic, float roughness) {
+    vec3 halfway = normalize(view + light);
+
+    float nDotL = max(dot(normal, light), 0.0);
+    float nDotV = max(dot(normal, view), 1e-4);
+    float nDotH = max(dot(normal, halfway), 0.0);
+    float vDotH = max(dot(view, halfway), 0.0);
+
+    float alpha = roughness * roughness;
+    float alpha2 = alpha * alpha;
+    float d = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+    float distribution = alpha2 / (PI * d * d);
+
+    float k = alpha / 2.0;
+    float geometry = 1.0 / (4.0 * (nDotL * (1.0 - k) + k) * (nDotV * (1.0 - k) + k));
+
+    vec3 f0 = mix(DIELECTRIC_F0, baseColor, metallic);
+    vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - vDotH, 5.0);
+
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * baseColor / PI;
+    vec3 specular = fresnel * distribution * geometry;
+
+    return (diffuse + specular) * nDotL;
+}
+
+// Diffuse only, like lit.frag. Scaled by PI like light colors are for PBR
+vec3 lambert(vec3 normal, vec3 light, vec3 albedo) {
+    return albedo / PI * max(dot(normal, light), 0.0);
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float depth = texelFetch(depthMap, pixel, 0).r;
+
+    // Nothing was drawn here, the skybox will be
+    if (depth == 0.0) {
+        outColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec2 ndc = gl_FragCoord.xy / vec2(textureSize(depthMap, 0)) * 2.0 - 1.0;
+    vec4 world = constants.invViewProj * vec4(ndc, depth, 1.0);
+    vec3 position = world.xyz / world.w;
+
+    vec3 albedo = texelFetch(albedoMap, pixel, 0).rgb;
+    vec3 normal = normalize(texelFetch(normalMap, pixel, 0).xyz);
+    vec4 material = texelFetch(materialMap, pixel, 0);
+
+    bool pbr = material.a > 0.5;
+    float metallic = material.r;
+    float roughness = clamp(material.g, MIN_ROUGHNESS, 1.0);
+    float occlusion = material.b;
+
+    vec3 view = normalize(constants.cameraPosition.xyz - position);
+
+    vec3 color = lights.ambient * albedo * occlusion;
+    vec3 sun = -lights.sunDirection;
+
+    color += (pbr ? brdf(normal, view, sun, albedo, metallic, roughness) : lambert(normal, sun, albedo))
+        * lights.sunColor * PI;
+
+    for (uint i = 0; i < lights.pointCount; i++) {
+        PointLight point = lights.points[i];
+        vec3 toLight = point.position - position;
+        float dist = length(toLight);
+
+        if (dist >= point.radius) {
+            continue;
+        }
+
+        vec3 light = toLight / dist;
+        float falloff = 1.0 - dist / point.radius;
+        vec3 radiance = point.color * falloff * falloff * shadowFactor(point.shadowMap, position) * PI;
+
+        color += (pbr ? brdf(normal, view, light, albedo, metallic, roughness)
+                      : lambert(normal, light, albedo)) * radiance;
+    }
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) out vec4 outColor;
+
+// Magenta and black checkers in screen space, so that broken materials stand out
+void main() {
+    ivec2 cell = ivec2(gl_FragCoord.xy) / 16;
+    bool odd = ((cell.x + cell.y) & 1) == 1;
+
+    outColor = odd ? vec4(1.0, 0.0, 1.0, 1.0) : vec4(0.0, 0.0, 0.0, 1.0);
+}
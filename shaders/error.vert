@@ -0,0 +1,19 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} constants;
+
+// Missing components of shorter position formats read as zero
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = ubo.proj * ubo.view * constants.model * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,98 @@
+#version 450
+
+// Have to match the renderer
+const int MAX_SHADOW_LIGHTS = 4;
+const int MAX_POINT_LIGHTS = 32;
+
+// Keeps surfaces from shadowing themselves
+const float SHADOW_BIAS = 0.005;
+
+struct ShadowLight {
+    vec3 position;
+    float radius;
+};
+
+struct PointLight {
+    vec3 position;
+    float radius;
+    vec3 color;
+    int shadowMap;
+};
+
+layout(binding = 1) uniform Lights {
+    vec3 sunDirection;
+    uint pointCount;
+    vec3 sunColor;
+    vec3 ambient;
+    ShadowLight shadows[MAX_SHADOW_LIGHTS];
+    PointLight points[MAX_POINT_LIGHTS];
+} lights;
+
+layout(binding = 2) uniform samplerCube shadowMaps[MAX_SHADOW_LIGHTS];
+
+layout(set = 1, binding = 0) uniform sampler2D texSampler;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} constants;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Indexing sampler arrays with anything but constants needs an optional device feature
+float closestOccluder(int shadowMap, vec3 direction) {
+    switch (shadowMap) {
+    case 0:
+        return texture(shadowMaps[0], direction).r;
+    case 1:
+        return texture(shadowMaps[1], direction).r;
+    case 2:
+        return texture(shadowMaps[2], direction).r;
+    case 3:
+        return texture(shadowMaps[3], direction).r;
+    }
+
+    return 0.0;
+}
+
+float shadowFactor(int shadowMap) {
+    if (shadowMap < 0) {
+        return 1.0;
+    }
+
+    ShadowLight shadow = lights.shadows[shadowMap];
+    vec3 offset = fragPosition - shadow.position;
+
+    // Reversed like in shadow.frag, 1 at the light and 0 at its radius
+    float depth = 1.0 - length(offset) / shadow.radius;
+
+    return depth + SHADOW_BIAS >= closestOccluder(shadowMap, offset) ? 1.0 : 0.0;
+}
+
+void main() {
+    vec3 normal = normalize(fragNormal);
+    vec3 light = lights.ambient + lights.sunColor * max(dot(normal, -lights.sunDirection), 0.0);
+
+    for (uint i = 0; i < lights.pointCount; i++) {
+        PointLight point = lights.points[i];
+        vec3 toLight = point.position - fragPosition;
+        float dist = length(toLight);
+
+        if (dist >= point.radius) {
+            continue;
+        }
+
+        float diffuse = max(dot(normal, toLight / dist), 0.0);
+        float falloff = 1.0 - dist / point.radius;
+
+        light += point.color * diffuse * falloff * falloff * shadowFactor(point.shadowMap);
+    }
+
+    vec4 albedo = texture(texSampler, fragTexCoord) * constants.color;
+
+    outColor = vec4(albedo.rgb * light, albedo.a);
+}
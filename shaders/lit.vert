@@ -0,0 +1,30 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} constants;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec2 fragTexCoord;
+
+void main() {
+    vec4 worldPosition = constants.model * vec4(inPosition, 1.0);
+
+    gl_Position = ubo.proj * ubo.view * worldPosition;
+    fragPosition = worldPosition.xyz;
+    // Only correct for uniform scaling
+    fragNormal = mat3(constants.model) * inNormal;
+    fragTexCoord = inTexCoord;
+}
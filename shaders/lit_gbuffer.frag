@@ -0,0 +1,26 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform sampler2D texSampler;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} constants;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec2 fragTexCoord;
+
+// See GBUFFER_FORMATS in the renderer
+layout(location = 0) out vec4 outAlbedo;
+layout(location = 1) out vec4 outNormal;
+layout(location = 2) out float outDepth;
+layout(location = 3) out vec4 outMaterial;
+
+void main() {
+    outAlbedo = texture(texSampler, fragTexCoord) * constants.color;
+    outNormal = vec4(normalize(fragNormal), 0.0);
+    outDepth = gl_FragCoord.z;
+    // Shading model 0 only uses the albedo
+    outMaterial = vec4(0.0, 1.0, 1.0, 0.0);
+}
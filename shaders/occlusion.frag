@@ -0,0 +1,5 @@
+#version 450
+
+// Only the depth test matters, the query counts the samples that pass it
+void main() {
+}
@@ -0,0 +1,12 @@
+#version 450
+
+// Transforms the unit cube to the mesh's bounding box in clip space
+layout(push_constant) uniform PushConstants {
+    mat4 mvp;
+} constants;
+
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = constants.mvp * vec4(inPosition, 1.0);
+}
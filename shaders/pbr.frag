@@ -0,0 +1,171 @@
+#version 450
+
+const float PI = 3.14159265359;
+
+// Have to match the renderer
+const int MAX_SHADOW_LIGHTS = 4;
+const int MAX_POINT_LIGHTS = 32;
+
+// Keeps surfaces from shadowing themselves
+const float SHADOW_BIAS = 0.005;
+
+// Reflectance of dielectrics at normal incidence, as glTF assumes
+const vec3 DIELECTRIC_F0 = vec3(0.04);
+
+// Perfectly smooth surfaces would shrink highlights of point lights to nothing
+const float MIN_ROUGHNESS = 0.045;
+
+struct ShadowLight {
+    vec3 position;
+    float radius;
+};
+
+struct PointLight {
+    vec3 position;
+    float radius;
+    vec3 color;
+    int shadowMap;
+};
+
+layout(binding = 1) uniform Lights {
+    vec3 sunDirection;
+    uint pointCount;
+    vec3 sunColor;
+    vec3 ambient;
+    ShadowLight shadows[MAX_SHADOW_LIGHTS];
+    PointLight points[MAX_POINT_LIGHTS];
+} lights;
+
+layout(binding = 2) uniform samplerCube shadowMaps[MAX_SHADOW_LIGHTS];
+
+layout(set = 1, binding = 0) uniform sampler2D baseColorMap;
+layout(set = 1, binding = 1) uniform sampler2D normalMap;
+layout(set = 1, binding = 2) uniform sampler2D metallicRoughnessMap;
+layout(set = 1, binding = 3) uniform sampler2D occlusionMap;
+
+// params: metallic, roughness, normal scale, occlusion strength
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+    vec4 params;
+} constants;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 fragTangent;
+layout(location = 3) in vec2 fragTexCoord;
+layout(location = 4) in vec3 fragToCamera;
+
+layout(location = 0) out vec4 outColor;
+
+// Indexing sampler arrays with anything but constants needs an optional device feature
+float closestOccluder(int shadowMap, vec3 direction) {
+    switch (shadowMap) {
+    case 0:
+        return texture(shadowMaps[0], direction).r;
+    case 1:
+        return texture(shadowMaps[1], direction).r;
+    case 2:
+        return texture(shadowMaps[2], direction).r;
+    case 3:
+        return texture(shadowMaps[3], direction).r;
+    }
+
+    return 0.0;
+}
+
+float shadowFactor(int shadowMap) {
+    if (shadowMap < 0) {
+        return 1.0;
+    }
+
+    ShadowLight shadow = lights.shadows[shadowMap];
+    vec3 offset = fragPosition - shadow.position;
+
+    // Reversed like in shadow.frag, 1 at the light and 0 at its radius
+    float depth = 1.0 - length(offset) / shadow.radius;
+
+    return depth + SHADOW_BIAS >= closestOccluder(shadowMap, offset) ? 1.0 : 0.0;
+}
+
+vec3 surfaceNormal() {
+    vec3 normal = normalize(fragNormal);
+    vec3 tangent = fragTangent.xyz - normal * dot(normal, fragTangent.xyz);
+
+    // Meshes without tangents can't be normal mapped
+    if (dot(tangent, tangent) < 1e-8) {
+        return normal;
+    }
+
+    tangent = normalize(tangent);
+
+    vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+    vec3 mapped = texture(normalMap, fragTexCoord).xyz * 2.0 - 1.0;
+
+    mapped.xy *= constants.params.z;
+
+    return normalize(mat3(tangent, bitangent, normal) * mapped);
+}
+
+// Cook-Torrance specular with the GGX distribution, Smith-Schlick geometry term and Schlick's
+// Fresnel, plus Lambertian diffuse, as in the glTF spec. Already multiplied by N.L
+vec3 brdf(vec3 normal, vec3 view, vec3 light, vec3 baseColor, float metallic, float roughness) {
+    vec3 halfway = normalize(view + light);
+
+    float nDotL = max(dot(normal, light), 0.0);
+    float nDotV = max(dot(normal, view), 1e-4);
+    float nDotH = max(dot(normal, halfway), 0.0);
+    float vDotH = max(dot(view, halfway), 0.0);
+
+    float alpha = roughness * roughness;
+    float alpha2 = alpha * alpha;
+    float d = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+    float distribution = alpha2 / (PI * d * d);
+
+    float k = alpha / 2.0;
+    float geometry = 1.0 / (4.0 * (nDotL * (1.0 - k) + k) * (nDotV * (1.0 - k) + k));
+
+    vec3 f0 = mix(DIELECTRIC_F0, baseColor, metallic);
+    vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - vDotH, 5.0);
+
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * baseColor / PI;
+    vec3 specular = fresnel * distribution * geometry;
+
+    return (diffuse + specular) * nDotL;
+}
+
+void main() {
+    vec4 baseColor = texture(baseColorMap, fragTexCoord) * constants.color;
+    vec4 metallicRoughness = texture(metallicRoughnessMap, fragTexCoord);
+
+    float metallic = metallicRoughness.b * constants.params.x;
+    float roughness = clamp(metallicRoughness.g * constants.params.y, MIN_ROUGHNESS, 1.0);
+    float occlusion = mix(1.0, texture(occlusionMap, fragTexCoord).r, constants.params.w);
+
+    vec3 normal = surfaceNormal();
+    vec3 view = normalize(fragToCamera);
+
+    // There's no image based lighting, so metals take ambient light as if it was diffuse
+    vec3 color = lights.ambient * baseColor.rgb * occlusion;
+
+    // Light colors are scaled by PI so that rough dielectrics are as bright as with lit.frag
+    color += brdf(normal, view, -lights.sunDirection, baseColor.rgb, metallic, roughness)
+        * lights.sunColor * PI;
+
+    for (uint i = 0; i < lights.pointCount; i++) {
+        PointLight point = lights.points[i];
+        vec3 toLight = point.position - fragPosition;
+        float dist = length(toLight);
+
+        if (dist >= point.radius) {
+            continue;
+        }
+
+        float falloff = 1.0 - dist / point.radius;
+        vec3 radiance = point.color * falloff * falloff * shadowFactor(point.shadowMap) * PI;
+
+        color += brdf(normal, view, toLight / dist, baseColor.rgb, metallic, roughness) * radiance;
+    }
+
+    outColor = vec4(color, baseColor.a);
+}
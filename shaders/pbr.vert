@@ -0,0 +1,40 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+    vec4 params;
+} constants;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec4 inTangent;
+layout(location = 3) in vec2 inTexCoord;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec4 fragTangent;
+layout(location = 3) out vec2 fragTexCoord;
+layout(location = 4) out vec3 fragToCamera;
+
+void main() {
+    vec4 worldPosition = constants.model * vec4(inPosition, 1.0);
+
+    gl_Position = ubo.proj * ubo.view * worldPosition;
+    fragPosition = worldPosition.xyz;
+    // Only correct for uniform scaling
+    fragNormal = mat3(constants.model) * inNormal;
+    fragTangent = vec4(mat3(constants.model) * inTangent.xyz, inTangent.w);
+    fragTexCoord = inTexCoord;
+
+    // The view matrix is a rotation and a translation, so its inverse is cheap
+    vec3 cameraPosition = -transpose(mat3(ubo.view)) * ubo.view[3].xyz;
+
+    fragToCamera = cameraPosition - worldPosition.xyz;
+}
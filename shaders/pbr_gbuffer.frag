@@ -0,0 +1,57 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform sampler2D baseColorMap;
+layout(set = 1, binding = 1) uniform sampler2D normalMap;
+layout(set = 1, binding = 2) uniform sampler2D metallicRoughnessMap;
+layout(set = 1, binding = 3) uniform sampler2D occlusionMap;
+
+// params: metallic, roughness, normal scale, occlusion strength
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+    vec4 params;
+} constants;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 fragTangent;
+layout(location = 3) in vec2 fragTexCoord;
+layout(location = 4) in vec3 fragToCamera;
+
+// See GBUFFER_FORMATS in the renderer
+layout(location = 0) out vec4 outAlbedo;
+layout(location = 1) out vec4 outNormal;
+layout(location = 2) out float outDepth;
+layout(location = 3) out vec4 outMaterial;
+
+vec3 surfaceNormal() {
+    vec3 normal = normalize(fragNormal);
+    vec3 tangent = fragTangent.xyz - normal * dot(normal, fragTangent.xyz);
+
+    // Meshes without tangents can't be normal mapped
+    if (dot(tangent, tangent) < 1e-8) {
+        return normal;
+    }
+
+    tangent = normalize(tangent);
+
+    vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+    vec3 mapped = texture(normalMap, fragTexCoord).xyz * 2.0 - 1.0;
+
+    mapped.xy *= constants.params.z;
+
+    return normalize(mat3(tangent, bitangent, normal) * mapped);
+}
+
+void main() {
+    vec4 metallicRoughness = texture(metallicRoughnessMap, fragTexCoord);
+
+    float metallic = metallicRoughness.b * constants.params.x;
+    float roughness = metallicRoughness.g * constants.params.y;
+    float occlusion = mix(1.0, texture(occlusionMap, fragTexCoord).r, constants.params.w);
+
+    outAlbedo = texture(baseColorMap, fragTexCoord) * constants.color;
+    outNormal = vec4(surfaceNormal(), 0.0);
+    outDepth = gl_FragCoord.z;
+    outMaterial = vec4(metallic, roughness, occlusion, 1.0);
+}
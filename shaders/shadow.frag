@@ -0,0 +1,9 @@
+#version 450
+
+// Offset from the light in units of its radius
+layout(location = 0) in vec3 fragLightOffset;
+
+void main() {
+    // Distance to the light, reversed like scene depth: 1 at the light and 0 at its radius
+    gl_FragDepth = 1.0 - length(fragLightOffset);
+}
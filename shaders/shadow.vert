@@ -0,0 +1,15 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    mat4 mvp;
+    mat4 light_space;
+} constants;
+
+layout(location = 0) in vec3 inPosition;
+
+layout(location = 0) out vec3 fragLightOffset;
+
+void main() {
+    gl_Position = constants.mvp * vec4(inPosition, 1.0);
+    fragLightOffset = (constants.light_space * vec4(inPosition, 1.0)).xyz;
+}
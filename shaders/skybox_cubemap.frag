@@ -0,0 +1,17 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform samplerCube skybox;
+
+layout(push_constant) uniform PushConstants {
+    mat4 inv_view_proj;
+    vec2 res;
+} consts;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec2 ndc = gl_FragCoord.xy / consts.res * 2.0 - 1.0;
+    vec4 dir = consts.inv_view_proj * vec4(ndc, 1.0, 1.0);
+
+    outColor = vec4(texture(skybox, dir.xyz / dir.w).rgb, 1.0);
+}
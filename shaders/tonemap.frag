@@ -0,0 +1,86 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D hdrColor;
+
+layout(push_constant) uniform PushConstants {
+    float exposure;
+    uint operator;
+    uint encoding;
+} consts;
+
+// Brightness of SDR white on HDR displays, and the brightest highlights are allowed to get
+const float PAPER_WHITE_NITS = 200.0;
+const float PEAK_NITS = 1000.0;
+
+layout(location = 0) out vec4 outColor;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+vec3 linear_to_srgb(vec3 x) {
+    vec3 low = x * 12.92;
+    vec3 high = 1.055 * pow(x, vec3(1.0 / 2.4)) - 0.055;
+
+    return mix(high, low, lessThanEqual(x, vec3(0.0031308)));
+}
+
+// Rec. 709 primaries to Rec. 2020 ones, both linear
+vec3 rec709_to_rec2020(vec3 x) {
+    const mat3 m = mat3(
+        0.6274, 0.0691, 0.0164,
+        0.3293, 0.9195, 0.0880,
+        0.0433, 0.0114, 0.8956
+    );
+
+    return m * x;
+}
+
+// SMPTE ST 2084 inverse EOTF, from absolute luminance over 10000 nits
+vec3 linear_to_pq(vec3 x) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 p = pow(max(x, 0.0), vec3(m1));
+
+    return pow((c1 + c2 * p) / (1.0 + c3 * p), vec3(m2));
+}
+
+void main() {
+    vec3 color = texelFetch(hdrColor, ivec2(gl_FragCoord.xy), 0).rgb * consts.exposure;
+
+    // HDR outputs get the curve stretched up to the peak, in units of paper white
+    float range = consts.encoding >= 2 ? PEAK_NITS / PAPER_WHITE_NITS : 1.0;
+
+    if (consts.operator == 0) {
+        color = aces(color / range) * range;
+    } else {
+        color = reinhard(color / range) * range;
+    }
+
+    // 0 is an sRGB swapchain that encodes on its own, 1 an UNORM one that stores what it's given,
+    // 2 is HDR10 and 3 is scRGB, where 1 stands for 80 nits
+    if (consts.encoding == 1) {
+        color = linear_to_srgb(color);
+    } else if (consts.encoding == 2) {
+        color = linear_to_pq(rec709_to_rec2020(color) * PAPER_WHITE_NITS / 10000.0);
+    } else if (consts.encoding == 3) {
+        color *= PAPER_WHITE_NITS / 80.0;
+    }
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,128 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Parts of a frame that allocations are attributed to, see scope
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Subsystem {
+    Other,
+    Input,
+    Simulation,
+    Renderer,
+}
+
+// Allocations made during a frame by each subsystem
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AllocCounts {
+    counts: [usize; Subsystem::ALL.len()],
+}
+
+// Counts every allocation and reallocation towards the subsystem of the calling thread's current
+// scope. Installed as the global allocator with the alloc-tracking feature
+pub struct TrackingAllocator;
+
+// Restores the previous subsystem when dropped
+pub struct Scope {
+    prev: Subsystem,
+}
+
+#[cfg(feature = "alloc-tracking")]
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+static COUNTS: [AtomicUsize; Subsystem::ALL.len()] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+thread_local! {
+    static CURRENT: Cell<Subsystem> = const { Cell::new(Subsystem::Other) };
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Other,
+        Subsystem::Input,
+        Subsystem::Simulation,
+        Subsystem::Renderer,
+    ];
+}
+
+impl AllocCounts {
+    pub fn get(&self, subsystem: Subsystem) -> usize {
+        self.counts[subsystem as usize]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    // Subsystem that allocated the most, None if nothing did
+    pub fn worst(&self) -> Option<(Subsystem, usize)> {
+        Subsystem::ALL
+            .into_iter()
+            .map(|subsystem| (subsystem, self.get(subsystem)))
+            .filter(|&(_, count)| count > 0)
+            .max_by_key(|&(_, count)| count)
+    }
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| current.set(self.prev));
+    }
+}
+
+// Whether allocations are actually being counted. Without the feature every count stays zero
+pub const fn enabled() -> bool {
+    cfg!(feature = "alloc-tracking")
+}
+
+// Attributes allocations on this thread to the subsystem until the returned scope is dropped
+#[must_use]
+pub fn scope(subsystem: Subsystem) -> Scope {
+    let prev = CURRENT.try_with(|current| current.replace(subsystem)).unwrap_or(Subsystem::Other);
+
+    Scope { prev }
+}
+
+// Counts since the previous call, which is meant to be once at the end of each frame
+pub fn end_frame() -> AllocCounts {
+    let mut counts = AllocCounts::default();
+
+    for (count, counter) in counts.counts.iter_mut().zip(&COUNTS) {
+        *count = counter.swap(0, Ordering::Relaxed);
+    }
+
+    counts
+}
+
+// Thread locals can be gone already while a thread is exiting, its allocations count as Other then
+fn count_allocation() {
+    let subsystem = CURRENT.try_with(Cell::get).unwrap_or(Subsystem::Other);
+
+    COUNTS[subsystem as usize].fetch_add(1, Ordering::Relaxed);
+}
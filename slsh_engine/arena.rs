@@ -0,0 +1,52 @@
+use bumpalo::collections::Vec as ArenaVec;
+use bumpalo::Bump;
+
+const INITIAL_CAPACITY: usize = 64 * 1024;
+
+// Scratch memory for data that lives for a single frame, like event lists and generated geometry.
+// Allocating is a pointer bump, and resetting frees everything at once while keeping the chunk
+// around, so after the first few frames there are no heap allocations at all.
+pub struct FrameArena {
+    bump: Bump,
+}
+
+impl FrameArena {
+    pub fn new() -> Self {
+        Self {
+            bump: Bump::with_capacity(INITIAL_CAPACITY),
+        }
+    }
+
+    pub fn alloc<T>(&self, value: T) -> &mut T {
+        self.bump.alloc(value)
+    }
+
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> &mut [T] {
+        self.bump.alloc_slice_copy(src)
+    }
+
+    // Values in the arena are never dropped, so this is best kept to plain data
+    pub fn vec<T>(&self) -> ArenaVec<'_, T> {
+        ArenaVec::new_in(&self.bump)
+    }
+
+    pub fn vec_with_capacity<T>(&self, capacity: usize) -> ArenaVec<'_, T> {
+        ArenaVec::with_capacity_in(capacity, &self.bump)
+    }
+
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+    // Called at the start of every frame. The borrow checker ensures nothing from the previous
+    // frame is still referenced
+    pub fn reset(&mut self) {
+        self.bump.reset();
+    }
+}
+
+impl Default for FrameArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,26 @@
+pub mod gltf;
+pub mod obj;
+
+use glam::{Vec2, Vec3};
+
+#[cfg(feature = "render")]
+use crate::renderer::TexturedVertex;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+
+// What Renderer::add_mesh takes, which has no normals
+#[cfg(feature = "render")]
+pub fn textured_vertices(vertices: &[Vertex]) -> Vec<TexturedVertex> {
+    vertices
+        .iter()
+        .map(|vertex| TexturedVertex {
+            position: vertex.position,
+            uv: vertex.uv,
+        })
+        .collect()
+}
@@ -0,0 +1,366 @@
+#[cfg(feature = "render")]
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use ::gltf::image::Format;
+use ::gltf::mesh::Mode;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+#[cfg(feature = "render")]
+use super::textured_vertices;
+use super::Vertex;
+#[cfg(feature = "render")]
+use crate::renderer::{Material, MeshHandle, Renderer, TextureHandle};
+
+// Every primitive of every mesh instance in the default scene, with its world transform
+#[derive(Debug)]
+pub struct GltfMesh {
+    pub transform: Mat4,
+    pub material: Option<usize>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfMaterial {
+    pub name: Option<String>,
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub base_color_image: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct GltfImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct GltfModel {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<GltfMaterial>,
+    pub images: Vec<Option<GltfImage>>,
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import(::gltf::Error),
+    MissingPositions,
+    TooManyVertices,
+}
+
+pub fn load(path: &Path) -> Result<GltfModel, GltfError> {
+    let (document, buffers, images) = ::gltf::import(path).map_err(GltfError::Import)?;
+
+    build(&document, &buffers, images)
+}
+
+fn build(
+    document: &::gltf::Document,
+    buffers: &[::gltf::buffer::Data],
+    images: Vec<::gltf::image::Data>,
+) -> Result<GltfModel, GltfError> {
+    let mut meshes = Vec::new();
+
+    let scene = match document.default_scene() {
+        Some(scene) => Some(scene),
+        None => document.scenes().next(),
+    };
+
+    if let Some(scene) = scene {
+        for node in scene.nodes() {
+            collect_node(&node, Mat4::IDENTITY, buffers, &mut meshes)?;
+        }
+    }
+
+    let materials = document
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+
+            GltfMaterial {
+                name: material.name().map(str::to_owned),
+                base_color: Vec4::from_array(pbr.base_color_factor()),
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor(),
+                base_color_image: pbr
+                    .base_color_texture()
+                    .map(|info| info.texture().source().index()),
+            }
+        })
+        .collect();
+
+    let images = images.into_iter().map(convert_image).collect();
+
+    Ok(GltfModel {
+        meshes,
+        materials,
+        images,
+    })
+}
+
+#[cfg(feature = "render")]
+impl GltfModel {
+    // Images in formats other than 8-bit RGB(A) are skipped and fall back to the base color
+    pub fn upload(&self, renderer: &mut Renderer) -> Vec<MeshHandle> {
+        let mut textures: HashMap<usize, TextureHandle> = HashMap::new();
+
+        self.meshes
+            .iter()
+            .map(|mesh| {
+                let material = mesh.material.and_then(|idx| self.materials.get(idx));
+
+                let texture = material.and_then(|material| {
+                    let image_idx = material.base_color_image?;
+                    let image = self.images.get(image_idx)?.as_ref()?;
+
+                    let texture = *textures.entry(image_idx).or_insert_with(|| {
+                        renderer.create_texture(image.width, image.height, &image.rgba)
+                    });
+
+                    Some(texture)
+                });
+
+                let material = match (texture, material) {
+                    (Some(texture), _) => Material::Textured(texture),
+                    (None, Some(material)) => Material::Color(material.base_color.truncate()),
+                    (None, None) => Material::Color(Vec3::ONE),
+                };
+
+                let handle =
+                    renderer.add_mesh(&textured_vertices(&mesh.vertices), &mesh.indices, material);
+
+                renderer.update_transform(handle, mesh.transform);
+
+                handle
+            })
+            .collect()
+    }
+}
+
+impl Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfError::Import(e) => write!(f, "failed to import glTF: {}", e),
+            GltfError::MissingPositions => write!(f, "primitive has no vertex positions"),
+            GltfError::TooManyVertices => write!(f, "primitive has more than 65535 vertices"),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+fn collect_node(
+    node: &::gltf::Node,
+    parent_transform: Mat4,
+    buffers: &[::gltf::buffer::Data],
+    out: &mut Vec<GltfMesh>,
+) -> Result<(), GltfError> {
+    let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            // Points, lines and strips aren't supported by the mesh pipeline
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.collect(),
+                None => return Err(GltfError::MissingPositions),
+            };
+
+            if positions.len() > usize::from(u16::MAX) {
+                return Err(GltfError::TooManyVertices);
+            }
+
+            let mut normals = reader.read_normals().into_iter().flatten();
+            let mut uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32()).into_iter().flatten();
+
+            let vertices = positions
+                .iter()
+                .map(|&position| Vertex {
+                    position: Vec3::from_array(position),
+                    normal: normals.next().map_or(Vec3::ZERO, Vec3::from_array),
+                    uv: uvs.next().map_or(Vec2::ZERO, Vec2::from_array),
+                })
+                .collect();
+
+            // Non-indexed primitives draw their vertices in order
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|idx| idx as u16).collect(),
+                None => (0..positions.len() as u16).collect(),
+            };
+
+            out.push(GltfMesh {
+                transform,
+                material: primitive.material().index(),
+                vertices,
+                indices,
+            });
+        }
+    }
+
+    for child in node.children() {
+        collect_node(&child, transform, buffers, out)?;
+    }
+
+    Ok(())
+}
+
+fn convert_image(image: ::gltf::image::Data) -> Option<GltfImage> {
+    let rgba = match image.format {
+        Format::R8G8B8A8 => image.pixels,
+        Format::R8G8B8 => {
+            image.pixels.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect()
+        }
+        _ => return None,
+    };
+
+    Some(GltfImage {
+        width: image.width,
+        height: image.height,
+        rgba,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Triangle positions (0, 0, 0), (1, 0, 0), (0, 1, 0) followed by u16 indices 0, 2, 1
+    const BUFFER: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAACAAEAAAA=";
+
+    fn document() -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [
+                    {{ "translation": [1, 0, 0], "children": [1] }},
+                    {{ "translation": [0, 2, 0], "mesh": 0 }}
+                ],
+                "meshes": [{{
+                    "primitives": [
+                        {{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }},
+                        {{ "attributes": {{ "POSITION": 0 }} }},
+                        {{ "attributes": {{ "POSITION": 0 }}, "mode": 1 }}
+                    ]
+                }}],
+                "materials": [{{
+                    "name": "red",
+                    "pbrMetallicRoughness": {{
+                        "baseColorFactor": [1, 0, 0, 1],
+                        "metallicFactor": 0.5,
+                        "roughnessFactor": 0.25
+                    }}
+                }}],
+                "accessors": [
+                    {{
+                        "bufferView": 0,
+                        "componentType": 5126,
+                        "count": 3,
+                        "type": "VEC3",
+                        "min": [0, 0, 0],
+                        "max": [1, 1, 0]
+                    }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+                ],
+                "buffers": [{{
+                    "byteLength": 44,
+                    "uri": "data:application/octet-stream;base64,{}"
+                }}]
+            }}"#,
+            BUFFER
+        )
+    }
+
+    fn model() -> GltfModel {
+        let (document, buffers, images) = ::gltf::import_slice(document()).unwrap();
+
+        build(&document, &buffers, images).unwrap()
+    }
+
+    fn image(format: Format, pixels: Vec<u8>) -> ::gltf::image::Data {
+        ::gltf::image::Data {
+            pixels,
+            format,
+            width: 1,
+            height: 1,
+        }
+    }
+
+    #[test]
+    fn indexed_triangles() {
+        let model = model();
+        let mesh = &model.meshes[0];
+
+        assert_eq!(mesh.material, Some(0));
+        assert_eq!(mesh.indices, [0, 2, 1]);
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.vertices[1].position, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.vertices[1].normal, Vec3::ZERO);
+        assert_eq!(mesh.vertices[1].uv, Vec2::ZERO);
+    }
+
+    #[test]
+    fn non_indexed_and_non_triangle_primitives() {
+        let model = model();
+
+        // The line primitive is skipped
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(model.meshes[1].material, None);
+        assert_eq!(model.meshes[1].indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn node_transforms_are_composed() {
+        let model = model();
+
+        for mesh in &model.meshes {
+            assert_eq!(mesh.transform.transform_point3(Vec3::ZERO), Vec3::new(1.0, 2.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn materials() {
+        let model = model();
+        let material = &model.materials[0];
+
+        assert_eq!(model.materials.len(), 1);
+        assert_eq!(material.name.as_deref(), Some("red"));
+        assert_eq!(material.base_color, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(material.metallic, 0.5);
+        assert_eq!(material.roughness, 0.25);
+        assert_eq!(material.base_color_image, None);
+        assert!(model.images.is_empty());
+    }
+
+    #[test]
+    fn rgb_images_get_opaque_alpha() {
+        let rgba = convert_image(image(Format::R8G8B8, vec![10, 20, 30])).unwrap();
+
+        assert_eq!(rgba.rgba, [10, 20, 30, 255]);
+        assert_eq!((rgba.width, rgba.height), (1, 1));
+
+        let rgba = convert_image(image(Format::R8G8B8A8, vec![10, 20, 30, 40])).unwrap();
+
+        assert_eq!(rgba.rgba, [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn unsupported_image_formats_are_skipped() {
+        assert!(convert_image(image(Format::R16G16B16A16, vec![0; 8])).is_none());
+        assert!(convert_image(image(Format::R8, vec![0])).is_none());
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::{fs, io};
+
+use glam::{Vec2, Vec3};
+
+#[cfg(feature = "render")]
+use super::textured_vertices;
+use super::Vertex;
+#[cfg(feature = "render")]
+use crate::renderer::{Material, MeshHandle, Renderer};
+
+const DEFAULT_COLOR: Vec3 = Vec3::new(0.8, 0.8, 0.8);
+
+// One mesh per material used in the file
+#[derive(Debug)]
+pub struct ObjMesh {
+    pub material: Option<String>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse: Vec3,
+    pub diffuse_map: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse(usize, String),
+    TooManyVertices,
+}
+
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+struct MeshBuilder {
+    mesh: ObjMesh,
+    lookup: HashMap<VertexKey, u16>,
+}
+
+pub fn load(path: &Path) -> Result<ObjModel, ObjError> {
+    let src = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_owned(), e))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let (meshes, mtllibs) = parse_obj(&src)?;
+    let mut materials = Vec::new();
+
+    for mtllib in mtllibs {
+        let mtl_path = base_dir.join(mtllib);
+        let mtl_src =
+            fs::read_to_string(&mtl_path).map_err(|e| ObjError::Io(mtl_path.clone(), e))?;
+
+        materials.extend(parse_mtl(&mtl_src, base_dir)?);
+    }
+
+    Ok(ObjModel { meshes, materials })
+}
+
+impl ObjModel {
+    pub fn material(&self, name: &str) -> Option<&ObjMaterial> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    // Textures aren't decoded yet, so every mesh gets the flat diffuse color of its material
+    #[cfg(feature = "render")]
+    pub fn upload(&self, renderer: &mut Renderer) -> Vec<MeshHandle> {
+        self.meshes
+            .iter()
+            .map(|mesh| {
+                let color = match mesh.material.as_deref().and_then(|name| self.material(name)) {
+                    Some(material) => material.diffuse,
+                    None => DEFAULT_COLOR,
+                };
+
+                renderer.add_mesh(
+                    &textured_vertices(&mesh.vertices),
+                    &mesh.indices,
+                    Material::Color(color),
+                )
+            })
+            .collect()
+    }
+}
+
+impl MeshBuilder {
+    fn new(material: Option<String>) -> Self {
+        Self {
+            mesh: ObjMesh {
+                material,
+                vertices: Vec::new(),
+                indices: Vec::new(),
+            },
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn vertex(&mut self, key: VertexKey, vertex: Vertex) -> Result<u16, ObjError> {
+        if let Some(&idx) = self.lookup.get(&key) {
+            return Ok(idx);
+        }
+
+        let idx = u16::try_from(self.mesh.vertices.len()).map_err(|_| ObjError::TooManyVertices)?;
+
+        self.mesh.vertices.push(vertex);
+        self.lookup.insert(key, idx);
+
+        Ok(idx)
+    }
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ObjError::Parse(line, msg) => write!(f, "line {}: {}", line, msg),
+            ObjError::TooManyVertices => write!(f, "mesh has more than 65536 unique vertices"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+fn parse_obj(src: &str) -> Result<(Vec<ObjMesh>, Vec<String>), ObjError> {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut mtllibs = Vec::new();
+
+    let mut meshes = Vec::new();
+    let mut current = MeshBuilder::new(None);
+
+    for (i, line) in src.lines().enumerate() {
+        let line_num = i + 1;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => positions.push(parse_vec3(&mut tokens, line_num)?),
+            Some("vn") => normals.push(parse_vec3(&mut tokens, line_num)?),
+            Some("vt") => {
+                let u = parse_float(tokens.next(), line_num)?;
+                let v = parse_float(tokens.next(), line_num)?;
+
+                // OBJ has its texture origin at the bottom left, Vulkan at the top left
+                uvs.push(Vec2::new(u, 1.0 - v));
+            }
+            Some("f") => {
+                let mut corners = Vec::new();
+
+                for token in tokens {
+                    let key = parse_face_vertex(
+                        token,
+                        positions.len(),
+                        uvs.len(),
+                        normals.len(),
+                        line_num,
+                    )?;
+
+                    let vertex = Vertex {
+                        position: positions[key.0],
+                        normal: key.2.map_or(Vec3::ZERO, |n| normals[n]),
+                        uv: key.1.map_or(Vec2::ZERO, |t| uvs[t]),
+                    };
+
+                    corners.push(current.vertex(key, vertex)?);
+                }
+
+                if corners.len() < 3 {
+                    return Err(ObjError::Parse(line_num, "face has less than 3 vertices".into()));
+                }
+
+                // Triangle fan, which is fine for the convex polygons exporters produce
+                for j in 1..corners.len() - 1 {
+                    current.mesh.indices.extend([corners[0], corners[j], corners[j + 1]]);
+                }
+            }
+            Some("usemtl") => {
+                let name = tokens.next().map(str::to_owned);
+                let finished = std::mem::replace(&mut current, MeshBuilder::new(name));
+
+                if !finished.mesh.indices.is_empty() {
+                    meshes.push(finished.mesh);
+                }
+            }
+            Some("mtllib") => mtllibs.extend(tokens.map(str::to_owned)),
+            _ => (),
+        }
+    }
+
+    if !current.mesh.indices.is_empty() {
+        meshes.push(current.mesh);
+    }
+
+    Ok((meshes, mtllibs))
+}
+
+fn parse_mtl(src: &str, base_dir: &Path) -> Result<Vec<ObjMaterial>, ObjError> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let line_num = i + 1;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("newmtl") => {
+                let name = tokens.next().unwrap_or_default().to_owned();
+
+                materials.push(ObjMaterial {
+                    name,
+                    diffuse: DEFAULT_COLOR,
+                    diffuse_map: None,
+                });
+            }
+            Some("Kd") => {
+                if let Some(material) = materials.last_mut() {
+                    material.diffuse = parse_vec3(&mut tokens, line_num)?;
+                }
+            }
+            Some("map_Kd") => {
+                // Options such as -s come before the file name
+                if let Some(material) = materials.last_mut() {
+                    material.diffuse_map = tokens.last().map(|file| base_dir.join(file));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(materials)
+}
+
+fn parse_face_vertex(
+    token: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+    line_num: usize,
+) -> Result<VertexKey, ObjError> {
+    let mut parts = token.split('/');
+
+    let position = match parts.next() {
+        Some(idx) => resolve_index(idx, position_count, line_num)?,
+        None => return Err(ObjError::Parse(line_num, "empty face vertex".into())),
+    };
+
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(idx) => Some(resolve_index(idx, uv_count, line_num)?),
+    };
+
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(idx) => Some(resolve_index(idx, normal_count, line_num)?),
+    };
+
+    Ok((position, uv, normal))
+}
+
+// Indices are 1-based, negative ones count back from the last element defined so far
+fn resolve_index(token: &str, count: usize, line_num: usize) -> Result<usize, ObjError> {
+    let idx: isize = token
+        .parse()
+        .map_err(|_| ObjError::Parse(line_num, format!("invalid index \"{}\"", token)))?;
+
+    let resolved = if idx < 0 {
+        count.checked_sub(idx.unsigned_abs())
+    } else {
+        idx.unsigned_abs().checked_sub(1)
+    };
+
+    match resolved {
+        Some(resolved) if resolved < count => Ok(resolved),
+        _ => Err(ObjError::Parse(line_num, format!("index {} out of range", idx))),
+    }
+}
+
+fn parse_vec3(tokens: &mut SplitWhitespace, line_num: usize) -> Result<Vec3, ObjError> {
+    let x = parse_float(tokens.next(), line_num)?;
+    let y = parse_float(tokens.next(), line_num)?;
+    let z = parse_float(tokens.next(), line_num)?;
+
+    Ok(Vec3::new(x, y, z))
+}
+
+fn parse_float(token: Option<&str>, line_num: usize) -> Result<f32, ObjError> {
+    match token.map(str::parse) {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(ObjError::Parse(line_num, "expected a number".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+f 1 2 3 4
+";
+
+    fn parse_err(src: &str) -> (usize, String) {
+        match parse_obj(src) {
+            Err(ObjError::Parse(line, msg)) => (line, msg),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quad_is_triangulated_as_fan() {
+        let (meshes, _) = parse_obj(QUAD).unwrap();
+
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].vertices.len(), 4);
+        assert_eq!(meshes[0].indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn pentagon_is_triangulated_as_fan() {
+        let src = "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n";
+        let (meshes, _) = parse_obj(src).unwrap();
+
+        assert_eq!(meshes[0].indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn negative_indices_count_from_last() {
+        let src = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+vt 0.25 0.25
+vn 0 0 1
+f -3/-1/-1 -2/-1/-1 -1/-1/-1
+";
+        let (meshes, _) = parse_obj(src).unwrap();
+        let vertices = &meshes[0].vertices;
+
+        assert_eq!(vertices[0].position, Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(vertices[2].position, Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(vertices[1].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(vertices[1].uv, Vec2::new(0.25, 0.75));
+    }
+
+    #[test]
+    fn negative_indices_are_relative_to_current_line() {
+        let src = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+f -3 -2 -1
+v 5 5 5
+f -4 -3 -1
+";
+        let (meshes, _) = parse_obj(src).unwrap();
+        let mesh = &meshes[0];
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 1, 3]);
+        assert_eq!(mesh.vertices[3].position, Vec3::new(5.0, 5.0, 5.0));
+    }
+
+    #[test]
+    fn resolve_index_bounds() {
+        assert_eq!(resolve_index("1", 3, 1).unwrap(), 0);
+        assert_eq!(resolve_index("3", 3, 1).unwrap(), 2);
+        assert_eq!(resolve_index("-1", 3, 1).unwrap(), 2);
+        assert_eq!(resolve_index("-3", 3, 1).unwrap(), 0);
+
+        assert!(resolve_index("0", 3, 1).is_err());
+        assert!(resolve_index("4", 3, 1).is_err());
+        assert!(resolve_index("-4", 3, 1).is_err());
+        assert!(resolve_index("1", 0, 1).is_err());
+        assert!(resolve_index("x", 3, 1).is_err());
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let (line, msg) = parse_err("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n");
+        assert_eq!(line, 4);
+        assert_eq!(msg, "index 4 out of range");
+
+        let (line, msg) = parse_err("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 -4\n");
+        assert_eq!(line, 4);
+        assert_eq!(msg, "index -4 out of range");
+
+        let (line, _) = parse_err("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1/1 2/1 3/1\n");
+        assert_eq!(line, 4);
+
+        let (line, _) = parse_err("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1//1 2//1 3//1\n");
+        assert_eq!(line, 4);
+    }
+
+    #[test]
+    fn degenerate_faces_are_errors() {
+        let (line, msg) = parse_err("v 0 0 0\nv 1 0 0\nf 1 2\n");
+        assert_eq!(line, 3);
+        assert_eq!(msg, "face has less than 3 vertices");
+    }
+
+    #[test]
+    fn bad_numbers_are_errors() {
+        let (line, msg) = parse_err("v 0 0 0\nv 1 zero 0\n");
+        assert_eq!(line, 2);
+        assert_eq!(msg, "expected a number");
+
+        let (line, _) = parse_err("v 0 0\n");
+        assert_eq!(line, 1);
+    }
+
+    #[test]
+    fn shared_corners_are_deduplicated() {
+        let src = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n";
+        let (meshes, _) = parse_obj(src).unwrap();
+
+        assert_eq!(meshes[0].vertices.len(), 4);
+        assert_eq!(meshes[0].indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn meshes_split_by_material() {
+        let src = "\
+mtllib a.mtl b.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+usemtl red
+f 1 2 3
+usemtl blue
+usemtl green
+f 3 2 1
+";
+        let (meshes, mtllibs) = parse_obj(src).unwrap();
+
+        assert_eq!(mtllibs, ["a.mtl", "b.mtl"]);
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].material.as_deref(), Some("red"));
+        assert_eq!(meshes[1].material.as_deref(), Some("green"));
+        assert_eq!(meshes[1].indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn mtl_materials() {
+        let src = "\
+Kd 1 0 0
+newmtl plain
+newmtl tex
+Kd 0.5 0.25 1
+map_Kd -s 2 2 1 textures/wall.png
+";
+        let materials = parse_mtl(src, Path::new("models")).unwrap();
+
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "plain");
+        assert_eq!(materials[0].diffuse, DEFAULT_COLOR);
+        assert_eq!(materials[0].diffuse_map, None);
+        assert_eq!(materials[1].diffuse, Vec3::new(0.5, 0.25, 1.0));
+        assert_eq!(
+            materials[1].diffuse_map.as_deref(),
+            Some(Path::new("models/textures/wall.png"))
+        );
+    }
+
+    #[test]
+    fn mtl_bad_color_is_error() {
+        match parse_mtl("newmtl a\nKd 1 1\n", Path::new("")) {
+            Err(ObjError::Parse(2, _)) => (),
+            other => panic!("expected a parse error on line 2, got {:?}", other),
+        }
+    }
+}
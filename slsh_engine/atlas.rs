@@ -0,0 +1,237 @@
+use glam::Vec2;
+
+// Packs many small RGBA8 images into one texture, so that HUD icons, decals and glyphs can be
+// drawn with a single texture binding. Images are placed left to right on shelves as tall as the
+// tallest image in them, which works well when sizes are similar. Each image is surrounded by
+// padding filled with copies of its edge pixels, so that filtering and smaller mip levels don't
+// bleed in colors from the neighbours
+pub struct TextureAtlas {
+    width: u32,
+    height: u32,
+    padding: u32,
+    pixels: Vec<u8>,
+    cursor_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+}
+
+// UV rectangle of an image in the atlas
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AtlasRegion {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl TextureAtlas {
+    pub fn new(width: u32, height: u32, padding: u32) -> Self {
+        assert!(width > 0 && height > 0, "atlas must not be empty");
+
+        Self {
+            width,
+            height,
+            padding,
+            pixels: vec![0; width as usize * height as usize * 4],
+            cursor_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        }
+    }
+
+    // Pixels are tightly packed RGBA8, row by row from the top. None if the atlas is full
+    pub fn insert(&mut self, width: u32, height: u32, pixels: &[u8]) -> Option<AtlasRegion> {
+        assert!(width > 0 && height > 0, "image must not be empty");
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * 4,
+            "image data must be tightly packed RGBA8"
+        );
+
+        let padded_width = width + self.padding * 2;
+        let padded_height = height + self.padding * 2;
+
+        if padded_width > self.width {
+            return None;
+        }
+
+        // A failed insert leaves the current shelf open for smaller images
+        let (x, y, shelf_height) = if self.cursor_x + padded_width > self.width {
+            (0, self.shelf_y + self.shelf_height, 0)
+        } else {
+            (self.cursor_x, self.shelf_y, self.shelf_height)
+        };
+
+        if y + padded_height > self.height {
+            return None;
+        }
+
+        self.copy_padded(x, y, width, height, pixels);
+
+        self.cursor_x = x + padded_width;
+        self.shelf_y = y;
+        self.shelf_height = shelf_height.max(padded_height);
+
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let min = Vec2::new((x + self.padding) as f32, (y + self.padding) as f32);
+        let max = min + Vec2::new(width as f32, height as f32);
+
+        Some(AtlasRegion {
+            min: min / size,
+            max: max / size,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // As taken by Renderer::create_texture
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    fn copy_padded(&mut self, x: u32, y: u32, width: u32, height: u32, pixels: &[u8]) {
+        let padding = self.padding as i64;
+
+        for dst_y in 0..height + self.padding * 2 {
+            let src_y = (dst_y as i64 - padding).clamp(0, height as i64 - 1) as usize;
+
+            for dst_x in 0..width + self.padding * 2 {
+                let src_x = (dst_x as i64 - padding).clamp(0, width as i64 - 1) as usize;
+
+                let src = (src_y * width as usize + src_x) * 4;
+                let dst = ((y + dst_y) as usize * self.width as usize + (x + dst_x) as usize) * 4;
+
+                self.pixels[dst..dst + 4].copy_from_slice(&pixels[src..src + 4]);
+            }
+        }
+    }
+}
+
+impl AtlasRegion {
+    // Maps UVs of the original image, from 0 to 1, into the atlas
+    pub fn uv(&self, local: Vec2) -> Vec2 {
+        self.min + (self.max - self.min) * local
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> Vec<u8> {
+        vec![value; width as usize * height as usize * 4]
+    }
+
+    fn pixel(atlas: &TextureAtlas, x: u32, y: u32) -> &[u8] {
+        let idx = (y as usize * atlas.width() as usize + x as usize) * 4;
+
+        &atlas.pixels()[idx..idx + 4]
+    }
+
+    #[test]
+    fn shelves() {
+        let mut atlas = TextureAtlas::new(8, 8, 0);
+
+        let a = atlas.insert(4, 2, &solid(4, 2, 1)).unwrap();
+        let b = atlas.insert(4, 3, &solid(4, 3, 2)).unwrap();
+        let c = atlas.insert(2, 2, &solid(2, 2, 3)).unwrap();
+
+        assert_eq!(
+            a,
+            AtlasRegion {
+                min: Vec2::new(0.0, 0.0),
+                max: Vec2::new(0.5, 0.25)
+            }
+        );
+        assert_eq!(b.min, Vec2::new(0.5, 0.0));
+        // Second shelf starts below the tallest image of the first
+        assert_eq!(c.min, Vec2::new(0.0, 0.375));
+
+        assert_eq!(pixel(&atlas, 3, 1), [1; 4]);
+        assert_eq!(pixel(&atlas, 4, 2), [2; 4]);
+        assert_eq!(pixel(&atlas, 1, 4), [3; 4]);
+        assert_eq!(pixel(&atlas, 3, 2), [0; 4]);
+    }
+
+    #[test]
+    fn padding_repeats_edges() {
+        let mut atlas = TextureAtlas::new(4, 4, 1);
+        let pixels = [
+            solid(1, 1, 10),
+            solid(1, 1, 20),
+            solid(1, 1, 30),
+            solid(1, 1, 40),
+        ]
+        .concat();
+
+        let region = atlas.insert(2, 2, &pixels).unwrap();
+
+        assert_eq!(
+            region,
+            AtlasRegion {
+                min: Vec2::new(0.25, 0.25),
+                max: Vec2::new(0.75, 0.75)
+            }
+        );
+        assert_eq!(pixel(&atlas, 0, 0), [10; 4]);
+        assert_eq!(pixel(&atlas, 3, 0), [20; 4]);
+        assert_eq!(pixel(&atlas, 0, 3), [30; 4]);
+        assert_eq!(pixel(&atlas, 3, 3), [40; 4]);
+        assert_eq!(pixel(&atlas, 2, 1), [20; 4]);
+        assert_eq!(region.uv(Vec2::new(0.5, 1.0)), Vec2::new(0.5, 0.75));
+    }
+
+    #[test]
+    fn exact_fit() {
+        let mut atlas = TextureAtlas::new(4, 4, 0);
+
+        for _ in 0..4 {
+            assert!(atlas.insert(2, 2, &solid(2, 2, 1)).is_some());
+        }
+
+        assert!(atlas.insert(1, 1, &solid(1, 1, 1)).is_none());
+    }
+
+    #[test]
+    fn overflow() {
+        let mut atlas = TextureAtlas::new(8, 8, 1);
+
+        // Padding makes these too big even though the images themselves would fit
+        assert!(atlas.insert(8, 1, &solid(8, 1, 1)).is_none());
+        assert!(atlas.insert(1, 7, &solid(1, 7, 1)).is_none());
+
+        assert!(atlas.insert(4, 4, &solid(4, 4, 1)).is_some());
+        assert!(atlas.insert(4, 4, &solid(4, 4, 1)).is_none());
+        assert!(atlas.insert(6, 6, &solid(6, 6, 1)).is_none());
+        assert_eq!(pixel(&atlas, 7, 7), [0; 4]);
+    }
+
+    #[test]
+    fn failed_insert_keeps_shelf_open() {
+        let mut atlas = TextureAtlas::new(8, 8, 0);
+
+        atlas.insert(4, 4, &solid(4, 4, 1)).unwrap();
+
+        // Too wide for the rest of the shelf and too tall for a new one
+        assert!(atlas.insert(6, 6, &solid(6, 6, 2)).is_none());
+
+        let region = atlas.insert(4, 4, &solid(4, 4, 3)).unwrap();
+
+        assert_eq!(region.min, Vec2::new(0.5, 0.0));
+
+        let region = atlas.insert(8, 4, &solid(8, 4, 4)).unwrap();
+
+        assert_eq!(region.min, Vec2::new(0.0, 0.5));
+        assert!(atlas.insert(1, 1, &solid(1, 1, 5)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "tightly packed RGBA8")]
+    fn wrong_pixel_count() {
+        TextureAtlas::new(8, 8, 0).insert(2, 2, &solid(2, 1, 0));
+    }
+}
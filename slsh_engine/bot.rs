@@ -0,0 +1,115 @@
+use glam::Vec3;
+
+use crate::camera::Camera;
+use crate::input::InputHandler;
+use crate::math;
+use crate::nav::{NavGraph, NodeId};
+use crate::physics::{CollisionWorld, Entity};
+use crate::rng::Rng;
+
+const ARRIVE_RADIUS: f32 = 1.5;
+const JUMP_HEIGHT: f32 = 1.0;
+
+// Computer-controlled player. It drives the same Entity movement code as the local player by
+// faking the input and view direction that a human would produce
+#[derive(Clone)]
+pub struct Bot {
+    entity: Entity,
+    camera: Camera,
+    input: InputHandler,
+    // Remaining waypoints, next one last
+    path: Vec<NodeId>,
+}
+
+impl Bot {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            entity: Entity::new(position.x, position.y, position.z),
+            camera: Camera::new(1.0),
+            input: InputHandler::new(0, 0),
+            path: Vec::new(),
+        }
+    }
+
+    pub fn entity(&self) -> &Entity {
+        &self.entity
+    }
+
+    pub fn entity_mut(&mut self) -> &mut Entity {
+        &mut self.entity
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    // Returns false if the goal can't be reached from the node nearest to the bot
+    pub fn go_to(&mut self, nav: &NavGraph, goal: NodeId) -> bool {
+        let start = match nav.nearest(self.entity.position()) {
+            Some(start) => start,
+            None => return false,
+        };
+
+        match nav.find_path(start, goal) {
+            Some(mut path) => {
+                path.reverse();
+                self.path = path;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Idle bots wander to random nodes
+    pub fn update(
+        &mut self,
+        nav: &NavGraph,
+        world: &CollisionWorld,
+        rng: &mut Rng,
+        dt: f64,
+        current_time: f64,
+    ) {
+        if self.is_idle() && !nav.is_empty() {
+            let idx = rng.range_u32(0..nav.len() as u32) as usize;
+
+            if let Some(goal) = nav.node(idx) {
+                self.go_to(nav, goal);
+            }
+        }
+
+        self.steer(nav);
+
+        self.entity.update(&self.input, &mut self.camera, world, dt, current_time);
+    }
+
+    fn steer(&mut self, nav: &NavGraph) {
+        self.input.forward = 0;
+        self.input.up = 0;
+
+        let position = self.entity.position();
+
+        while let Some(&next) = self.path.last() {
+            let mut delta = nav.position(next) - position;
+            let rise = delta.y;
+
+            delta.y = 0.0;
+
+            if delta.length() < ARRIVE_RADIUS {
+                self.path.pop();
+                continue;
+            }
+
+            // Matches the forward vector that Entity derives from the camera yaw
+            let yaw = math::atan2(delta.x, delta.z);
+
+            self.camera.set_orientation(0.0, yaw, 0.0);
+            self.input.forward = 1;
+
+            if rise > JUMP_HEIGHT {
+                self.input.up = 1;
+            }
+
+            break;
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec3};
+
+type Cell = (i32, i32, i32);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ItemId(usize);
+
+pub struct UniformGrid<T> {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<usize>>,
+    // Smallest and largest cell anything was ever linked to. Not shrunk on removal, so it may be
+    // larger than needed
+    occupied: Option<(Cell, Cell)>,
+    items: Vec<Option<(Aabb, T)>>,
+    free_slots: Vec<usize>,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center(center: Vec3, half_extents: Vec3) -> Self {
+        Self {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    // None when there are no points
+    pub fn from_points(mut points: impl Iterator<Item = Vec3>) -> Option<Self> {
+        let first = points.next()?;
+
+        Some(points.fold(Aabb::new(first, first), |aabb, point| {
+            Aabb::new(aabb.min.min(point), aabb.max.max(point))
+        }))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn expanded(&self, amount: Vec3) -> Aabb {
+        Aabb::new(self.min - amount, self.max + amount)
+    }
+
+    pub fn translated(&self, offset: Vec3) -> Aabb {
+        Aabb::new(self.min + offset, self.max + offset)
+    }
+
+    // Box around this one after an affine transform, so larger than it when rotated
+    pub fn transformed(&self, transform: &Mat4) -> Aabb {
+        let half_extents = self.half_extents();
+
+        let extent = transform.x_axis.truncate().abs() * half_extents.x
+            + transform.y_axis.truncate().abs() * half_extents.y
+            + transform.z_axis.truncate().abs() * half_extents.z;
+
+        Aabb::from_center(transform.transform_point3(self.center()), extent)
+    }
+
+    // Slab test. Returns distance along the ray to the entry point, or 0 if origin is inside
+    pub fn ray_intersection(&self, origin: Vec3, inv_dir: Vec3, max_dist: f32) -> Option<f32> {
+        let mut t_near = 0.0_f32;
+        let mut t_far = max_dist;
+
+        for axis in 0..3 {
+            // Rays parallel to the slab are inside it along their whole length or never. Their
+            // distances would be infinite or NaN
+            if inv_dir[axis].is_infinite() {
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+
+                continue;
+            }
+
+            let t1 = (self.min[axis] - origin[axis]) * inv_dir[axis];
+            let t2 = (self.max[axis] - origin[axis]) * inv_dir[axis];
+
+            t_near = t_near.max(t1.min(t2));
+            t_far = t_far.min(t1.max(t2));
+        }
+
+        if t_near <= t_far {
+            Some(t_near)
+        } else {
+            None
+        }
+    }
+
+    // Swept test of this box moving by `motion` against a static box. Returns the fraction of
+    // `motion` travelled before contact and the contact normal. Boxes that already overlap are
+    // not reported, so that they can separate.
+    pub fn sweep(&self, motion: Vec3, target: &Aabb) -> Option<(f32, Vec3)> {
+        let expanded = target.expanded(self.half_extents());
+        let origin = self.center();
+
+        let mut t_near = f32::NEG_INFINITY;
+        let mut t_far = f32::INFINITY;
+        let mut normal = Vec3::ZERO;
+
+        for axis in 0..3 {
+            // Without motion along the axis the boxes overlap on it for the whole move or never.
+            // Touching doesn't count, so that boxes can slide along faces they rest against
+            if motion[axis] == 0.0 {
+                if origin[axis] <= expanded.min[axis] || origin[axis] >= expanded.max[axis] {
+                    return None;
+                }
+
+                continue;
+            }
+
+            let t1 = (expanded.min[axis] - origin[axis]) / motion[axis];
+            let t2 = (expanded.max[axis] - origin[axis]) / motion[axis];
+
+            if t1.min(t2) > t_near {
+                t_near = t1.min(t2);
+                normal = Vec3::ZERO;
+                normal[axis] = -motion[axis].signum();
+            }
+
+            t_far = t_far.min(t1.max(t2));
+        }
+
+        if t_near > t_far || t_near < 0.0 || t_near > 1.0 {
+            return None;
+        }
+
+        Some((t_near, normal))
+    }
+}
+
+impl<T> UniformGrid<T> {
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "Cell size must be positive");
+
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            occupied: None,
+            items: Vec::new(),
+            free_slots: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, bounds: Aabb, item: T) -> ItemId {
+        let idx = match self.free_slots.pop() {
+            Some(idx) => {
+                self.items[idx] = Some((bounds, item));
+                idx
+            }
+            None => {
+                self.items.push(Some((bounds, item)));
+                self.items.len() - 1
+            }
+        };
+
+        self.link(idx, &bounds);
+
+        ItemId(idx)
+    }
+
+    pub fn remove(&mut self, id: ItemId) -> Option<T> {
+        let (bounds, item) = self.items.get_mut(id.0)?.take()?;
+
+        self.unlink(id.0, &bounds);
+        self.free_slots.push(id.0);
+
+        Some(item)
+    }
+
+    pub fn update(&mut self, id: ItemId, bounds: Aabb) {
+        let old_bounds = match &self.items[id.0] {
+            Some((old_bounds, _)) => *old_bounds,
+            None => return,
+        };
+
+        if self.cell_range(&old_bounds) != self.cell_range(&bounds) {
+            self.unlink(id.0, &old_bounds);
+            self.link(id.0, &bounds);
+        }
+
+        if let Some((item_bounds, _)) = &mut self.items[id.0] {
+            *item_bounds = bounds;
+        }
+    }
+
+    pub fn get(&self, id: ItemId) -> Option<(&Aabb, &T)> {
+        self.items.get(id.0)?.as_ref().map(|(bounds, item)| (bounds, item))
+    }
+
+    pub fn query_aabb(&self, bounds: &Aabb, out: &mut Vec<ItemId>) {
+        let start = out.len();
+        let (min, max) = self.cell_range(bounds);
+
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    for &idx in self.cells.get(&(x, y, z)).into_iter().flatten() {
+                        if let Some((item_bounds, _)) = &self.items[idx] {
+                            if item_bounds.intersects(bounds) {
+                                out.push(ItemId(idx));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Items spanning several cells are found once per cell
+        let mut found = out.split_off(start);
+        found.sort_unstable();
+        found.dedup();
+        out.append(&mut found);
+    }
+
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<(ItemId, f32)> {
+        let dir = dir.normalize_or_zero();
+
+        if dir == Vec3::ZERO {
+            return None;
+        }
+
+        let (occupied_min, occupied_max) = self.occupied?;
+        let inv_dir = dir.recip();
+        let step = Vec3::select(dir.cmpeq(Vec3::ZERO), Vec3::ZERO, dir.signum());
+
+        let start = self.cell_of(origin);
+        let mut cell = Vec3::new(start.0 as f32, start.1 as f32, start.2 as f32);
+
+        // Distance along the ray to the next cell boundary on each axis, and between boundaries
+        let next_boundary = (cell + step.max(Vec3::ZERO)) * self.cell_size;
+        let mut t_max = Vec3::select(
+            dir.cmpeq(Vec3::ZERO),
+            Vec3::splat(f32::INFINITY),
+            (next_boundary - origin) * inv_dir,
+        );
+        let t_delta = (inv_dir * self.cell_size).abs();
+
+        let mut best: Option<(ItemId, f32)> = None;
+        let mut t = 0.0;
+
+        while t <= max_dist {
+            let key = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+            // Past the occupied cells and moving away from them, which also ends rays with an
+            // infinite max_dist that hit nothing
+            let leaving = |cell: i32, min: i32, max: i32, step: f32| {
+                (cell < min && step <= 0.0) || (cell > max && step >= 0.0)
+            };
+
+            if leaving(key.0, occupied_min.0, occupied_max.0, step.x)
+                || leaving(key.1, occupied_min.1, occupied_max.1, step.y)
+                || leaving(key.2, occupied_min.2, occupied_max.2, step.z)
+            {
+                break;
+            }
+
+            for &idx in self.cells.get(&key).into_iter().flatten() {
+                if let Some((bounds, _)) = &self.items[idx] {
+                    if let Some(hit) = bounds.ray_intersection(origin, inv_dir, max_dist) {
+                        if best.map_or(true, |(_, best_hit)| hit < best_hit) {
+                            best = Some((ItemId(idx), hit));
+                        }
+                    }
+                }
+            }
+
+            let next_t = t_max.min_element();
+
+            // Nothing in cells further away can be closer than what we already have
+            if let Some((_, hit)) = best {
+                if hit <= next_t {
+                    break;
+                }
+            }
+
+            if t_max.x <= t_max.y && t_max.x <= t_max.z {
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+            } else if t_max.y <= t_max.z {
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+            } else {
+                cell.z += step.z;
+                t_max.z += t_delta.z;
+            }
+
+            t = next_t;
+        }
+
+        best
+    }
+
+    fn cell_of(&self, point: Vec3) -> Cell {
+        let cell = (point / self.cell_size).floor();
+
+        (cell.x as i32, cell.y as i32, cell.z as i32)
+    }
+
+    fn cell_range(&self, bounds: &Aabb) -> (Cell, Cell) {
+        (self.cell_of(bounds.min), self.cell_of(bounds.max))
+    }
+
+    fn link(&mut self, idx: usize, bounds: &Aabb) {
+        let (min, max) = self.cell_range(bounds);
+
+        self.occupied = Some(match self.occupied {
+            Some((lo, hi)) => (
+                (lo.0.min(min.0), lo.1.min(min.1), lo.2.min(min.2)),
+                (hi.0.max(max.0), hi.1.max(max.1), hi.2.max(max.2)),
+            ),
+            None => (min, max),
+        });
+
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    self.cells.entry((x, y, z)).or_default().push(idx);
+                }
+            }
+        }
+    }
+
+    fn unlink(&mut self, idx: usize, bounds: &Aabb) {
+        let (min, max) = self.cell_range(bounds);
+
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    if let Some(indices) = self.cells.get_mut(&(x, y, z)) {
+                        indices.retain(|&i| i != idx);
+
+                        if indices.is_empty() {
+                            self.cells.remove(&(x, y, z));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(min: Vec3) -> Aabb {
+        Aabb::new(min, min + Vec3::ONE)
+    }
+
+    #[test]
+    fn query_aabb_reports_items_spanning_cells_once() {
+        let mut grid = UniformGrid::new(1.0);
+        let big = grid.insert(Aabb::new(Vec3::splat(-2.5), Vec3::splat(2.5)), ());
+        let small = grid.insert(unit_box(Vec3::new(1.2, 1.2, 1.2)), ());
+        grid.insert(unit_box(Vec3::splat(10.0)), ());
+
+        let mut found = Vec::new();
+        grid.query_aabb(&Aabb::new(Vec3::splat(-3.0), Vec3::splat(3.0)), &mut found);
+
+        assert_eq!(found, [big, small]);
+    }
+
+    #[test]
+    fn query_aabb_keeps_earlier_results() {
+        let mut grid = UniformGrid::new(1.0);
+        let id = grid.insert(Aabb::new(Vec3::ZERO, Vec3::splat(3.0)), ());
+
+        let mut found = vec![id];
+        grid.query_aabb(&Aabb::new(Vec3::splat(0.5), Vec3::splat(2.5)), &mut found);
+
+        assert_eq!(found, [id, id]);
+    }
+
+    #[test]
+    fn removed_items_are_not_found() {
+        let mut grid = UniformGrid::new(1.0);
+        let id = grid.insert(unit_box(Vec3::ZERO), ());
+
+        assert_eq!(grid.remove(id), Some(()));
+
+        let mut found = Vec::new();
+        grid.query_aabb(&unit_box(Vec3::ZERO), &mut found);
+
+        assert!(found.is_empty());
+        assert_eq!(grid.raycast(Vec3::new(-5.0, 0.5, 0.5), Vec3::X, 100.0), None);
+    }
+
+    #[test]
+    fn raycast_hits_nearest_item() {
+        let mut grid = UniformGrid::new(2.0);
+        let far = grid.insert(unit_box(Vec3::new(9.0, 0.0, 0.0)), ());
+        let near = grid.insert(unit_box(Vec3::new(4.0, 0.0, 0.0)), ());
+        let origin = Vec3::new(0.0, 0.5, 0.5);
+
+        assert_eq!(grid.raycast(origin, Vec3::X, 100.0), Some((near, 4.0)));
+        assert_eq!(grid.remove(near), Some(()));
+        assert_eq!(grid.raycast(origin, Vec3::X, 100.0), Some((far, 9.0)));
+        assert_eq!(grid.raycast(origin, Vec3::X, 8.0), None);
+    }
+
+    #[test]
+    fn raycast_diagonal() {
+        let mut grid = UniformGrid::new(1.0);
+        let id = grid.insert(unit_box(Vec3::new(3.0, -4.0, 3.0)), ());
+        let (hit, dist) = grid.raycast(Vec3::ZERO, Vec3::new(1.0, -1.0, 1.0), 100.0).unwrap();
+
+        assert_eq!(hit, id);
+        assert!((dist - 3.0 * 3.0_f32.sqrt()).abs() < 1e-4, "{}", dist);
+    }
+
+    // Axis-aligned rays starting on a slab boundary used to compute 0 * inf
+    #[test]
+    fn raycast_along_box_face() {
+        let mut grid = UniformGrid::new(1.0);
+        let id = grid.insert(unit_box(Vec3::new(5.0, 0.0, 0.0)), ());
+
+        assert_eq!(grid.raycast(Vec3::ZERO, Vec3::X, 100.0), Some((id, 5.0)));
+        assert_eq!(grid.raycast(Vec3::new(0.0, 1.0, 1.0), Vec3::X, 100.0), Some((id, 5.0)));
+        assert_eq!(grid.raycast(Vec3::new(0.0, 1.01, 0.5), Vec3::X, 100.0), None);
+    }
+
+    #[test]
+    fn unbounded_raycast_that_misses_ends() {
+        let mut grid = UniformGrid::new(1.0);
+        grid.insert(unit_box(Vec3::new(5.0, 0.0, 0.0)), ());
+
+        let origin = Vec3::new(0.0, 0.5, 0.5);
+
+        assert_eq!(grid.raycast(origin, -Vec3::X, f32::INFINITY), None);
+        assert_eq!(grid.raycast(origin, Vec3::Y, f32::INFINITY), None);
+        assert_eq!(grid.raycast(origin, Vec3::new(1.0, 0.3, 0.0), f32::INFINITY), None);
+        assert_eq!(grid.raycast(Vec3::new(0.0, 3.0, 0.5), Vec3::X, f32::INFINITY), None);
+    }
+
+    #[test]
+    fn raycast_in_empty_grid() {
+        let grid = UniformGrid::<()>::new(1.0);
+
+        assert_eq!(grid.raycast(Vec3::ZERO, Vec3::X, f32::INFINITY), None);
+        assert_eq!(grid.raycast(Vec3::ZERO, Vec3::ZERO, 1.0), None);
+    }
+}
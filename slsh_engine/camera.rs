@@ -4,6 +4,11 @@ use glam::{Mat4, Vec3};
 
 use crate::input::InputHandler;
 
+const PUNCH_SPRING: f32 = 65.0;
+const PUNCH_DAMPING: f32 = 9.0;
+const PUNCH_EPSILON: f32 = 0.0001;
+
+#[derive(Clone)]
 pub struct Camera {
     fov: f32,
     near: f32,
@@ -23,16 +28,59 @@ pub struct Camera {
     proj: Mat4,
     view: Mat4,
 
+    effects: CameraEffects,
+    motion: MotionSettings,
+    // What set_fov asked for, reached gradually when FOV changes are rate limited
+    target_fov: f32,
+
     proj_needs_recalc: bool,
     view_needs_recalc: bool,
 }
 
+// Temporary angle offsets that only affect the rendered view, not the orientation used for
+// movement
+#[derive(Clone, Default)]
+struct CameraEffects {
+    punch_angles: Vec3,
+    punch_velocity: Vec3,
+    shakes: Vec<Shake>,
+    shake_angles: Vec3,
+    time: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClipPlanes {
+    pub near: f32,
+    pub far: f32,
+}
+
+// Accessibility options for effects that can cause motion sickness
+#[derive(Clone, Copy, Debug)]
+pub struct MotionSettings {
+    // Screen shake and view punch
+    pub shake: bool,
+    // Roll added by effects; roll set explicitly with set_orientation is kept
+    pub roll: bool,
+    // In radians per second, FOV changes are instant when not set
+    pub max_fov_speed: Option<f32>,
+}
+
+#[derive(Clone)]
+struct Shake {
+    amplitude: f32,
+    frequency: f32,
+    duration: f32,
+    time_left: f32,
+}
+
 impl Camera {
     pub fn new(aspect_ratio: f32) -> Self {
+        let planes = ClipPlanes::default();
+
         Self {
             fov: 70.0_f32.to_radians(),
-            near: 0.05,
-            far: 100.0,
+            near: planes.near,
+            far: planes.far,
             pitch: 0.0,
             yaw: 0.0,
             roll: 0.0,
@@ -42,6 +90,9 @@ impl Camera {
             position: Vec3::new(0.0, 0.0, 0.0),
             proj: Mat4::IDENTITY,
             view: Mat4::IDENTITY,
+            effects: CameraEffects::default(),
+            motion: MotionSettings::default(),
+            target_fov: 70.0_f32.to_radians(),
             proj_needs_recalc: true,
             view_needs_recalc: true,
         }
@@ -73,11 +124,120 @@ impl Camera {
         self.yaw
     }
 
+    pub fn roll(&self) -> f32 {
+        self.roll
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    // Pitch, yaw and roll as seen on screen, with effects applied
+    pub fn view_angles(&self) -> Vec3 {
+        let mut effect_angles = self.effects.angles();
+
+        if !self.motion.roll {
+            effect_angles.z = 0.0;
+        }
+
+        Vec3::new(self.pitch, self.yaw, self.roll) + effect_angles
+    }
+
+    pub fn motion_settings(&self) -> MotionSettings {
+        self.motion
+    }
+
+    pub fn set_motion_settings(&mut self, motion: MotionSettings) {
+        self.motion = motion;
+
+        if !motion.shake {
+            self.effects = CameraEffects::default();
+        }
+
+        if motion.max_fov_speed.is_none() {
+            self.set_fov(self.target_fov);
+        }
+
+        self.view_needs_recalc = true;
+    }
+
+    // Kicks the view by given pitch/yaw/roll velocities; the view springs back on its own
+    pub fn punch(&mut self, angular_velocity: Vec3) {
+        if self.motion.shake {
+            self.effects.punch_velocity += angular_velocity;
+        }
+    }
+
+    pub fn shake(&mut self, origin: Vec3, amplitude: f32, radius: f32, duration: f32) {
+        if !self.motion.shake {
+            return;
+        }
+
+        let distance = origin.distance(self.position);
+
+        if distance >= radius || duration <= 0.0 {
+            return;
+        }
+
+        let falloff = 1.0 - distance / radius;
+
+        self.effects.shakes.push(Shake {
+            amplitude: amplitude * falloff,
+            frequency: 25.0,
+            duration,
+            time_left: duration,
+        });
+    }
+
     pub fn set_position(&mut self, position: Vec3) {
         self.position = position;
+        self.view_needs_recalc = true;
+    }
+
+    pub fn set_orientation(&mut self, pitch: f32, yaw: f32, roll: f32) {
+        self.pitch = pitch.clamp(self.pitch_min, self.pitch_max);
+        self.yaw = yaw;
+        self.roll = roll;
+        self.view_needs_recalc = true;
     }
 
-    pub fn update(&mut self, input: &InputHandler, _dt: f64, _current_time: f64) {
+    pub fn clip_planes(&self) -> ClipPlanes {
+        ClipPlanes {
+            near: self.near,
+            far: self.far,
+        }
+    }
+
+    pub fn set_clip_planes(&mut self, planes: ClipPlanes) {
+        assert!(
+            planes.near > 0.0 && planes.far > planes.near,
+            "Clip planes must satisfy 0 < near < far"
+        );
+
+        self.near = planes.near;
+        self.far = planes.far;
+        self.proj_needs_recalc = true;
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+        self.proj_needs_recalc = true;
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.target_fov = fov;
+
+        if self.motion.max_fov_speed.is_none() {
+            self.fov = fov;
+            self.proj_needs_recalc = true;
+        }
+    }
+
+    pub fn update(&mut self, input: &InputHandler, dt: f64, _current_time: f64) {
         let sensitiviy = 2.2;
         let m_yaw = 0.022;
         let m_pitch = 0.022;
@@ -88,21 +248,106 @@ impl Camera {
 
         self.yaw += input.mouse_diff_x as f32 * m_yaw * sensitiviy * to_rads;
 
+        self.update_effects(dt as f32);
+    }
+
+    // Also called on its own while the view is driven by something other than the mouse
+    pub fn update_effects(&mut self, dt: f32) {
+        self.effects.update(dt);
+
+        if let Some(max_speed) = self.motion.max_fov_speed {
+            let max_step = max_speed * dt;
+            let step = (self.target_fov - self.fov).clamp(-max_step, max_step);
+
+            if step != 0.0 {
+                self.fov += step;
+                self.proj_needs_recalc = true;
+            }
+        }
+
         self.view_needs_recalc = true;
     }
 
     fn recalc_view_matrix(&mut self) {
+        let angles = self.view_angles();
+
         self.view = Mat4::IDENTITY
-            * Mat4::from_euler(glam::EulerRot::XYZ, -self.pitch, -self.yaw, -self.roll)
+            * Mat4::from_euler(glam::EulerRot::XYZ, -angles.x, -angles.y, -angles.z)
             * Mat4::from_translation(-self.position);
 
         self.view_needs_recalc = false;
     }
 
     fn recalc_proj_matrix(&mut self) {
-        self.proj = Mat4::perspective_lh(self.fov, self.aspect_ratio, self.near, self.far);
+        // Reversed-Z: swapping the planes maps near to depth 1 and far to 0, which together with a
+        // float depth buffer spreads precision evenly instead of wasting it close to the camera
+        self.proj = Mat4::perspective_lh(self.fov, self.aspect_ratio, self.far, self.near);
         self.proj.y_axis.y *= -1.0;
 
         self.proj_needs_recalc = false;
     }
 }
+
+impl Default for ClipPlanes {
+    fn default() -> Self {
+        Self {
+            near: 0.05,
+            far: 4096.0,
+        }
+    }
+}
+
+impl Default for MotionSettings {
+    fn default() -> Self {
+        Self {
+            shake: true,
+            roll: true,
+            max_fov_speed: None,
+        }
+    }
+}
+
+impl CameraEffects {
+    fn angles(&self) -> Vec3 {
+        self.punch_angles + self.shake_angles
+    }
+
+    fn update(&mut self, dt: f32) {
+        self.time += dt;
+
+        self.update_punch(dt);
+        self.update_shakes(dt);
+    }
+
+    fn update_punch(&mut self, dt: f32) {
+        let accel = -self.punch_angles * PUNCH_SPRING - self.punch_velocity * PUNCH_DAMPING;
+
+        self.punch_velocity += accel * dt;
+        self.punch_angles += self.punch_velocity * dt;
+
+        if self.punch_angles.length() < PUNCH_EPSILON
+            && self.punch_velocity.length() < PUNCH_EPSILON
+        {
+            self.punch_angles = Vec3::ZERO;
+            self.punch_velocity = Vec3::ZERO;
+        }
+    }
+
+    fn update_shakes(&mut self, dt: f32) {
+        self.shake_angles = Vec3::ZERO;
+
+        for shake in &mut self.shakes {
+            shake.time_left -= dt;
+
+            let fade = (shake.time_left / shake.duration).max(0.0);
+            let phase = self.time * shake.frequency;
+
+            // Incommensurate frequencies per axis so the motion doesn't look periodic
+            let wobble = Vec3::new(phase.sin(), (phase * 1.37).sin(), (phase * 0.71).sin() * 0.5);
+
+            self.shake_angles += wobble * shake.amplitude * fade;
+        }
+
+        self.shakes.retain(|shake| shake.time_left > 0.0);
+    }
+}
@@ -0,0 +1,107 @@
+use glam::Vec3;
+
+use crate::camera::Camera;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub position: Vec3,
+    pub angles: Vec3,
+    pub fov: f32,
+}
+
+#[derive(Default)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> Self {
+        Self {
+            time,
+            position: camera.position(),
+            angles: Vec3::new(camera.pitch(), camera.yaw(), camera.roll()),
+            fov: camera.fov(),
+        }
+    }
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_keyframe(&mut self, keyframe: Keyframe) {
+        let idx = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(idx, keyframe);
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn start_time(&self) -> f32 {
+        self.keyframes.first().map_or(0.0, |k| k.time)
+    }
+
+    pub fn end_time(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+
+        if time <= first.time {
+            return Some(*first);
+        }
+
+        if time >= last.time {
+            return Some(*last);
+        }
+
+        // Index of the keyframe that starts the segment containing `time`
+        let i = self.keyframes.partition_point(|k| k.time <= time) - 1;
+        let n = self.keyframes.len();
+
+        let k0 = self.keyframes[i.saturating_sub(1)];
+        let k1 = self.keyframes[i];
+        let k2 = self.keyframes[i + 1];
+        let k3 = self.keyframes[(i + 2).min(n - 1)];
+
+        let t = (time - k1.time) / (k2.time - k1.time);
+
+        Some(Keyframe {
+            time,
+            position: catmull_rom(k0.position, k1.position, k2.position, k3.position, t),
+            angles: catmull_rom(k0.angles, k1.angles, k2.angles, k3.angles, t),
+            fov: catmull_rom_scalar(k0.fov, k1.fov, k2.fov, k3.fov, t),
+        })
+    }
+
+    pub fn apply(&self, camera: &mut Camera, time: f32) {
+        if let Some(keyframe) = self.sample(time) {
+            camera.set_position(keyframe.position);
+            camera.set_orientation(keyframe.angles.x, keyframe.angles.y, keyframe.angles.z);
+            camera.set_fov(keyframe.fov);
+        }
+    }
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+fn catmull_rom_scalar(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    catmull_rom(Vec3::splat(p0), Vec3::splat(p1), Vec3::splat(p2), Vec3::splat(p3), t).x
+}
@@ -0,0 +1,39 @@
+#[cfg(feature = "renderdoc")]
+use renderdoc::{RenderDoc, V110};
+
+// Programmatic single-frame RenderDoc captures. Only works when built with the `renderdoc` feature
+// and the game was launched from RenderDoc, otherwise triggering is a no-op.
+pub struct FrameCapture {
+    #[cfg(feature = "renderdoc")]
+    api: Option<RenderDoc<V110>>,
+}
+
+impl FrameCapture {
+    // Has to be created before the Vulkan instance for RenderDoc to hook into it
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "renderdoc")]
+            api: match RenderDoc::new() {
+                Ok(api) => Some(api),
+                Err(e) => {
+                    eprintln!("RenderDoc is not available: {}", e);
+                    None
+                }
+            },
+        }
+    }
+
+    // Captures the next frame that gets presented
+    pub fn trigger(&mut self) {
+        #[cfg(feature = "renderdoc")]
+        if let Some(api) = &mut self.api {
+            api.trigger_capture();
+        }
+    }
+}
+
+impl Default for FrameCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,174 @@
+// Subset of TOML shared by the engine's text files: [section] headers followed by key = value
+// lines, with # comments. Values are booleans, numbers, quoted strings and arrays of numbers.
+// Each pair is passed to `set` along with its section, and errors come back with the line number
+pub(crate) fn parse<F>(src: &str, mut set: F) -> Result<(), (usize, String)>
+where
+    F: FnMut(&str, &str, &str) -> Result<(), String>,
+{
+    let mut section = None;
+
+    for (i, line) in src.lines().enumerate() {
+        let line_num = i + 1;
+        let line = match line.split_once('#') {
+            Some((before, _)) => before.trim(),
+            None => line.trim(),
+        };
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = Some(name.trim());
+            continue;
+        }
+
+        let section = match section {
+            Some(section) => section,
+            None => return Err((line_num, "expected a section".into())),
+        };
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err((line_num, "expected key = value".into())),
+        };
+
+        set(section, key, value).map_err(|msg| (line_num, msg))?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn parse_string(value: &str) -> Result<&str, String> {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(string) => Ok(string),
+        None => Err(format!("expected a quoted string, got {}", value)),
+    }
+}
+
+pub(crate) fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected true or false, got {}", value)),
+    }
+}
+
+// Rust also parses "nan" and "inf", which no option wants
+pub(crate) fn parse_float(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(float) if float.is_finite() => Ok(float),
+        _ => Err(format!("expected a number, got {}", value)),
+    }
+}
+
+pub(crate) fn parse_floats<const N: usize>(value: &str) -> Result<[f32; N], String> {
+    let error = || format!("expected an array of {} numbers, got {}", N, value);
+
+    let inner = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')).ok_or_else(error)?;
+    let mut out = [0.0; N];
+    let mut count = 0;
+
+    for item in inner.split(',') {
+        let slot = out.get_mut(count).ok_or_else(error)?;
+
+        *slot = parse_float(item.trim()).map_err(|_| error())?;
+        count += 1;
+    }
+
+    if count == N {
+        Ok(out)
+    } else {
+        Err(error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(src: &str) -> Result<Vec<(String, String, String)>, (usize, String)> {
+        let mut pairs = Vec::new();
+
+        parse(src, |section, key, value| {
+            pairs.push((section.to_owned(), key.to_owned(), value.to_owned()));
+            Ok(())
+        })?;
+
+        Ok(pairs)
+    }
+
+    #[test]
+    fn sections_keys_and_comments() {
+        let src = "\
+# leading comment
+
+[ a ]
+x = 1   # trailing comment
+  y=[1, 2]
+[b]
+z = \"text\"
+";
+        let pairs = collect(src).unwrap();
+
+        assert_eq!(
+            pairs,
+            [
+                ("a".into(), "x".into(), "1".into()),
+                ("a".into(), "y".into(), "[1, 2]".into()),
+                ("b".into(), "z".into(), "\"text\"".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert_eq!(collect("x = 1\n"), Err((1, "expected a section".into())));
+        assert_eq!(collect("# c\n[a]\nx 1\n"), Err((3, "expected key = value".into())));
+        assert_eq!(collect("[a]\n[b\nx = 1\n"), Err((2, "expected key = value".into())));
+    }
+
+    #[test]
+    fn setter_errors_get_line_numbers() {
+        let result = parse("[a]\nx = 1\n\ny = 2\n", |_, key, _| match key {
+            "x" => Ok(()),
+            _ => Err(format!("unknown key {}", key)),
+        });
+
+        assert_eq!(result, Err((4, "unknown key y".into())));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_string("\"abc\""), Ok("abc"));
+        assert_eq!(parse_string("\"\""), Ok(""));
+        assert!(parse_string("abc").is_err());
+        assert!(parse_string("\"abc").is_err());
+        assert!(parse_string("\"").is_err());
+
+        assert_eq!(parse_bool("true"), Ok(true));
+        assert_eq!(parse_bool("false"), Ok(false));
+        assert!(parse_bool("yes").is_err());
+
+        assert_eq!(parse_float("-1.5"), Ok(-1.5));
+        assert!(parse_float("").is_err());
+        assert!(parse_float("1.5.2").is_err());
+        assert!(parse_float("nan").is_err());
+        assert!(parse_float("inf").is_err());
+        assert!(parse_float("-infinity").is_err());
+    }
+
+    #[test]
+    fn float_arrays() {
+        assert_eq!(parse_floats::<2>("[1, -2.5]"), Ok([1.0, -2.5]));
+        assert_eq!(parse_floats::<3>("[ 0,0 , 1 ]"), Ok([0.0, 0.0, 1.0]));
+
+        assert_eq!(parse_floats::<2>("[1]"), Err("expected an array of 2 numbers, got [1]".into()));
+        assert!(parse_floats::<2>("[1, 2, 3]").is_err());
+        assert!(parse_floats::<2>("[1, x]").is_err());
+        assert!(parse_floats::<2>("[1, 2,]").is_err());
+        assert!(parse_floats::<2>("1, 2").is_err());
+        assert!(parse_floats::<1>("[]").is_err());
+        assert!(parse_floats::<2>("[1, NaN]").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::panic::{self, PanicInfo};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, thread};
+
+const REPORT_DIR: &str = "crashes";
+
+// Key-value pairs describing engine state, included in every report
+static CONTEXT: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+pub fn install(app_name: &'static str) {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        match write_report(app_name, info) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Failed to write crash report: {}", e),
+        }
+    }));
+}
+
+pub fn set_context(key: &'static str, value: String) {
+    let mut context = match CONTEXT.lock() {
+        Ok(context) => context,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    match context.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, v)) => *v = value,
+        None => context.push((key, value)),
+    }
+}
+
+fn write_report(app_name: &str, info: &PanicInfo) -> std::io::Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let path = PathBuf::from(REPORT_DIR).join(format!("crash-{}.txt", timestamp));
+
+    let mut report = String::new();
+
+    let _ = writeln!(report, "{} crashed", app_name);
+    let _ = writeln!(report, "Engine version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "Time: {}", timestamp);
+    let _ = writeln!(report, "Platform: {} {}", std::env::consts::OS, std::env::consts::ARCH);
+    let _ = writeln!(report, "Thread: {}", thread::current().name().unwrap_or("<unnamed>"));
+    let _ = writeln!(report, "{}", info);
+
+    // Panic may have happened while context was locked on this thread
+    if let Ok(context) = CONTEXT.try_lock() {
+        let _ = writeln!(report, "\nContext:");
+
+        for (key, value) in context.iter() {
+            let _ = writeln!(report, "{}: {}", key, value);
+        }
+    }
+
+    let _ = writeln!(report, "\nBacktrace:\n{}", Backtrace::force_capture());
+
+    fs::create_dir_all(REPORT_DIR)?;
+    fs::write(&path, report)?;
+
+    Ok(path)
+}
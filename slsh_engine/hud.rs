@@ -0,0 +1,218 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glam::{Vec2, Vec3};
+
+use crate::config::{self, parse_bool, parse_floats, parse_string};
+use crate::theme::{Palette, Theme};
+
+// Placement of HUD elements, read from a subset of TOML:
+//
+//     [theme]
+//     palette = "deuteranopia"
+//     accent = [1.0, 1.0, 1.0]
+//
+//     [crosshair]
+//     visible = true
+//     anchor = [0.5, 0.5]
+//     offset = [0, 0]
+//     color = [0.0, 1.0, 0.0]
+//
+// Elements without a section keep their default placement. Colors set in the theme section
+// override the palette's, and colors set for an element override the theme
+#[derive(Clone, Debug)]
+pub struct HudLayout {
+    pub theme: Theme,
+    pub crosshair: HudElement,
+    pub showkeys: HudElement,
+}
+
+#[derive(Clone, Debug)]
+pub struct HudElement {
+    pub visible: bool,
+    // Fraction of the window size, (0, 0) is the bottom left corner
+    pub anchor: Vec2,
+    // In pixels, added after anchoring
+    pub offset: Vec2,
+    // Theme color when not set
+    pub color: Option<Vec3>,
+}
+
+#[derive(Debug)]
+pub enum HudError {
+    Io(PathBuf, std::io::Error),
+    Parse(usize, String),
+}
+
+impl HudLayout {
+    pub fn load(path: &Path) -> Result<Self, HudError> {
+        let src = fs::read_to_string(path).map_err(|e| HudError::Io(path.to_owned(), e))?;
+
+        Self::parse(&src)
+    }
+
+    pub fn parse(src: &str) -> Result<Self, HudError> {
+        let mut layout = Self::default();
+
+        config::parse(src, |section, key, value| match section {
+            "theme" => set_theme(&mut layout.theme, key, value),
+            "crosshair" => layout.crosshair.set(key, value),
+            "showkeys" => layout.showkeys.set(key, value),
+            _ => Err(format!("unknown section {}", section)),
+        })
+        .map_err(|(line, msg)| HudError::Parse(line, msg))?;
+
+        Ok(layout)
+    }
+
+    pub fn crosshair_color(&self) -> Vec3 {
+        self.crosshair.color.unwrap_or(self.theme.crosshair)
+    }
+
+    pub fn showkeys_color(&self) -> Vec3 {
+        self.showkeys.color.unwrap_or(self.theme.accent)
+    }
+}
+
+impl Default for HudLayout {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            crosshair: HudElement {
+                visible: true,
+                anchor: Vec2::new(0.5, 0.5),
+                offset: Vec2::ZERO,
+                color: None,
+            },
+            showkeys: HudElement {
+                visible: false,
+                anchor: Vec2::new(0.5, 0.0),
+                offset: Vec2::new(0.0, 120.0),
+                color: None,
+            },
+        }
+    }
+}
+
+impl HudElement {
+    pub fn position(&self, win_width: u32, win_height: u32) -> Vec2 {
+        self.anchor * Vec2::new(win_width as f32, win_height as f32) + self.offset
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "visible" => self.visible = parse_bool(value)?,
+            "anchor" => self.anchor = Vec2::from_array(parse_floats(value)?),
+            "offset" => self.offset = Vec2::from_array(parse_floats(value)?),
+            "color" => self.color = Some(Vec3::from_array(parse_floats(value)?)),
+            _ => return Err(format!("unknown key {}", key)),
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for HudError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HudError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            HudError::Parse(line, msg) => write!(f, "line {}: {}", line, msg),
+        }
+    }
+}
+
+impl std::error::Error for HudError {}
+
+// Palette has to come before the colors that override it
+fn set_theme(theme: &mut Theme, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "palette" => {
+            let name = parse_string(value)?;
+            let palette =
+                Palette::from_name(name).ok_or_else(|| format!("unknown palette {}", name))?;
+
+            *theme = Theme::new(palette);
+        }
+        "crosshair" => theme.crosshair = Vec3::from_array(parse_floats(value)?),
+        "accent" => theme.accent = Vec3::from_array(parse_floats(value)?),
+        _ => return Err(format!("unknown key {}", key)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(src: &str) -> (usize, String) {
+        match HudLayout::parse(src) {
+            Err(HudError::Parse(line, msg)) => (line, msg),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn empty_layout_is_default() {
+        let layout = HudLayout::parse("# nothing here\n").unwrap();
+
+        assert!(layout.crosshair.visible);
+        assert!(!layout.showkeys.visible);
+        assert_eq!(layout.crosshair_color(), Theme::default().crosshair);
+    }
+
+    #[test]
+    fn element_overrides() {
+        let src = "\
+[showkeys]
+visible = true
+anchor = [0.25, 1]
+offset = [10, -20]
+
+[crosshair]
+color = [1, 0, 0]
+";
+        let layout = HudLayout::parse(src).unwrap();
+
+        assert!(layout.showkeys.visible);
+        assert_eq!(layout.showkeys.position(800, 600), Vec2::new(210.0, 580.0));
+        assert_eq!(layout.crosshair_color(), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(layout.showkeys_color(), layout.theme.accent);
+    }
+
+    #[test]
+    fn theme_colors_override_palette() {
+        let src = "[theme]\npalette = \"tritanopia\"\naccent = [0, 0, 1]\n";
+        let layout = HudLayout::parse(src).unwrap();
+        let palette = Theme::new(Palette::from_name("tritanopia").unwrap());
+
+        assert_eq!(layout.theme.crosshair, palette.crosshair);
+        assert_eq!(layout.showkeys_color(), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert_eq!(parse_err("visible = true\n"), (1, "expected a section".into()));
+        assert_eq!(parse_err("[crosshair]\nvisible\n"), (2, "expected key = value".into()));
+        assert_eq!(parse_err("[radar]\nvisible = true\n"), (2, "unknown section radar".into()));
+        assert_eq!(parse_err("[crosshair]\nsize = 3\n"), (2, "unknown key size".into()));
+        assert_eq!(
+            parse_err("[crosshair]\nvisible = 1\n"),
+            (2, "expected true or false, got 1".into())
+        );
+        assert_eq!(
+            parse_err("[crosshair]\n\nanchor = [0.5]\n"),
+            (3, "expected an array of 2 numbers, got [0.5]".into())
+        );
+        assert_eq!(
+            parse_err("[theme]\npalette = default\n"),
+            (2, "expected a quoted string, got default".into())
+        );
+        assert_eq!(
+            parse_err("[theme]\npalette = \"sepia\"\n"),
+            (2, "unknown palette sepia".into())
+        );
+    }
+}
@@ -1,5 +1,29 @@
-use crate::window::Key;
+use crate::keys::{Key, MouseButton, Scancode};
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Forward,
+    Back,
+    MoveLeft,
+    MoveRight,
+    Jump,
+}
+
+// Binds are kept by scancode, so that they stay on the same physical keys when the keyboard
+// layout changes. The key is what the scancode produced when bound, used as a fallback name
+#[derive(Clone, Default, Debug)]
+pub struct Bindings {
+    binds: Vec<Bind>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bind {
+    pub scancode: Scancode,
+    pub key: Key,
+    pub action: Action,
+}
+
+#[derive(Clone)]
 pub struct InputHandler {
     mouse_prev_x: i32,
     mouse_prev_y: i32,
@@ -10,6 +34,22 @@ pub struct InputHandler {
     pub forward: i8,
     pub right: i8,
     pub up: i8,
+
+    pub mouse_left: bool,
+    pub mouse_right: bool,
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "forward" => Some(Action::Forward),
+            "back" => Some(Action::Back),
+            "moveleft" => Some(Action::MoveLeft),
+            "moveright" => Some(Action::MoveRight),
+            "jump" => Some(Action::Jump),
+            _ => None,
+        }
+    }
 }
 
 impl InputHandler {
@@ -22,9 +62,15 @@ impl InputHandler {
             forward: 0,
             right: 0,
             up: 0,
+            mouse_left: false,
+            mouse_right: false,
         }
     }
 
+    pub fn reset(&mut self, mouse_x: i32, mouse_y: i32) {
+        *self = Self::new(mouse_x, mouse_y);
+    }
+
     pub fn handle_mouse(&mut self, x: i32, y: i32) {
         self.mouse_diff_x = x - self.mouse_prev_x;
         self.mouse_diff_y = y - self.mouse_prev_y;
@@ -33,41 +79,75 @@ impl InputHandler {
         self.mouse_prev_y = y;
     }
 
-    pub fn handle_key_press(&mut self, key: Key) {
-        match key {
-            Key::W => self.forward = 1,
-            Key::S => self.forward = -1,
-            Key::D => self.right = 1,
-            Key::A => self.right = -1,
-            Key::Space => self.up = 1,
+    pub fn handle_mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        match button {
+            MouseButton::Left => self.mouse_left = pressed,
+            MouseButton::Right => self.mouse_right = pressed,
             _ => (),
         }
     }
 
-    pub fn handle_key_release(&mut self, key: Key) {
-        match key {
-            Key::W => {
+    pub fn handle_action_press(&mut self, action: Action) {
+        match action {
+            Action::Forward => self.forward = 1,
+            Action::Back => self.forward = -1,
+            Action::MoveRight => self.right = 1,
+            Action::MoveLeft => self.right = -1,
+            Action::Jump => self.up = 1,
+        }
+    }
+
+    pub fn handle_action_release(&mut self, action: Action) {
+        match action {
+            Action::Forward => {
                 if self.forward == 1 {
                     self.forward = 0;
                 }
             }
-            Key::S => {
+            Action::Back => {
                 if self.forward == -1 {
                     self.forward = 0;
                 }
             }
-            Key::D => {
+            Action::MoveRight => {
                 if self.right == 1 {
                     self.right = 0;
                 }
             }
-            Key::A => {
+            Action::MoveLeft => {
                 if self.right == -1 {
                     self.right = 0;
                 }
             }
-            Key::Space => self.up = 0,
-            _ => (),
+            Action::Jump => self.up = 0,
         }
     }
 }
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Replaces whatever the scancode was bound to
+    pub fn bind(&mut self, scancode: Scancode, key: Key, action: Action) {
+        self.unbind(scancode);
+        self.binds.push(Bind {
+            scancode,
+            key,
+            action,
+        });
+    }
+
+    pub fn unbind(&mut self, scancode: Scancode) {
+        self.binds.retain(|bind| bind.scancode != scancode);
+    }
+
+    pub fn action(&self, scancode: Scancode) -> Option<Action> {
+        self.binds.iter().find(|bind| bind.scancode == scancode).map(|bind| bind.action)
+    }
+
+    pub fn binds_for(&self, action: Action) -> impl Iterator<Item = &Bind> {
+        self.binds.iter().filter(move |bind| bind.action == action)
+    }
+}
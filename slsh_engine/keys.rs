@@ -0,0 +1,57 @@
+// Input types shared by the window backend and the game code. Conversions from GLFW are only
+// compiled in with the `render` feature
+
+pub type Scancode = i32;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    pub super_: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u8),
+}
+
+macro_rules! define_keys {
+    ($($key:ident),* $(,)?) => {
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+        pub enum Key {
+            $($key,)*
+        }
+
+        #[cfg(feature = "window")]
+        impl Key {
+            pub(crate) fn from_glfw(key: glfw::Key) -> Self {
+                match key {
+                    $(glfw::Key::$key => Key::$key,)*
+                }
+            }
+
+            pub(crate) fn to_glfw(self) -> glfw::Key {
+                match self {
+                    $(Key::$key => glfw::Key::$key,)*
+                }
+            }
+        }
+    };
+}
+
+define_keys! {
+    Space, Apostrophe, Comma, Minus, Period, Slash, Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7,
+    Num8, Num9, Semicolon, Equal, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V,
+    W, X, Y, Z, LeftBracket, Backslash, RightBracket, GraveAccent, World1, World2, Escape, Enter,
+    Tab, Backspace, Insert, Delete, Right, Left, Down, Up, PageUp, PageDown, Home, End, CapsLock,
+    ScrollLock, NumLock, PrintScreen, Pause, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13,
+    F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24, F25, Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6,
+    Kp7, Kp8, Kp9, KpDecimal, KpDivide, KpMultiply, KpSubtract, KpAdd, KpEnter, KpEqual, LeftShift,
+    LeftControl, LeftAlt, LeftSuper, RightShift, RightControl, RightAlt, RightSuper, Menu, Unknown,
+}
@@ -7,10 +7,35 @@
     clippy::uninlined_format_args
 )]
 
+pub mod alloc_tracking;
+pub mod arena;
+pub mod assets;
+pub mod atlas;
+pub mod bot;
+pub mod broadphase;
 pub mod camera;
+pub mod camera_path;
+#[cfg(feature = "render")]
+pub mod capture;
+mod config;
+pub mod crash;
+pub mod hud;
 pub mod input;
+pub mod keys;
+#[cfg(feature = "window")]
 pub mod main_loop;
+pub mod math;
+pub mod nav;
+pub mod photo_mode;
 pub mod physics;
+pub mod remote;
+#[cfg(feature = "render")]
 pub mod renderer;
+pub mod rewind;
+pub mod rng;
+pub mod settings;
+pub mod theme;
+pub mod time;
 pub mod ui;
+#[cfg(feature = "window")]
 pub mod window;
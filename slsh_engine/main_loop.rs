@@ -1,24 +1,99 @@
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use ash::vk;
+use glam::Vec3;
+
+use crate::alloc_tracking::{self, Subsystem};
+use crate::arena::FrameArena;
+use crate::bot::Bot;
 use crate::camera::Camera;
-use crate::input::InputHandler;
-use crate::physics::Entity;
-use crate::renderer::Renderer;
+use crate::camera_path::{CameraPath, Keyframe};
+use crate::capture::FrameCapture;
+use crate::hud::HudError;
+use crate::input::{Action, Bindings, InputHandler};
+use crate::nav::NavGraph;
+use crate::photo_mode::PhotoMode;
+use crate::physics::{self, CollisionWorld, Entity, EntityCollision};
+use crate::remote::{RemoteCommand, RemoteControl, TickState};
+use crate::renderer::{Renderer, RendererConfig, RendererError, ViewHandle};
+use crate::rewind::{RewindBuffer, RewindConfig};
+use crate::rng::{Rng, RngService, Stream};
+use crate::settings::Settings;
+use crate::theme::Palette;
+use crate::time::{self, GameTime, TimerHandle};
 use crate::ui::UserInterface;
-use crate::window::{Event, Key, Resolution, Window};
+use crate::window::{self, Event, Key, MonitorInfo, Resolution, WindowId, WindowManager};
+
+const KEYFRAME_INTERVAL: f32 = 2.0;
+const UPDATES_PER_SECOND: i16 = 60;
+const DEFAULT_BINDS: [(Key, Action); 5] = [
+    (Key::W, Action::Forward),
+    (Key::S, Action::Back),
+    (Key::A, Action::MoveLeft),
+    (Key::D, Action::MoveRight),
+    (Key::Space, Action::Jump),
+];
+
+// Run by MainLoop::after on the simulation tick it's due. Shared, so that snapshots can keep
+// timers to fire again after a rewind
+pub type TimerCallback = Rc<dyn Fn(&mut MainLoop)>;
+
+// Moves a tool view's camera before each of its frames, given the main camera
+pub type ToolCameraCallback = Box<dyn FnMut(&Camera, &mut Camera)>;
 
 pub struct MainLoop {
-    window: Window,
+    windows: WindowManager,
     renderer: Renderer,
+    capture: FrameCapture,
     camera: Camera,
     input: InputHandler,
+    bindings: Bindings,
     ui: UserInterface,
     player: Entity,
+    world: CollisionWorld,
+    rng: RngService,
+    nav: NavGraph,
+    bots: Vec<Bot>,
+    bot_rng: Rng,
+    entity_collision: EntityCollision,
+    tool_views: Vec<ToolView>,
+    camera_path: CameraPath,
+    cinematic_start: Option<f64>,
+    photo_mode: Option<PhotoMode>,
+    history: RewindBuffer<Snapshot>,
+    remote: Option<RemoteControl>,
+    time: GameTime<TimerCallback>,
+    rewinding: bool,
     running: bool,
+    focused: bool,
+}
+
+// Everything needed to put the simulation back to an earlier tick
+struct Snapshot {
+    player: Entity,
+    view_angles: Vec3,
+    bots: Vec<Bot>,
+    bot_rng: Rng,
+    // Along with the timers pending at the time
+    time: GameTime<TimerCallback>,
+}
+
+// Another window drawn by the main renderer, showing the same world from a camera of its own
+pub struct ToolView {
+    window_id: WindowId,
+    view: ViewHandle,
+    pub camera: Camera,
+    ui: UserInterface,
+    camera_callback: Option<ToolCameraCallback>,
 }
 
 impl MainLoop {
-    pub fn new(res: &Resolution, app_name: &'static str) -> Self {
-        let window = Window::new(res, app_name);
-        let renderer = unsafe { Renderer::new(app_name, &window) };
+    pub fn new(res: &Resolution, app_name: &'static str) -> Result<Self, RendererError> {
+        let capture = FrameCapture::new();
+        let windows = WindowManager::new(res, app_name);
+        let window = windows.primary();
+        let renderer = unsafe { Renderer::new(app_name, window, &RendererConfig::default())? };
 
         let aspect_ratio = window.width() as f32 / window.height() as f32;
         let camera = Camera::new(aspect_ratio);
@@ -26,64 +101,444 @@ impl MainLoop {
         let (prev_mouse_x, prev_mouse_y) = window.mouse_pos();
         let input = InputHandler::new(prev_mouse_x as i32, prev_mouse_y as i32);
 
+        let mut bindings = Bindings::new();
+
+        // Scancodes of the keys in the layout active at startup
+        for (key, action) in DEFAULT_BINDS {
+            if let Some(scancode) = key.scancode() {
+                bindings.bind(scancode, key, action);
+            }
+        }
+
         let ui = UserInterface::new(window.width(), window.height());
 
         let player = Entity::new(0.0, 8.0, 0.0);
 
-        Self {
-            window,
+        let rng = RngService::from_time();
+        let bot_rng = rng.stream(Stream::Gameplay);
+
+        Ok(Self {
+            windows,
             renderer,
+            capture,
             camera,
             input,
+            bindings,
             ui,
             player,
+            world: CollisionWorld::new(),
+            rng,
+            nav: NavGraph::new(),
+            bots: Vec::new(),
+            bot_rng,
+            entity_collision: EntityCollision::default(),
+            tool_views: Vec::new(),
+            camera_path: CameraPath::new(),
+            cinematic_start: None,
+            photo_mode: None,
+            history: RewindBuffer::new(&RewindConfig::default(), UPDATES_PER_SECOND as u32),
+            remote: None,
+            time: GameTime::new(UPDATES_PER_SECOND as u32),
+            rewinding: false,
             running: true,
+            focused: true,
+        })
+    }
+
+    pub fn set_resolution(&mut self, res: &Resolution) {
+        self.windows.primary_mut().set_resolution(res);
+    }
+
+    pub fn monitors(&mut self) -> Vec<MonitorInfo> {
+        self.windows.primary_mut().monitors()
+    }
+
+    pub fn renderer_mut(&mut self) -> &mut Renderer {
+        &mut self.renderer
+    }
+
+    pub fn set_rewind_config(&mut self, config: &RewindConfig) {
+        self.history = RewindBuffer::new(config, UPDATES_PER_SECOND as u32);
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+
+    // Names of the keys bound to the action in the current keyboard layout, for menus and the HUD
+    pub fn binding_names(&self, action: Action) -> Vec<String> {
+        self.bindings
+            .binds_for(action)
+            .map(|bind| {
+                window::scancode_name(bind.scancode).unwrap_or_else(|| format!("{:?}", bind.key))
+            })
+            .collect()
+    }
+
+    // F9 reloads the file from the same path
+    pub fn load_hud(&mut self, path: &Path) -> Result<(), HudError> {
+        self.ui.load_hud(path)
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.ui.set_palette(palette);
+    }
+
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.camera.set_motion_settings(settings.motion);
+        self.camera.set_clip_planes(settings.clip_planes);
+    }
+
+    // Pauses the simulation and detaches the camera from the player, see PhotoMode for controls
+    pub fn set_photo_mode(&mut self, enabled: bool) {
+        match (self.photo_mode.take(), enabled) {
+            (Some(photo_mode), false) => {
+                photo_mode.exit(&mut self.camera);
+                self.ui.set_hidden(false);
+            }
+            (None, true) => {
+                self.cinematic_start = None;
+                self.photo_mode = Some(PhotoMode::enter(&mut self.camera));
+            }
+            (photo_mode, _) => self.photo_mode = photo_mode,
+        }
+    }
+
+    pub fn photo_mode_active(&self) -> bool {
+        self.photo_mode.is_some()
+    }
+
+    // Off unless enabled. Commands are applied and state is published once per simulation tick
+    pub fn enable_remote_control(&mut self, remote: RemoteControl) {
+        self.remote = Some(remote);
+    }
+
+    pub fn rng(&self) -> &RngService {
+        &self.rng
+    }
+
+    // Has to be called before anything draws from the streams for runs to be reproducible
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = RngService::new(seed);
+        self.bot_rng = self.rng.stream(Stream::Gameplay);
+    }
+
+    pub fn time(&self) -> &GameTime<TimerCallback> {
+        &self.time
+    }
+
+    // Game time doesn't pass during cinematics or photo mode, and neither do timers. Rewinds take
+    // it back, so timers that fired since the tick rewound to fire again
+    pub fn after(
+        &mut self,
+        seconds: f64,
+        callback: impl Fn(&mut MainLoop) + 'static,
+    ) -> TimerHandle {
+        self.time.after(seconds, Rc::new(callback))
+    }
+
+    pub fn cancel_timer(&mut self, handle: TimerHandle) {
+        self.time.cancel(handle);
+    }
+
+    pub fn nav_graph_mut(&mut self) -> &mut NavGraph {
+        &mut self.nav
+    }
+
+    pub fn spawn_bot(&mut self, position: Vec3) {
+        self.bots.push(Bot::new(position));
+    }
+
+    pub fn bots(&self) -> &[Bot] {
+        &self.bots
+    }
+
+    // How the player and bots treat each other, they pass through one another by default
+    pub fn set_entity_collision(&mut self, mode: EntityCollision) {
+        self.entity_collision = mode;
+    }
+
+    // The window is closed again if it can't be rendered to
+    pub fn open_tool_view(
+        &mut self,
+        width: u32,
+        height: u32,
+        title: &str,
+    ) -> Result<WindowId, RendererError> {
+        let window_id = self.windows.open_tool_window(width, height, title);
+        let added = match self.windows.get(window_id) {
+            Some(window) => unsafe { self.renderer.add_view(window) },
+            // Just opened, so there's no surface to create only if opening it failed
+            None => Err(RendererError::Surface(vk::Result::ERROR_INITIALIZATION_FAILED)),
+        };
+        let view = match added {
+            Ok(view) => view,
+            Err(e) => {
+                self.windows.close(window_id);
+                return Err(e);
+            }
+        };
+        let camera = Camera::new(width as f32 / height as f32);
+        let ui = UserInterface::new(width, height);
+
+        self.tool_views.push(ToolView {
+            window_id,
+            view,
+            camera,
+            ui,
+            camera_callback: None,
+        });
+
+        Ok(window_id)
+    }
+
+    // Without one the view's camera only moves when changed through tool_view_mut
+    pub fn set_tool_view_camera(
+        &mut self,
+        window_id: WindowId,
+        callback: impl FnMut(&Camera, &mut Camera) + 'static,
+    ) {
+        if let Some(view) = self.tool_view_mut(window_id) {
+            view.camera_callback = Some(Box::new(callback));
         }
     }
 
+    pub fn tool_view_mut(&mut self, window_id: WindowId) -> Option<&mut ToolView> {
+        self.tool_views.iter_mut().find(|view| view.window_id == window_id)
+    }
+
+    pub fn close_tool_view(&mut self, window_id: WindowId) {
+        // View has to go before the window that its surface belongs to
+        if let Some(view) = self.tool_views.iter().find(|view| view.window_id == window_id) {
+            self.renderer.remove_view(view.view);
+        }
+
+        self.tool_views.retain(|view| view.window_id != window_id);
+        self.windows.close(window_id);
+    }
+
     pub fn run(&mut self) {
-        let updates_per_second: i16 = 60;
-        let dt = 1.0 / f64::from(updates_per_second);
+        let dt = 1.0 / f64::from(UPDATES_PER_SECOND);
 
         let title_update_delay = 0.1;
         let mut next_title_update_time = 0.0;
 
-        let mut current_time = self.window.current_time();
-        let minimized = false;
+        let mut current_time = self.windows.primary().current_time();
+        let mut minimized = false;
+
+        let mut frame_arena = FrameArena::new();
 
         while self.running {
+            frame_arena.reset();
+
             if minimized {
-                self.window.block_until_event();
+                self.windows.primary_mut().block_until_event();
             }
 
-            self.window.poll_events(|event| match event {
-                Event::KeyPress(Key::Escape) => self.running = false,
-                Event::KeyPress(key) => self.input.handle_key_press(key),
-                Event::KeyRelease(key) => self.input.handle_key_release(key),
-                _ => (),
+            let mut focus_change = None;
+            let mut toggle_photo_mode = false;
+
+            let input_scope = alloc_tracking::scope(Subsystem::Input);
+
+            self.windows.poll_events(|window_id, event| {
+                if window_id != WindowManager::PRIMARY {
+                    if let Event::Resize(width, height) = event {
+                        for view in &mut self.tool_views {
+                            if view.window_id == window_id {
+                                view.resize(&mut self.renderer, width, height);
+                            }
+                        }
+                    }
+
+                    return;
+                }
+
+                match event {
+                    Event::KeyPress(Key::Escape, ..) => self.running = false,
+                    Event::KeyPress(Key::F5, ..) => {
+                        let time = if self.camera_path.keyframes().is_empty() {
+                            0.0
+                        } else {
+                            self.camera_path.end_time() + KEYFRAME_INTERVAL
+                        };
+
+                        self.camera_path.add_keyframe(Keyframe::from_camera(&self.camera, time));
+                    }
+                    Event::KeyPress(Key::F6, ..) => {
+                        self.cinematic_start = match self.cinematic_start {
+                            Some(_) => None,
+                            None if self.camera_path.keyframes().is_empty() => None,
+                            None => Some(current_time),
+                        };
+                    }
+                    Event::KeyPress(Key::F7, ..) => {
+                        self.cinematic_start = None;
+                        self.camera_path.clear();
+                    }
+                    Event::KeyPress(Key::F8, ..) => self.rewinding = true,
+                    Event::KeyRelease(Key::F8, ..) => self.rewinding = false,
+                    Event::KeyPress(Key::F9, ..) => {
+                        if let Err(e) = self.ui.reload_hud() {
+                            eprintln!("Failed to reload HUD layout: {}", e);
+                        }
+                    }
+                    Event::KeyPress(Key::F10, ..) => toggle_photo_mode = true,
+                    Event::KeyPress(Key::F11, ..) => {
+                        let secs = time::wall_clock().as_secs();
+                        let path = PathBuf::from(format!("screenshots/screenshot-{}.png", secs));
+
+                        match self.renderer.capture_screenshot(&path) {
+                            Ok(()) => println!("Screenshot written to {}", path.display()),
+                            Err(e) => eprintln!("Failed to capture screenshot: {}", e),
+                        }
+                    }
+                    Event::KeyPress(Key::F12, ..) => self.capture.trigger(),
+                    // Photo mode controls take precedence over binds while it's active
+                    Event::KeyPress(key, ..)
+                        if photo_mode_key(&mut self.photo_mode, &mut self.camera, key, true) => {}
+                    Event::KeyRelease(key, ..)
+                        if photo_mode_key(&mut self.photo_mode, &mut self.camera, key, false) => {}
+                    Event::KeyPress(_, scancode, _) => {
+                        if let Some(action) = self.bindings.action(scancode) {
+                            self.input.handle_action_press(action);
+                        }
+                    }
+                    Event::KeyRelease(_, scancode, _) => {
+                        if let Some(action) = self.bindings.action(scancode) {
+                            self.input.handle_action_release(action);
+                        }
+                    }
+                    Event::MouseButtonPress(button) => self.input.handle_mouse_button(button, true),
+                    Event::MouseButtonRelease(button) => {
+                        self.input.handle_mouse_button(button, false);
+                    }
+                    Event::Focus(focused) => focus_change = Some(focused),
+                    Event::Resize(width, height) => {
+                        minimized = width == 0 || height == 0;
+
+                        if !minimized {
+                            self.renderer.resize(width, height);
+                            self.camera.set_aspect_ratio(width as f32 / height as f32);
+                            self.ui.resize(width, height);
+                        }
+                    }
+                    _ => (),
+                }
             });
 
-            let real_time = self.window.current_time();
+            drop(input_scope);
+
+            if let Some(focused) = focus_change {
+                self.handle_focus_change(focused);
+            }
+
+            if toggle_photo_mode {
+                self.set_photo_mode(self.photo_mode.is_none());
+            }
+
+            let real_time = self.windows.primary().current_time();
+
+            let simulation_scope = alloc_tracking::scope(Subsystem::Simulation);
 
             while current_time < real_time {
                 current_time += dt;
 
-                let (mouse_x, mouse_y) = self.window.mouse_pos();
-                self.input.handle_mouse(mouse_x as i32, mouse_y as i32);
-                self.player.update(&self.input, &mut self.camera, dt, current_time);
-                self.camera.set_position(self.player.eye_position());
-                self.camera.update(&self.input, dt, current_time);
+                if let Some(photo_mode) = &mut self.photo_mode {
+                    if self.focused {
+                        let (mouse_x, mouse_y) = self.windows.primary().mouse_pos();
+                        self.input.handle_mouse(mouse_x as i32, mouse_y as i32);
+                    }
+
+                    photo_mode.update(&mut self.camera, &self.input, dt);
+                    self.ui.set_hidden(photo_mode.hud_hidden());
+                } else if let Some(start_time) = self.cinematic_start {
+                    self.update_cinematic(current_time - start_time);
+                    self.camera.update_effects(dt as f32);
+                } else if self.rewinding {
+                    self.step_back();
+                } else {
+                    if self.focused {
+                        let (mouse_x, mouse_y) = self.windows.primary().mouse_pos();
+                        self.input.handle_mouse(mouse_x as i32, mouse_y as i32);
+                    }
+
+                    self.poll_remote();
+
+                    let prev_yaw = self.camera.yaw();
+
+                    self.player.update(
+                        &self.input,
+                        &mut self.camera,
+                        &self.world,
+                        dt,
+                        current_time,
+                    );
+
+                    for bot in &mut self.bots {
+                        bot.update(&self.nav, &self.world, &mut self.bot_rng, dt, current_time);
+                    }
+
+                    let mut entities = frame_arena.vec();
+
+                    entities.push(&mut self.player);
+                    entities.extend(self.bots.iter_mut().map(Bot::entity_mut));
+
+                    physics::resolve_entity_collisions(
+                        &mut entities,
+                        &self.world,
+                        self.entity_collision,
+                        dt,
+                    );
+
+                    drop(entities);
+
+                    self.camera.set_position(self.player.eye_position());
+                    self.camera.update(&self.input, dt, current_time);
+                    self.ui.update_showkeys(&self.input, self.camera.yaw() - prev_yaw);
+
+                    let view_angles =
+                        Vec3::new(self.camera.pitch(), self.camera.yaw(), self.camera.roll());
+
+                    if let Some(remote) = &mut self.remote {
+                        remote.publish(&TickState {
+                            tick: self.time.tick(),
+                            position: self.player.position(),
+                            velocity: self.player.velocity(),
+                            view_angles,
+                        });
+                    }
+
+                    for callback in self.time.advance() {
+                        callback(self);
+                    }
+
+                    // After the timers, which can change the world too
+                    let snapshot = self.snapshot();
+
+                    self.history.push(snapshot);
+                }
+
                 self.renderer.update(dt, current_time);
             }
 
-            if self.window.should_close() {
+            drop(simulation_scope);
+
+            if self.windows.primary().should_close() {
                 break;
             }
 
+            let renderer_scope = alloc_tracking::scope(Subsystem::Renderer);
+
             self.renderer.update_data(&mut self.ui, &mut self.camera);
             self.renderer.present();
 
-            let frame_end = self.window.current_time();
+            self.present_tool_views(&frame_arena);
+
+            drop(renderer_scope);
+
+            let alloc_counts = alloc_tracking::end_frame();
+
+            let frame_end = self.windows.primary().current_time();
 
             if frame_end > next_title_update_time {
                 next_title_update_time = frame_end + title_update_delay;
@@ -92,10 +547,142 @@ impl MainLoop {
 
                 let fps = 1.0 / frame_time;
 
-                let title = format!("slsh | speed = {:03.1} FPS = {:04.0}", self.player.speed(), fps);
+                let cull_stats = self.renderer.cull_stats();
+
+                // CPU time covers the whole frame, waiting on the GPU included
+                let gpu_time = match self.renderer.gpu_stats() {
+                    Some(stats) => format!("{:.2} ms", stats.frame.as_secs_f64() * 1000.0),
+                    None => "n/a".to_string(),
+                };
+
+                let mut title = format!(
+                    "slsh | speed = {:03.1} FPS = {:04.0} CPU = {:.2} ms GPU = {} meshes = {}/{}",
+                    self.player.speed(),
+                    fps,
+                    frame_time * 1000.0,
+                    gpu_time,
+                    cull_stats.visible,
+                    cull_stats.visible + cull_stats.culled + cull_stats.occluded
+                );
+
+                // Of the last frame. Building the title allocates too, which shows up as Other
+                if alloc_tracking::enabled() {
+                    title += &format!(" allocs = {}", alloc_counts.total());
+
+                    if let Some((subsystem, count)) = alloc_counts.worst() {
+                        title += &format!(" ({:?} {})", subsystem, count);
+                    }
+                }
+
+                self.windows.primary_mut().set_title(&title);
+            }
+        }
+    }
+
+    fn update_cinematic(&mut self, elapsed: f64) {
+        let time = self.camera_path.start_time() + elapsed as f32;
+
+        self.camera_path.apply(&mut self.camera, time);
+
+        if time >= self.camera_path.end_time() {
+            self.cinematic_start = None;
+        }
+    }
+
+    fn poll_remote(&mut self) {
+        let input = &mut self.input;
+
+        if let Some(remote) = &mut self.remote {
+            remote.poll(|command| match command {
+                RemoteCommand::Press(action) => input.handle_action_press(action),
+                RemoteCommand::Release(action) => input.handle_action_release(action),
+            });
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            player: self.player.clone(),
+            view_angles: Vec3::new(self.camera.pitch(), self.camera.yaw(), self.camera.roll()),
+            bots: self.bots.clone(),
+            bot_rng: self.bot_rng.clone(),
+            time: self.time.clone(),
+        }
+    }
+
+    fn step_back(&mut self) {
+        if let Some(snapshot) = self.history.step_back() {
+            let angles = snapshot.view_angles;
+
+            self.player = snapshot.player;
+            self.bots = snapshot.bots;
+            self.bot_rng = snapshot.bot_rng;
+            self.time = snapshot.time;
+            self.camera.set_orientation(angles.x, angles.y, angles.z);
+            self.camera.set_position(self.player.eye_position());
+        }
+    }
 
-                self.window.set_title(&title);
+    fn handle_focus_change(&mut self, focused: bool) {
+        self.focused = focused;
+
+        let window = self.windows.primary_mut();
+
+        if focused {
+            window.capture_cursor();
+        } else {
+            window.release_cursor();
+        }
+
+        // Keys held while focus is lost never get their release events
+        let (mouse_x, mouse_y) = window.mouse_pos();
+        self.input.reset(mouse_x as i32, mouse_y as i32);
+    }
+
+    fn present_tool_views(&mut self, frame_arena: &FrameArena) {
+        let mut closed = frame_arena.vec();
+
+        closed.extend(
+            self.tool_views
+                .iter()
+                .map(|view| view.window_id)
+                .filter(|&id| self.windows.get(id).map_or(true, |window| window.should_close())),
+        );
+
+        for window_id in closed {
+            self.close_tool_view(window_id);
+        }
+
+        for view in &mut self.tool_views {
+            if let Some(callback) = &mut view.camera_callback {
+                callback(&self.camera, &mut view.camera);
             }
+
+            self.renderer.present_view(view.view, &mut view.ui, &mut view.camera);
         }
     }
 }
+
+fn photo_mode_key(
+    photo_mode: &mut Option<PhotoMode>,
+    camera: &mut Camera,
+    key: Key,
+    pressed: bool,
+) -> bool {
+    match photo_mode {
+        Some(photo_mode) => photo_mode.handle_key(key, pressed, camera),
+        None => false,
+    }
+}
+
+impl ToolView {
+    fn resize(&mut self, renderer: &mut Renderer, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        renderer.resize_view(self.view, width, height);
+        self.camera.set_aspect_ratio(width as f32 / height as f32);
+        self.ui.resize(width, height);
+    }
+}
@@ -0,0 +1,106 @@
+// Trigonometry for the simulation. std's versions call into the platform's libm, whose results
+// can differ in the last bits between operating systems and CPUs, which is enough for replays and
+// lockstep peers to drift apart. These are computed with only basic f64 arithmetic, which IEEE 754
+// makes exact, so every target gets the same bits. The `native-math` feature switches back to std
+// where that doesn't matter
+//
+// Everything else the simulation does is plain f32 arithmetic and sqrt, which are already
+// deterministic as Rust never fuses multiplies and adds on its own
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_6, PI};
+
+// pi/2 split in two so that k * FRAC_PI_2_HI is exact for the k that f32 angles produce
+const FRAC_PI_2_HI: f64 = 1.570_796_326_734_125_6;
+const FRAC_PI_2_LO: f64 = 6.077_100_506_506_192e-11;
+
+const SQRT_3: f64 = 1.732_050_807_568_877_2;
+const TAN_FRAC_PI_12: f64 = 0.267_949_192_431_122_7;
+
+pub fn sin_cos(angle: f32) -> (f32, f32) {
+    if cfg!(feature = "native-math") {
+        return angle.sin_cos();
+    }
+
+    let x = f64::from(angle);
+    let quadrant = (x / FRAC_PI_2).round();
+    let r = (x - quadrant * FRAC_PI_2_HI) - quadrant * FRAC_PI_2_LO;
+
+    let (sin, cos) = (sin_poly(r), cos_poly(r));
+
+    let (sin, cos) = match (quadrant as i64).rem_euclid(4) {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    };
+
+    (sin as f32, cos as f32)
+}
+
+pub fn atan2(y: f32, x: f32) -> f32 {
+    if cfg!(feature = "native-math") {
+        return y.atan2(x);
+    }
+
+    let (y, x) = (f64::from(y), f64::from(x));
+
+    let angle = if x > 0.0 {
+        atan(y / x)
+    } else if x < 0.0 && y >= 0.0 {
+        atan(y / x) + PI
+    } else if x < 0.0 {
+        atan(y / x) - PI
+    } else if y > 0.0 {
+        FRAC_PI_2
+    } else if y < 0.0 {
+        -FRAC_PI_2
+    } else {
+        0.0
+    };
+
+    angle as f32
+}
+
+// Taylor series, accurate to well beyond f32 precision for |x| <= pi/4
+fn sin_poly(x: f64) -> f64 {
+    let x2 = x * x;
+
+    x * (1.0
+        - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0 * (1.0 - x2 / 110.0)))))
+}
+
+fn cos_poly(x: f64) -> f64 {
+    let x2 = x * x;
+
+    1.0 - x2 / 2.0
+        * (1.0
+            - x2 / 12.0
+                * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0 * (1.0 - x2 / 90.0 * (1.0 - x2 / 132.0)))))
+}
+
+fn atan(x: f64) -> f64 {
+    if x < 0.0 {
+        return -atan(-x);
+    }
+
+    if x > 1.0 {
+        return FRAC_PI_2 - atan(1.0 / x);
+    }
+
+    // atan(x) = pi/6 + atan((x * sqrt(3) - 1) / (x + sqrt(3))) brings x close enough to zero for
+    // the series to converge quickly
+    if x > TAN_FRAC_PI_12 {
+        return FRAC_PI_6 + atan((x * SQRT_3 - 1.0) / (x + SQRT_3));
+    }
+
+    let x2 = x * x;
+    let mut term = x;
+    let mut sum = x;
+
+    for n in 1..10 {
+        term *= -x2;
+        sum += term / f64::from(2 * n + 1);
+    }
+
+    sum
+}
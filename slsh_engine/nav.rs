@@ -0,0 +1,346 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt::{self, Display, Write as _};
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::{fs, io};
+
+use glam::Vec3;
+
+// Waypoints connected by walkable links. Bots path between nodes and walk straight lines between
+// consecutive ones, so links must not cross obstacles
+#[derive(Default)]
+pub struct NavGraph {
+    nodes: Vec<Vec3>,
+    links: Vec<Vec<usize>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(usize);
+
+#[derive(Debug)]
+pub enum NavError {
+    Io(PathBuf, io::Error),
+    Parse(usize, String),
+}
+
+struct OpenNode {
+    node: usize,
+    estimate: f32,
+}
+
+impl NavGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, position: Vec3) -> NodeId {
+        self.nodes.push(position);
+        self.links.push(Vec::new());
+
+        NodeId(self.nodes.len() - 1)
+    }
+
+    // Links are walkable both ways
+    pub fn link(&mut self, a: NodeId, b: NodeId) {
+        if !self.links[a.0].contains(&b.0) {
+            self.links[a.0].push(b.0);
+            self.links[b.0].push(a.0);
+        }
+    }
+
+    pub fn position(&self, node: NodeId) -> Vec3 {
+        self.nodes[node.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, idx: usize) -> Option<NodeId> {
+        (idx < self.nodes.len()).then_some(NodeId(idx))
+    }
+
+    pub fn nearest(&self, position: Vec3) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                a.distance_squared(position).total_cmp(&b.distance_squared(position))
+            })
+            .map(|(idx, _)| NodeId(idx))
+    }
+
+    // Text format with one node per `n x y z` line and one link per `l a b` line, where nodes are
+    // referred to by their zero-based order in the file. Lines starting with # are comments
+    pub fn load(path: &Path) -> Result<Self, NavError> {
+        let src = fs::read_to_string(path).map_err(|e| NavError::Io(path.to_owned(), e))?;
+
+        Self::parse(&src)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), NavError> {
+        fs::write(path, self.serialize()).map_err(|e| NavError::Io(path.to_owned(), e))
+    }
+
+    pub fn parse(src: &str) -> Result<Self, NavError> {
+        let mut graph = Self::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line_num = i + 1;
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("n") => {
+                    let x = parse_float(&mut tokens, line_num)?;
+                    let y = parse_float(&mut tokens, line_num)?;
+                    let z = parse_float(&mut tokens, line_num)?;
+
+                    graph.add_node(Vec3::new(x, y, z));
+                }
+                Some("l") => {
+                    let a = parse_node(&mut tokens, &graph, line_num)?;
+                    let b = parse_node(&mut tokens, &graph, line_num)?;
+
+                    graph.link(a, b);
+                }
+                Some(token) if token.starts_with('#') => (),
+                Some(token) => {
+                    return Err(NavError::Parse(line_num, format!("unknown statement {}", token)))
+                }
+                None => (),
+            }
+        }
+
+        Ok(graph)
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+
+        for node in &self.nodes {
+            let _ = writeln!(out, "n {} {} {}", node.x, node.y, node.z);
+        }
+
+        for (a, links) in self.links.iter().enumerate() {
+            // Every link is stored in both directions
+            for &b in links.iter().filter(|&&b| b > a) {
+                let _ = writeln!(out, "l {} {}", a, b);
+            }
+        }
+
+        out
+    }
+
+    // Path between the nodes closest to the two points, as a list of positions to walk through
+    pub fn find_path_between(&self, from: Vec3, to: Vec3) -> Option<Vec<Vec3>> {
+        let path = self.find_path(self.nearest(from)?, self.nearest(to)?)?;
+
+        Some(path.into_iter().map(|node| self.position(node)).collect())
+    }
+
+    // A* over straight-line distances. The path includes both ends
+    pub fn find_path(&self, from: NodeId, to: NodeId) -> Option<Vec<NodeId>> {
+        let goal = self.nodes[to.0];
+
+        let mut cost = vec![f32::INFINITY; self.nodes.len()];
+        let mut came_from = vec![usize::MAX; self.nodes.len()];
+        let mut open = BinaryHeap::new();
+
+        cost[from.0] = 0.0;
+        open.push(OpenNode {
+            node: from.0,
+            estimate: self.nodes[from.0].distance(goal),
+        });
+
+        while let Some(OpenNode { node, .. }) = open.pop() {
+            if node == to.0 {
+                let mut path = vec![to];
+                let mut current = node;
+
+                while current != from.0 {
+                    current = came_from[current];
+                    path.push(NodeId(current));
+                }
+
+                path.reverse();
+
+                return Some(path);
+            }
+
+            for &next in &self.links[node] {
+                let next_cost = cost[node] + self.nodes[node].distance(self.nodes[next]);
+
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    came_from[next] = node;
+                    open.push(OpenNode {
+                        node: next,
+                        estimate: next_cost + self.nodes[next].distance(goal),
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Display for NavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NavError::Io(path, e) => write!(f, "failed to access {}: {}", path.display(), e),
+            NavError::Parse(line, msg) => write!(f, "line {}: {}", line, msg),
+        }
+    }
+}
+
+impl std::error::Error for NavError {}
+
+// Reversed so that BinaryHeap pops the lowest estimate first
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+fn parse_float(tokens: &mut SplitWhitespace, line_num: usize) -> Result<f32, NavError> {
+    match tokens.next().map(str::parse) {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(NavError::Parse(line_num, "expected a number".into())),
+    }
+}
+
+fn parse_node(
+    tokens: &mut SplitWhitespace,
+    graph: &NavGraph,
+    line_num: usize,
+) -> Result<NodeId, NavError> {
+    match tokens.next().map(str::parse) {
+        Some(Ok(idx)) => graph
+            .node(idx)
+            .ok_or_else(|| NavError::Parse(line_num, format!("no node with index {}", idx))),
+        _ => Err(NavError::Parse(line_num, "expected a node index".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_graph() -> NavGraph {
+        let mut graph = NavGraph::new();
+
+        let a = graph.add_node(Vec3::new(0.0, 0.0, 0.0));
+        let b = graph.add_node(Vec3::new(10.5, -0.25, 3.0));
+        let c = graph.add_node(Vec3::new(0.1, 1.0e-7, -123_456.79));
+
+        // Unlinked
+        graph.add_node(Vec3::new(-4.0, 0.0, 8.0));
+
+        graph.link(a, b);
+        graph.link(b, c);
+        graph.link(c, a);
+        graph.link(b, a);
+
+        graph
+    }
+
+    fn assert_same(a: &NavGraph, b: &NavGraph) {
+        assert_eq!(a.nodes.len(), b.nodes.len());
+
+        for (pa, pb) in a.nodes.iter().zip(&b.nodes) {
+            assert_eq!(pa.to_array().map(f32::to_bits), pb.to_array().map(f32::to_bits));
+        }
+
+        for (la, lb) in a.links.iter().zip(&b.links) {
+            let mut la = la.clone();
+            let mut lb = lb.clone();
+
+            la.sort_unstable();
+            lb.sort_unstable();
+
+            assert_eq!(la, lb);
+        }
+    }
+
+    fn parse_err(src: &str) -> (usize, String) {
+        match NavGraph::parse(src) {
+            Err(NavError::Parse(line, msg)) => (line, msg),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let graph = sample_graph();
+        let text = graph.serialize();
+        let parsed = NavGraph::parse(&text).unwrap();
+
+        assert_same(&graph, &parsed);
+        assert_eq!(parsed.serialize(), text);
+        assert_eq!(text.lines().filter(|line| line.starts_with('l')).count(), 3);
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("slsh-nav-test-{}.nav", std::process::id()));
+        let graph = sample_graph();
+
+        graph.save(&path).unwrap();
+        let loaded = NavGraph::load(&path);
+        let _ = fs::remove_file(&path);
+
+        assert_same(&graph, &loaded.unwrap());
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let path = Path::new("/nonexistent/slsh.nav");
+
+        assert!(matches!(NavGraph::load(path), Err(NavError::Io(p, _)) if p == path));
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let graph = NavGraph::parse("# header\n\nn 1 2 3\n  # indented\nn 4 5 6\nl 0 1\n").unwrap();
+
+        assert_eq!(graph.len(), 2);
+        assert_eq!(graph.links[0], [1]);
+        assert_eq!(graph.links[1], [0]);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert_eq!(parse_err("n 1 2\n"), (1, "expected a number".into()));
+        assert_eq!(parse_err("n 0 0 0\nn 1 x 2\n"), (2, "expected a number".into()));
+        assert_eq!(parse_err("n 0 0 0\nl 0 1\n"), (2, "no node with index 1".into()));
+        assert_eq!(parse_err("n 0 0 0\nl 0 -1\n"), (2, "expected a node index".into()));
+        assert_eq!(parse_err("n 0 0 0\nl 0\n"), (2, "expected a node index".into()));
+        assert_eq!(parse_err("\nnode 0 0 0\n"), (2, "unknown statement node".into()));
+    }
+
+    #[test]
+    fn links_refer_to_earlier_nodes_only() {
+        assert_eq!(parse_err("n 0 0 0\nl 0 1\nn 1 0 0\n"), (2, "no node with index 1".into()));
+    }
+}
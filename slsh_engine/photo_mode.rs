@@ -0,0 +1,147 @@
+use std::f32::consts::PI;
+
+use glam::{EulerRot, Mat4, Vec3};
+
+use crate::camera::{Camera, MotionSettings};
+use crate::input::InputHandler;
+use crate::keys::Key;
+
+const FLY_SPEED: f32 = 8.0;
+const FAST_MULTIPLIER: f32 = 4.0;
+const SLOW_MULTIPLIER: f32 = 0.25;
+// Radians per second
+const ROLL_SPEED: f32 = PI / 4.0;
+const ZOOM_SPEED: f32 = PI / 6.0;
+const MIN_FOV: f32 = 5.0 * PI / 180.0;
+const MAX_FOV: f32 = 120.0 * PI / 180.0;
+
+// Free camera for screenshots. The simulation is paused and the view flies on its own with the
+// movement binds, jump going straight up. On top of mouse look:
+//
+//     Q / E          roll
+//     Z / X          zoom in and out
+//     R              level the horizon
+//     Shift / Ctrl   fly faster or slower
+//     H              hide the HUD
+//
+// Camera effects are turned off for the duration, and the view is put back as it was on exit
+pub struct PhotoMode {
+    saved: SavedView,
+    roll_input: i8,
+    zoom_input: i8,
+    fast: bool,
+    slow: bool,
+    hud_hidden: bool,
+}
+
+struct SavedView {
+    position: Vec3,
+    angles: Vec3,
+    fov: f32,
+    motion: MotionSettings,
+}
+
+impl PhotoMode {
+    pub fn enter(camera: &mut Camera) -> Self {
+        let saved = SavedView {
+            position: camera.position(),
+            angles: Vec3::new(camera.pitch(), camera.yaw(), camera.roll()),
+            fov: camera.fov(),
+            motion: camera.motion_settings(),
+        };
+
+        camera.set_motion_settings(MotionSettings {
+            shake: false,
+            roll: false,
+            max_fov_speed: None,
+        });
+
+        Self {
+            saved,
+            roll_input: 0,
+            zoom_input: 0,
+            fast: false,
+            slow: false,
+            hud_hidden: false,
+        }
+    }
+
+    pub fn exit(self, camera: &mut Camera) {
+        let saved = self.saved;
+
+        camera.set_motion_settings(saved.motion);
+        camera.set_position(saved.position);
+        camera.set_orientation(saved.angles.x, saved.angles.y, saved.angles.z);
+        camera.set_fov(saved.fov);
+    }
+
+    pub fn hud_hidden(&self) -> bool {
+        self.hud_hidden
+    }
+
+    // False for keys that photo mode leaves to the binds
+    pub fn handle_key(&mut self, key: Key, pressed: bool, camera: &mut Camera) -> bool {
+        match key {
+            Key::Q => self.roll_input = if pressed { -1 } else { 0 },
+            Key::E => self.roll_input = if pressed { 1 } else { 0 },
+            Key::Z => self.zoom_input = if pressed { -1 } else { 0 },
+            Key::X => self.zoom_input = if pressed { 1 } else { 0 },
+            Key::LeftShift | Key::RightShift => self.fast = pressed,
+            Key::LeftControl | Key::RightControl => self.slow = pressed,
+            Key::R => {
+                if pressed {
+                    camera.set_orientation(camera.pitch(), camera.yaw(), 0.0);
+                }
+            }
+            Key::H => {
+                if pressed {
+                    self.hud_hidden = !self.hud_hidden;
+                }
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &InputHandler, dt: f64) {
+        camera.update(input, dt, 0.0);
+
+        let dt = dt as f32;
+
+        let roll = camera.roll() + f32::from(self.roll_input) * ROLL_SPEED * dt;
+
+        camera.set_orientation(camera.pitch(), camera.yaw(), roll);
+
+        if self.zoom_input != 0 {
+            let fov = camera.fov() + f32::from(self.zoom_input) * ZOOM_SPEED * dt;
+
+            camera.set_fov(fov.clamp(MIN_FOV, MAX_FOV));
+        }
+
+        let rotation =
+            Mat4::from_euler(EulerRot::XYZ, -camera.pitch(), -camera.yaw(), -camera.roll())
+                .inverse();
+
+        let forward = rotation.transform_vector3(Vec3::Z);
+        let right = rotation.transform_vector3(Vec3::X);
+
+        let direction = forward * f32::from(input.forward)
+            + right * f32::from(input.right)
+            + Vec3::Y * f32::from(input.up);
+
+        let mut speed = FLY_SPEED;
+
+        if self.fast {
+            speed *= FAST_MULTIPLIER;
+        }
+
+        if self.slow {
+            speed *= SLOW_MULTIPLIER;
+        }
+
+        let velocity = direction.normalize_or_zero() * speed;
+
+        camera.set_position(camera.position() + velocity * dt);
+    }
+}
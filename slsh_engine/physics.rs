@@ -1,7 +1,9 @@
-use glam::{Mat3, Vec3};
+use glam::Vec3;
 
+use crate::broadphase::{Aabb, ItemId, UniformGrid};
 use crate::camera::Camera;
 use crate::input::InputHandler;
+use crate::math;
 
 const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
 
@@ -13,7 +15,35 @@ const STOP_SPEED: f32 = 100.0;
 const FRICTION: f32 = 6.0;
 const JUMP_VEL: f32 = 600.0;
 const GRAVITY: f32 = -30.0;
+const LANDING_PUNCH_SCALE: f32 = 0.03;
+const LANDING_PUNCH_MAX: f32 = 0.5;
+const HALF_WIDTH: f32 = 0.8;
+const HEIGHT: f32 = 3.6;
+const MAX_CLIP_PLANES: usize = 4;
+const SKIN: f32 = 0.001;
+const GROUND_NORMAL_Y: f32 = 0.7;
+const GROUND_PROBE_DIST: f32 = 0.01;
+const COLLIDER_CELL_SIZE: f32 = 8.0;
+const PUSH_STRENGTH: f32 = 240.0;
+
+pub struct CollisionWorld {
+    colliders: UniformGrid<()>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum EntityCollision {
+    #[default]
+    PassThrough,
+    Collide,
+    Push,
+}
+
+pub struct SweepHit {
+    pub time: f32,
+    pub normal: Vec3,
+}
 
+#[derive(Clone, Debug)]
 pub struct Entity {
     position: Vec3,
     velocity: Vec3,
@@ -35,16 +65,35 @@ impl Entity {
         }
     }
 
-    pub fn update(&mut self, input: &InputHandler, camera: &mut Camera, dt: f64, _t: f64) {
+    pub fn update(
+        &mut self,
+        input: &InputHandler,
+        camera: &mut Camera,
+        world: &CollisionWorld,
+        dt: f64,
+        _t: f64,
+    ) {
         let dt = dt as f32;
 
         self.copy_orientation(camera);
 
         self.movement(input, dt);
 
-        self.position += self.velocity * dt;
+        let was_on_ground = self.on_ground;
+        let fall_speed = -self.velocity.y;
 
-        self.detect_collisions();
+        self.move_and_slide(world, dt);
+
+        self.detect_collisions(world);
+
+        if self.on_ground && !was_on_ground {
+            let punch = (fall_speed * LANDING_PUNCH_SCALE).min(LANDING_PUNCH_MAX);
+            camera.punch(Vec3::new(punch, 0.0, 0.0));
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
     }
 
     pub fn eye_position(&self) -> Vec3 {
@@ -53,16 +102,29 @@ impl Entity {
         eye
     }
 
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    pub fn set_velocity(&mut self, velocity: Vec3) {
+        self.velocity = velocity;
+    }
+
     pub fn speed(&self) -> f32 {
         self.velocity.length()
     }
 
+    pub fn bounds(&self) -> Aabb {
+        let min = self.position - Vec3::new(HALF_WIDTH, 0.0, HALF_WIDTH);
+        let max = self.position + Vec3::new(HALF_WIDTH, HEIGHT, HALF_WIDTH);
+
+        Aabb::new(min, max)
+    }
+
     fn copy_orientation(&mut self, camera: &mut Camera) {
-        let view = Mat3::from_rotation_y(-camera.yaw());
-        let rot_x = view.x_axis[2];
-        let rot_y = view.y_axis[2];
-        let rot_z = view.z_axis[2];
-        self.rotation = Vec3::new(rot_x, rot_y, rot_z);
+        // Third row of a rotation by -yaw around Y
+        let (sin, cos) = math::sin_cos(-camera.yaw());
+        self.rotation = Vec3::new(-sin, 0.0, cos);
     }
 
     fn movement(&mut self, input: &InputHandler, dt: f32) {
@@ -75,9 +137,35 @@ impl Entity {
         }
     }
 
-    fn detect_collisions(&mut self) {
+    fn move_and_slide(&mut self, world: &CollisionWorld, dt: f32) {
+        self.slide(world, self.velocity * dt);
+    }
+
+    // Sweeps the whole move instead of stepping to the end position, so that fast movers can't
+    // skip over thin colliders between ticks
+    fn slide(&mut self, world: &CollisionWorld, mut motion: Vec3) {
+        for _ in 0..MAX_CLIP_PLANES {
+            let hit = match world.sweep(&self.bounds(), motion) {
+                Some(hit) => hit,
+                None => break,
+            };
+
+            let length = motion.length();
+            let time = (hit.time - SKIN / length).max(0.0);
+
+            self.position += motion * time;
+
+            motion *= 1.0 - time;
+            motion -= hit.normal * motion.dot(hit.normal);
+            self.velocity -= hit.normal * self.velocity.dot(hit.normal);
+        }
+
+        self.position += motion;
+    }
+
+    fn detect_collisions(&mut self, world: &CollisionWorld) {
         if self.position.y > 0.0 {
-            self.on_ground = false;
+            self.on_ground = self.probe_ground(world);
             return;
         }
 
@@ -86,6 +174,66 @@ impl Entity {
         self.position.y = 0.0;
     }
 
+    // Entities are treated as upright cylinders
+    fn collide_with(
+        &mut self,
+        other: &mut Entity,
+        world: &CollisionWorld,
+        mode: EntityCollision,
+        dt: f32,
+    ) {
+        let overlap_y = self.position.y < other.position.y + HEIGHT
+            && other.position.y < self.position.y + HEIGHT;
+
+        if !overlap_y {
+            return;
+        }
+
+        let mut delta = other.position - self.position;
+        delta.y = 0.0;
+
+        let distance = delta.length();
+        let penetration = 2.0 * HALF_WIDTH - distance;
+
+        if penetration <= 0.0 {
+            return;
+        }
+
+        let normal = if distance > f32::EPSILON {
+            delta / distance
+        } else {
+            Vec3::X
+        };
+
+        match mode {
+            EntityCollision::PassThrough => (),
+            EntityCollision::Collide => {
+                // Swept like any other move, so that neither is pushed into a wall
+                self.slide(world, -normal * penetration * 0.5);
+                other.slide(world, normal * penetration * 0.5);
+
+                let closing_speed = (self.velocity - other.velocity).dot(normal);
+
+                if closing_speed > 0.0 {
+                    self.velocity -= normal * closing_speed * 0.5;
+                    other.velocity += normal * closing_speed * 0.5;
+                }
+            }
+            EntityCollision::Push => {
+                let push = normal * penetration * PUSH_STRENGTH * dt;
+
+                self.velocity -= push;
+                other.velocity += push;
+            }
+        }
+    }
+
+    fn probe_ground(&self, world: &CollisionWorld) -> bool {
+        let probe = Vec3::new(0.0, -GROUND_PROBE_DIST, 0.0);
+
+        world.sweep(&self.bounds(), probe).map_or(false, |hit| hit.normal.y > GROUND_NORMAL_Y)
+    }
+
     fn movement_ground(&mut self, input: &InputHandler, dt: f32) {
         if input.up == 1 {
             self.on_ground = false;
@@ -161,3 +309,64 @@ impl Entity {
         self.velocity *= new_speed;
     }
 }
+
+// Once per simulation tick, after every entity has moved
+pub fn resolve_entity_collisions(
+    entities: &mut [&mut Entity],
+    world: &CollisionWorld,
+    mode: EntityCollision,
+    dt: f64,
+) {
+    if mode == EntityCollision::PassThrough {
+        return;
+    }
+
+    for i in 0..entities.len() {
+        let (head, tail) = entities.split_at_mut(i + 1);
+        let entity = &mut head[i];
+
+        for other in tail {
+            entity.collide_with(other, world, mode, dt as f32);
+        }
+    }
+}
+
+impl CollisionWorld {
+    pub fn new() -> Self {
+        Self {
+            colliders: UniformGrid::new(COLLIDER_CELL_SIZE),
+        }
+    }
+
+    pub fn add_box(&mut self, bounds: Aabb) -> ItemId {
+        self.colliders.insert(bounds, ())
+    }
+
+    pub fn remove_box(&mut self, id: ItemId) {
+        self.colliders.remove(id);
+    }
+
+    pub fn sweep(&self, bounds: &Aabb, motion: Vec3) -> Option<SweepHit> {
+        if motion == Vec3::ZERO {
+            return None;
+        }
+
+        let swept_bounds = bounds.union(&bounds.translated(motion));
+
+        let mut candidates = Vec::new();
+        self.colliders.query_aabb(&swept_bounds, &mut candidates);
+
+        candidates
+            .into_iter()
+            .filter_map(|id| self.colliders.get(id))
+            .filter_map(|(collider, _)| bounds.sweep(motion, collider))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(time, normal)| SweepHit { time, normal })
+    }
+}
+
+impl Default for CollisionWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,277 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use glam::Vec3;
+
+use crate::input::Action;
+
+// Commands are a few words long, longer lines only come from broken or hostile clients
+const MAX_LINE_LEN: usize = 256;
+// Whatever else was sent is left in the socket until the next poll
+const MAX_READ_PER_POLL: usize = 4096;
+// Over a hundred state lines, more than a subscriber that keeps up ever has waiting
+const MAX_OUTGOING: usize = 16 * 1024;
+
+// Opt-in TCP interface for external tools such as trainer overlays and bots. Clients send one
+// command per line:
+//
+//     press forward      hold an action until released
+//     release forward
+//     subscribe          get a state line after every tick
+//     unsubscribe
+//
+// State lines look like `state <tick> <x> <y> <z> <vx> <vy> <vz> <pitch> <yaw> <roll>`. Sockets are
+// non-blocking and only touched between ticks, so clients that can't keep up are disconnected
+// rather than stalling the engine
+pub struct RemoteControl {
+    listener: TcpListener,
+    clients: Vec<Client>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RemoteCommand {
+    Press(Action),
+    Release(Action),
+}
+
+// Simulation state after a tick, as sent to subscribers
+#[derive(Clone, Copy, Debug)]
+pub struct TickState {
+    pub tick: u64,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub view_angles: Vec3,
+}
+
+struct Client {
+    stream: TcpStream,
+    pending: Vec<u8>,
+    // Written as far as the socket takes it, the rest on later polls
+    outgoing: Vec<u8>,
+    subscribed: bool,
+    connected: bool,
+}
+
+impl RemoteControl {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    // Accepts new clients and hands over the input commands received since the last call
+    pub fn poll(&mut self, mut handle: impl FnMut(RemoteCommand)) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.clients.push(Client::new(stream));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("Remote control: failed to accept client: {}", e);
+                    break;
+                }
+            }
+        }
+
+        for client in &mut self.clients {
+            client.flush();
+            client.receive();
+
+            while let Some(line) = client.next_line() {
+                match parse_command(&line) {
+                    Ok(Request::Input(command)) => handle(command),
+                    Ok(Request::Subscribe(subscribed)) => client.subscribed = subscribed,
+                    Err(msg) => client.send(&format!("error {}\n", msg)),
+                }
+            }
+        }
+
+        self.clients.retain(|client| client.connected);
+    }
+
+    pub fn publish(&mut self, state: &TickState) {
+        if !self.clients.iter().any(|client| client.subscribed) {
+            return;
+        }
+
+        let line = format!(
+            "state {} {} {} {} {} {} {} {} {} {}\n",
+            state.tick,
+            state.position.x,
+            state.position.y,
+            state.position.z,
+            state.velocity.x,
+            state.velocity.y,
+            state.velocity.z,
+            state.view_angles.x,
+            state.view_angles.y,
+            state.view_angles.z,
+        );
+
+        for client in self.clients.iter_mut().filter(|client| client.subscribed) {
+            client.send(&line);
+        }
+
+        self.clients.retain(|client| client.connected);
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum Request {
+    Input(RemoteCommand),
+    Subscribe(bool),
+}
+
+impl Client {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            pending: Vec::new(),
+            outgoing: Vec::new(),
+            subscribed: false,
+            connected: true,
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut buf = [0; 512];
+        let mut received = 0;
+
+        while received < MAX_READ_PER_POLL {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.connected = false;
+                    break;
+                }
+                Ok(len) => {
+                    self.pending.extend_from_slice(&buf[..len]);
+                    received += len;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(_) => {
+                    self.connected = false;
+                    break;
+                }
+            }
+        }
+
+        // Including the unfinished last line, so that one never ending doesn't keep growing
+        if self.pending.split(|&byte| byte == b'\n').any(|line| line.len() > MAX_LINE_LEN) {
+            self.pending.clear();
+            self.connected = false;
+        }
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        let end = self.pending.iter().position(|&byte| byte == b'\n')?;
+        let line: Vec<u8> = self.pending.drain(..=end).collect();
+
+        Some(String::from_utf8_lossy(&line).trim().to_owned())
+    }
+
+    // Lines are queued whole, so a partial write never leaves half of one on the stream
+    fn send(&mut self, line: &str) {
+        if self.outgoing.len() + line.len() > MAX_OUTGOING {
+            self.connected = false;
+            return;
+        }
+
+        self.outgoing.extend_from_slice(line.as_bytes());
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => {
+                    self.connected = false;
+                    break;
+                }
+                Ok(len) => {
+                    self.outgoing.drain(..len);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(_) => {
+                    self.connected = false;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn parse_command(line: &str) -> Result<Request, String> {
+    let mut tokens = line.split_whitespace();
+
+    let request = match tokens.next() {
+        Some("press") => Request::Input(RemoteCommand::Press(parse_action(tokens.next())?)),
+        Some("release") => Request::Input(RemoteCommand::Release(parse_action(tokens.next())?)),
+        Some("subscribe") => Request::Subscribe(true),
+        Some("unsubscribe") => Request::Subscribe(false),
+        Some(command) => return Err(format!("unknown command {}", command)),
+        None => return Err("empty command".into()),
+    };
+
+    match tokens.next() {
+        Some(token) => Err(format!("unexpected {}", token)),
+        None => Ok(request),
+    }
+}
+
+fn parse_action(name: Option<&str>) -> Result<Action, String> {
+    let name = name.ok_or("expected an action")?;
+
+    Action::from_name(name).ok_or_else(|| format!("unknown action {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_and_release() {
+        assert_eq!(
+            parse_command("press forward"),
+            Ok(Request::Input(RemoteCommand::Press(Action::Forward)))
+        );
+        assert_eq!(
+            parse_command("  release   jump "),
+            Ok(Request::Input(RemoteCommand::Release(Action::Jump)))
+        );
+    }
+
+    #[test]
+    fn subscriptions() {
+        assert_eq!(parse_command("subscribe"), Ok(Request::Subscribe(true)));
+        assert_eq!(parse_command("unsubscribe"), Ok(Request::Subscribe(false)));
+    }
+
+    #[test]
+    fn unknown_commands_and_actions() {
+        assert_eq!(parse_command("fire"), Err("unknown command fire".into()));
+        assert_eq!(parse_command("press crouch"), Err("unknown action crouch".into()));
+        assert_eq!(parse_command("release"), Err("expected an action".into()));
+    }
+
+    #[test]
+    fn extra_tokens() {
+        assert_eq!(parse_command("press back now"), Err("unexpected now".into()));
+        assert_eq!(parse_command("subscribe all"), Err("unexpected all".into()));
+    }
+
+    #[test]
+    fn empty_lines() {
+        assert_eq!(parse_command(""), Err("empty command".into()));
+        assert_eq!(parse_command(" \t "), Err("empty command".into()));
+    }
+}
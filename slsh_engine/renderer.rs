@@ -1,17 +1,96 @@
+mod culling;
+mod debug;
+mod deferred;
+#[cfg(feature = "shaderc")]
+mod hot_reload;
+mod indirect;
+mod lighting;
+mod material;
+mod occlusion;
+mod pbr;
+mod pipeline_cache;
+mod post;
+mod push_consts;
+mod reflect;
+mod report;
+mod screenshot;
+mod shader;
+mod shadow;
+mod targets;
+mod texture;
+mod timestamps;
+mod tonemap;
+mod upload;
+mod vertex;
+mod view;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::default::Default;
-use std::ffi::{c_char, CStr, CString};
-use std::fmt::Display;
-use std::mem::{size_of, transmute};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fmt::{self, Display};
+use std::mem::size_of;
+use std::path::Path;
 use std::ptr;
 use std::str::FromStr;
 
+use ash::extensions::ext::DebugUtils;
 use ash::extensions::khr::{Surface, Swapchain};
 use ash::vk;
-use glam::{Mat4, Vec2, Vec3};
-
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+pub use self::culling::CullStats;
+use self::culling::Frustum;
+pub use self::debug::ValidationSeverity;
+use self::debug::{DebugMarkers, DebugMessenger};
+use self::deferred::{DeferredPath, GBuffer, GBUFFER_FORMATS};
+#[cfg(feature = "shaderc")]
+use self::hot_reload::{shader_path, ShaderWatcher};
+use self::indirect::IndirectBuffer;
+pub use self::lighting::{DirectionalLight, PointLight, PointLightHandle, MAX_POINT_LIGHTS};
+use self::lighting::{Lights, LightsUniform};
+use self::material::{MaterialData, MaterialKey};
+use self::occlusion::{OcclusionPushConstants, OcclusionQueries};
+use self::pbr::{
+    create_pbr_desc_pool, create_pbr_desc_set, create_pbr_desc_set_layout, PbrMaterialData,
+    MAX_PBR_MATERIALS,
+};
+pub use self::pbr::{PbrMaterial, PbrMaterialHandle};
+use self::post::{create_target_sampler, PostProcessChain, PostPushConstants};
+pub use self::post::{PostEffectHandle, POST_EFFECT_PARAMS};
+use self::push_consts::PushConstants;
+use self::screenshot::ScreenshotReadback;
+pub use self::screenshot::{Screenshot, ScreenshotError};
+#[cfg(feature = "shaderc")]
+use self::shader::try_compile_glsl;
+pub use self::shader::ShaderSource;
+use self::shader::ShaderStage;
+use self::shadow::{
+    choose_shadow_format, create_shadow_render_pass, create_shadow_sampler, ShadowMap, ShadowMaps,
+    ShadowPushConstants,
+};
+pub use self::shadow::{ShadowLight, ShadowLightHandle, MAX_SHADOW_LIGHTS};
+use self::targets::{SceneTargets, TargetConfig};
+use self::texture::{
+    create_texture_desc_pool, create_texture_desc_set_layout, equirect_to_cube_faces,
+    supports_mipmap_generation, SamplerSettings, Texture, LINEAR_TEXTURE_FORMAT, MAX_TEXTURES,
+    TEXTURE_FORMAT,
+};
+use self::timestamps::GpuTimestamps;
+pub use self::timestamps::{GpuPass, GpuStats};
+pub use self::tonemap::Tonemapper;
+use self::tonemap::{
+    is_hdr, output_encoding, TonemapPushConstants, HDR_FORMAT, HDR_SURFACE_FORMATS,
+};
+use self::upload::{UploadBatch, Uploader};
+pub use self::vertex::{LitVertex, PbrVertex, TexturedVertex, Vertex, VertexAttribute};
+use self::vertex::{Pos2Vertex, Pos3Vertex, VertexLayout};
+use self::view::{ViewConfig, WindowView};
+use crate::broadphase::Aabb;
 use crate::camera::Camera;
+use crate::crash;
 use crate::ui::UserInterface;
-use crate::window::Window;
 
 macro_rules! include_shader {
     ($name:literal) => {
@@ -19,8 +98,8 @@ macro_rules! include_shader {
     };
 }
 
+// VK_KHR_swapchain is required on top of these when there's a surface to present to
 const REQ_DEVICE_EXTENSIONS: &[&str] = &[
-    "VK_KHR_swapchain",
     #[cfg(any(target_os = "macos", target_os = "ios"))]
     "VK_KHR_portability_subset",
 ];
@@ -34,7 +113,18 @@ const API_VER_MAJOR: u32 = 1;
 const API_VER_MINOR: u32 = 0;
 const API_VER_PATCH: u32 = 0;
 
-const FRAMES_IN_FLIGHT: usize = 2;
+const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+const INITIAL_INDIRECT_DRAWS: usize = 64;
+
+// What headless renderers draw into in place of swapchain images
+const OFFSCREEN_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::R8G8B8A8_SRGB,
+    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+};
+
+// Drawn in place of materials whose shaders failed to compile or whose texture is missing.
+// error.vert only reads positions, so it works with any vertex layout
+const ERROR_SHADERS: [&str; 2] = ["error.vert", "error.frag"];
 
 trait CheckVkError<T> {
     fn check_err(self, action: &'static str) -> T;
@@ -44,22 +134,59 @@ pub struct Renderer {
     instance: ash::Instance,
     surface_loader: Surface,
     surface: vk::SurfaceKHR,
+    phys_device: vk::PhysicalDevice,
+    queue_family_indices: QueueFamilyIndices,
     device: ash::Device,
+    debug: DebugMarkers,
+    // Destroyed right before the instance
+    debug_messenger: Option<DebugMessenger>,
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
+    window_extent: vk::Extent2D,
+    swapchain_format: vk::SurfaceFormatKHR,
     swapchain_extent: vk::Extent2D,
     swapchain_loader: Swapchain,
+    // Null along with the surface when headless
     swapchain: vk::SwapchainKHR,
     swapchain_image_views: Vec<vk::ImageView>,
+    // Takes the place of the swapchain images when headless
+    offscreen_target: Option<RenderTarget>,
     command_pool: vk::CommandPool,
     command_buffers: Vec<vk::CommandBuffer>,
-    render_pass: vk::RenderPass,
+    uploader: Uploader,
+    // Submitted upload batches that the next frame has to wait on
+    pending_uploads: Vec<UploadBatch>,
+    // Batches waited on by each frame in flight, freed once that frame finishes
+    frame_uploads: Vec<Vec<UploadBatch>>,
+    msaa_samples: vk::SampleCountFlags,
+    depth_format: vk::Format,
+    // Scene color before tonemapping, depth and the framebuffers drawing into them, at the size
+    // of the swapchain. None while it's being recreated
+    targets: Option<SceneTargets>,
+    // Scene into the first HDR target, then each post effect, then tonemapping and HUD into swapchain
+    scene_render_pass: vk::RenderPass,
+    post_render_pass: vk::RenderPass,
+    present_render_pass: vk::RenderPass,
+    // Depth-only, into one face of a shadow map at a time
+    shadow_render_pass: vk::RenderPass,
+    shadow_format: vk::Format,
+    max_shadow_resolution: u32,
+    pipeline_cache: vk::PipelineCache,
+    // One for each swapchain image
     framebuffers: Vec<vk::Framebuffer>,
+    device_mem_properties: vk::PhysicalDeviceMemoryProperties,
     image_available: Vec<vk::Semaphore>,
     render_finished: Vec<vk::Semaphore>,
     is_rendering: Vec<vk::Fence>,
-    skybox_push_consts: SkyboxPushConstants,
-    crosshair_push_consts: CrosshairPushConstants,
+    skybox_push_consts: PushConstants<SkyboxPushConstants>,
+    cubemap_push_consts: PushConstants<CubemapPushConstants>,
+    crosshair_push_consts: PushConstants<CrosshairPushConstants>,
+    crosshair_visible: bool,
+    hud_box_push_consts: Vec<PushConstants<CrosshairPushConstants>>,
+    tonemap_push_consts: PushConstants<TonemapPushConstants>,
+    shadow_push_consts: PushConstants<ShadowPushConstants>,
+    occlusion_push_consts: PushConstants<OcclusionPushConstants>,
+    max_push_consts_size: u32,
     desc_set_layout: vk::DescriptorSetLayout,
     desc_pool: vk::DescriptorPool,
     desc_sets: Vec<vk::DescriptorSet>,
@@ -67,9 +194,172 @@ pub struct Renderer {
     uniform_buffers_memories: Vec<vk::DeviceMemory>,
     uniform_buffers_mappings: Vec<*mut UniformBufferObject>,
     uniform_buffer_object: UniformBufferObject,
+    lights: Lights,
+    // Same sets as the uniform buffers, for each frame in flight
+    lights_buffers: Vec<vk::Buffer>,
+    lights_buffers_memories: Vec<vk::DeviceMemory>,
+    lights_buffers_mappings: Vec<*mut LightsUniform>,
+    texture_desc_set_layout: vk::DescriptorSetLayout,
+    texture_desc_pool: vk::DescriptorPool,
+    sampler_settings: SamplerSettings,
+    mipmaps_supported: bool,
+    textures: Vec<Texture>,
+    // Stands in for the texture of lit meshes with plain colors, and for missing PBR textures
+    white_texture: Option<TextureHandle>,
+    flat_normal_texture: Option<TextureHandle>,
+    pbr_desc_set_layout: vk::DescriptorSetLayout,
+    pbr_desc_pool: vk::DescriptorPool,
+    pbr_materials: Vec<PbrMaterialData>,
+    // Drawn instead of the star field when set, with a descriptor set from a pool of its own
+    skybox: Option<Texture>,
+    skybox_desc_pool: vk::DescriptorPool,
+    // For sampling the HDR targets of the renderer and its views
+    target_sampler: vk::Sampler,
+    post_chain: PostProcessChain,
+    deferred: Option<DeferredPath>,
+    shadow_maps: ShadowMaps,
+    // Bound in the slots of the shadow map array that have no shadow map
+    placeholder_shadow_map: ShadowMap,
+    shadow_sampler: vk::Sampler,
+    // Built-in meshes come first, each with a material of its own at the same index
+    materials: Vec<MaterialData>,
+    material_ids: HashMap<MaterialKey, usize>,
+    // Pipelines that draw scene meshes into shadow maps, one for each vertex layout
+    shadow_materials: HashMap<VertexLayout, MaterialData>,
     meshes: Vec<MeshData>,
+    scene_meshes: Vec<Option<SceneMesh>>,
+    // Of each slot in scene_meshes, bumped whenever its mesh is removed
+    mesh_generations: Vec<u32>,
+    // Removed since the last submit, the frames before it may still be drawing them
+    removed_meshes: Vec<MeshData>,
+    // Meshes removed before each frame in flight, freed once that frame finishes
+    frame_removed_meshes: Vec<Vec<MeshData>>,
+    // Indices into scene_meshes, sorted by material
+    draw_order: Vec<usize>,
+    // Draw parameters of the scene meshes for each frame in flight, in draw_order
+    indirect_buffers: Vec<IndirectBuffer>,
+    // Whether each mesh in draw_order is drawn in the camera's view this frame. Shadow maps draw
+    // all of them
+    draw_visibility: Vec<Visibility>,
+    cull_stats: CullStats,
+    occlusion: Option<OcclusionQueries>,
+    // None if the graphics queue doesn't support timestamps
+    timestamps: Option<GpuTimestamps>,
+    free_mesh_slots: Vec<usize>,
+    #[cfg(feature = "shaderc")]
+    shader_watcher: Option<ShaderWatcher>,
+    // Latest versions of built-in shaders compiled at runtime, by file name
+    reloaded_shaders: HashMap<String, Vec<u8>>,
+    // Whether read_frame wants the next frame copied, and the copy of that frame
+    screenshot_requested: bool,
+    screenshot: Option<ScreenshotReadback>,
     current_frame: usize,
     current_time: f64,
+    swapchain_outdated: bool,
+    vsync: VSyncMode,
+    frames_in_flight: usize,
+    // Requested image count, to recreate the swapchain with
+    swapchain_images: Option<u32>,
+    // Other windows drawn from the same scene, indexed by ViewHandle
+    views: Vec<Option<WindowView>>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RendererConfig {
+    // None picks the highest sample count supported by the device, 1 disables MSAA
+    pub msaa_samples: Option<u32>,
+    // Positive values pick smaller mip levels, making textures blurrier but shimmer less
+    pub mip_lod_bias: f32,
+    // Anisotropic filtering level for texture samplers, clamped to the device limit. Values of 1
+    // and below disable it, as does the device not supporting it
+    pub anisotropy: f32,
+    // Rebuilds the pipelines of built-in shaders when their sources change. Needs the shaderc
+    // feature, does nothing without it
+    pub hot_reload_shaders: bool,
+    // Lights lit and PBR meshes in a single fullscreen pass over a G-buffer instead of while
+    // drawing each of them, which is faster with many point lights. Turns off MSAA
+    pub deferred: bool,
+    // Skips scene meshes that occlusion queries found to be hidden behind others. Results arrive
+    // a couple of frames late, so meshes coming into view can pop in
+    pub occlusion_culling: bool,
+    // Validation layer messages below it aren't printed
+    pub validation_severity: ValidationSeverity,
+    pub vsync: VSyncMode,
+    // Frames the CPU can record ahead of the GPU, more raise throughput but also input latency.
+    // None picks 2, values are clamped to between 1 and the number of swapchain images
+    pub frames_in_flight: Option<u32>,
+    // None picks one more than the surface's minimum. Clamped to what the surface supports
+    pub swapchain_images: Option<u32>,
+    // Presents in HDR10 or scRGB when the display supports either, SDR otherwise
+    pub hdr_output: bool,
+}
+
+// Modes the device doesn't support fall back to VSync, which is always available
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum VSyncMode {
+    // Lowest latency, tears
+    #[default]
+    Off,
+    // Waits for vertical blank, capping the frame rate at the refresh rate
+    VSync,
+    // Doesn't tear or cap the frame rate, later frames replace queued ones
+    Mailbox,
+    // VSync, except frames that miss a vertical blank are shown right away and tear
+    Adaptive,
+}
+
+// What a renderer draws to and presents, implemented by Window. Lets the renderer be built
+// without GLFW, which headless renderers have no use for
+pub trait PresentTarget {
+    fn required_extensions(&self) -> Vec<String>;
+    fn create_surface(&self, instance: &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result>;
+    fn size(&self) -> (u32, u32);
+}
+
+#[derive(Debug)]
+pub enum RendererError {
+    // Usually means there is no Vulkan driver installed
+    Instance(vk::Result),
+    Surface(vk::Result),
+    // None of the devices can both draw and present to the window
+    NoSuitableDevice,
+    Device(vk::Result),
+    Swapchain(vk::Result),
+    // Creating one of the renderer's own objects, e.g. a render pass or pipeline
+    Resource(&'static str, vk::Result),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextureHandle(usize);
+
+// The generation tells apart meshes that took the slot of a removed one
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MeshHandle(usize, u32);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ViewHandle(usize);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Material {
+    Color(Vec3),
+    Textured(TextureHandle),
+}
+
+// Pixels are tightly packed 8-bit sRGB RGBA, row by row from the top
+#[derive(Clone, Copy, Debug)]
+pub enum SkyboxImage<'a> {
+    // Square faces one after another, in the order +X, -X, +Y, -Y, +Z, -Z
+    Faces {
+        size: u32,
+        pixels: &'a [u8],
+    },
+    // 360 by 180 degree panorama with +Z at its center, resampled into faces of the given size
+    Equirectangular {
+        width: u32,
+        height: u32,
+        pixels: &'a [u8],
+        face_size: u32,
+    },
 }
 
 #[derive(Default, Clone)]
@@ -84,6 +374,7 @@ struct QueueFamilyIndices {
 struct PhysDeviceInfo {
     phys_device: vk::PhysicalDevice,
     properties: vk::PhysicalDeviceProperties,
+    features: vk::PhysicalDeviceFeatures,
     queue_family_indices: QueueFamilyIndices,
 }
 
@@ -95,22 +386,43 @@ struct UniformBufferObject {
 }
 
 struct Mesh {
-    vertices: Vec<f32>,
+    vertices: Vec<u8>,
     indices: Vec<u16>,
+    layout: VertexLayout,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
 struct SkyboxPushConstants {
     res: Vec2,
     view_angles: Vec2,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct CubemapPushConstants {
+    // From clip space to world space directions, ignoring the camera position
+    inv_view_proj: Mat4,
+    res: Vec2,
+    _pad: Vec2,
+}
+
+// Also used for the flat colored boxes of HUD widgets
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
 struct CrosshairPushConstants {
     proj: Mat4,
     color: Vec3,
+    _pad: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct MeshPushConstants {
+    model: Mat4,
+    color: Vec4,
+    // Only read by pbr.frag, see PbrMaterialData
+    params: Vec4,
 }
 
 struct MeshData {
@@ -120,72 +432,348 @@ struct MeshData {
     index_buffer: vk::Buffer,
     index_buffer_memory: vk::DeviceMemory,
     index_count: u32,
-    pipeline_layout: vk::PipelineLayout,
-    pipeline: vk::Pipeline,
+    // Index into Renderer::materials
+    material: usize,
+}
+
+// What's needed to build a material's pipeline again when its shaders change
+#[derive(Clone, Copy)]
+#[cfg_attr(not(feature = "shaderc"), allow(dead_code))]
+struct PipelineDesc {
+    layout: VertexLayout,
+    topology: vk::PrimitiveTopology,
+    push_const_range: Option<vk::PushConstantRange>,
+    // File names of the vertex and fragment shaders, if they are built-in ones
+    shader_names: Option<[&'static str; 2]>,
+    // Color attachments of the render pass it's used in, 0 for depth-only passes
+    color_attachments: u32,
+    // Writes neither color nor depth and draws back faces too, for occlusion query proxies
+    depth_test_only: bool,
+}
+
+struct RenderTarget {
+    device: ash::Device,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+}
+
+// What a scene mesh is drawn with besides its shaders
+#[derive(Clone, Copy, Debug)]
+enum SceneMaterial {
+    Basic(Material),
+    Pbr(PbrMaterialHandle),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+// Destroys what Renderer::new has created so far when it fails, newest first
+#[derive(Default)]
+struct InitGuard {
+    cleanups: Vec<Box<dyn FnOnce()>>,
+}
+
+enum Visibility {
+    // Outside the camera frustum
+    Culled,
+    // Behind other meshes, only its bounding box is drawn, to find out when it comes into view
+    Occluded,
+    Visible,
+}
+
+struct SceneMesh {
+    data: MeshData,
+    // Drawn into the G-buffer rather than in the scene pass, see DeferredPath
+    gbuffer: bool,
+    // In model space. None for meshes without positions, which are never culled
+    bounds: Option<Aabb>,
+    // By the latest occlusion query result
+    occluded: bool,
+    // Bound at set 1, e.g. the texture
+    material_desc_set: Option<vk::DescriptorSet>,
+    push_consts: PushConstants<MeshPushConstants>,
 }
 
 impl Renderer {
-    pub unsafe fn new(app_name: &'static str, window: &Window) -> Self {
+    // Fails when there is no Vulkan driver or device that can draw to the window, or when the
+    // swapchain or one of the renderer's objects can't be created, e.g. when out of memory.
+    // Everything created up to that point is destroyed
+    pub unsafe fn new(
+        app_name: &'static str,
+        window: &dyn PresentTarget,
+        config: &RendererConfig,
+    ) -> Result<Self, RendererError> {
+        let (width, height) = window.size();
+        let window_extent = vk::Extent2D { width, height };
+
+        Self::with_target(app_name, Some(window), window_extent, config)
+    }
+
+    // Draws into an offscreen image of the given size instead of a window, so it needs neither
+    // GLFW nor a display. Frames are read back with read_frame, resize changes the size. Renders
+    // one frame at a time, ignoring frames_in_flight and the swapchain settings
+    pub unsafe fn new_headless(
+        app_name: &'static str,
+        width: u32,
+        height: u32,
+        config: &RendererConfig,
+    ) -> Result<Self, RendererError> {
+        let extent = vk::Extent2D {
+            width: width.max(1),
+            height: height.max(1),
+        };
+
+        Self::with_target(app_name, None, extent, config)
+    }
+
+    unsafe fn with_target(
+        app_name: &'static str,
+        window: Option<&dyn PresentTarget>,
+        window_extent: vk::Extent2D,
+        config: &RendererConfig,
+    ) -> Result<Self, RendererError> {
         let entry = ash::Entry::linked();
-        let instance = create_instance(app_name, &entry, window);
+        let (instance, debug_utils_enabled) =
+            create_instance(app_name, &entry, window, config.validation_severity)?;
+        // Objects owning a Vulkan handle are declared after it, so they're dropped before it
+        // destroys the device and instance
+        let mut guard = InitGuard::default();
+        let guard_instance = instance.clone();
+        guard.defer(move || guard_instance.destroy_instance(None));
+        let debug_messenger = debug_utils_enabled
+            .then(|| DebugMessenger::new(&entry, &instance, config.validation_severity))
+            .transpose()?;
         let surface_loader = Surface::new(&entry, &instance);
-        let surface = window.create_surface(&instance);
-        let phys_device_info = pick_phys_device(&instance, surface, &surface_loader);
+        let (surface, phys_device_info, device) =
+            create_device_for_window(&instance, &surface_loader, window)?;
+        if window.is_some() {
+            let surface_loader = surface_loader.clone();
+            guard.defer(move || surface_loader.destroy_surface(surface, None));
+        }
+        let guard_device = device.clone();
+        guard.defer(move || guard_device.destroy_device(None));
         let phys_device = phys_device_info.phys_device;
         let device_mem_properties = instance.get_physical_device_memory_properties(phys_device);
-        let device = create_logical_device(&instance, &phys_device_info);
+        let debug = DebugMarkers::new(&entry, &instance, &device, debug_utils_enabled);
+
+        report_device_info(&phys_device_info.properties);
         let gfx_queue_idx = phys_device_info.queue_family_indices.graphics.unwrap();
         let present_queue_idx = phys_device_info.queue_family_indices.present.unwrap();
+        let transfer_queue_idx =
+            phys_device_info.queue_family_indices.transfer.unwrap_or(gfx_queue_idx);
         let graphics_queue = device.get_device_queue(gfx_queue_idx, 0);
+        let transfer_queue = device.get_device_queue(transfer_queue_idx, 0);
         let present_queue = device.get_device_queue(present_queue_idx, 0);
-        let surface_capabilities = get_surface_capabilities(phys_device, &surface_loader, surface);
-        let swapchain_format = choose_swapchain_format(phys_device, &surface_loader, surface);
-        let swapchain_extent = choose_swapchain_extent(window, &surface_capabilities);
+        let headless = window.is_none();
         let swapchain_loader = Swapchain::new(&instance, &device);
-        let swapchain = create_swapchain(
-            phys_device,
-            surface,
-            &surface_loader,
-            &surface_capabilities,
-            swapchain_format,
-            swapchain_extent,
-            &swapchain_loader,
-            &phys_device_info.queue_family_indices,
-        );
-        let swapchain_images = get_swapchain_images(&swapchain_loader, swapchain);
+        let (swapchain_format, swapchain_extent, swapchain, swapchain_images) = if headless {
+            (OFFSCREEN_FORMAT, window_extent, vk::SwapchainKHR::null(), Vec::new())
+        } else {
+            let surface_capabilities =
+                get_surface_capabilities(phys_device, &surface_loader, surface)?;
+            let swapchain_format =
+                choose_swapchain_format(phys_device, &surface_loader, surface, config.hdr_output)?;
+            let swapchain_extent = choose_swapchain_extent(window_extent, &surface_capabilities);
+            let swapchain = create_swapchain(
+                phys_device,
+                surface,
+                &surface_loader,
+                &surface_capabilities,
+                swapchain_format,
+                swapchain_extent,
+                config.vsync,
+                config.swapchain_images,
+                &swapchain_loader,
+                &phys_device_info.queue_family_indices,
+            )?;
+            let guard_loader = swapchain_loader.clone();
+            guard.defer(move || guard_loader.destroy_swapchain(swapchain, None));
+            let swapchain_images = get_swapchain_images(&swapchain_loader, swapchain)?;
+
+            (swapchain_format, swapchain_extent, swapchain, swapchain_images)
+        };
         let swapchain_image_views =
-            create_image_views(&device, swapchain_format, &swapchain_images);
-        let command_pool = create_command_pool(&device, gfx_queue_idx, true);
+            create_image_views(&device, swapchain_format, &swapchain_images)?;
+        guard.destroy_all(&device, &swapchain_image_views, |device, view| {
+            device.destroy_image_view(view, None)
+        });
+        let offscreen_target = headless
+            .then(|| create_offscreen_target(&device, &device_mem_properties, swapchain_extent))
+            .transpose()?;
+        let present_views = match &offscreen_target {
+            Some(target) => vec![target.view],
+            None => swapchain_image_views.clone(),
+        };
+        let frames_in_flight = config
+            .frames_in_flight
+            .map_or(DEFAULT_FRAMES_IN_FLIGHT, |frames| frames as usize)
+            .clamp(1, present_views.len());
+        let command_pool = create_command_pool(&device, gfx_queue_idx, true)?;
+        guard
+            .destroy(&device, command_pool, |device, pool| device.destroy_command_pool(pool, None));
+        let mut uploader =
+            Uploader::new(device.clone(), transfer_queue, transfer_queue_idx, gfx_queue_idx)?;
+        // Batches recorded by the uploader are dropped along with it, before this
+        guard.destroy(&device, uploader.command_pool(), |device, pool| {
+            device.destroy_command_pool(pool, None)
+        });
         let command_buffers =
-            create_command_buffers(&device, command_pool, FRAMES_IN_FLIGHT.try_into().unwrap());
-        let render_pass = create_render_pass(&device, swapchain_format.format);
+            create_command_buffers(&device, command_pool, frames_in_flight.try_into().unwrap())?;
+        let timestamp_valid_bits = instance
+            .get_physical_device_queue_family_properties(phys_device)[gfx_queue_idx as usize]
+            .timestamp_valid_bits;
+        let timestamps = GpuTimestamps::new(
+            device.clone(),
+            frames_in_flight,
+            phys_device_info.properties.limits.timestamp_period,
+            timestamp_valid_bits,
+        )?;
+        let indirect_buffers = (0..frames_in_flight)
+            .map(|_| {
+                IndirectBuffer::new(device.clone(), &device_mem_properties, INITIAL_INDIRECT_DRAWS)
+            })
+            .collect::<Result<_, _>>()?;
+        // The G-buffer has one sample per pixel, and the scene pass draws over its depth
+        let msaa_samples = if config.deferred {
+            vk::SampleCountFlags::TYPE_1
+        } else {
+            choose_sample_count(&phys_device_info.properties.limits, config.msaa_samples)
+        };
+        let depth_format = choose_depth_format(&instance, phys_device);
+        let destroy_render_pass =
+            |device: &ash::Device, render_pass| device.destroy_render_pass(render_pass, None);
+        let scene_render_pass =
+            create_scene_render_pass(&device, depth_format, msaa_samples, config.deferred)?;
+        guard.destroy(&device, scene_render_pass, destroy_render_pass);
+        let post_render_pass = create_fullscreen_render_pass(
+            &device,
+            HDR_FORMAT,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+        guard.destroy(&device, post_render_pass, destroy_render_pass);
+        let present_render_pass = create_fullscreen_render_pass(
+            &device,
+            swapchain_format.format,
+            present_layout(headless),
+        )?;
+        guard.destroy(&device, present_render_pass, destroy_render_pass);
+        let shadow_format = choose_shadow_format(&instance, phys_device);
+        let shadow_render_pass = create_shadow_render_pass(&device, shadow_format)?;
+        guard.destroy(&device, shadow_render_pass, destroy_render_pass);
         let framebuffers =
-            create_framebuffers(&device, &swapchain_image_views, swapchain_extent, render_pass);
-        let (image_available, render_finished, is_rendering) = create_sync_objects(&device);
+            create_framebuffers(&device, &present_views, swapchain_extent, present_render_pass)?;
+        guard.destroy_all(&device, &framebuffers, |device, framebuffer| {
+            device.destroy_framebuffer(framebuffer, None)
+        });
+        let (image_available, render_finished, is_rendering) =
+            create_sync_objects(&device, frames_in_flight)?;
+        let destroy_semaphore = |device: &ash::Device, sem| device.destroy_semaphore(sem, None);
+        guard.destroy_all(&device, &image_available, destroy_semaphore);
+        guard.destroy_all(&device, &render_finished, destroy_semaphore);
+        guard
+            .destroy_all(&device, &is_rendering, |device, fence| device.destroy_fence(fence, None));
+
+        let max_push_consts_size = phys_device_info.properties.limits.max_push_constants_size;
+
+        let skybox_push_consts = PushConstants::new(
+            SkyboxPushConstants {
+                res: Vec2::new(swapchain_extent.width as f32, swapchain_extent.height as f32),
+                view_angles: Vec2::new(0.0, 0.0),
+            },
+            vk::ShaderStageFlags::FRAGMENT,
+        );
 
-        let skybox_push_consts = SkyboxPushConstants {
-            res: Vec2::new(window.width() as f32, window.height() as f32),
-            view_angles: Vec2::new(0.0, 0.0),
-        };
+        let cubemap_push_consts = PushConstants::new(
+            CubemapPushConstants {
+                inv_view_proj: Mat4::IDENTITY,
+                res: Vec2::new(swapchain_extent.width as f32, swapchain_extent.height as f32),
+                _pad: Vec2::ZERO,
+            },
+            vk::ShaderStageFlags::FRAGMENT,
+        );
 
-        let crosshair_push_consts = CrosshairPushConstants {
-            proj: Mat4::IDENTITY,
-            color: Vec3::new(0.0, 1.0, 0.0),
-        };
+        let crosshair_push_consts = PushConstants::new(
+            CrosshairPushConstants {
+                proj: Mat4::IDENTITY,
+                color: Vec3::new(0.0, 1.0, 0.0),
+                _pad: 0.0,
+            },
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        );
+
+        let tonemap_push_consts = PushConstants::new(
+            TonemapPushConstants {
+                exposure: 1.0,
+                operator: Tonemapper::default().shader_index(),
+                encoding: output_encoding(swapchain_format),
+            },
+            vk::ShaderStageFlags::FRAGMENT,
+        );
 
-        let push_const_range_skybox =
-            create_push_const_range::<SkyboxPushConstants>(vk::ShaderStageFlags::FRAGMENT);
+        let shadow_push_consts = PushConstants::new(
+            ShadowPushConstants {
+                mvp: Mat4::IDENTITY,
+                light_space: Mat4::IDENTITY,
+            },
+            vk::ShaderStageFlags::VERTEX,
+        );
 
-        let push_const_range_crosshair = create_push_const_range::<CrosshairPushConstants>(
-            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        let occlusion_push_consts = PushConstants::new(
+            OcclusionPushConstants {
+                mvp: Mat4::IDENTITY,
+            },
+            vk::ShaderStageFlags::VERTEX,
         );
 
-        let desc_set_layout = create_desc_set_layout(&device);
-        let desc_pool = create_desc_pool(&device);
-        let desc_sets = create_desc_sets(&device, desc_set_layout, desc_pool);
+        let push_const_range_skybox = skybox_push_consts.range(max_push_consts_size);
+        let push_const_range_cubemap = cubemap_push_consts.range(max_push_consts_size);
+        let push_const_range_crosshair = crosshair_push_consts.range(max_push_consts_size);
+        let push_const_range_tonemap = tonemap_push_consts.range(max_push_consts_size);
+
+        // Descriptor sets are freed along with their pools
+        let destroy_layout =
+            |device: &ash::Device, layout| device.destroy_descriptor_set_layout(layout, None);
+        let destroy_pool = |device: &ash::Device, pool| device.destroy_descriptor_pool(pool, None);
+
+        let desc_set_layout = create_desc_set_layout(&device)?;
+        guard.destroy(&device, desc_set_layout, destroy_layout);
+        let desc_pool = create_desc_pool(&device, frames_in_flight)?;
+        guard.destroy(&device, desc_pool, destroy_pool);
+        let desc_sets = create_desc_sets(&device, desc_set_layout, desc_pool, frames_in_flight)?;
+
+        let texture_desc_set_layout = create_texture_desc_set_layout(&device)?;
+        guard.destroy(&device, texture_desc_set_layout, destroy_layout);
+        let texture_desc_pool = create_texture_desc_pool(&device, MAX_TEXTURES)?;
+        guard.destroy(&device, texture_desc_pool, destroy_pool);
+        let skybox_desc_pool = create_texture_desc_pool(&device, 1)?;
+        guard.destroy(&device, skybox_desc_pool, destroy_pool);
+
+        let pbr_desc_set_layout = create_pbr_desc_set_layout(&device)?;
+        guard.destroy(&device, pbr_desc_set_layout, destroy_layout);
+        let pbr_desc_pool = create_pbr_desc_pool(&device)?;
+        guard.destroy(&device, pbr_desc_pool, destroy_pool);
+
+        let destroy_sampler = |device: &ash::Device, sampler| device.destroy_sampler(sampler, None);
+        let target_sampler = create_target_sampler(&device)?;
+        guard.destroy(&device, target_sampler, destroy_sampler);
+        let destroy_buffer = |device: &ash::Device, buffer| device.destroy_buffer(buffer, None);
+        let free_memory = |device: &ash::Device, memory| device.free_memory(memory, None);
 
         let (uniform_buffers, uniform_buffers_memories, uniform_buffers_mappings) =
-            create_uniform_buffers(&device, &device_mem_properties);
+            create_uniform_buffers::<UniformBufferObject>(
+                &device,
+                &device_mem_properties,
+                frames_in_flight,
+            )?;
+        guard.destroy_all(&device, &uniform_buffers, destroy_buffer);
+        guard.destroy_all(&device, &uniform_buffers_memories, free_memory);
+        let (lights_buffers, lights_buffers_memories, lights_buffers_mappings) =
+            create_uniform_buffers::<LightsUniform>(
+                &device,
+                &device_mem_properties,
+                frames_in_flight,
+            )?;
+        guard.destroy_all(&device, &lights_buffers, destroy_buffer);
+        guard.destroy_all(&device, &lights_buffers_memories, free_memory);
 
         let uniform_buffer_object = UniformBufferObject {
             model: Mat4::IDENTITY,
@@ -193,81 +781,289 @@ impl Renderer {
             proj: Mat4::IDENTITY,
         };
 
-        fill_desc_sets(&device, &uniform_buffers, &desc_sets);
-
-        let skybox_vert_shader_compiled = include_shader!("skybox.vert");
-        let skybox_frag_shader_compiled = include_shader!("skybox.frag");
+        fill_desc_sets(&device, &uniform_buffers, &lights_buffers, &desc_sets);
 
-        let skybox = create_skybox_mesh().into_mesh_data(
+        let shadow_maps = ShadowMaps::new();
+        let placeholder_shadow_map = ShadowMap::placeholder(
             device.clone(),
             &device_mem_properties,
+            shadow_format,
+            shadow_render_pass,
             command_pool,
             graphics_queue,
-            Some(push_const_range_skybox),
-            None,
-            skybox_vert_shader_compiled,
-            skybox_frag_shader_compiled,
-            vk::PrimitiveTopology::TRIANGLE_LIST,
-            swapchain_extent,
-            render_pass,
-        );
-
-        let grid_vert_shader_compiled = include_shader!("grid.vert");
-        let grid_frag_shader_compiled = include_shader!("grid.frag");
+        )?;
+        let shadow_sampler = create_shadow_sampler(&device)?;
+        guard.destroy(&device, shadow_sampler, destroy_sampler);
 
-        let grid = create_grid_mesh(2.0, 32).into_mesh_data(
-            device.clone(),
-            &device_mem_properties,
-            command_pool,
-            graphics_queue,
-            None,
-            Some(desc_set_layout),
-            grid_vert_shader_compiled,
-            grid_frag_shader_compiled,
-            vk::PrimitiveTopology::LINE_LIST,
-            swapchain_extent,
-            render_pass,
+        write_shadow_map_descs(
+            &device,
+            &desc_sets,
+            &shadow_maps,
+            &placeholder_shadow_map,
+            shadow_sampler,
         );
 
-        let crosshair_vert_shader_compiled = include_shader!("crosshair.vert");
-        let crosshair_frag_shader_compiled = include_shader!("crosshair.frag");
+        let pipeline_cache = pipeline_cache::load(&device, &phys_device_info.properties)?;
+        guard.destroy(&device, pipeline_cache, |device, cache| {
+            device.destroy_pipeline_cache(cache, None)
+        });
+
+        let skybox = create_skybox_mesh();
+        let grid = create_grid_mesh(2.0, 32);
+        let crosshair = create_crosshair_mesh(6.0, 2.0);
+        let hud_box = create_hud_box_mesh();
+        let cubemap_skybox = create_skybox_mesh();
+        let tonemap = create_skybox_mesh();
+        let occlusion_box = create_occlusion_box_mesh();
 
-        let crosshair = create_crosshair_mesh(6.0, 2.0, window).into_mesh_data(
+        let skybox_material = MaterialData::new(
             device.clone(),
-            &device_mem_properties,
-            command_pool,
-            graphics_queue,
-            Some(push_const_range_crosshair),
-            None,
-            crosshair_vert_shader_compiled,
-            crosshair_frag_shader_compiled,
-            vk::PrimitiveTopology::LINE_LIST,
+            PipelineDesc {
+                layout: skybox.layout,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(push_const_range_skybox),
+                shader_names: Some(["skybox.vert", "skybox.frag"]),
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[],
+            include_shader!("skybox.vert"),
+            include_shader!("skybox.frag"),
+            pipeline_cache,
+            scene_render_pass,
+            msaa_samples,
+        )?;
+
+        let grid_material = MaterialData::new(
+            device.clone(),
+            PipelineDesc {
+                layout: grid.layout,
+                topology: vk::PrimitiveTopology::LINE_LIST,
+                push_const_range: None,
+                shader_names: Some(["grid.vert", "grid.frag"]),
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[desc_set_layout],
+            include_shader!("grid.vert"),
+            include_shader!("grid.frag"),
+            pipeline_cache,
+            scene_render_pass,
+            msaa_samples,
+        )?;
+
+        let crosshair_material = MaterialData::new(
+            device.clone(),
+            PipelineDesc {
+                layout: crosshair.layout,
+                topology: vk::PrimitiveTopology::LINE_LIST,
+                push_const_range: Some(push_const_range_crosshair),
+                shader_names: Some(["crosshair.vert", "crosshair.frag"]),
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[],
+            include_shader!("crosshair.vert"),
+            include_shader!("crosshair.frag"),
+            pipeline_cache,
+            present_render_pass,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+
+        let hud_box_material = MaterialData::new(
+            device.clone(),
+            PipelineDesc {
+                layout: hud_box.layout,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(push_const_range_crosshair),
+                shader_names: Some(["crosshair.vert", "crosshair.frag"]),
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[],
+            include_shader!("crosshair.vert"),
+            include_shader!("crosshair.frag"),
+            pipeline_cache,
+            present_render_pass,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+
+        let cubemap_skybox_material = MaterialData::new(
+            device.clone(),
+            PipelineDesc {
+                layout: cubemap_skybox.layout,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(push_const_range_cubemap),
+                shader_names: Some(["skybox.vert", "skybox_cubemap.frag"]),
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[texture_desc_set_layout],
+            include_shader!("skybox.vert"),
+            include_shader!("skybox_cubemap.frag"),
+            pipeline_cache,
+            scene_render_pass,
+            msaa_samples,
+        )?;
+
+        let tonemap_material = MaterialData::new(
+            device.clone(),
+            PipelineDesc {
+                layout: tonemap.layout,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(push_const_range_tonemap),
+                shader_names: Some(["skybox.vert", "tonemap.frag"]),
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[texture_desc_set_layout],
+            include_shader!("skybox.vert"),
+            include_shader!("tonemap.frag"),
+            pipeline_cache,
+            present_render_pass,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+
+        let occlusion_box_material = MaterialData::new(
+            device.clone(),
+            PipelineDesc {
+                layout: occlusion_box.layout,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(occlusion_push_consts.range(max_push_consts_size)),
+                shader_names: Some(["occlusion.vert", "occlusion.frag"]),
+                color_attachments: 1,
+                depth_test_only: true,
+            },
+            &[],
+            include_shader!("occlusion.vert"),
+            include_shader!("occlusion.frag"),
+            pipeline_cache,
+            scene_render_pass,
+            msaa_samples,
+        )?;
+
+        let materials = vec![
+            skybox_material,
+            grid_material,
+            crosshair_material,
+            hud_box_material,
+            cubemap_skybox_material,
+            tonemap_material,
+            occlusion_box_material,
+        ];
+
+        let deferred = if config.deferred {
+            let deferred = DeferredPath::new(
+                &device,
+                depth_format,
+                desc_set_layout,
+                [
+                    &include_shader!("skybox.vert")[..],
+                    &include_shader!("deferred_lighting.frag")[..],
+                ],
+                pipeline_cache,
+                max_push_consts_size,
+            )?;
+
+            Some(deferred)
+        } else {
+            None
+        };
+
+        let targets = SceneTargets::new(
+            &device,
             swapchain_extent,
-            render_pass,
-        );
+            &TargetConfig {
+                device_mem_properties: &device_mem_properties,
+                depth_format,
+                samples: msaa_samples,
+                scene_render_pass,
+                post_render_pass,
+                desc_set_layout: texture_desc_set_layout,
+                sampler: target_sampler,
+                deferred: deferred.as_ref(),
+            },
+        )?;
+
+        let occlusion = config
+            .occlusion_culling
+            .then(|| {
+                OcclusionQueries::new(
+                    device.clone(),
+                    frames_in_flight,
+                    INITIAL_INDIRECT_DRAWS as u32,
+                )
+            })
+            .transpose()?;
+
+        let meshes = [
+            skybox,
+            grid,
+            crosshair,
+            hud_box,
+            cubemap_skybox,
+            tonemap,
+            occlusion_box,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(material, mesh)| {
+            mesh.into_mesh_data(device.clone(), &device_mem_properties, &mut uploader, material)
+        })
+        .collect::<Result<_, _>>()?;
 
-        let meshes = vec![skybox, grid, crosshair];
+        let mipmaps_supported = supports_mipmap_generation(&instance, phys_device);
 
-        Self {
+        // Owned by the renderer from here on
+        guard.disarm();
+
+        let renderer = Self {
             instance,
             surface_loader,
             surface,
+            phys_device,
+            queue_family_indices: phys_device_info.queue_family_indices,
             device,
+            debug,
+            debug_messenger,
             graphics_queue,
             present_queue,
+            window_extent,
+            swapchain_format,
             swapchain_extent,
             swapchain_loader,
             swapchain,
             swapchain_image_views,
+            offscreen_target,
             command_pool,
             command_buffers,
-            render_pass,
+            uploader,
+            pending_uploads: Vec::new(),
+            frame_uploads: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+            msaa_samples,
+            depth_format,
+            targets: Some(targets),
+            scene_render_pass,
+            post_render_pass,
+            present_render_pass,
+            shadow_render_pass,
+            shadow_format,
+            max_shadow_resolution: phys_device_info.properties.limits.max_image_dimension_cube,
+            pipeline_cache,
             framebuffers,
+            device_mem_properties,
             image_available,
             render_finished,
             is_rendering,
             skybox_push_consts,
+            cubemap_push_consts,
             crosshair_push_consts,
+            crosshair_visible: true,
+            hud_box_push_consts: Vec::new(),
+            tonemap_push_consts,
+            shadow_push_consts,
+            occlusion_push_consts,
+            max_push_consts_size,
             desc_set_layout,
             desc_pool,
             desc_sets,
@@ -275,15 +1071,71 @@ impl Renderer {
             uniform_buffers_memories,
             uniform_buffers_mappings,
             uniform_buffer_object,
+            lights: Lights::new(),
+            lights_buffers,
+            lights_buffers_memories,
+            lights_buffers_mappings,
+            texture_desc_set_layout,
+            texture_desc_pool,
+            sampler_settings: SamplerSettings {
+                mip_lod_bias: config.mip_lod_bias,
+                max_anisotropy: choose_anisotropy(&phys_device_info, config.anisotropy),
+            },
+            mipmaps_supported,
+            textures: Vec::new(),
+            white_texture: None,
+            flat_normal_texture: None,
+            pbr_desc_set_layout,
+            pbr_desc_pool,
+            pbr_materials: Vec::new(),
+            skybox: None,
+            skybox_desc_pool,
+            target_sampler,
+            post_chain: PostProcessChain::new(),
+            deferred,
+            shadow_maps,
+            placeholder_shadow_map,
+            shadow_sampler,
+            materials,
+            material_ids: HashMap::new(),
+            shadow_materials: HashMap::new(),
             meshes,
+            scene_meshes: Vec::new(),
+            mesh_generations: Vec::new(),
+            removed_meshes: Vec::new(),
+            frame_removed_meshes: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+            draw_order: Vec::new(),
+            indirect_buffers,
+            draw_visibility: Vec::new(),
+            cull_stats: CullStats::default(),
+            occlusion,
+            timestamps,
+            free_mesh_slots: Vec::new(),
+            #[cfg(feature = "shaderc")]
+            shader_watcher: config.hot_reload_shaders.then(ShaderWatcher::new),
+            reloaded_shaders: HashMap::new(),
+            screenshot_requested: false,
+            screenshot: None,
             current_frame: 0,
             current_time: 0.0,
-        }
+            swapchain_outdated: false,
+            vsync: config.vsync,
+            frames_in_flight,
+            swapchain_images: config.swapchain_images,
+            views: Vec::new(),
+        };
+
+        renderer.set_debug_names();
+
+        Ok(renderer)
     }
 
+    // The passes before the present pass draw into the targets, which have the size of the
+    // framebuffer
     fn record_commands_to_buffer(
         &self,
         cmd_buffer: vk::CommandBuffer,
+        targets: &SceneTargets,
         framebuffer: vk::Framebuffer,
     ) {
         let begin_info = vk::CommandBufferBeginInfo {
@@ -297,19 +1149,17 @@ impl Renderer {
             },
         };
 
-        let render_pass_info = vk::RenderPassBeginInfo {
-            s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
-            render_pass: self.render_pass,
-            framebuffer,
-            render_area: vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.swapchain_extent,
+        // Reversed-Z, 0 is the far plane
+        let clear_depth = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 0.0,
+                stencil: 0,
             },
-            clear_value_count: 1,
-            p_clear_values: &clear_color,
-            ..Default::default()
         };
 
+        // Values are indexed by attachment, resolve attachment isn't cleared
+        let clear_values = [clear_color, clear_depth];
+
         unsafe {
             self.device
                 .reset_command_buffer(cmd_buffer, vk::CommandBufferResetFlags::empty())
@@ -319,154 +1169,2114 @@ impl Renderer {
                 .begin_command_buffer(cmd_buffer, &begin_info)
                 .check_err("begin recording to command buffer");
 
-            self.device.cmd_begin_render_pass(
+            if let Some(timestamps) = &self.timestamps {
+                timestamps.record_frame_start(cmd_buffer, self.current_frame);
+            }
+
+            let acquire_barriers: Vec<vk::BufferMemoryBarrier> = self
+                .pending_uploads
+                .iter()
+                .flat_map(|batch| batch.acquire_barriers.iter().copied())
+                .collect();
+
+            if !acquire_barriers.is_empty() {
+                self.device.cmd_pipeline_barrier(
+                    cmd_buffer,
+                    vk::PipelineStageFlags::VERTEX_INPUT,
+                    vk::PipelineStageFlags::VERTEX_INPUT,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &acquire_barriers,
+                    &[],
+                );
+            }
+
+            if let Some(occlusion) = &self.occlusion {
+                occlusion.record_reset(cmd_buffer, self.current_frame);
+            }
+
+            self.record_shadow_maps(cmd_buffer);
+            self.record_pass_end(cmd_buffer, GpuPass::ShadowMaps);
+
+            if let (Some(deferred), Some(gbuffer)) = (&self.deferred, &targets.gbuffer) {
+                self.record_deferred(cmd_buffer, deferred, gbuffer, targets, clear_depth);
+            }
+
+            self.record_pass_end(cmd_buffer, GpuPass::Deferred);
+
+            self.debug.begin_label(cmd_buffer, "main pass", [0.2, 0.2, 0.8, 1.0]);
+
+            self.begin_render_pass(
                 cmd_buffer,
-                &render_pass_info,
-                vk::SubpassContents::INLINE,
+                self.scene_render_pass,
+                targets.scene_framebuffer,
+                targets.render_area(),
+                &clear_values,
             );
 
-            let stage_frag = vk::ShaderStageFlags::FRAGMENT;
-            let stage_all = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT;
+            self.set_viewport(cmd_buffer, targets.render_area());
+
+            self.debug.begin_label(cmd_buffer, "skybox", [0.4, 0.6, 0.9, 1.0]);
+
+            let res = Vec2::new(targets.extent.width as f32, targets.extent.height as f32);
+            let mut skybox_push_consts = self.skybox_push_consts;
+            let mut cubemap_push_consts = self.cubemap_push_consts;
+
+            skybox_push_consts.res = res;
+            cubemap_push_consts.res = res;
+
+            match &self.skybox {
+                Some(skybox) => self.meshes[4].record_draw_commands(
+                    cmd_buffer,
+                    &self.materials,
+                    Some(cubemap_push_consts.as_push()),
+                    &[skybox.desc_set],
+                ),
+                None => self.meshes[0].record_draw_commands(
+                    cmd_buffer,
+                    &self.materials,
+                    Some(skybox_push_consts.as_push()),
+                    &[],
+                ),
+            }
 
-            // unfortunately a copy, because can't find good transmute
-            let skybox_push_const_bytes: [u8; 16] = transmute(self.skybox_push_consts);
-            let crosshair_push_const_bytes: [u8; 80] = transmute(self.crosshair_push_consts);
+            self.debug.end_label(cmd_buffer);
+            self.debug.begin_label(cmd_buffer, "grid", [0.4, 0.4, 0.4, 1.0]);
 
-            self.meshes[0].record_draw_commands(
+            self.meshes[1].record_draw_commands(
                 cmd_buffer,
-                Some((stage_frag, &skybox_push_const_bytes)),
+                &self.materials,
                 None,
+                &[self.desc_sets[self.current_frame]],
             );
 
-            self.meshes[1].record_draw_commands(
+            self.debug.end_label(cmd_buffer);
+            self.debug.begin_label(cmd_buffer, "scene meshes", [0.8, 0.6, 0.2, 1.0]);
+
+            self.record_scene_meshes(cmd_buffer, false);
+
+            self.debug.end_label(cmd_buffer);
+
+            if self.occlusion.is_some() {
+                self.debug.begin_label(cmd_buffer, "occlusion proxies", [0.5, 0.5, 0.5, 1.0]);
+
+                self.record_occlusion_proxies(cmd_buffer);
+
+                self.debug.end_label(cmd_buffer);
+            }
+
+            self.device.cmd_end_render_pass(cmd_buffer);
+            self.record_pass_end(cmd_buffer, GpuPass::Scene);
+
+            self.debug.end_label(cmd_buffer);
+
+            let hdr_result = self.record_post_effects(cmd_buffer, targets);
+
+            self.record_pass_end(cmd_buffer, GpuPass::PostEffects);
+
+            self.debug.begin_label(cmd_buffer, "present pass", [0.2, 0.8, 0.2, 1.0]);
+
+            self.begin_render_pass(
                 cmd_buffer,
-                None,
-                Some(self.desc_sets[self.current_frame]),
+                self.present_render_pass,
+                framebuffer,
+                targets.render_area(),
+                &[],
             );
 
-            self.meshes[2].record_draw_commands(
+            self.set_viewport(cmd_buffer, targets.render_area());
+
+            self.debug.begin_label(cmd_buffer, "tonemap", [0.9, 0.8, 0.5, 1.0]);
+
+            self.meshes[5].record_draw_commands(
                 cmd_buffer,
-                Some((stage_all, &crosshair_push_const_bytes)),
-                None,
+                &self.materials,
+                Some(self.tonemap_push_consts.as_push()),
+                &[targets.desc_sets[hdr_result]],
             );
 
+            self.debug.end_label(cmd_buffer);
+
+            if self.crosshair_visible {
+                self.debug.begin_label(cmd_buffer, "crosshair", [0.0, 1.0, 0.0, 1.0]);
+
+                self.meshes[2].record_draw_commands(
+                    cmd_buffer,
+                    &self.materials,
+                    Some(self.crosshair_push_consts.as_push()),
+                    &[],
+                );
+
+                self.debug.end_label(cmd_buffer);
+            }
+
+            if !self.hud_box_push_consts.is_empty() {
+                self.debug.begin_label(cmd_buffer, "hud widgets", [1.0, 1.0, 1.0, 1.0]);
+
+                for push_consts in &self.hud_box_push_consts {
+                    self.meshes[3].record_draw_commands(
+                        cmd_buffer,
+                        &self.materials,
+                        Some(push_consts.as_push()),
+                        &[],
+                    );
+                }
+
+                self.debug.end_label(cmd_buffer);
+            }
+
             self.device.cmd_end_render_pass(cmd_buffer);
+            self.record_pass_end(cmd_buffer, GpuPass::Present);
+
+            self.debug.end_label(cmd_buffer);
+
+            if let Some(screenshot) = &self.screenshot {
+                screenshot.record_copy(cmd_buffer);
+            }
 
             self.device.end_command_buffer(cmd_buffer).check_err("end command buffer recording");
         }
     }
 
-    pub fn present(&mut self) {
-        let command_buffer = self.command_buffers[self.current_frame];
-        let image_index = self.begin_frame();
+    // Timestamps every pass whether or not it ran, see GpuTimestamps
+    unsafe fn record_pass_end(&self, cmd_buffer: vk::CommandBuffer, pass: GpuPass) {
+        if let Some(timestamps) = &self.timestamps {
+            timestamps.record_pass_end(cmd_buffer, self.current_frame, pass);
+        }
+    }
+
+    // Every face of every shadow map gets the scene meshes drawn from the light's position
+    unsafe fn record_shadow_maps(&self, cmd_buffer: vk::CommandBuffer) {
+        let clear_depth = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 0.0,
+                stencil: 0,
+            },
+        };
 
-        self.record_commands_to_buffer(command_buffer, self.framebuffers[image_index as usize]);
+        let indirect_buffer = &self.indirect_buffers[self.current_frame];
 
-        self.end_frame(image_index);
-    }
+        for shadow_map in self.shadow_maps.iter() {
+            let light = &shadow_map.light;
+            let render_area = shadow_map.render_area();
+            let light_space = light.light_space();
 
-    fn begin_frame(&mut self) -> u32 {
-        let timeout = u64::MAX;
+            let viewport = vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: light.resolution as f32,
+                height: light.resolution as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            };
 
-        let image_available = self.image_available[self.current_frame];
-        let is_rendering = self.is_rendering[self.current_frame];
+            self.debug.begin_label(cmd_buffer, "shadow map", [0.3, 0.3, 0.3, 1.0]);
 
-        unsafe {
-            self.device
-                .wait_for_fences(&[is_rendering], true, timeout)
-                .check_err("wait for fences");
+            let faces = shadow_map.framebuffers.iter().zip(light.face_view_projs());
 
-            let (image_index, _out_of_date) = self
-                .swapchain_loader
-                .acquire_next_image(self.swapchain, timeout, image_available, vk::Fence::null())
-                .check_err("acquire next image");
+            for (&framebuffer, view_proj) in faces {
+                self.begin_render_pass(
+                    cmd_buffer,
+                    self.shadow_render_pass,
+                    framebuffer,
+                    render_area,
+                    &[clear_depth],
+                );
 
-            // if out_of_date {
-            //     self.recreate_swapchain();
-            //     return;
-            // }
+                self.device.cmd_set_viewport(cmd_buffer, 0, &[viewport]);
+                self.device.cmd_set_scissor(cmd_buffer, 0, &[render_area]);
 
-            self.device.reset_fences(&[is_rendering]).check_err("reset fences");
+                let mut bound_layout = None;
 
-            image_index
-        }
-    }
+                let draws =
+                    self.draw_order.iter().filter_map(|&idx| self.scene_meshes[idx].as_ref());
 
-    fn end_frame(&mut self, image_index: u32) {
-        let command_buffer = self.command_buffers[self.current_frame];
-        let image_available = self.image_available[self.current_frame];
-        let render_finished = self.render_finished[self.current_frame];
-        let is_rendering = self.is_rendering[self.current_frame];
+                for (idx, mesh) in draws.enumerate() {
+                    let layout = self.materials[mesh.data.material].desc.layout;
 
-        let submit_info = vk::SubmitInfo {
-            s_type: vk::StructureType::SUBMIT_INFO,
-            wait_semaphore_count: 1,
-            p_wait_semaphores: &image_available,
-            p_wait_dst_stage_mask: &vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            command_buffer_count: 1,
-            p_command_buffers: &command_buffer,
-            signal_semaphore_count: 1,
-            p_signal_semaphores: &render_finished,
-            ..Default::default()
-        };
+                    // Meshes without positions to read don't cast shadows
+                    let material = match self.shadow_materials.get(&layout) {
+                        Some(material) => material,
+                        None => continue,
+                    };
 
-        unsafe {
-            self.device
-                .queue_submit(self.graphics_queue, &[submit_info], is_rendering)
-                .check_err("submit to draw queue");
+                    if bound_layout != Some(layout) {
+                        material.bind(cmd_buffer);
+                        bound_layout = Some(layout);
+                    }
+
+                    let mut push_consts = self.shadow_push_consts;
+
+                    push_consts.mvp = view_proj * mesh.push_consts.model;
+                    push_consts.light_space = light_space * mesh.push_consts.model;
+
+                    mesh.data.record_bind_commands(
+                        cmd_buffer,
+                        material,
+                        Some(push_consts.as_push()),
+                        &[],
+                    );
+
+                    self.device.cmd_draw_indexed_indirect(
+                        cmd_buffer,
+                        indirect_buffer.buffer(),
+                        IndirectBuffer::offset(idx),
+                        1,
+                        IndirectBuffer::stride(),
+                    );
+                }
+
+                self.device.cmd_end_render_pass(cmd_buffer);
+            }
+
+            self.debug.end_label(cmd_buffer);
         }
+    }
 
-        let present_info = vk::PresentInfoKHR {
-            s_type: vk::StructureType::PRESENT_INFO_KHR,
-            wait_semaphore_count: 1,
-            p_wait_semaphores: &render_finished,
-            swapchain_count: 1,
-            p_swapchains: &self.swapchain,
-            p_image_indices: &image_index,
-            ..Default::default()
+    // Fills the G-buffer and lights it into the first HDR target, which the scene pass then loads
+    unsafe fn record_deferred(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        deferred: &DeferredPath,
+        gbuffer: &GBuffer,
+        targets: &SceneTargets,
+        clear_depth: vk::ClearValue,
+    ) {
+        let clear_gbuffer = vk::ClearValue {
+            color: vk::ClearColorValue { float32: [0.0; 4] },
         };
 
-        unsafe {
-            self.swapchain_loader
-                .queue_present(self.present_queue, &present_info)
-                .check_err("queue image for presentation");
-        }
+        let mut clear_values = [clear_gbuffer; GBUFFER_FORMATS.len() + 1];
+        clear_values[GBUFFER_FORMATS.len()] = clear_depth;
+
+        self.debug.begin_label(cmd_buffer, "G-buffer pass", [0.8, 0.5, 0.2, 1.0]);
+
+        self.begin_render_pass(
+            cmd_buffer,
+            deferred.gbuffer_render_pass,
+            gbuffer.framebuffer,
+            targets.render_area(),
+            &clear_values,
+        );
+
+        self.set_viewport(cmd_buffer, targets.render_area());
+        self.record_scene_meshes(cmd_buffer, true);
+
+        self.device.cmd_end_render_pass(cmd_buffer);
+
+        self.debug.end_label(cmd_buffer);
+        self.debug.begin_label(cmd_buffer, "lighting pass", [0.9, 0.9, 0.4, 1.0]);
+
+        self.begin_render_pass(
+            cmd_buffer,
+            deferred.lighting_render_pass,
+            targets.post_framebuffers[0],
+            targets.render_area(),
+            &[],
+        );
+
+        self.meshes[5].record_draw_commands_with(
+            cmd_buffer,
+            &deferred.lighting_material,
+            Some(deferred.push_consts.as_push()),
+            &[self.desc_sets[self.current_frame], gbuffer.desc_set],
+        );
 
-        self.current_frame = (self.current_frame + 1) % FRAMES_IN_FLIGHT;
+        self.device.cmd_end_render_pass(cmd_buffer);
+
+        self.debug.end_label(cmd_buffer);
     }
 
-    pub fn update(&mut self, _dt: f64, t: f64) {
-        self.current_time = t;
+    // Draws the scene meshes that go to the G-buffer, or all of the others
+    unsafe fn record_scene_meshes(&self, cmd_buffer: vk::CommandBuffer, gbuffer: bool) {
+        let indirect_buffer = &self.indirect_buffers[self.current_frame];
+
+        let mut bound_material = None;
+
+        let draws = self.draw_order.iter().filter_map(|&idx| self.scene_meshes[idx].as_ref());
+
+        // Indirect commands and queries are laid out for every draw, so the index counts skipped
+        // meshes too
+        let draws = draws.enumerate().filter(|&(idx, mesh)| {
+            mesh.gbuffer == gbuffer && self.draw_visibility[idx] == Visibility::Visible
+        });
+
+        let query_pool =
+            self.occlusion.as_ref().map(|occlusion| occlusion.pool(self.current_frame));
+
+        for (idx, mesh) in draws {
+            let material = &self.materials[mesh.data.material];
+
+            if bound_material != Some(mesh.data.material) {
+                material.bind(cmd_buffer);
+                bound_material = Some(mesh.data.material);
+            }
+
+            let ubo_desc_set = self.desc_sets[self.current_frame];
+            let push_consts = mesh.push_consts.as_push();
+
+            match mesh.material_desc_set {
+                None => mesh.data.record_bind_commands(
+                    cmd_buffer,
+                    material,
+                    Some(push_consts),
+                    &[ubo_desc_set],
+                ),
+                Some(desc_set) => mesh.data.record_bind_commands(
+                    cmd_buffer,
+                    material,
+                    Some(push_consts),
+                    &[ubo_desc_set, desc_set],
+                ),
+            }
+
+            if let Some(pool) = query_pool {
+                self.device.cmd_begin_query(
+                    cmd_buffer,
+                    pool,
+                    idx as u32,
+                    vk::QueryControlFlags::empty(),
+                );
+            }
+
+            self.device.cmd_draw_indexed_indirect(
+                cmd_buffer,
+                indirect_buffer.buffer(),
+                IndirectBuffer::offset(idx),
+                1,
+                IndirectBuffer::stride(),
+            );
+
+            if let Some(pool) = query_pool {
+                self.device.cmd_end_query(cmd_buffer, pool, idx as u32);
+            }
+        }
+    }
+
+    // Bounding boxes of the meshes that were hidden, for their queries to tell when they come back
+    // into view. Drawn last, so that they're tested against everything else
+    unsafe fn record_occlusion_proxies(&self, cmd_buffer: vk::CommandBuffer) {
+        let pool = match &self.occlusion {
+            Some(occlusion) => occlusion.pool(self.current_frame),
+            None => return,
+        };
+
+        let occlusion_box = &self.meshes[6];
+        let material = &self.materials[occlusion_box.material];
+
+        let ubo = &self.uniform_buffer_object;
+        let view_proj = ubo.proj * ubo.view;
+
+        material.bind(cmd_buffer);
+
+        let draws = self.draw_order.iter().filter_map(|&idx| self.scene_meshes[idx].as_ref());
+
+        let occluded = draws
+            .enumerate()
+            .filter(|&(idx, _)| self.draw_visibility[idx] == Visibility::Occluded)
+            .filter_map(|(idx, mesh)| Some((idx, mesh, mesh.bounds?)));
+
+        for (idx, mesh, bounds) in occluded {
+            let mut push_consts = self.occlusion_push_consts;
+
+            push_consts.mvp = view_proj
+                * mesh.push_consts.model
+                * Mat4::from_translation(bounds.min)
+                * Mat4::from_scale(bounds.max - bounds.min);
+
+            self.device.cmd_begin_query(
+                cmd_buffer,
+                pool,
+                idx as u32,
+                vk::QueryControlFlags::empty(),
+            );
+
+            occlusion_box.record_bind_commands(
+                cmd_buffer,
+                material,
+                Some(push_consts.as_push()),
+                &[],
+            );
+
+            self.device.cmd_draw_indexed(cmd_buffer, occlusion_box.index_count, 1, 0, 0, 0);
+            self.device.cmd_end_query(cmd_buffer, pool, idx as u32);
+        }
+    }
+
+    unsafe fn set_viewport(&self, cmd_buffer: vk::CommandBuffer, area: vk::Rect2D) {
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: area.extent.width as f32,
+            height: area.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+
+        self.device.cmd_set_viewport(cmd_buffer, 0, &[viewport]);
+        self.device.cmd_set_scissor(cmd_buffer, 0, &[area]);
+    }
+
+    // Returns the index of the HDR target that holds the output of the last effect
+    unsafe fn record_post_effects(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        targets: &SceneTargets,
+    ) -> usize {
+        let fullscreen_quad = &self.meshes[5];
+        let mut source = 0;
+
+        for effect in self.post_chain.enabled() {
+            let target = 1 - source;
+
+            let mut push_consts = effect.push_consts;
+
+            push_consts.res = Vec2::new(targets.extent.width as f32, targets.extent.height as f32);
+            push_consts.time = self.current_time as f32;
+
+            self.debug.begin_label(cmd_buffer, "post effect", [0.7, 0.3, 0.7, 1.0]);
+
+            self.begin_render_pass(
+                cmd_buffer,
+                self.post_render_pass,
+                targets.post_framebuffers[target],
+                targets.render_area(),
+                &[],
+            );
+
+            fullscreen_quad.record_draw_commands_with(
+                cmd_buffer,
+                &effect.material,
+                Some(push_consts.as_push()),
+                &[targets.desc_sets[source]],
+            );
+
+            self.device.cmd_end_render_pass(cmd_buffer);
+
+            self.debug.end_label(cmd_buffer);
+
+            source = target;
+        }
+
+        source
+    }
+
+    unsafe fn begin_render_pass(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        render_area: vk::Rect2D,
+        clear_values: &[vk::ClearValue],
+    ) {
+        let render_pass_info = vk::RenderPassBeginInfo {
+            s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+            render_pass,
+            framebuffer,
+            render_area,
+            clear_value_count: clear_values.len() as u32,
+            p_clear_values: clear_values.as_ptr(),
+            ..Default::default()
+        };
+
+        self.device.cmd_begin_render_pass(
+            cmd_buffer,
+            &render_pass_info,
+            vk::SubpassContents::INLINE,
+        );
+    }
+
+    pub fn present(&mut self) {
+        #[cfg(feature = "shaderc")]
+        self.reload_changed_shaders();
+
+        if self.swapchain_outdated {
+            self.recreate_swapchain();
+        }
+
+        // Minimized window, nothing to present to
+        if self.swapchain_outdated {
+            return;
+        }
+
+        if let Some(batch) = self.uploader.flush() {
+            self.pending_uploads.push(batch);
+        }
+
+        let command_buffer = self.command_buffers[self.current_frame];
+        let image_index = match self.begin_frame(None) {
+            Some(image_index) => image_index,
+            None => return,
+        };
+
+        if let Some(timestamps) = &mut self.timestamps {
+            timestamps.read_results(self.current_frame);
+        }
+
+        if std::mem::take(&mut self.screenshot_requested) {
+            let image = match &self.offscreen_target {
+                Some(target) => target.image,
+                None => {
+                    let images =
+                        unsafe { get_swapchain_images(&self.swapchain_loader, self.swapchain) }
+                            .check_err("get swapchain images");
+
+                    images[image_index as usize]
+                }
+            };
+
+            self.screenshot = Some(ScreenshotReadback::new(
+                self.device.clone(),
+                &self.device_mem_properties,
+                image,
+                present_layout(self.headless()),
+                screenshot::is_bgra(self.swapchain_format.format).unwrap_or_default(),
+                self.swapchain_extent,
+            ));
+        }
+
+        self.write_frame_uniforms();
+        self.write_draw_commands(true);
+        self.record_commands_to_buffer(
+            command_buffer,
+            self.targets.as_ref().unwrap(),
+            self.framebuffers[image_index as usize],
+        );
+
+        self.end_frame(image_index, None);
+    }
+
+    // Shows the scene in another window, from the camera passed to present_view. The view has a
+    // surface and swapchain of its own, and shares the device, meshes, textures and lights with
+    // the renderer. Headless renderers can't have views
+    pub unsafe fn add_view(
+        &mut self,
+        window: &dyn PresentTarget,
+    ) -> Result<ViewHandle, RendererError> {
+        if self.headless() {
+            return Err(RendererError::Surface(vk::Result::ERROR_EXTENSION_NOT_PRESENT));
+        }
+
+        let surface = window.create_surface(&self.instance).map_err(RendererError::Surface)?;
+        let (width, height) = window.size();
+        let mut view = WindowView::new(
+            self.device.clone(),
+            self.surface_loader.clone(),
+            self.swapchain_loader.clone(),
+            surface,
+            vk::Extent2D { width, height },
+            self.frames_in_flight,
+        )?;
+
+        let present_queue_idx = self.queue_family_indices.present.unwrap();
+        let present_support = self
+            .surface_loader
+            .get_physical_device_surface_support(self.phys_device, present_queue_idx, surface)
+            .map_err(RendererError::Surface)?;
+
+        if !present_support {
+            return Err(RendererError::NoSuitableDevice);
+        }
+
+        if !view.supports_format(self.phys_device, self.swapchain_format)? {
+            return Err(RendererError::Swapchain(vk::Result::ERROR_FORMAT_NOT_SUPPORTED));
+        }
+
+        view.recreate(&self.view_config())?;
+
+        if let Some(idx) = self.views.iter().position(Option::is_none) {
+            self.views[idx] = Some(view);
+            return Ok(ViewHandle(idx));
+        }
+
+        self.views.push(Some(view));
+
+        Ok(ViewHandle(self.views.len() - 1))
+    }
+
+    // Has to be called before the view's window is destroyed. Unknown handles are ignored
+    pub fn remove_view(&mut self, handle: ViewHandle) {
+        if let Some(view) = self.views.get_mut(handle.0) {
+            *view = None;
+        }
+    }
+
+    pub fn resize_view(&mut self, handle: ViewHandle, width: u32, height: u32) {
+        if let Some(Some(view)) = self.views.get_mut(handle.0) {
+            view.window_extent = vk::Extent2D { width, height };
+            view.outdated = true;
+        }
+    }
+
+    // Draws the scene from the camera, with the UI's HUD on top. Culling and occlusion results
+    // of the renderer's own frames aren't affected
+    pub fn present_view(
+        &mut self,
+        handle: ViewHandle,
+        ui: &mut UserInterface,
+        camera: &mut Camera,
+    ) {
+        let config = self.view_config();
+        let view = match self.views.get_mut(handle.0) {
+            Some(Some(view)) => view,
+            _ => return,
+        };
+
+        if view.outdated {
+            unsafe { view.recreate(&config) }.check_err("recreate view swapchain");
+        }
+
+        // Minimized window, nothing to present to
+        if view.outdated {
+            return;
+        }
+
+        // The view's scene is drawn at the size of its own swapchain
+        if view.targets.is_none() {
+            let extent = view.extent;
+            let targets = SceneTargets::new(&self.device, extent, &self.target_config())
+                .check_err("create view render targets");
+
+            targets.set_debug_names(&self.debug, &format!("view {}", handle.0));
+            self.views[handle.0].as_mut().unwrap().targets = Some(targets);
+        }
+
+        if let Some(batch) = self.uploader.flush() {
+            self.pending_uploads.push(batch);
+        }
+
+        let cull_stats = self.cull_stats;
+
+        self.update_data(ui, camera);
+
+        let command_buffer = self.command_buffers[self.current_frame];
+        let image_index = match self.begin_frame(Some(handle.0)) {
+            Some(image_index) => image_index,
+            None => return,
+        };
+
+        self.write_frame_uniforms();
+        self.write_draw_commands(false);
+
+        let view = self.views[handle.0].as_ref().unwrap();
+
+        self.record_commands_to_buffer(
+            command_buffer,
+            view.targets.as_ref().unwrap(),
+            view.framebuffers[image_index as usize],
+        );
+        self.end_frame(image_index, Some(handle.0));
+
+        self.cull_stats = cull_stats;
+    }
+
+    fn view_config(&self) -> ViewConfig {
+        ViewConfig {
+            phys_device: self.phys_device,
+            queue_family_indices: self.queue_family_indices.clone(),
+            format: self.swapchain_format,
+            vsync: self.vsync,
+            requested_images: self.swapchain_images,
+            render_pass: self.present_render_pass,
+        }
+    }
+
+    // The swapchain and semaphores of the frame, the renderer's own or those of a view
+    fn frame_target(
+        &self,
+        view: Option<usize>,
+    ) -> (vk::SwapchainKHR, vk::Semaphore, vk::Semaphore) {
+        let frame = self.current_frame;
+
+        match view.and_then(|idx| self.views[idx].as_ref()) {
+            Some(view) => {
+                (view.swapchain, view.image_available[frame], view.render_finished[frame])
+            }
+            None => (self.swapchain, self.image_available[frame], self.render_finished[frame]),
+        }
+    }
+
+    fn mark_outdated(&mut self, view: Option<usize>, outdated: bool) {
+        match view.and_then(|idx| self.views[idx].as_mut()) {
+            Some(view) => view.outdated |= outdated,
+            None => self.swapchain_outdated |= outdated,
+        }
+    }
+
+    // Presents the current frame once more and reads it back. Waits for the GPU to finish, so it
+    // stalls rendering for a moment
+    pub fn read_frame(&mut self) -> Result<Screenshot, ScreenshotError> {
+        let format = self.swapchain_format.format;
+
+        screenshot::is_bgra(format)?;
+
+        if !self.headless() {
+            let surface_capabilities = unsafe {
+                get_surface_capabilities(self.phys_device, &self.surface_loader, self.surface)
+            }
+            .check_err("get surface capabilities");
+            let usage = surface_capabilities.supported_usage_flags;
+
+            if !usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+                return Err(ScreenshotError::UnsupportedSwapchain(format));
+            }
+        }
+
+        self.screenshot_requested = true;
+        self.present();
+        self.screenshot_requested = false;
+
+        let screenshot = self.screenshot.take().ok_or(ScreenshotError::NotPresented)?;
+
+        unsafe {
+            self.device.queue_wait_idle(self.graphics_queue).check_err("wait for screenshot");
+        }
+
+        Ok(screenshot.read())
+    }
+
+    // Like read_frame, and saves the frame as a PNG
+    pub fn capture_screenshot(&mut self, path: &Path) -> Result<(), ScreenshotError> {
+        self.read_frame()?.save_png(path)
+    }
+
+    fn headless(&self) -> bool {
+        self.surface == vk::SurfaceKHR::null()
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.window_extent = vk::Extent2D { width, height };
+        self.swapchain_outdated = true;
+    }
+
+    pub fn gpu_report(&self) -> String {
+        report::gpu_report(&self.instance, self.phys_device, &self.surface_loader, self.surface)
+    }
+
+    pub fn vsync(&self) -> VSyncMode {
+        self.vsync
+    }
+
+    // Takes effect on the next frame, which recreates the swapchain
+    pub fn set_vsync(&mut self, mode: VSyncMode) {
+        if mode != self.vsync {
+            self.vsync = mode;
+            self.swapchain_outdated = true;
+
+            for view in self.views.iter_mut().flatten() {
+                view.outdated = true;
+            }
+        }
+    }
+
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats
+    }
+
+    // Of the latest frame that the GPU has finished, None until there is one or if the device
+    // can't measure it
+    pub fn gpu_stats(&self) -> Option<GpuStats> {
+        self.timestamps.as_ref().and_then(GpuTimestamps::stats)
+    }
+
+    // Pixels are tightly packed 8-bit sRGB RGBA, row by row from the top
+    pub fn create_texture(&mut self, width: u32, height: u32, pixels: &[u8]) -> TextureHandle {
+        self.create_texture_with_format(TEXTURE_FORMAT, width, height, pixels)
+    }
+
+    // Same layout as create_texture, but the values are used as they are. For normal maps and
+    // other data that isn't color
+    pub fn create_linear_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> TextureHandle {
+        self.create_texture_with_format(LINEAR_TEXTURE_FORMAT, width, height, pixels)
+    }
+
+    fn create_texture_with_format(
+        &mut self,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> TextureHandle {
+        assert!(self.textures.len() < MAX_TEXTURES as usize, "Too many textures");
+
+        let texture = Texture::from_rgba(
+            self.device.clone(),
+            &self.device_mem_properties,
+            self.command_pool,
+            self.graphics_queue,
+            self.texture_desc_pool,
+            self.texture_desc_set_layout,
+            self.sampler_settings,
+            self.mipmaps_supported,
+            format,
+            width,
+            height,
+            pixels,
+        );
+
+        texture.set_debug_names(&self.debug, &format!("texture {}", self.textures.len()));
+
+        self.textures.push(texture);
+
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    // Replaces the star field, or the skybox set before
+    pub fn set_skybox(&mut self, image: SkyboxImage) {
+        let converted;
+
+        let (size, faces) = match image {
+            SkyboxImage::Faces { size, pixels } => (size, pixels),
+            SkyboxImage::Equirectangular {
+                width,
+                height,
+                pixels,
+                face_size,
+            } => {
+                converted = equirect_to_cube_faces(width, height, pixels, face_size);
+                (face_size, &converted[..])
+            }
+        };
+
+        self.clear_skybox();
+
+        let skybox = Texture::cube_from_rgba(
+            self.device.clone(),
+            &self.device_mem_properties,
+            self.command_pool,
+            self.graphics_queue,
+            self.skybox_desc_pool,
+            self.texture_desc_set_layout,
+            self.sampler_settings,
+            size,
+            faces,
+        );
+
+        skybox.set_debug_names(&self.debug, "skybox");
+
+        self.skybox = Some(skybox);
+    }
+
+    // Goes back to the star field
+    pub fn clear_skybox(&mut self) {
+        if self.skybox.is_none() {
+            return;
+        }
+
+        // Frames in flight may still sample it
+        unsafe {
+            self.device.device_wait_idle().check_err("wait for device idle");
+        }
+
+        self.skybox = None;
+
+        unsafe {
+            self.device
+                .reset_descriptor_pool(self.skybox_desc_pool, vk::DescriptorPoolResetFlags::empty())
+                .check_err("reset skybox descriptor pool");
+        }
+    }
+
+    // Scales scene colors before tonemapping, 1 leaves them as they are
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.tonemap_push_consts.exposure = exposure.max(0.0);
+    }
+
+    pub fn set_tonemapper(&mut self, tonemapper: Tonemapper) {
+        self.tonemap_push_consts.operator = tonemapper.shader_index();
+    }
+
+    // Whether HDR output was asked for and the display supports it
+    pub fn hdr_output(&self) -> bool {
+        is_hdr(self.swapchain_format)
+    }
+
+    // Appends a fullscreen pass to the post-processing chain, which runs on the HDR scene before
+    // tonemapping. See PostPushConstants for the interface the fragment shader has to follow;
+    // params are passed to it as is
+    pub fn add_post_effect(
+        &mut self,
+        frag_shader: ShaderSource,
+        params: [Vec4; POST_EFFECT_PARAMS],
+    ) -> PostEffectHandle {
+        let push_consts = PushConstants::new(
+            PostPushConstants {
+                res: Vec2::ZERO,
+                time: 0.0,
+                _pad: 0.0,
+                params,
+            },
+            vk::ShaderStageFlags::FRAGMENT,
+        );
+
+        let frag_shader_compiled = match frag_shader.to_spirv(ShaderStage::Fragment) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("{}\nDrawing post effect with the error material", e);
+
+                error_shader_code(&self.reloaded_shaders, ShaderStage::Fragment)
+            }
+        };
+
+        let material = MaterialData::new(
+            self.device.clone(),
+            PipelineDesc {
+                layout: VertexLayout::of::<Pos2Vertex>(),
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(push_consts.range(self.max_push_consts_size)),
+                shader_names: None,
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[self.texture_desc_set_layout],
+            include_shader!("skybox.vert"),
+            &frag_shader_compiled,
+            self.pipeline_cache,
+            self.post_render_pass,
+            vk::SampleCountFlags::TYPE_1,
+        )
+        .check_err("create post effect material");
+
+        material.set_debug_names(&self.debug, "post effect");
+
+        self.post_chain.push(material, push_consts)
+    }
+
+    pub fn set_post_effect_params(
+        &mut self,
+        handle: PostEffectHandle,
+        params: [Vec4; POST_EFFECT_PARAMS],
+    ) {
+        if let Some(effect) = self.post_chain.get_mut(handle) {
+            effect.push_consts.params = params;
+        }
+    }
+
+    // Disabled effects are skipped without being removed from the chain
+    pub fn set_post_effect_enabled(&mut self, handle: PostEffectHandle, enabled: bool) {
+        if let Some(effect) = self.post_chain.get_mut(handle) {
+            effect.enabled = enabled;
+        }
+    }
+
+    pub fn remove_post_effect(&mut self, handle: PostEffectHandle) {
+        // Frames in flight may still use its pipeline
+        unsafe {
+            self.device.device_wait_idle().check_err("wait for device idle");
+        }
+
+        self.post_chain.remove(handle);
+    }
+
+    // The light's surroundings are rendered into a cube map every frame, six passes over the
+    // scene meshes for each light
+    pub fn add_shadow_light(&mut self, light: ShadowLight) -> ShadowLightHandle {
+        assert!(light.radius > 0.0, "Shadow light radius must be positive");
+
+        let light = ShadowLight {
+            resolution: light.resolution.clamp(1, self.max_shadow_resolution),
+            ..light
+        };
+
+        let shadow_map = ShadowMap::new(
+            self.device.clone(),
+            &self.device_mem_properties,
+            self.shadow_format,
+            self.shadow_render_pass,
+            light,
+        )
+        .check_err("create shadow map");
+
+        let handle = self.shadow_maps.insert(shadow_map);
+
+        if let Some(shadow_map) = self.shadow_maps.get_mut(handle) {
+            shadow_map.set_debug_names(&self.debug, &format!("shadow map {}", handle.0));
+        }
+
+        // Frames in flight may still use the descriptor sets
+        unsafe {
+            self.device.device_wait_idle().check_err("wait for device idle");
+        }
+
+        self.write_shadow_map_descs();
+
+        handle
+    }
+
+    pub fn set_shadow_light_position(&mut self, handle: ShadowLightHandle, position: Vec3) {
+        if let Some(shadow_map) = self.shadow_maps.get_mut(handle) {
+            shadow_map.light.position = position;
+        }
+    }
+
+    pub fn remove_shadow_light(&mut self, handle: ShadowLightHandle) {
+        // Frames in flight may still render into or sample it
+        unsafe {
+            self.device.device_wait_idle().check_err("wait for device idle");
+        }
+
+        self.shadow_maps.remove(handle);
+
+        self.write_shadow_map_descs();
+    }
+
+    fn write_shadow_map_descs(&self) {
+        write_shadow_map_descs(
+            &self.device,
+            &self.desc_sets,
+            &self.shadow_maps,
+            &self.placeholder_shadow_map,
+            self.shadow_sampler,
+        );
+    }
+
+    // Only affects lit meshes. Nothing lights them but the ambient light by default
+    pub fn set_sun(&mut self, sun: Option<DirectionalLight>) {
+        self.lights.sun = sun;
+    }
+
+    pub fn set_ambient_light(&mut self, color: Vec3) {
+        self.lights.ambient = color;
+    }
+
+    pub fn add_point_light(&mut self, light: PointLight) -> PointLightHandle {
+        self.lights.add_point(light)
+    }
+
+    pub fn set_point_light(&mut self, handle: PointLightHandle, light: PointLight) {
+        if let Some(point) = self.lights.point_mut(handle) {
+            *point = light;
+        }
+    }
+
+    pub fn remove_point_light(&mut self, handle: PointLightHandle) {
+        self.lights.remove_point(handle);
+    }
+
+    // UVs are ignored by plain color materials
+    pub fn add_mesh(
+        &mut self,
+        vertices: &[TexturedVertex],
+        indices: &[u16],
+        material: Material,
+    ) -> MeshHandle {
+        let (frag_shader_name, frag_shader) = match material {
+            Material::Color(_) => ("color.frag", &include_shader!("color.frag")[..]),
+            Material::Textured(_) => ("textured.frag", &include_shader!("textured.frag")[..]),
+        };
+
+        self.add_scene_mesh(
+            vertices,
+            indices,
+            SceneMaterial::Basic(material),
+            ShaderSource::Spirv(include_shader!("mesh.vert")),
+            ShaderSource::Spirv(frag_shader),
+            Some(["mesh.vert", frag_shader_name]),
+            false,
+        )
+    }
+
+    // Shaded by the sun, ambient and point lights, see lit.frag. Normals are transformed by the mesh's
+    // transform without correcting for non-uniform scaling
+    pub fn add_lit_mesh(
+        &mut self,
+        vertices: &[LitVertex],
+        indices: &[u16],
+        material: Material,
+    ) -> MeshHandle {
+        // lit.frag always samples a texture, plain colors multiply a white one
+        let (material, color) = match material {
+            Material::Color(color) => (Material::Textured(self.white_texture()), color.extend(1.0)),
+            Material::Textured(_) => (material, Vec4::ONE),
+        };
+
+        let gbuffer = self.deferred.is_some();
+
+        let (frag_shader_name, frag_shader) = if gbuffer {
+            ("lit_gbuffer.frag", &include_shader!("lit_gbuffer.frag")[..])
+        } else {
+            ("lit.frag", &include_shader!("lit.frag")[..])
+        };
+
+        let handle = self.add_scene_mesh(
+            vertices,
+            indices,
+            SceneMaterial::Basic(material),
+            ShaderSource::Spirv(include_shader!("lit.vert")),
+            ShaderSource::Spirv(frag_shader),
+            Some(["lit.vert", frag_shader_name]),
+            gbuffer,
+        );
+
+        if let Some(mesh) = self.scene_mesh_mut(handle) {
+            mesh.push_consts.color = color;
+        }
+
+        handle
+    }
+
+    fn white_texture(&mut self) -> TextureHandle {
+        if let Some(texture) = self.white_texture {
+            return texture;
+        }
+
+        let texture = self.create_texture(1, 1, &[255; 4]);
+
+        self.white_texture = Some(texture);
+
+        texture
+    }
+
+    fn flat_normal_texture(&mut self) -> TextureHandle {
+        if let Some(texture) = self.flat_normal_texture {
+            return texture;
+        }
+
+        let texture = self.create_linear_texture(1, 1, &[128, 128, 255, 255]);
+
+        self.flat_normal_texture = Some(texture);
+
+        texture
+    }
+
+    // Materials are kept until the renderer is dropped. Meshes with a material that refers to
+    // missing textures are drawn with the error material
+    pub fn create_pbr_material(&mut self, material: &PbrMaterial) -> PbrMaterialHandle {
+        assert!(self.pbr_materials.len() < MAX_PBR_MATERIALS as usize, "Too many PBR materials");
+
+        let white = self.white_texture();
+        let flat_normal = self.flat_normal_texture();
+
+        let textures = [
+            material.base_color.unwrap_or(white),
+            material.normal.unwrap_or(flat_normal),
+            material.metallic_roughness.unwrap_or(white),
+            material.occlusion.unwrap_or(white),
+        ];
+
+        let handle = PbrMaterialHandle(self.pbr_materials.len());
+
+        let image_infos: Option<Vec<vk::DescriptorImageInfo>> = textures
+            .iter()
+            .map(|texture| self.textures.get(texture.0).map(Texture::image_info))
+            .collect();
+
+        let desc_set = match image_infos {
+            Some(image_infos) => {
+                let desc_set = create_pbr_desc_set(
+                    &self.device,
+                    self.pbr_desc_pool,
+                    self.pbr_desc_set_layout,
+                    &image_infos,
+                );
+
+                self.debug.name(desc_set, &format!("PBR material {} descriptor set", handle.0));
+
+                Some(desc_set)
+            }
+            None => {
+                eprintln!("PBR material {} refers to a texture that doesn't exist", handle.0);
+                None
+            }
+        };
+
+        self.pbr_materials.push(PbrMaterialData::new(desc_set, material));
+
+        handle
+    }
+
+    // Shaded by the same lights as lit meshes, with the metallic-roughness model of glTF, see
+    // pbr.frag
+    pub fn add_pbr_mesh(
+        &mut self,
+        vertices: &[PbrVertex],
+        indices: &[u16],
+        material: PbrMaterialHandle,
+    ) -> MeshHandle {
+        let gbuffer = self.deferred.is_some();
+
+        let (frag_shader_name, frag_shader) = if gbuffer {
+            ("pbr_gbuffer.frag", &include_shader!("pbr_gbuffer.frag")[..])
+        } else {
+            ("pbr.frag", &include_shader!("pbr.frag")[..])
+        };
+
+        self.add_scene_mesh(
+            vertices,
+            indices,
+            SceneMaterial::Pbr(material),
+            ShaderSource::Spirv(include_shader!("pbr.vert")),
+            ShaderSource::Spirv(frag_shader),
+            Some(["pbr.vert", frag_shader_name]),
+            gbuffer,
+        )
+    }
+
+    // Custom shaders have to follow the interface of mesh.vert and color.frag or textured.frag:
+    // the uniform buffer at set 0, the texture at set 1 and the same push constants block. Vertex
+    // inputs are up to the shaders, as long as they match the attributes of V
+    pub fn add_mesh_with_shaders<V: Vertex>(
+        &mut self,
+        vertices: &[V],
+        indices: &[u16],
+        material: Material,
+        vert_shader: ShaderSource,
+        frag_shader: ShaderSource,
+    ) -> MeshHandle {
+        let material = SceneMaterial::Basic(material);
+
+        self.add_scene_mesh(vertices, indices, material, vert_shader, frag_shader, None, false)
+    }
+
+    fn add_scene_mesh<V: Vertex>(
+        &mut self,
+        vertices: &[V],
+        indices: &[u16],
+        material: SceneMaterial,
+        vert_shader: ShaderSource,
+        frag_shader: ShaderSource,
+        shader_names: Option<[&'static str; 2]>,
+        gbuffer: bool,
+    ) -> MeshHandle {
+        let mesh = Mesh::new(vertices, indices.to_vec());
+
+        let bindings = match material {
+            SceneMaterial::Basic(Material::Color(color)) => {
+                Some((color.extend(1.0), Vec4::ZERO, None))
+            }
+            SceneMaterial::Basic(Material::Textured(texture)) => {
+                self.textures.get(texture.0).map(|texture| {
+                    let material_set = (self.texture_desc_set_layout, texture.desc_set);
+
+                    (Vec4::ONE, Vec4::ZERO, Some(material_set))
+                })
+            }
+            SceneMaterial::Pbr(handle) => self.pbr_materials.get(handle.0).and_then(|data| {
+                let material_set = (self.pbr_desc_set_layout, data.desc_set?);
+
+                Some((data.base_color_factor, data.params, Some(material_set)))
+            }),
+        };
+
+        // The error material only has a color output, so it's always drawn in the scene pass
+        let ((color, params, material_set), vert_shader, frag_shader, shader_names, gbuffer) =
+            match bindings {
+                Some(bindings) => (bindings, vert_shader, frag_shader, shader_names, gbuffer),
+                None => {
+                    eprintln!(
+                        "{:?} is missing or incomplete, drawing mesh with the error material",
+                        material
+                    );
+
+                    (
+                        (Vec4::ONE, Vec4::ZERO, None),
+                        ShaderSource::Spirv(include_shader!("error.vert")),
+                        ShaderSource::Spirv(include_shader!("error.frag")),
+                        Some(ERROR_SHADERS),
+                        false,
+                    )
+                }
+            };
+
+        let push_consts = PushConstants::new(
+            MeshPushConstants {
+                model: Mat4::IDENTITY,
+                color,
+                params,
+            },
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        );
+
+        let desc = PipelineDesc {
+            layout: mesh.layout,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            push_const_range: Some(push_consts.range(self.max_push_consts_size)),
+            shader_names,
+            color_attachments: if gbuffer { GBUFFER_FORMATS.len() as u32 } else { 1 },
+            depth_test_only: false,
+        };
+
+        let material_set_layout = material_set.map(|(layout, _)| layout);
+
+        let (material_id, gbuffer) =
+            self.scene_material(desc, material_set_layout, vert_shader, frag_shader, gbuffer);
+
+        self.create_shadow_material(mesh.layout);
+
+        let bounds = mesh.bounds();
+
+        let data = mesh
+            .into_mesh_data(
+                self.device.clone(),
+                &self.device_mem_properties,
+                &mut self.uploader,
+                material_id,
+            )
+            .check_err("upload mesh");
+
+        let scene_mesh = SceneMesh {
+            data,
+            gbuffer,
+            bounds,
+            occluded: false,
+            material_desc_set: material_set.map(|(_, desc_set)| desc_set),
+            push_consts,
+        };
+
+        let idx = match self.free_mesh_slots.pop() {
+            Some(idx) => idx,
+            None => {
+                self.scene_meshes.push(None);
+                self.mesh_generations.push(0);
+                self.scene_meshes.len() - 1
+            }
+        };
+
+        scene_mesh.data.set_debug_names(&self.debug, &format!("mesh {}", idx));
+
+        self.scene_meshes[idx] = Some(scene_mesh);
+
+        MeshHandle(idx, self.mesh_generations[idx])
+    }
+
+    // None for handles of meshes that were removed, even if another mesh took their slot
+    fn scene_mesh_mut(&mut self, handle: MeshHandle) -> Option<&mut SceneMesh> {
+        if self.mesh_generations.get(handle.0) != Some(&handle.1) {
+            return None;
+        }
+
+        self.scene_meshes[handle.0].as_mut()
+    }
+
+    // Materials are kept until the renderer is dropped, there are only as many as shader pairs.
+    // Shaders that fail to compile are replaced by the error material, which is drawn in the scene
+    // pass even if the G-buffer was asked for. Returns whether the material ended up in the latter
+    fn scene_material(
+        &mut self,
+        desc: PipelineDesc,
+        material_set_layout: Option<vk::DescriptorSetLayout>,
+        vert_shader: ShaderSource,
+        frag_shader: ShaderSource,
+        gbuffer: bool,
+    ) -> (usize, bool) {
+        let vert_shader_name = desc.shader_names.map(|[vert, _]| vert);
+        let frag_shader_name = desc.shader_names.map(|[_, frag]| frag);
+
+        let compiled =
+            shader_code(&self.reloaded_shaders, vert_shader_name, vert_shader, ShaderStage::Vertex)
+                .and_then(|vert| {
+                    let frag = shader_code(
+                        &self.reloaded_shaders,
+                        frag_shader_name,
+                        frag_shader,
+                        ShaderStage::Fragment,
+                    )?;
+
+                    Ok((vert, frag))
+                });
+
+        let (desc, vert_shader_compiled, frag_shader_compiled, gbuffer) = match compiled {
+            Ok((vert, frag)) => (desc, vert, frag, gbuffer),
+            Err(e) => {
+                eprintln!("{}\nDrawing mesh with the error material", e);
+
+                let desc = PipelineDesc {
+                    shader_names: Some(ERROR_SHADERS),
+                    color_attachments: 1,
+                    ..desc
+                };
+
+                (
+                    desc,
+                    error_shader_code(&self.reloaded_shaders, ShaderStage::Vertex),
+                    error_shader_code(&self.reloaded_shaders, ShaderStage::Fragment),
+                    false,
+                )
+            }
+        };
+
+        let key = match desc.shader_names {
+            Some(names) => MaterialKey::Named(names, desc.layout, material_set_layout),
+            None => MaterialKey::Code(
+                vert_shader_compiled.to_vec(),
+                frag_shader_compiled.to_vec(),
+                desc.layout,
+                material_set_layout,
+            ),
+        };
+
+        if let Some(&id) = self.material_ids.get(&key) {
+            return (id, gbuffer);
+        }
+
+        let desc_set_layouts: Vec<vk::DescriptorSetLayout> =
+            [Some(self.desc_set_layout), material_set_layout].into_iter().flatten().collect();
+
+        let (render_pass, samples) = match &self.deferred {
+            Some(deferred) if gbuffer => {
+                (deferred.gbuffer_render_pass, vk::SampleCountFlags::TYPE_1)
+            }
+            _ => (self.scene_render_pass, self.msaa_samples),
+        };
+
+        let material = MaterialData::new(
+            self.device.clone(),
+            desc,
+            &desc_set_layouts,
+            &vert_shader_compiled,
+            &frag_shader_compiled,
+            self.pipeline_cache,
+            render_pass,
+            samples,
+        )
+        .check_err("create material");
+
+        let id = self.materials.len();
+
+        material.set_debug_names(&self.debug, &format!("material {}", id));
+
+        self.materials.push(material);
+        self.material_ids.insert(key, id);
+
+        (id, gbuffer)
+    }
+
+    // shadow.vert only reads positions, so any layout that starts with one can share it
+    fn create_shadow_material(&mut self, layout: VertexLayout) {
+        if self.shadow_materials.contains_key(&layout)
+            || layout.first_attribute() != Some(VertexAttribute::Vec3)
+        {
+            return;
+        }
+
+        let vert_shader = builtin_shader_code(
+            &self.reloaded_shaders,
+            "shadow.vert",
+            include_shader!("shadow.vert"),
+        );
+        let frag_shader = builtin_shader_code(
+            &self.reloaded_shaders,
+            "shadow.frag",
+            include_shader!("shadow.frag"),
+        );
+
+        let material = MaterialData::new(
+            self.device.clone(),
+            PipelineDesc {
+                layout,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(self.shadow_push_consts.range(self.max_push_consts_size)),
+                shader_names: Some(["shadow.vert", "shadow.frag"]),
+                color_attachments: 0,
+                depth_test_only: false,
+            },
+            &[],
+            vert_shader,
+            frag_shader,
+            self.pipeline_cache,
+            self.shadow_render_pass,
+            vk::SampleCountFlags::TYPE_1,
+        )
+        .check_err("create shadow material");
+
+        let name = format!("shadow material {}", self.shadow_materials.len());
+
+        material.set_debug_names(&self.debug, &name);
+
+        self.shadow_materials.insert(layout, material);
+    }
+
+    // Unknown handles and those of meshes already removed are ignored. The buffers are destroyed
+    // once the next frame finishes, which also waits for uploads into them
+    pub fn remove_mesh(&mut self, handle: MeshHandle) {
+        if self.scene_mesh_mut(handle).is_none() {
+            return;
+        }
+
+        if let Some(mesh) = self.scene_meshes[handle.0].take() {
+            self.removed_meshes.push(mesh.data);
+            self.mesh_generations[handle.0] = self.mesh_generations[handle.0].wrapping_add(1);
+            self.free_mesh_slots.push(handle.0);
+        }
+    }
+
+    pub fn update_transform(&mut self, handle: MeshHandle, transform: Mat4) {
+        if let Some(mesh) = self.scene_mesh_mut(handle) {
+            mesh.push_consts.model = transform;
+        }
+    }
+
+    // Only called after the current frame's fence has been waited on
+    // Views draw everything in the frustum and issue no occlusion queries of their own, their
+    // cameras would leave the results useless to the renderer's frames
+    fn write_draw_commands(&mut self, occlusion_culling: bool) {
+        if occlusion_culling {
+            self.read_occlusion_results();
+        }
+
+        let scene_meshes = &self.scene_meshes;
+
+        self.draw_order.clear();
+        self.draw_order.extend(
+            scene_meshes.iter().enumerate().filter(|(_, mesh)| mesh.is_some()).map(|(idx, _)| idx),
+        );
+
+        // Stable, so meshes of the same material keep the order they were added in
+        self.draw_order
+            .sort_by_key(|&idx| scene_meshes[idx].as_ref().map(|mesh| mesh.data.material));
+
+        let draw_count = self.draw_order.len();
+
+        if draw_count > self.indirect_buffers[self.current_frame].capacity() {
+            self.indirect_buffers[self.current_frame] = unsafe {
+                IndirectBuffer::new(
+                    self.device.clone(),
+                    &self.device_mem_properties,
+                    draw_count.next_power_of_two(),
+                )
+                .check_err("grow indirect buffer")
+            };
+        }
+
+        let indirect_buffer = &mut self.indirect_buffers[self.current_frame];
+
+        indirect_buffer.clear();
+
+        for mesh in self.draw_order.iter().filter_map(|&idx| self.scene_meshes[idx].as_ref()) {
+            indirect_buffer.push(mesh.data.draw_command());
+        }
+
+        let ubo = &self.uniform_buffer_object;
+        let frustum = Frustum::from_view_proj(ubo.proj * ubo.view);
+
+        self.draw_visibility.clear();
+        self.draw_visibility.extend(
+            self.draw_order.iter().filter_map(|&idx| self.scene_meshes[idx].as_ref()).map(|mesh| {
+                let in_frustum = mesh.bounds.map_or(true, |bounds| {
+                    frustum.intersects(&bounds.transformed(&mesh.push_consts.model))
+                });
+
+                // Without bounds there's no box to query in the mesh's place
+                match (in_frustum, occlusion_culling && mesh.occluded && mesh.bounds.is_some()) {
+                    (false, _) => Visibility::Culled,
+                    (true, true) => Visibility::Occluded,
+                    (true, false) => Visibility::Visible,
+                }
+            }),
+        );
+
+        if let Some(occlusion) = &mut self.occlusion {
+            let issued = if occlusion_culling {
+                self.draw_order
+                    .iter()
+                    .zip(&self.draw_visibility)
+                    .zip(0..)
+                    .filter(|((_, &visibility), _)| visibility != Visibility::Culled)
+                    .map(|((&slot, _), query)| (query, slot))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            occlusion.prepare(self.current_frame, draw_count, issued);
+        }
+
+        let count = |visibility| self.draw_visibility.iter().filter(|&&v| v == visibility).count();
+
+        self.cull_stats = CullStats {
+            visible: count(Visibility::Visible),
+            culled: count(Visibility::Culled),
+            occluded: count(Visibility::Occluded),
+        };
+    }
+
+    // Results of the queries this frame slot issued last time, which its fence has waited for
+    fn read_occlusion_results(&mut self) {
+        let results = match &self.occlusion {
+            Some(occlusion) => occlusion.results(self.current_frame),
+            None => return,
+        };
+
+        for (slot, visible) in results {
+            if let Some(Some(mesh)) = self.scene_meshes.get_mut(slot) {
+                mesh.occluded = !visible;
+            }
+        }
+    }
+
+    fn begin_frame(&mut self, view: Option<usize>) -> Option<u32> {
+        let timeout = u64::MAX;
+
+        let (swapchain, image_available, _) = self.frame_target(view);
+        let is_rendering = self.is_rendering[self.current_frame];
+
+        unsafe {
+            self.device
+                .wait_for_fences(&[is_rendering], true, timeout)
+                .check_err("wait for fences");
+
+            self.frame_uploads[self.current_frame].clear();
+            self.frame_removed_meshes[self.current_frame].clear();
+
+            // There's only the one offscreen image to draw into
+            if self.headless() {
+                self.device.reset_fences(&[is_rendering]).check_err("reset fences");
+
+                return Some(0);
+            }
+
+            let acquire_result = self.swapchain_loader.acquire_next_image(
+                swapchain,
+                timeout,
+                image_available,
+                vk::Fence::null(),
+            );
+
+            // Suboptimal swapchain can still be presented to, so it's recreated after this frame
+            let image_index = match acquire_result {
+                Ok((image_index, suboptimal)) => {
+                    self.mark_outdated(view, suboptimal);
+                    image_index
+                }
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.mark_outdated(view, true);
+                    return None;
+                }
+                Err(e) => panic!("Failed to acquire next image: err = {}", e),
+            };
+
+            // Only reset after acquiring, so that skipped frame doesn't leave fence unsignaled
+            self.device.reset_fences(&[is_rendering]).check_err("reset fences");
+
+            Some(image_index)
+        }
+    }
+
+    fn end_frame(&mut self, image_index: u32, view: Option<usize>) {
+        let command_buffer = self.command_buffers[self.current_frame];
+        let (swapchain, image_available, render_finished) = self.frame_target(view);
+        let is_rendering = self.is_rendering[self.current_frame];
+        let headless = self.headless();
+
+        let (mut wait_semaphores, mut wait_stages) = if headless {
+            (Vec::new(), Vec::new())
+        } else {
+            (vec![image_available], vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
+        };
+
+        for batch in &self.pending_uploads {
+            wait_semaphores.push(batch.semaphore);
+            wait_stages.push(vk::PipelineStageFlags::VERTEX_INPUT);
+        }
+
+        let submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            wait_semaphore_count: wait_semaphores.len() as u32,
+            p_wait_semaphores: wait_semaphores.as_ptr(),
+            p_wait_dst_stage_mask: wait_stages.as_ptr(),
+            command_buffer_count: 1,
+            p_command_buffers: &command_buffer,
+            signal_semaphore_count: u32::from(!headless),
+            p_signal_semaphores: &render_finished,
+            ..Default::default()
+        };
+
+        unsafe {
+            self.device
+                .queue_submit(self.graphics_queue, &[submit_info], is_rendering)
+                .check_err("submit to draw queue");
+        }
+
+        let waited_uploads = std::mem::take(&mut self.pending_uploads);
+        self.frame_uploads[self.current_frame].extend(waited_uploads);
+
+        let removed_meshes = std::mem::take(&mut self.removed_meshes);
+        self.frame_removed_meshes[self.current_frame].extend(removed_meshes);
+        self.current_frame = (self.current_frame + 1) % self.frames_in_flight;
+
+        if headless {
+            return;
+        }
+
+        let present_info = vk::PresentInfoKHR {
+            s_type: vk::StructureType::PRESENT_INFO_KHR,
+            wait_semaphore_count: 1,
+            p_wait_semaphores: &render_finished,
+            swapchain_count: 1,
+            p_swapchains: &swapchain,
+            p_image_indices: &image_index,
+            ..Default::default()
+        };
+
+        let present_result =
+            unsafe { self.swapchain_loader.queue_present(self.present_queue, &present_info) };
+
+        match present_result {
+            Ok(suboptimal) => self.mark_outdated(view, suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.mark_outdated(view, true),
+            Err(e) => panic!("Failed to queue image for presentation: err = {}", e),
+        }
+    }
+
+    pub fn update(&mut self, _dt: f64, t: f64) {
+        self.current_time = t;
+    }
+
+    pub fn update_data(&mut self, ui: &mut UserInterface, camera: &mut Camera) {
+        let view_angles = camera.view_angles();
+
+        self.skybox_push_consts.view_angles.x = view_angles.x;
+        self.skybox_push_consts.view_angles.y = view_angles.y;
+
+        let mut rotation = *camera.view();
+        rotation.w_axis = Vec4::W;
+
+        self.cubemap_push_consts.inv_view_proj = (*camera.proj() * rotation).inverse();
+
+        self.crosshair_visible = !ui.hidden() && ui.hud().crosshair.visible;
+        self.crosshair_push_consts.color = ui.hud().crosshair_color();
+
+        let crosshair_pos = ui.crosshair_position();
+
+        self.crosshair_push_consts.proj =
+            *ui.proj() * Mat4::from_translation(Vec3::new(crosshair_pos.x, crosshair_pos.y, 0.0));
+
+        self.hud_box_push_consts.clear();
+
+        let showkeys_color = ui.hud().showkeys_color();
+
+        if !ui.hidden() && ui.hud().showkeys.visible {
+            let origin = ui.showkeys_position();
+            let boxes = ui.showkeys().boxes();
+            let proj = *ui.proj();
+
+            for hud_box in boxes {
+                let pos = origin + hud_box.pos;
+
+                // Same pipeline as the crosshair, so same stages
+                let mut push_consts = self.crosshair_push_consts;
+
+                push_consts.proj = proj
+                    * Mat4::from_translation(Vec3::new(pos.x, pos.y, 0.0))
+                    * Mat4::from_scale(Vec3::new(hud_box.size.x, hud_box.size.y, 1.0));
+                push_consts.color = if hud_box.lit {
+                    showkeys_color
+                } else {
+                    showkeys_color * 0.25
+                };
+
+                self.hud_box_push_consts.push(push_consts);
+            }
+        }
+
+        self.uniform_buffer_object.view = *camera.view();
+        self.uniform_buffer_object.proj = *camera.proj();
+
+        if let Some(deferred) = &mut self.deferred {
+            let view = self.uniform_buffer_object.view;
+
+            deferred.push_consts.inv_view_proj = (self.uniform_buffer_object.proj * view).inverse();
+            deferred.push_consts.camera_position = view.inverse().w_axis;
+        }
+    }
+
+    // Only called after the current frame's fence has been waited on, until then the GPU may still
+    // be reading the frame's buffers
+    fn write_frame_uniforms(&self) {
+        let lights = self.lights.to_uniform(&self.shadow_maps);
+
+        unsafe {
+            self.uniform_buffers_mappings[self.current_frame]
+                .copy_from_nonoverlapping(&self.uniform_buffer_object, 1);
+            self.lights_buffers_mappings[self.current_frame].copy_from_nonoverlapping(&lights, 1);
+        }
+    }
+
+    #[cfg(feature = "shaderc")]
+    fn reload_changed_shaders(&mut self) {
+        let changed = match &mut self.shader_watcher {
+            Some(watcher) => watcher.poll(self.current_time),
+            None => return,
+        };
+
+        if changed.is_empty() {
+            return;
+        }
+
+        let uses_changed = |desc: &PipelineDesc| match desc.shader_names {
+            Some(names) => names.iter().any(|name| changed.iter().any(|c| c == name)),
+            None => false,
+        };
+
+        // Scene materials can fall back to the error material, the rest only know their own layout.
+        // So do G-buffer materials, as the error material doesn't fill the G-buffer
+        let scene_material_ids: Vec<usize> = self.material_ids.values().copied().collect();
+
+        let affected: Vec<(&mut MaterialData, bool)> = self
+            .materials
+            .iter_mut()
+            .enumerate()
+            .map(|(id, material)| {
+                let has_error_fallback =
+                    scene_material_ids.contains(&id) && material.desc.color_attachments == 1;
+
+                (material, has_error_fallback)
+            })
+            .chain(self.shadow_materials.values_mut().map(|material| (material, false)))
+            .chain(
+                self.deferred.iter_mut().map(|deferred| (&mut deferred.lighting_material, false)),
+            )
+            .filter(|(material, _)| uses_changed(&material.desc))
+            .collect();
+
+        if affected.is_empty() {
+            return;
+        }
+
+        // Both stages are compiled from source so that an edited shader is never paired with a
+        // stale embedded version of the other one
+        for (material, _) in &affected {
+            for name in material.desc.shader_names.into_iter().flatten() {
+                if self.reloaded_shaders.contains_key(name) && !changed.iter().any(|c| c == name) {
+                    continue;
+                }
+
+                let stage = match ShaderStage::from_file_name(name) {
+                    Some(stage) => stage,
+                    None => continue,
+                };
+
+                match try_compile_glsl(&shader_path(name), stage) {
+                    Ok(code) => {
+                        println!("Reloaded shader {}", name);
+                        self.reloaded_shaders.insert(name.to_owned(), code);
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        self.reloaded_shaders.remove(name);
+                    }
+                }
+            }
+        }
+
+        unsafe {
+            self.device.device_wait_idle().check_err("wait for device idle");
+        }
+
+        for (material, has_error_fallback) in affected {
+            let [vert, frag] = material.desc.shader_names.unwrap();
+
+            match (self.reloaded_shaders.get(vert), self.reloaded_shaders.get(frag)) {
+                (Some(vert_code), Some(frag_code)) => unsafe {
+                    material.rebuild_pipeline(vert_code, frag_code, self.pipeline_cache);
+                },
+                // Meshes are drawn with the error material until the shader is fixed, other
+                // materials keep their old pipeline
+                _ if has_error_fallback => unsafe {
+                    material.rebuild_pipeline(
+                        &error_shader_code(&self.reloaded_shaders, ShaderStage::Vertex),
+                        &error_shader_code(&self.reloaded_shaders, ShaderStage::Fragment),
+                        self.pipeline_cache,
+                    );
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn recreate_swapchain(&mut self) {
+        unsafe {
+            let surface_capabilities = (!self.headless()).then(|| {
+                get_surface_capabilities(self.phys_device, &self.surface_loader, self.surface)
+                    .check_err("get surface capabilities")
+            });
+
+            let extent = match &surface_capabilities {
+                Some(capabilities) => choose_swapchain_extent(self.window_extent, capabilities),
+                None => self.window_extent,
+            };
+
+            if extent.width == 0 || extent.height == 0 {
+                return;
+            }
+
+            self.cleanup_swapchain();
+
+            self.swapchain_extent = extent;
+
+            if let Some(surface_capabilities) = surface_capabilities {
+                self.swapchain = create_swapchain(
+                    self.phys_device,
+                    self.surface,
+                    &self.surface_loader,
+                    &surface_capabilities,
+                    self.swapchain_format,
+                    self.swapchain_extent,
+                    self.vsync,
+                    self.swapchain_images,
+                    &self.swapchain_loader,
+                    &self.queue_family_indices,
+                )
+                .check_err("recreate swapchain");
+
+                let swapchain_images = get_swapchain_images(&self.swapchain_loader, self.swapchain)
+                    .check_err("get swapchain images");
+
+                self.swapchain_image_views =
+                    create_image_views(&self.device, self.swapchain_format, &swapchain_images)
+                        .check_err("create swapchain image views");
+            } else {
+                self.offscreen_target = Some(
+                    create_offscreen_target(
+                        &self.device,
+                        &self.device_mem_properties,
+                        self.swapchain_extent,
+                    )
+                    .check_err("create offscreen target"),
+                );
+            }
+
+            self.targets = Some(
+                SceneTargets::new(&self.device, self.swapchain_extent, &self.target_config())
+                    .check_err("create render targets"),
+            );
+
+            self.framebuffers = create_framebuffers(
+                &self.device,
+                &self.present_views(),
+                self.swapchain_extent,
+                self.present_render_pass,
+            )
+            .check_err("create framebuffers");
+        }
+
+        self.name_swapchain_objects();
+
+        self.swapchain_outdated = false;
+    }
+
+    fn target_config(&self) -> TargetConfig<'_> {
+        TargetConfig {
+            device_mem_properties: &self.device_mem_properties,
+            depth_format: self.depth_format,
+            samples: self.msaa_samples,
+            scene_render_pass: self.scene_render_pass,
+            post_render_pass: self.post_render_pass,
+            desc_set_layout: self.texture_desc_set_layout,
+            sampler: self.target_sampler,
+            deferred: self.deferred.as_ref(),
+        }
+    }
+
+    fn set_debug_names(&self) {
+        let debug = &self.debug;
+
+        debug.name(self.scene_render_pass, "scene render pass");
+        debug.name(self.post_render_pass, "post-processing render pass");
+        debug.name(self.present_render_pass, "present render pass");
+        debug.name(self.shadow_render_pass, "shadow render pass");
+        debug.name(self.command_pool, "graphics command pool");
+        debug.name(self.uploader.command_pool(), "transfer command pool");
+        debug.name(self.desc_set_layout, "uniform descriptor set layout");
+        debug.name(self.desc_pool, "uniform descriptor pool");
+        debug.name(self.texture_desc_set_layout, "texture descriptor set layout");
+        debug.name(self.texture_desc_pool, "texture descriptor pool");
+        debug.name(self.pbr_desc_set_layout, "PBR descriptor set layout");
+        debug.name(self.pbr_desc_pool, "PBR descriptor pool");
+        debug.name(self.skybox_desc_pool, "skybox descriptor pool");
+        debug.name(self.target_sampler, "render target sampler");
+
+        if let Some(deferred) = &self.deferred {
+            deferred.set_debug_names(debug);
+        }
+
+        if let Some(occlusion) = &self.occlusion {
+            occlusion.set_debug_names(debug);
+        }
+
+        if let Some(timestamps) = &self.timestamps {
+            timestamps.set_debug_names(debug);
+        }
+
+        for i in 0..self.frames_in_flight {
+            debug.name(self.command_buffers[i], &format!("frame {} command buffer", i));
+            debug.name(self.image_available[i], &format!("frame {} image available", i));
+            debug.name(self.render_finished[i], &format!("frame {} render finished", i));
+            debug.name(self.is_rendering[i], &format!("frame {} is rendering", i));
+            debug.name(self.uniform_buffers[i], &format!("frame {} uniform buffer", i));
+            debug.name(self.desc_sets[i], &format!("frame {} descriptor set", i));
+        }
+
+        let names = [
+            "skybox",
+            "grid",
+            "crosshair",
+            "hud box",
+            "cubemap skybox",
+            "tonemap",
+            "occlusion box",
+        ];
+
+        for (idx, name) in names.into_iter().enumerate() {
+            self.meshes[idx].set_debug_names(debug, name);
+            self.materials[idx].set_debug_names(debug, name);
+        }
+
+        self.name_swapchain_objects();
     }
 
-    pub fn update_data(&mut self, ui: &mut UserInterface, camera: &mut Camera) {
-        self.skybox_push_consts.view_angles.x = camera.pitch();
-        self.skybox_push_consts.view_angles.y = camera.yaw();
+    fn name_swapchain_objects(&self) {
+        let debug = &self.debug;
 
-        self.crosshair_push_consts.proj = *ui.proj();
+        match &self.offscreen_target {
+            Some(target) => target.set_debug_names(debug, "offscreen target"),
+            None => debug.name(self.swapchain, "swapchain"),
+        }
 
-        self.uniform_buffer_object.view = *camera.view();
-        self.uniform_buffer_object.proj = *camera.proj();
+        for (i, image_view) in self.swapchain_image_views.iter().enumerate() {
+            debug.name(*image_view, &format!("swapchain image view {}", i));
+        }
 
-        unsafe {
-            self.uniform_buffers_mappings[self.current_frame]
-                .copy_from_nonoverlapping(&self.uniform_buffer_object, 1);
+        for (i, framebuffer) in self.framebuffers.iter().enumerate() {
+            debug.name(*framebuffer, &format!("framebuffer {}", i));
+        }
+
+        if let Some(targets) = &self.targets {
+            targets.set_debug_names(debug, "main");
         }
     }
 
-    unsafe fn cleanup_swapchain(&self) {
+    unsafe fn cleanup_swapchain(&mut self) {
         self.device.device_wait_idle().unwrap();
 
-        for fb in &self.framebuffers {
-            self.device.destroy_framebuffer(*fb, None);
+        for fb in self.framebuffers.drain(..) {
+            self.device.destroy_framebuffer(fb, None);
+        }
+
+        for image_view in self.swapchain_image_views.drain(..) {
+            self.device.destroy_image_view(image_view, None);
+        }
+
+        self.targets = None;
+        self.offscreen_target = None;
+
+        // Headless devices don't enable the swapchain extension, so its functions aren't loaded
+        if !self.headless() {
+            self.swapchain_loader.destroy_swapchain(self.swapchain, None);
         }
+    }
 
-        self.device.destroy_render_pass(self.render_pass, None);
-        self.device.free_command_buffers(self.command_pool, &self.command_buffers);
-        for image_view in &self.swapchain_image_views {
-            self.device.destroy_image_view(*image_view, None);
+    // Images the present render pass draws into
+    fn present_views(&self) -> Vec<vk::ImageView> {
+        match &self.offscreen_target {
+            Some(target) => vec![target.view],
+            None => self.swapchain_image_views.clone(),
         }
-        self.swapchain_loader.destroy_swapchain(self.swapchain, None);
     }
 }
 
@@ -475,6 +3285,8 @@ impl Drop for Renderer {
         unsafe {
             self.device.device_wait_idle().unwrap();
 
+            self.views.clear();
+
             for sem in &self.image_available {
                 self.device.destroy_semaphore(*sem, None);
             }
@@ -489,6 +3301,12 @@ impl Drop for Renderer {
 
             self.cleanup_swapchain();
 
+            self.device.destroy_render_pass(self.scene_render_pass, None);
+            self.device.destroy_render_pass(self.post_render_pass, None);
+            self.device.destroy_render_pass(self.present_render_pass, None);
+            self.device.destroy_render_pass(self.shadow_render_pass, None);
+            self.device.free_command_buffers(self.command_pool, &self.command_buffers);
+
             for buf in &self.uniform_buffers {
                 self.device.destroy_buffer(*buf, None);
             }
@@ -497,77 +3315,129 @@ impl Drop for Renderer {
                 self.device.free_memory(*mem, None);
             }
 
+            for buf in &self.lights_buffers {
+                self.device.destroy_buffer(*buf, None);
+            }
+
+            for mem in &self.lights_buffers_memories {
+                self.device.free_memory(*mem, None);
+            }
+
             self.meshes.drain(..);
+            self.scene_meshes.drain(..);
+            self.removed_meshes.clear();
+            self.frame_removed_meshes.clear();
+            self.materials.clear();
+            self.shadow_materials.clear();
+            self.post_chain.clear();
+            self.deferred = None;
+            self.occlusion = None;
+            self.timestamps = None;
+            self.shadow_maps.clear();
+            self.device.destroy_sampler(self.shadow_sampler, None);
+            self.indirect_buffers.clear();
+            self.textures.drain(..);
+            self.skybox = None;
+
+            self.pending_uploads.clear();
+            self.frame_uploads.clear();
+            self.uploader.destroy();
+
+            self.device.destroy_sampler(self.target_sampler, None);
+
+            self.device.destroy_descriptor_pool(self.skybox_desc_pool, None);
+            self.device.destroy_descriptor_pool(self.texture_desc_pool, None);
+            self.device.destroy_descriptor_set_layout(self.texture_desc_set_layout, None);
+
+            self.device.destroy_descriptor_pool(self.pbr_desc_pool, None);
+            self.device.destroy_descriptor_set_layout(self.pbr_desc_set_layout, None);
 
             self.device.destroy_descriptor_pool(self.desc_pool, None);
             self.device.destroy_descriptor_set_layout(self.desc_set_layout, None);
 
+            pipeline_cache::save(&self.device, self.pipeline_cache);
+            self.device.destroy_pipeline_cache(self.pipeline_cache, None);
+
             self.device.destroy_command_pool(self.command_pool, None);
             self.device.destroy_device(None);
-            self.surface_loader.destroy_surface(self.surface, None);
+
+            if !self.headless() {
+                self.surface_loader.destroy_surface(self.surface, None);
+            }
+
+            self.debug_messenger = None;
             self.instance.destroy_instance(None);
         }
     }
 }
 
 impl Mesh {
+    fn new<V: Vertex>(vertices: &[V], indices: Vec<u16>) -> Self {
+        Self {
+            vertices: bytemuck::cast_slice(vertices).to_vec(),
+            indices,
+            layout: VertexLayout::of::<V>(),
+        }
+    }
+
+    // Built-in 2D meshes are written as flat x, y pairs
+    fn pos2(vertices: &[f32], indices: Vec<u16>) -> Self {
+        Self::new::<Pos2Vertex>(bytemuck::cast_slice(vertices), indices)
+    }
+
+    // Mesh positions are the first attribute, when it's a vec3
+    fn bounds(&self) -> Option<Aabb> {
+        if self.layout.first_attribute() != Some(VertexAttribute::Vec3) {
+            return None;
+        }
+
+        let positions = self.vertices.chunks_exact(self.layout.stride() as usize).map(|vertex| {
+            Vec3::from_array(bytemuck::pod_read_unaligned(&vertex[..size_of::<Vec3>()]))
+        });
+
+        Aabb::from_points(positions)
+    }
+
     fn into_mesh_data(
         self,
         device: ash::Device,
         device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
-        command_pool: vk::CommandPool,
-        graphics_queue: vk::Queue,
-        push_const_range: Option<vk::PushConstantRange>,
-        desc_set_layout: Option<vk::DescriptorSetLayout>,
-        vert_shader_compiled: &[u8],
-        frag_shader_compiled: &[u8],
-        topology: vk::PrimitiveTopology,
-        swapchain_extent: vk::Extent2D,
-        render_pass: vk::RenderPass,
-    ) -> MeshData {
-        let (vertex_buffer, vertex_buffer_memory) = create_buffer_of_type(
-            &device,
+        uploader: &mut Uploader,
+        material: usize,
+    ) -> Result<MeshData, RendererError> {
+        let (vertex_buffer, vertex_buffer_memory) = uploader.upload_buffer(
             device_mem_properties,
-            command_pool,
-            graphics_queue,
             vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
             &self.vertices,
-        );
+        )?;
 
-        let (index_buffer, index_buffer_memory) = create_buffer_of_type(
-            &device,
+        let index_buffer = uploader.upload_buffer(
             device_mem_properties,
-            command_pool,
-            graphics_queue,
             vk::BufferUsageFlags::INDEX_BUFFER,
+            vk::AccessFlags::INDEX_READ,
             &self.indices,
         );
 
-        let index_count = self.indices.len().try_into().unwrap();
-
-        let pipeline_layout =
-            create_pipeline_layout(&device, push_const_range.as_ref(), desc_set_layout.as_ref());
+        let (index_buffer, index_buffer_memory) = match index_buffer {
+            Ok(created) => created,
+            Err(e) => {
+                uploader.discard_buffer(vertex_buffer, vertex_buffer_memory);
+                return Err(e);
+            }
+        };
 
-        let pipeline = create_graphics_pipeline(
-            &device,
-            vert_shader_compiled,
-            frag_shader_compiled,
-            topology,
-            swapchain_extent,
-            render_pass,
-            pipeline_layout,
-        );
+        let index_count = self.indices.len().try_into().unwrap();
 
-        MeshData {
+        Ok(MeshData {
             device,
             vertex_buffer,
             vertex_buffer_memory,
             index_buffer,
             index_buffer_memory,
             index_count,
-            pipeline_layout,
-            pipeline,
-        }
+            material,
+        })
     }
 }
 
@@ -575,11 +3445,53 @@ impl MeshData {
     unsafe fn record_draw_commands(
         &self,
         cmd_buffer: vk::CommandBuffer,
+        materials: &[MaterialData],
+        push_consts: Option<(vk::ShaderStageFlags, &[u8])>,
+        desc_sets: &[vk::DescriptorSet],
+    ) {
+        self.record_draw_commands_with(
+            cmd_buffer,
+            &materials[self.material],
+            push_consts,
+            desc_sets,
+        );
+    }
+
+    // Draws with a material other than the mesh's own, such as the fullscreen quad for each post
+    // effect
+    unsafe fn record_draw_commands_with(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        material: &MaterialData,
         push_consts: Option<(vk::ShaderStageFlags, &[u8])>,
-        desc_sets: Option<vk::DescriptorSet>,
+        desc_sets: &[vk::DescriptorSet],
     ) {
-        self.device.cmd_bind_pipeline(cmd_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+        material.bind(cmd_buffer);
+
+        self.record_bind_commands(cmd_buffer, material, push_consts, desc_sets);
+
+        self.device.cmd_draw_indexed(cmd_buffer, self.index_count, 1, 0, 0, 0);
+    }
+
+    fn draw_command(&self) -> vk::DrawIndexedIndirectCommand {
+        vk::DrawIndexedIndirectCommand {
+            index_count: self.index_count,
+            instance_count: 1,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+        }
+    }
 
+    // Everything but binding the material's pipeline and the draw itself, for draws sorted by
+    // material with parameters from an indirect buffer
+    unsafe fn record_bind_commands(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        material: &MaterialData,
+        push_consts: Option<(vk::ShaderStageFlags, &[u8])>,
+        desc_sets: &[vk::DescriptorSet],
+    ) {
         self.device.cmd_bind_vertex_buffers(cmd_buffer, 0, &[self.vertex_buffer], &[0]);
 
         self.device.cmd_bind_index_buffer(cmd_buffer, self.index_buffer, 0, vk::IndexType::UINT16);
@@ -587,25 +3499,28 @@ impl MeshData {
         if let Some((push_const_stage_flags, push_const_bytes)) = push_consts {
             self.device.cmd_push_constants(
                 cmd_buffer,
-                self.pipeline_layout,
+                material.pipeline_layout,
                 push_const_stage_flags,
                 0,
                 push_const_bytes,
             );
         }
 
-        if let Some(set) = desc_sets {
+        if !desc_sets.is_empty() {
             self.device.cmd_bind_descriptor_sets(
                 cmd_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline_layout,
+                material.pipeline_layout,
                 0,
-                &[set],
+                desc_sets,
                 &[],
             );
         }
+    }
 
-        self.device.cmd_draw_indexed(cmd_buffer, self.index_count, 1, 0, 0, 0);
+    fn set_debug_names(&self, debug: &DebugMarkers, name: &str) {
+        debug.name(self.vertex_buffer, &format!("{} vertex buffer", name));
+        debug.name(self.index_buffer, &format!("{} index buffer", name));
     }
 }
 
@@ -616,8 +3531,100 @@ impl Drop for MeshData {
             self.device.free_memory(self.index_buffer_memory, None);
             self.device.destroy_buffer(self.vertex_buffer, None);
             self.device.free_memory(self.vertex_buffer_memory, None);
-            self.device.destroy_pipeline(self.pipeline, None);
-            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+impl RenderTarget {
+    fn new(
+        device: &ash::Device,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        samples: vk::SampleCountFlags,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<Self, RendererError> {
+        let (image, memory) = unsafe {
+            create_image(
+                device,
+                device_mem_properties,
+                extent.width,
+                extent.height,
+                1,
+                format,
+                samples,
+                usage,
+            )?
+        };
+
+        let mut target = Self {
+            device: device.clone(),
+            image,
+            memory,
+            view: vk::ImageView::null(),
+        };
+
+        // Destroying a null view is a no-op, so a failed target frees its image on drop
+        target.view = create_image_view(device, image, format, aspect_mask, 1)?;
+
+        Ok(target)
+    }
+
+    fn set_debug_names(&self, debug: &DebugMarkers, name: &str) {
+        debug.name(self.image, &format!("{} image", name));
+        debug.name(self.memory, &format!("{} memory", name));
+        debug.name(self.view, &format!("{} view", name));
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+impl InitGuard {
+    fn defer(&mut self, cleanup: impl FnOnce() + 'static) {
+        self.cleanups.push(Box::new(cleanup));
+    }
+
+    fn destroy<T: 'static>(
+        &mut self,
+        device: &ash::Device,
+        handle: T,
+        destroy: impl FnOnce(&ash::Device, T) + 'static,
+    ) {
+        let device = device.clone();
+
+        self.defer(move || destroy(&device, handle));
+    }
+
+    fn destroy_all<T: Copy + 'static>(
+        &mut self,
+        device: &ash::Device,
+        handles: &[T],
+        destroy: impl Fn(&ash::Device, T) + 'static,
+    ) {
+        let device = device.clone();
+        let handles = handles.to_vec();
+
+        self.defer(move || handles.into_iter().for_each(|handle| destroy(&device, handle)));
+    }
+
+    fn disarm(mut self) {
+        self.cleanups.clear();
+    }
+}
+
+impl Drop for InitGuard {
+    fn drop(&mut self) {
+        while let Some(cleanup) = self.cleanups.pop() {
+            cleanup();
         }
     }
 }
@@ -640,7 +3647,33 @@ impl<T, E: Display> CheckVkError<T> for Result<T, E> {
     }
 }
 
-fn create_instance(app_name: &'static str, entry: &ash::Entry, window: &Window) -> ash::Instance {
+impl Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RendererError::Instance(e) => {
+                write!(f, "failed to create Vulkan instance, is a driver installed? ({})", e)
+            }
+            RendererError::Surface(e) => write!(f, "failed to create window surface: {}", e),
+            RendererError::NoSuitableDevice => write!(f, "no suitable GPU found"),
+            RendererError::Device(e) => write!(f, "failed to create device: {}", e),
+            RendererError::Swapchain(e) => write!(f, "failed to create swapchain: {}", e),
+            RendererError::Resource(action, e) => write!(f, "failed to {}: {}", action, e),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+fn resource_err(action: &'static str) -> impl FnOnce(vk::Result) -> RendererError {
+    move |e| RendererError::Resource(action, e)
+}
+
+fn create_instance(
+    app_name: &'static str,
+    entry: &ash::Entry,
+    window: Option<&dyn PresentTarget>,
+    validation_severity: ValidationSeverity,
+) -> Result<(ash::Instance, bool), RendererError> {
     let app_cstring = CString::new(app_name).check_err("convert app_name to CString");
     let app_cstr = app_cstring.as_c_str();
 
@@ -667,12 +3700,30 @@ fn create_instance(app_name: &'static str, entry: &ash::Entry, window: &Window)
     let req_layers_cstrs = convert_to_c_strs(&req_layers_owned);
     let req_layers_cptrs = convert_to_c_ptrs(&req_layers_cstrs);
 
-    let req_exts_owned = window.get_required_extensions();
+    let req_exts_owned = window.map(PresentTarget::required_extensions).unwrap_or_default();
     let req_exts_cstrs = convert_to_c_strs(&req_exts_owned);
 
-    #[allow(unused_mut)]
     let mut req_exts_cptrs = convert_to_c_ptrs(&req_exts_cstrs);
 
+    let available_exts = unsafe { entry.enumerate_instance_extension_properties(None) }
+        .map_err(RendererError::Instance)?;
+    let ext_available = |name: &CStr| {
+        available_exts
+            .iter()
+            .any(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == name)
+    };
+
+    let debug_utils_available = ext_available(DebugUtils::name());
+
+    if debug_utils_available {
+        req_exts_cptrs.push(DebugUtils::name().as_ptr());
+    }
+
+    // Needed for the HDR color spaces to show up among the surface formats
+    if ext_available(vk::ExtSwapchainColorspaceFn::name()) {
+        req_exts_cptrs.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+    }
+
     #[cfg(any(target_os = "macos", target_os = "ios"))]
     {
         req_exts_cptrs.push(vk::KhrPortabilityEnumerationFn::name().as_ptr());
@@ -685,8 +3736,15 @@ fn create_instance(app_name: &'static str, entry: &ash::Entry, window: &Window)
         vk::InstanceCreateFlags::default()
     };
 
+    let messenger_info = debug::messenger_create_info(validation_severity);
+
     let create_info = vk::InstanceCreateInfo {
         s_type: vk::StructureType::INSTANCE_CREATE_INFO,
+        p_next: if debug_utils_available {
+            &messenger_info as *const _ as *const c_void
+        } else {
+            ptr::null()
+        },
         p_application_info: &app_info,
         enabled_layer_count: req_layers_cptrs.len() as u32,
         pp_enabled_layer_names: req_layers_cptrs.as_ptr(),
@@ -696,7 +3754,10 @@ fn create_instance(app_name: &'static str, entry: &ash::Entry, window: &Window)
         ..Default::default()
     };
 
-    unsafe { entry.create_instance(&create_info, None) }.check_err("create instance")
+    let instance =
+        unsafe { entry.create_instance(&create_info, None) }.map_err(RendererError::Instance)?;
+
+    Ok((instance, debug_utils_available))
 }
 
 fn convert_to_strings(strs: &[&str]) -> Vec<String> {
@@ -714,20 +3775,48 @@ fn convert_to_c_ptrs(cstrings: &[CString]) -> Vec<*const c_char> {
     cstrings.iter().map(|cstring| cstring.as_ptr()).collect()
 }
 
+// Destroys the surface again if there's no device for it. Without a window the surface is null
+// and any device that can draw will do
+unsafe fn create_device_for_window(
+    instance: &ash::Instance,
+    surface_loader: &Surface,
+    window: Option<&dyn PresentTarget>,
+) -> Result<(vk::SurfaceKHR, PhysDeviceInfo, ash::Device), RendererError> {
+    let surface = match window {
+        Some(window) => window.create_surface(instance).map_err(RendererError::Surface)?,
+        None => vk::SurfaceKHR::null(),
+    };
+
+    let device = pick_phys_device(instance, surface, surface_loader).and_then(|info| {
+        let device = create_logical_device(instance, &info, window.is_none())?;
+
+        Ok((info, device))
+    });
+
+    match device {
+        Ok((info, device)) => Ok((surface, info, device)),
+        Err(e) => {
+            if window.is_some() {
+                surface_loader.destroy_surface(surface, None);
+            }
+
+            Err(e)
+        }
+    }
+}
+
 unsafe fn pick_phys_device(
     instance: &ash::Instance,
     surface: vk::SurfaceKHR,
     surface_loader: &Surface,
-) -> PhysDeviceInfo {
-    let phys_devices = instance.enumerate_physical_devices().check_err("get physical devices");
+) -> Result<PhysDeviceInfo, RendererError> {
+    let phys_devices = instance.enumerate_physical_devices().map_err(RendererError::Device)?;
     let mut phys_device_infos =
-        gather_phys_device_infos(instance, surface, surface_loader, &phys_devices);
-
-    assert!(!phys_device_infos.is_empty(), "No suitable devices found");
+        gather_phys_device_infos(instance, surface, surface_loader, &phys_devices)?;
 
     phys_device_infos.sort_by_key(|d| device_type_to_priority(d.properties.device_type));
 
-    phys_device_infos[0].clone()
+    phys_device_infos.into_iter().next().ok_or(RendererError::NoSuitableDevice)
 }
 
 unsafe fn gather_phys_device_infos(
@@ -735,24 +3824,28 @@ unsafe fn gather_phys_device_infos(
     surface: vk::SurfaceKHR,
     surface_loader: &Surface,
     phys_devices: &[vk::PhysicalDevice],
-) -> Vec<PhysDeviceInfo> {
+) -> Result<Vec<PhysDeviceInfo>, RendererError> {
     let mut phys_device_infos = Vec::with_capacity(phys_devices.len());
 
     for device_ref in phys_devices {
         let phys_device = *device_ref;
         let properties = instance.get_physical_device_properties(phys_device);
+        let features = instance.get_physical_device_features(phys_device);
         let queue_family_indices =
-            get_queue_family_indices(instance, phys_device, surface, surface_loader);
+            get_queue_family_indices(instance, phys_device, surface, surface_loader)?;
         let supports_required_queues =
             queue_family_indices.graphics.is_some() && queue_family_indices.present.is_some();
         let extensions = instance
             .enumerate_device_extension_properties(phys_device)
-            .check_err("enumerate device extensions");
+            .map_err(resource_err("enumerate device extensions"))?;
 
-        if supports_required_queues && supports_required_extensions(&extensions) {
+        let headless = surface == vk::SurfaceKHR::null();
+
+        if supports_required_queues && supports_required_extensions(&extensions, headless) {
             let info = PhysDeviceInfo {
                 phys_device,
                 properties,
+                features,
                 queue_family_indices,
             };
 
@@ -760,11 +3853,38 @@ unsafe fn gather_phys_device_infos(
         }
     }
 
-    phys_device_infos
+    Ok(phys_device_infos)
+}
+
+fn report_device_info(properties: &vk::PhysicalDeviceProperties) {
+    let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) };
+    let api_version = properties.api_version;
+
+    crash::set_context("gpu", name.to_string_lossy().into_owned());
+    crash::set_context("driver version", format!("{:#x}", properties.driver_version));
+    crash::set_context(
+        "vulkan version",
+        format!(
+            "{}.{}.{}",
+            vk::api_version_major(api_version),
+            vk::api_version_minor(api_version),
+            vk::api_version_patch(api_version)
+        ),
+    );
+}
+
+fn required_device_extensions(headless: bool) -> Vec<String> {
+    let mut exts = convert_to_strings(REQ_DEVICE_EXTENSIONS);
+
+    if !headless {
+        exts.push("VK_KHR_swapchain".to_string());
+    }
+
+    exts
 }
 
-fn supports_required_extensions(exts: &[vk::ExtensionProperties]) -> bool {
-    let req_device_exts = convert_to_strings(REQ_DEVICE_EXTENSIONS);
+fn supports_required_extensions(exts: &[vk::ExtensionProperties], headless: bool) -> bool {
+    let req_device_exts = required_device_extensions(headless);
     let req_exts = convert_to_c_strs(&req_device_exts);
 
     let mut support_found = Vec::with_capacity(req_exts.len());
@@ -797,49 +3917,62 @@ fn choose_swapchain_format(
     phys_device: vk::PhysicalDevice,
     surface_loader: &Surface,
     surface: vk::SurfaceKHR,
-) -> vk::SurfaceFormatKHR {
+    hdr_output: bool,
+) -> Result<vk::SurfaceFormatKHR, RendererError> {
     let formats =
         unsafe { surface_loader.get_physical_device_surface_formats(phys_device, surface) }
-            .check_err("get surface formats");
+            .map_err(RendererError::Swapchain)?;
+
+    let supported = |wanted: &vk::SurfaceFormatKHR| {
+        formats.iter().any(|format| {
+            format.format == wanted.format && format.color_space == wanted.color_space
+        })
+    };
+
+    if hdr_output {
+        if let Some(format) = HDR_SURFACE_FORMATS.iter().find(|format| supported(format)) {
+            return Ok(*format);
+        }
+    }
 
     for format in &formats {
         if format.format == vk::Format::B8G8R8A8_UNORM
             && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
         {
-            return *format;
+            return Ok(*format);
         }
     }
 
-    formats[0]
+    formats
+        .first()
+        .copied()
+        .ok_or(RendererError::Swapchain(vk::Result::ERROR_FORMAT_NOT_SUPPORTED))
 }
 
 unsafe fn get_surface_capabilities(
     phys_device: vk::PhysicalDevice,
     surface_loader: &Surface,
     surface: vk::SurfaceKHR,
-) -> vk::SurfaceCapabilitiesKHR {
+) -> Result<vk::SurfaceCapabilitiesKHR, RendererError> {
     surface_loader
         .get_physical_device_surface_capabilities(phys_device, surface)
-        .check_err("get surface capabilities")
+        .map_err(RendererError::Swapchain)
 }
 
 fn choose_swapchain_extent(
-    window: &Window,
+    window_extent: vk::Extent2D,
     capabilities: &vk::SurfaceCapabilitiesKHR,
 ) -> vk::Extent2D {
     if capabilities.current_extent.width != u32::MAX {
         return capabilities.current_extent;
     }
 
-    let win_width = window.width();
-    let win_height = window.height();
-
     let min = capabilities.min_image_extent;
     let max = capabilities.max_image_extent;
 
     vk::Extent2D {
-        width: win_width.clamp(min.width, max.width),
-        height: win_height.clamp(min.height, max.height),
+        width: window_extent.width.clamp(min.width, max.width),
+        height: window_extent.height.clamp(min.height, max.height),
     }
 }
 
@@ -848,7 +3981,7 @@ fn get_queue_family_indices(
     phys_device: vk::PhysicalDevice,
     surface: vk::SurfaceKHR,
     surface_loader: &Surface,
-) -> QueueFamilyIndices {
+) -> Result<QueueFamilyIndices, RendererError> {
     let queue_families =
         unsafe { instance.get_physical_device_queue_family_properties(phys_device) };
 
@@ -866,14 +3999,25 @@ fn get_queue_family_indices(
             families.compute = opt;
         }
 
-        if f.queue_flags.contains(vk::QueueFlags::TRANSFER) {
+        // Prefer a family that only does transfers, those are backed by dedicated DMA engines
+        let transfer_only =
+            !f.queue_flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE);
+
+        if f.queue_flags.contains(vk::QueueFlags::TRANSFER)
+            && (families.transfer.is_none() || transfer_only)
+        {
             families.transfer = opt;
         }
 
-        let present_support = unsafe {
-            surface_loader
-                .get_physical_device_surface_support(phys_device, idx, surface)
-                .check_err("get surface support")
+        // Nothing is presented without a surface, the graphics queue stands in for it
+        let present_support = if surface == vk::SurfaceKHR::null() {
+            f.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        } else {
+            unsafe {
+                surface_loader
+                    .get_physical_device_surface_support(phys_device, idx, surface)
+                    .map_err(RendererError::Surface)?
+            }
         };
 
         if present_support {
@@ -881,15 +4025,21 @@ fn get_queue_family_indices(
         }
     }
 
-    families
+    Ok(families)
 }
 
-fn create_logical_device(instance: &ash::Instance, info: &PhysDeviceInfo) -> ash::Device {
+fn create_logical_device(
+    instance: &ash::Instance,
+    info: &PhysDeviceInfo,
+    headless: bool,
+) -> Result<ash::Device, RendererError> {
     let mut unique_families = vec![
         info.queue_family_indices.graphics.unwrap(),
         info.queue_family_indices.present.unwrap(),
     ];
 
+    unique_families.extend(info.queue_family_indices.transfer);
+
     unique_families.sort_unstable();
     unique_families.dedup();
 
@@ -908,16 +4058,13 @@ fn create_logical_device(instance: &ash::Instance, info: &PhysDeviceInfo) -> ash
         queue_create_infos.push(queue_create_info);
     }
 
-    let features = vk::PhysicalDeviceFeatures {
-        shader_clip_distance: 1,
-        ..Default::default()
-    };
+    let features = enabled_device_features(&info.features);
 
     let req_layers_owned = convert_to_strings(REQ_VALIDATION_LAYERS);
     let req_layers_cstrs = convert_to_c_strs(&req_layers_owned);
     let req_layers_cptrs = convert_to_c_ptrs(&req_layers_cstrs);
 
-    let req_exts_strings = convert_to_strings(REQ_DEVICE_EXTENSIONS);
+    let req_exts_strings = required_device_extensions(headless);
     let req_exts_cstrings = convert_to_c_strs(&req_exts_strings);
     let req_exts_cptrs = convert_to_c_ptrs(&req_exts_cstrings);
 
@@ -934,7 +4081,16 @@ fn create_logical_device(instance: &ash::Instance, info: &PhysDeviceInfo) -> ash
     };
 
     unsafe { instance.create_device(info.phys_device, &create_info, None) }
-        .check_err("create device")
+        .map_err(RendererError::Device)
+}
+
+// Optional features are enabled only when the device supports them
+fn enabled_device_features(supported: &vk::PhysicalDeviceFeatures) -> vk::PhysicalDeviceFeatures {
+    vk::PhysicalDeviceFeatures {
+        shader_clip_distance: 1,
+        sampler_anisotropy: supported.sampler_anisotropy,
+        ..Default::default()
+    }
 }
 
 fn create_swapchain(
@@ -944,17 +4100,20 @@ fn create_swapchain(
     surface_capabilities: &vk::SurfaceCapabilitiesKHR,
     swapchain_format: vk::SurfaceFormatKHR,
     swapchain_extent: vk::Extent2D,
+    vsync: VSyncMode,
+    requested_images: Option<u32>,
     swapchain_loader: &Swapchain,
     queue_family_indices: &QueueFamilyIndices,
-) -> vk::SwapchainKHR {
-    let mut image_count = surface_capabilities.min_image_count + 1;
+) -> Result<vk::SwapchainKHR, RendererError> {
+    let min_image_count = surface_capabilities.min_image_count;
+    let mut image_count = requested_images.unwrap_or(min_image_count + 1).max(min_image_count);
     let max_image_count = surface_capabilities.max_image_count;
 
     if image_count > max_image_count && max_image_count != 0 {
         image_count = max_image_count;
     }
 
-    let present_mode = choose_swapchain_present_mode(phys_device, surface, surface_loader);
+    let present_mode = choose_swapchain_present_mode(phys_device, surface, surface_loader, vsync)?;
 
     let gfx_queue_idx = queue_family_indices.graphics.unwrap();
     let present_queue_idx = queue_family_indices.present.unwrap();
@@ -966,6 +4125,10 @@ fn create_swapchain(
             (vk::SharingMode::CONCURRENT, 2, vec![gfx_queue_idx, present_queue_idx])
         };
 
+    // Copied from for screenshots where supported
+    let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (surface_capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
     let create_info = vk::SwapchainCreateInfoKHR {
         s_type: vk::StructureType::SWAPCHAIN_CREATE_INFO_KHR,
         surface,
@@ -973,7 +4136,7 @@ fn create_swapchain(
         image_color_space: swapchain_format.color_space,
         image_format: swapchain_format.format,
         image_extent: swapchain_extent,
-        image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        image_usage,
         image_sharing_mode,
         p_queue_family_indices: queue_family_indices.as_ptr(),
         queue_family_index_count,
@@ -985,29 +4148,66 @@ fn create_swapchain(
         ..Default::default()
     };
 
-    unsafe { swapchain_loader.create_swapchain(&create_info, None) }.check_err("create swapchain")
+    unsafe { swapchain_loader.create_swapchain(&create_info, None) }
+        .map_err(RendererError::Swapchain)
 }
 
 fn choose_swapchain_present_mode(
     phys_device: vk::PhysicalDevice,
     surface: vk::SurfaceKHR,
     surface_loader: &Surface,
-) -> vk::PresentModeKHR {
-    let mut modes =
+    vsync: VSyncMode,
+) -> Result<vk::PresentModeKHR, RendererError> {
+    let modes =
         unsafe { surface_loader.get_physical_device_surface_present_modes(phys_device, surface) }
-            .check_err("get present modes");
+            .map_err(RendererError::Swapchain)?;
+
+    let mode = present_mode_preference(vsync)
+        .iter()
+        .copied()
+        .find(|mode| modes.contains(mode))
+        .unwrap_or(vk::PresentModeKHR::FIFO);
+
+    Ok(mode)
+}
 
-    modes.sort_by_key(|m| present_mode_to_priority(*m));
+// FIFO is the only mode every device supports
+fn present_mode_preference(vsync: VSyncMode) -> &'static [vk::PresentModeKHR] {
+    match vsync {
+        VSyncMode::Off => &[
+            vk::PresentModeKHR::IMMEDIATE,
+            vk::PresentModeKHR::MAILBOX,
+            vk::PresentModeKHR::FIFO_RELAXED,
+        ],
+        VSyncMode::VSync => &[vk::PresentModeKHR::FIFO],
+        VSyncMode::Mailbox => &[vk::PresentModeKHR::MAILBOX],
+        VSyncMode::Adaptive => &[vk::PresentModeKHR::FIFO_RELAXED],
+    }
+}
 
-    modes[0]
+// Color target that headless renderers draw and read frames back from
+fn create_offscreen_target(
+    device: &ash::Device,
+    device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+    extent: vk::Extent2D,
+) -> Result<RenderTarget, RendererError> {
+    RenderTarget::new(
+        device,
+        device_mem_properties,
+        OFFSCREEN_FORMAT.format,
+        extent,
+        vk::SampleCountFlags::TYPE_1,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        vk::ImageAspectFlags::COLOR,
+    )
 }
 
-fn present_mode_to_priority(mode: vk::PresentModeKHR) -> u32 {
-    match mode {
-        vk::PresentModeKHR::IMMEDIATE => 1,
-        vk::PresentModeKHR::FIFO_RELAXED => 2,
-        vk::PresentModeKHR::MAILBOX => 3,
-        _ => 4,
+// What the present render pass leaves its image in, offscreen ones are only ever copied from
+fn present_layout(headless: bool) -> vk::ImageLayout {
+    if headless {
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+    } else {
+        vk::ImageLayout::PRESENT_SRC_KHR
     }
 }
 
@@ -1015,7 +4215,7 @@ fn create_command_pool(
     device: &ash::Device,
     queue_family_index: u32,
     reset: bool,
-) -> vk::CommandPool {
+) -> Result<vk::CommandPool, RendererError> {
     let flags = if reset {
         vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER
     } else {
@@ -1029,14 +4229,15 @@ fn create_command_pool(
         ..Default::default()
     };
 
-    unsafe { device.create_command_pool(&create_info, None) }.check_err("create command pool")
+    unsafe { device.create_command_pool(&create_info, None) }
+        .map_err(resource_err("create command pool"))
 }
 
 fn create_command_buffers(
     device: &ash::Device,
     command_pool: vk::CommandPool,
     num: u32,
-) -> Vec<vk::CommandBuffer> {
+) -> Result<Vec<vk::CommandBuffer>, RendererError> {
     let allocate_info = vk::CommandBufferAllocateInfo {
         s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
         command_pool,
@@ -1045,24 +4246,25 @@ fn create_command_buffers(
         ..Default::default()
     };
 
-    unsafe { device.allocate_command_buffers(&allocate_info) }.check_err("allocate command buffers")
+    unsafe { device.allocate_command_buffers(&allocate_info) }
+        .map_err(resource_err("allocate command buffers"))
 }
 
 unsafe fn get_swapchain_images(
     swapchain_loader: &Swapchain,
     swapchain: vk::SwapchainKHR,
-) -> Vec<vk::Image> {
-    swapchain_loader.get_swapchain_images(swapchain).check_err("get swapchain images")
+) -> Result<Vec<vk::Image>, RendererError> {
+    swapchain_loader.get_swapchain_images(swapchain).map_err(RendererError::Swapchain)
 }
 
 fn create_image_views(
     device: &ash::Device,
     swapchain_format: vk::SurfaceFormatKHR,
     images: &[vk::Image],
-) -> Vec<vk::ImageView> {
-    images
-        .iter()
-        .map(|&image| {
+) -> Result<Vec<vk::ImageView>, RendererError> {
+    create_each(
+        images,
+        |&image| {
             create_image_view(
                 device,
                 image,
@@ -1070,8 +4272,9 @@ fn create_image_views(
                 vk::ImageAspectFlags::COLOR,
                 1,
             )
-        })
-        .collect()
+        },
+        |view| unsafe { device.destroy_image_view(view, None) },
+    )
 }
 
 fn create_image_view(
@@ -1080,7 +4283,7 @@ fn create_image_view(
     format: vk::Format,
     aspect_mask: vk::ImageAspectFlags,
     mip_levels: u32,
-) -> vk::ImageView {
+) -> Result<vk::ImageView, RendererError> {
     let components = vk::ComponentMapping {
         r: vk::ComponentSwizzle::IDENTITY,
         g: vk::ComponentSwizzle::IDENTITY,
@@ -1088,38 +4291,182 @@ fn create_image_view(
         a: vk::ComponentSwizzle::IDENTITY,
     };
 
-    let subresource_range = vk::ImageSubresourceRange {
-        aspect_mask,
-        base_mip_level: 0,
-        level_count: mip_levels,
-        base_array_layer: 0,
-        layer_count: 1,
-    };
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask,
+        base_mip_level: 0,
+        level_count: mip_levels,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+
+    let create_info = vk::ImageViewCreateInfo {
+        s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+        view_type: vk::ImageViewType::TYPE_2D,
+        format,
+        components,
+        subresource_range,
+        image,
+        ..Default::default()
+    };
+
+    unsafe { device.create_image_view(&create_info, None) }
+        .map_err(resource_err("create image view"))
+}
+
+fn create_scene_render_pass(
+    device: &ash::Device,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    load_existing: bool,
+) -> Result<vk::RenderPass, RendererError> {
+    let msaa = samples != vk::SampleCountFlags::TYPE_1;
+
+    // The deferred path has already filled color and depth by the time this pass begins
+    let (load_op, color_initial_layout, depth_initial_layout) = if load_existing {
+        (
+            vk::AttachmentLoadOp::LOAD,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        )
+    } else {
+        (vk::AttachmentLoadOp::CLEAR, vk::ImageLayout::UNDEFINED, vk::ImageLayout::UNDEFINED)
+    };
+
+    // With MSAA the color attachment is only an intermediate that gets resolved to the HDR target
+    let color_attachment = vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format: HDR_FORMAT,
+        samples,
+        load_op,
+        store_op: if msaa {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        },
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: color_initial_layout,
+        final_layout: if msaa {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        },
+    };
+
+    let depth_attachment = vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format: depth_format,
+        samples,
+        load_op,
+        store_op: vk::AttachmentStoreOp::DONT_CARE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: depth_initial_layout,
+        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let resolve_attachment = vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format: HDR_FORMAT,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::DONT_CARE,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    };
+
+    let color_attachment_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    };
+
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let resolve_attachment_ref = vk::AttachmentReference {
+        attachment: 2,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    };
+
+    let attachments = [color_attachment, depth_attachment, resolve_attachment];
+    let attachment_count = if msaa { 3 } else { 2 };
+
+    let subpass = vk::SubpassDescription {
+        pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+        color_attachment_count: 1,
+        p_color_attachments: &color_attachment_ref,
+        p_depth_stencil_attachment: &depth_attachment_ref,
+        p_resolve_attachments: if msaa { &resolve_attachment_ref } else { ptr::null() },
+        ..Default::default()
+    };
+
+    let attachment_stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS;
+
+    let attachment_writes =
+        vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+
+    // The HDR target may still be sampled by the previous frame, or be written by the deferred
+    // lighting pass
+    let subpass_dependencies = [
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: attachment_stages | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: if load_existing {
+                attachment_writes
+            } else {
+                vk::AccessFlags::empty()
+            },
+            dst_stage_mask: attachment_stages,
+            dst_access_mask: if load_existing {
+                attachment_writes
+                    | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            } else {
+                attachment_writes
+            },
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+        sampled_output_dependency(),
+    ];
 
-    let create_info = vk::ImageViewCreateInfo {
-        s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
-        view_type: vk::ImageViewType::TYPE_2D,
-        format,
-        components,
-        subresource_range,
-        image,
+    let create_info = vk::RenderPassCreateInfo {
+        s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
+        attachment_count,
+        p_attachments: attachments.as_ptr(),
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        dependency_count: subpass_dependencies.len() as u32,
+        p_dependencies: subpass_dependencies.as_ptr(),
         ..Default::default()
     };
 
-    unsafe { device.create_image_view(&create_info, None) }.check_err("create image view")
+    unsafe { device.create_render_pass(&create_info, None) }
+        .map_err(resource_err("create render pass"))
 }
 
-fn create_render_pass(device: &ash::Device, swapchain_format: vk::Format) -> vk::RenderPass {
+// Render pass of a single fullscreen draw that overwrites every pixel, for post effects and
+// presenting. Its output ends up in final_layout
+fn create_fullscreen_render_pass(
+    device: &ash::Device,
+    format: vk::Format,
+    final_layout: vk::ImageLayout,
+) -> Result<vk::RenderPass, RendererError> {
     let color_attachment = vk::AttachmentDescription {
         flags: vk::AttachmentDescriptionFlags::empty(),
-        format: swapchain_format,
+        format,
         samples: vk::SampleCountFlags::TYPE_1,
-        load_op: vk::AttachmentLoadOp::CLEAR,
+        load_op: vk::AttachmentLoadOp::DONT_CARE,
         store_op: vk::AttachmentStoreOp::STORE,
         stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
         stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
         initial_layout: vk::ImageLayout::UNDEFINED,
-        final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        final_layout,
     };
 
     let color_attachment_ref = vk::AttachmentReference {
@@ -1134,15 +4481,20 @@ fn create_render_pass(device: &ash::Device, swapchain_format: vk::Format) -> vk:
         ..Default::default()
     };
 
-    let subpass_dependency = vk::SubpassDependency {
-        src_subpass: vk::SUBPASS_EXTERNAL,
-        dst_subpass: 0,
-        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-        src_access_mask: vk::AccessFlags::empty(),
-        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-        dependency_flags: vk::DependencyFlags::empty(),
-    };
+    // Waits for the swapchain image to be acquired, or for earlier reads of the target to finish
+    let subpass_dependencies = [
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+        sampled_output_dependency(),
+    ];
 
     let create_info = vk::RenderPassCreateInfo {
         s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
@@ -1150,72 +4502,228 @@ fn create_render_pass(device: &ash::Device, swapchain_format: vk::Format) -> vk:
         p_attachments: &color_attachment,
         subpass_count: 1,
         p_subpasses: &subpass,
-        dependency_count: 1,
-        p_dependencies: &subpass_dependency,
+        dependency_count: subpass_dependencies.len() as u32,
+        p_dependencies: subpass_dependencies.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe { device.create_render_pass(&create_info, None) }
+        .map_err(resource_err("create render pass"))
+}
+
+// Makes color written by a render pass visible to fragment shaders of the ones after it
+fn sampled_output_dependency() -> vk::SubpassDependency {
+    vk::SubpassDependency {
+        src_subpass: 0,
+        dst_subpass: vk::SUBPASS_EXTERNAL,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+        dst_access_mask: vk::AccessFlags::SHADER_READ,
+        dependency_flags: vk::DependencyFlags::empty(),
+    }
+}
+
+fn choose_sample_count(
+    limits: &vk::PhysicalDeviceLimits,
+    requested: Option<u32>,
+) -> vk::SampleCountFlags {
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    let max = requested.unwrap_or(u32::MAX);
+
+    // Flag values are equal to the sample counts they stand for
+    for count in [64, 32, 16, 8, 4, 2] {
+        let flag = vk::SampleCountFlags::from_raw(count);
+
+        if count <= max && supported.contains(flag) {
+            return flag;
+        }
+    }
+
+    vk::SampleCountFlags::TYPE_1
+}
+
+unsafe fn choose_anisotropy(info: &PhysDeviceInfo, requested: f32) -> Option<f32> {
+    if info.features.sampler_anisotropy == vk::FALSE || requested <= 1.0 {
+        return None;
+    }
+
+    Some(requested.min(info.properties.limits.max_sampler_anisotropy))
+}
+
+// Reversed-Z only evens out precision over distance with a float format, the others are fallbacks
+fn choose_depth_format(instance: &ash::Instance, phys_device: vk::PhysicalDevice) -> vk::Format {
+    let candidates = [
+        vk::Format::D32_SFLOAT,
+        vk::Format::D32_SFLOAT_S8_UINT,
+        vk::Format::D24_UNORM_S8_UINT,
+        vk::Format::D16_UNORM,
+    ];
+
+    candidates
+        .into_iter()
+        .find(|&format| {
+            instance
+                .get_physical_device_format_properties(phys_device, format)
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .check_err("find supported depth format")
+}
+
+unsafe fn create_image(
+    device: &ash::Device,
+    device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
+    usage: vk::ImageUsageFlags,
+) -> Result<(vk::Image, vk::DeviceMemory), RendererError> {
+    let create_info = vk::ImageCreateInfo {
+        s_type: vk::StructureType::IMAGE_CREATE_INFO,
+        image_type: vk::ImageType::TYPE_2D,
+        format,
+        extent: vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        },
+        mip_levels,
+        array_layers: 1,
+        samples,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
         ..Default::default()
     };
 
-    unsafe { device.create_render_pass(&create_info, None) }.check_err("create render pass")
+    create_image_with_info(device, device_mem_properties, &create_info)
+}
+
+// Device-local memory is allocated and bound to the image
+unsafe fn create_image_with_info(
+    device: &ash::Device,
+    device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+    create_info: &vk::ImageCreateInfo,
+) -> Result<(vk::Image, vk::DeviceMemory), RendererError> {
+    let image = device.create_image(create_info, None).map_err(resource_err("create image"))?;
+
+    let mem_requirements = device.get_image_memory_requirements(image);
+
+    let memory = match allocate_memory(
+        device,
+        device_mem_properties,
+        mem_requirements,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    ) {
+        Ok(memory) => memory,
+        Err(e) => {
+            device.destroy_image(image, None);
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = device.bind_image_memory(image, memory, 0) {
+        device.destroy_image(image, None);
+        device.free_memory(memory, None);
+
+        return Err(RendererError::Resource("bind image memory", e));
+    }
+
+    Ok((image, memory))
 }
 
 fn create_pipeline_layout(
     device: &ash::Device,
     push_const_range: Option<&vk::PushConstantRange>,
-    desc_set_layout: Option<&vk::DescriptorSetLayout>,
-) -> vk::PipelineLayout {
+    desc_set_layouts: &[vk::DescriptorSetLayout],
+) -> Result<vk::PipelineLayout, RendererError> {
     let (push_constant_range_count, p_push_constant_ranges) = match push_const_range {
         Some(range) => (1, range as *const vk::PushConstantRange),
         None => (0, ptr::null()),
     };
 
-    let (set_layout_count, p_set_layouts) = match desc_set_layout {
-        Some(set_layout) => (1, set_layout as *const vk::DescriptorSetLayout),
-        None => (0, ptr::null()),
-    };
-
     let create_info = vk::PipelineLayoutCreateInfo {
         s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
         push_constant_range_count,
         p_push_constant_ranges,
-        set_layout_count,
-        p_set_layouts,
+        set_layout_count: desc_set_layouts.len() as u32,
+        p_set_layouts: desc_set_layouts.as_ptr(),
         ..Default::default()
     };
 
-    unsafe { device.create_pipeline_layout(&create_info, None) }.check_err("create pipeline layout")
+    unsafe { device.create_pipeline_layout(&create_info, None) }
+        .map_err(resource_err("create pipeline layout"))
 }
 
-fn create_desc_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
-    let binding = vk::DescriptorSetLayoutBinding {
-        binding: 0,
-        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-        descriptor_count: 1,
-        stage_flags: vk::ShaderStageFlags::VERTEX,
-        p_immutable_samplers: ptr::null(),
-    };
+// Camera matrices, then lights and shadow maps for lit.frag
+fn create_desc_set_layout(device: &ash::Device) -> Result<vk::DescriptorSetLayout, RendererError> {
+    let bindings = [
+        vk::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            p_immutable_samplers: ptr::null(),
+        },
+        vk::DescriptorSetLayoutBinding {
+            binding: 1,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            p_immutable_samplers: ptr::null(),
+        },
+        vk::DescriptorSetLayoutBinding {
+            binding: 2,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: MAX_SHADOW_LIGHTS as u32,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            p_immutable_samplers: ptr::null(),
+        },
+    ];
 
     let create_info = vk::DescriptorSetLayoutCreateInfo {
         s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
-        binding_count: 1,
-        p_bindings: &binding,
+        binding_count: bindings.len() as u32,
+        p_bindings: bindings.as_ptr(),
         ..Default::default()
     };
 
     unsafe { device.create_descriptor_set_layout(&create_info, None) }
-        .check_err("create descriptor set layout")
+        .map_err(resource_err("create descriptor set layout"))
 }
 
 fn create_graphics_pipeline(
     device: &ash::Device,
+    vertex_layout: VertexLayout,
     vert_shader_compiled: &[u8],
     frag_shader_compiled: &[u8],
     topology: vk::PrimitiveTopology,
-    extent: vk::Extent2D,
+    pipeline_cache: vk::PipelineCache,
     render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+    color_attachments: u32,
+    depth_test_only: bool,
     pipeline_layout: vk::PipelineLayout,
-) -> vk::Pipeline {
-    let vert_shader_mod = create_shader_module(device, vert_shader_compiled);
-    let frag_shader_mod = create_shader_module(device, frag_shader_compiled);
+    push_const_range: Option<&vk::PushConstantRange>,
+) -> Result<vk::Pipeline, RendererError> {
+    let vert_shader_code = pack_to_u32s(vert_shader_compiled);
+    let frag_shader_code = pack_to_u32s(frag_shader_compiled);
+
+    validate_push_consts(&vert_shader_code, vk::ShaderStageFlags::VERTEX, push_const_range);
+    validate_push_consts(&frag_shader_code, vk::ShaderStageFlags::FRAGMENT, push_const_range);
+
+    let vert_shader_mod = create_shader_module(device, &vert_shader_code)?;
+    let frag_shader_mod = match create_shader_module(device, &frag_shader_code) {
+        Ok(module) => module,
+        Err(e) => {
+            unsafe { device.destroy_shader_module(vert_shader_mod, None) };
+            return Err(e);
+        }
+    };
 
     let entrypoint_name = CString::new("main").unwrap();
 
@@ -1237,27 +4745,15 @@ fn create_graphics_pipeline(
 
     let shader_stages = [vert_shader_stage, frag_shader_stage];
 
-    let size_f32 = size_of::<f32>() as u32;
-
-    let binding_desc = vk::VertexInputBindingDescription {
-        binding: 0,
-        stride: size_f32 * 2,
-        input_rate: vk::VertexInputRate::VERTEX,
-    };
-
-    let attribute_desc = vk::VertexInputAttributeDescription {
-        binding: 0,
-        location: 0,
-        format: vk::Format::R32G32_SFLOAT,
-        offset: 0,
-    };
+    let binding_desc = vertex_layout.binding_desc();
+    let attribute_descs = vertex_layout.attribute_descs();
 
     let vertex_input_state = vk::PipelineVertexInputStateCreateInfo {
         s_type: vk::StructureType::PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
         vertex_binding_description_count: 1,
         p_vertex_binding_descriptions: &binding_desc,
-        vertex_attribute_description_count: 1,
-        p_vertex_attribute_descriptions: &attribute_desc,
+        vertex_attribute_description_count: attribute_descs.len() as u32,
+        p_vertex_attribute_descriptions: attribute_descs.as_ptr(),
         ..Default::default()
     };
 
@@ -1268,26 +4764,20 @@ fn create_graphics_pipeline(
         ..Default::default()
     };
 
-    let viewport = vk::Viewport {
-        x: 0.0,
-        y: 0.0,
-        width: extent.width as f32,
-        height: extent.height as f32,
-        min_depth: 0.0,
-        max_depth: 1.0,
-    };
-
-    let scissor = vk::Rect2D {
-        offset: vk::Offset2D { x: 0, y: 0 },
-        extent,
-    };
-
+    // Viewport and scissor are set when recording, so pipelines survive swapchain recreation
     let viewport_state = vk::PipelineViewportStateCreateInfo {
         s_type: vk::StructureType::PIPELINE_VIEWPORT_STATE_CREATE_INFO,
         viewport_count: 1,
-        p_viewports: &viewport,
         scissor_count: 1,
-        p_scissors: &scissor,
+        ..Default::default()
+    };
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo {
+        s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+        dynamic_state_count: dynamic_states.len() as u32,
+        p_dynamic_states: dynamic_states.as_ptr(),
         ..Default::default()
     };
 
@@ -1296,7 +4786,11 @@ fn create_graphics_pipeline(
         depth_clamp_enable: vk::FALSE,
         rasterizer_discard_enable: vk::FALSE,
         polygon_mode: vk::PolygonMode::FILL,
-        cull_mode: vk::CullModeFlags::BACK,
+        cull_mode: if depth_test_only {
+            vk::CullModeFlags::NONE
+        } else {
+            vk::CullModeFlags::BACK
+        },
         front_face: vk::FrontFace::COUNTER_CLOCKWISE,
         depth_bias_enable: vk::FALSE,
         line_width: 1.0,
@@ -1305,7 +4799,7 @@ fn create_graphics_pipeline(
 
     let multisample_state = vk::PipelineMultisampleStateCreateInfo {
         s_type: vk::StructureType::PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
-        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        rasterization_samples: samples,
         sample_shading_enable: vk::FALSE,
         min_sample_shading: 0.0,
         p_sample_mask: ptr::null(),
@@ -1326,9 +4820,9 @@ fn create_graphics_pipeline(
 
     let depth_state = vk::PipelineDepthStencilStateCreateInfo {
         s_type: vk::StructureType::PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
-        depth_test_enable: vk::FALSE,
-        depth_write_enable: vk::FALSE,
-        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        depth_test_enable: vk::TRUE,
+        depth_write_enable: if depth_test_only { vk::FALSE } else { vk::TRUE },
+        depth_compare_op: vk::CompareOp::GREATER_OR_EQUAL,
         depth_bounds_test_enable: vk::FALSE,
         stencil_test_enable: vk::FALSE,
         front: stencil_state,
@@ -1338,17 +4832,24 @@ fn create_graphics_pipeline(
         ..Default::default()
     };
 
-    let color_blend_attachment = vk::PipelineColorBlendAttachmentState {
-        blend_enable: vk::FALSE,
-        color_write_mask: vk::ColorComponentFlags::RGBA,
-        ..Default::default()
-    };
+    let color_blend_attachments = vec![
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::FALSE,
+            color_write_mask: if depth_test_only {
+                vk::ColorComponentFlags::empty()
+            } else {
+                vk::ColorComponentFlags::RGBA
+            },
+            ..Default::default()
+        };
+        color_attachments as usize
+    ];
 
     let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
         s_type: vk::StructureType::PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
         logic_op_enable: vk::FALSE,
-        attachment_count: 1,
-        p_attachments: &color_blend_attachment,
+        attachment_count: color_attachments,
+        p_attachments: color_blend_attachments.as_ptr(),
         ..Default::default()
     };
 
@@ -1366,7 +4867,7 @@ fn create_graphics_pipeline(
         p_multisample_state: &multisample_state,
         p_depth_stencil_state: &depth_state,
         p_color_blend_state: &color_blend_state,
-        p_dynamic_state: ptr::null(),
+        p_dynamic_state: &dynamic_state,
         layout: pipeline_layout,
         render_pass,
         subpass: 0,
@@ -1375,7 +4876,7 @@ fn create_graphics_pipeline(
     }];
 
     let graphics_pipelines =
-        unsafe { device.create_graphics_pipelines(vk::PipelineCache::null(), &create_info, None) };
+        unsafe { device.create_graphics_pipelines(pipeline_cache, &create_info, None) };
 
     unsafe {
         device.destroy_shader_module(vert_shader_mod, None);
@@ -1383,22 +4884,90 @@ fn create_graphics_pipeline(
     }
 
     match graphics_pipelines {
-        Ok(pipelines) => pipelines[0],
-        Err((_pipelines, err)) => panic!("failed to create pipeline: {}", err),
+        Ok(pipelines) => Ok(pipelines[0]),
+        Err((_pipelines, err)) => Err(RendererError::Resource("create pipeline", err)),
     }
 }
 
-fn create_shader_module(device: &ash::Device, code: &[u8]) -> vk::ShaderModule {
-    let transmuted_copy = pack_to_u32s(code);
-
+fn create_shader_module(
+    device: &ash::Device,
+    code: &[u32],
+) -> Result<vk::ShaderModule, RendererError> {
     let create_info = vk::ShaderModuleCreateInfo {
         s_type: vk::StructureType::SHADER_MODULE_CREATE_INFO,
-        code_size: code.len(),
-        p_code: transmuted_copy.as_ptr(),
+        code_size: code.len() * size_of::<u32>(),
+        p_code: code.as_ptr(),
         ..Default::default()
     };
 
-    unsafe { device.create_shader_module(&create_info, None) }.check_err("create shader module")
+    unsafe { device.create_shader_module(&create_info, None) }
+        .map_err(resource_err("create shader module"))
+}
+
+fn validate_push_consts(
+    code: &[u32],
+    stage: vk::ShaderStageFlags,
+    push_const_range: Option<&vk::PushConstantRange>,
+) {
+    let block_size = match reflect::push_constant_block_size(code) {
+        Some(size) => size,
+        None => return,
+    };
+
+    let range = push_const_range.check_err("find push constant range for shader");
+
+    assert!(
+        range.stage_flags.contains(stage),
+        "Push constant range is not visible to {:?} shader stage",
+        stage
+    );
+
+    assert!(
+        block_size <= range.offset + range.size,
+        "Push constant block of {:?} shader ({} bytes) doesn't fit in range of {} bytes",
+        stage,
+        block_size,
+        range.size
+    );
+}
+
+// Built-in shaders that were reloaded at runtime take precedence over the ones embedded in the
+// binary
+fn shader_code<'a>(
+    reloaded_shaders: &'a HashMap<String, Vec<u8>>,
+    name: Option<&str>,
+    source: ShaderSource<'a>,
+    stage: ShaderStage,
+) -> Result<Cow<'a, [u8]>, String> {
+    match name.and_then(|name| reloaded_shaders.get(name)) {
+        Some(code) => Ok(Cow::Borrowed(code)),
+        None => source.to_spirv(stage),
+    }
+}
+
+// Embedded shaders are compiled by the build, so only GLSL sources can fail
+fn builtin_shader_code<'a>(
+    reloaded_shaders: &'a HashMap<String, Vec<u8>>,
+    name: &str,
+    code: &'static [u8],
+) -> &'a [u8] {
+    reloaded_shaders.get(name).map_or(code, Vec::as_slice)
+}
+
+fn error_shader_code(
+    reloaded_shaders: &HashMap<String, Vec<u8>>,
+    stage: ShaderStage,
+) -> Cow<'_, [u8]> {
+    let code = match stage {
+        ShaderStage::Vertex => {
+            builtin_shader_code(reloaded_shaders, ERROR_SHADERS[0], include_shader!("error.vert"))
+        }
+        ShaderStage::Fragment => {
+            builtin_shader_code(reloaded_shaders, ERROR_SHADERS[1], include_shader!("error.frag"))
+        }
+    };
+
+    Cow::Borrowed(code)
 }
 
 fn pack_to_u32s(bytes: &[u8]) -> Vec<u32> {
@@ -1413,75 +4982,39 @@ fn pack_to_u32s(bytes: &[u8]) -> Vec<u32> {
         .collect()
 }
 
+// One framebuffer for each image, for render passes with a single color attachment
 fn create_framebuffers(
     device: &ash::Device,
     image_views: &[vk::ImageView],
     extent: vk::Extent2D,
     render_pass: vk::RenderPass,
-) -> Vec<vk::Framebuffer> {
-    let mut framebuffers = Vec::with_capacity(image_views.len());
-
-    for image_view in image_views {
-        let create_info = vk::FramebufferCreateInfo {
-            s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
-            render_pass,
-            attachment_count: 1,
-            p_attachments: image_view,
-            width: extent.width,
-            height: extent.height,
-            layers: 1,
-            ..Default::default()
-        };
-
-        let framebuffer = unsafe { device.create_framebuffer(&create_info, None) }
-            .check_err("create framebuffer");
-
-        framebuffers.push(framebuffer);
-    }
-
-    framebuffers
+) -> Result<Vec<vk::Framebuffer>, RendererError> {
+    create_each(
+        image_views,
+        |&image_view| create_framebuffer(device, &[image_view], extent, render_pass),
+        |framebuffer| unsafe { device.destroy_framebuffer(framebuffer, None) },
+    )
 }
 
-fn create_buffer_of_type<T: Copy>(
+fn create_framebuffer(
     device: &ash::Device,
-    device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
-    command_pool: vk::CommandPool,
-    queue: vk::Queue,
-    usage: vk::BufferUsageFlags,
-    data: &[T],
-) -> (vk::Buffer, vk::DeviceMemory) {
-    let size_bytes: u64 = (data.len() * size_of::<T>()).try_into().unwrap();
-
-    let (staging_buffer, staging_memory) = unsafe {
-        create_buffer(
-            device,
-            device_mem_properties,
-            size_bytes,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE,
-        )
-    };
-
-    upload_to_buffer_memory(device, staging_memory, data);
-
-    let (buffer, memory) = unsafe {
-        create_buffer(
-            device,
-            device_mem_properties,
-            size_bytes,
-            usage | vk::BufferUsageFlags::TRANSFER_DST,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        )
+    attachments: &[vk::ImageView],
+    extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Framebuffer, RendererError> {
+    let create_info = vk::FramebufferCreateInfo {
+        s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
+        render_pass,
+        attachment_count: attachments.len() as u32,
+        p_attachments: attachments.as_ptr(),
+        width: extent.width,
+        height: extent.height,
+        layers: 1,
+        ..Default::default()
     };
 
-    copy_buffers(device, command_pool, queue, staging_buffer, buffer, size_bytes);
-
-    unsafe {
-        device.destroy_buffer(staging_buffer, None);
-        device.free_memory(staging_memory, None);
-    }
-
-    (buffer, memory)
+    unsafe { device.create_framebuffer(&create_info, None) }
+        .map_err(resource_err("create framebuffer"))
 }
 
 unsafe fn create_buffer(
@@ -1490,7 +5023,7 @@ unsafe fn create_buffer(
     size: u64,
     usage: vk::BufferUsageFlags,
     properties: vk::MemoryPropertyFlags,
-) -> (vk::Buffer, vk::DeviceMemory) {
+) -> Result<(vk::Buffer, vk::DeviceMemory), RendererError> {
     let create_info = vk::BufferCreateInfo {
         s_type: vk::StructureType::BUFFER_CREATE_INFO,
         size,
@@ -1499,26 +5032,49 @@ unsafe fn create_buffer(
         ..Default::default()
     };
 
-    let buffer = device.create_buffer(&create_info, None).check_err("create buffer");
+    let buffer = device.create_buffer(&create_info, None).map_err(resource_err("create buffer"))?;
 
     let mem_requirements = device.get_buffer_memory_requirements(buffer);
 
+    let memory = match allocate_memory(device, device_mem_properties, mem_requirements, properties)
+    {
+        Ok(memory) => memory,
+        Err(e) => {
+            device.destroy_buffer(buffer, None);
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = device.bind_buffer_memory(buffer, memory, 0) {
+        device.destroy_buffer(buffer, None);
+        device.free_memory(memory, None);
+
+        return Err(RendererError::Resource("bind buffer memory", e));
+    }
+
+    Ok((buffer, memory))
+}
+
+unsafe fn allocate_memory(
+    device: &ash::Device,
+    device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+    requirements: vk::MemoryRequirements,
+    properties: vk::MemoryPropertyFlags,
+) -> Result<vk::DeviceMemory, RendererError> {
+    // No memory type with the properties counts as running out of it
     let memory_type_index =
-        find_memory_type(mem_requirements.memory_type_bits, properties, device_mem_properties)
-            .check_err("find appropriate memory type");
+        find_memory_type(requirements.memory_type_bits, properties, device_mem_properties).ok_or(
+            RendererError::Resource("find memory type", vk::Result::ERROR_OUT_OF_DEVICE_MEMORY),
+        )?;
 
     let alloc_info = vk::MemoryAllocateInfo {
         s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
-        allocation_size: mem_requirements.size,
+        allocation_size: requirements.size,
         memory_type_index,
         ..Default::default()
     };
 
-    let memory = device.allocate_memory(&alloc_info, None).check_err("allocate buffer memory");
-
-    device.bind_buffer_memory(buffer, memory, 0).check_err("bind buffer");
-
-    (buffer, memory)
+    device.allocate_memory(&alloc_info, None).map_err(resource_err("allocate memory"))
 }
 
 fn find_memory_type(
@@ -1541,7 +5097,11 @@ fn find_memory_type(
     None
 }
 
-fn upload_to_buffer_memory<T: Copy>(device: &ash::Device, memory: vk::DeviceMemory, data: &[T]) {
+fn upload_to_buffer_memory<T: Copy>(
+    device: &ash::Device,
+    memory: vk::DeviceMemory,
+    data: &[T],
+) -> Result<(), RendererError> {
     let size_bytes: u64 = (data.len() * size_of::<T>()).try_into().unwrap();
 
     let memory_range = vk::MappedMemoryRange {
@@ -1555,26 +5115,24 @@ fn upload_to_buffer_memory<T: Copy>(device: &ash::Device, memory: vk::DeviceMemo
     unsafe {
         let out_ptr = device
             .map_memory(memory, 0, size_bytes, vk::MemoryMapFlags::empty())
-            .check_err("map memory")
+            .map_err(resource_err("map memory"))?
             .cast::<T>();
 
         out_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
 
-        device.flush_mapped_memory_ranges(&[memory_range]).check_err("flush mapped memory");
+        let flushed = device.flush_mapped_memory_ranges(&[memory_range]);
 
         device.unmap_memory(memory);
+
+        flushed.map_err(resource_err("flush mapped memory"))
     }
 }
 
-fn copy_buffers(
+fn begin_one_time_commands(
     device: &ash::Device,
     command_pool: vk::CommandPool,
-    queue: vk::Queue,
-    src: vk::Buffer,
-    dst: vk::Buffer,
-    size: u64,
-) {
-    let cmd_buffer = create_command_buffers(device, command_pool, 1)[0];
+) -> Result<vk::CommandBuffer, RendererError> {
+    let cmd_buffer = create_command_buffers(device, command_pool, 1)?[0];
 
     let begin_info = vk::CommandBufferBeginInfo {
         s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
@@ -1582,11 +5140,23 @@ fn copy_buffers(
         ..Default::default()
     };
 
-    let copy_region = vk::BufferCopy {
-        size,
-        ..Default::default()
-    };
+    unsafe {
+        if let Err(e) = device.begin_command_buffer(cmd_buffer, &begin_info) {
+            device.free_command_buffers(command_pool, &[cmd_buffer]);
+
+            return Err(RendererError::Resource("begin command buffer", e));
+        }
+    }
+
+    Ok(cmd_buffer)
+}
 
+fn end_one_time_commands(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    cmd_buffer: vk::CommandBuffer,
+) -> Result<(), RendererError> {
     let submit_info = vk::SubmitInfo {
         s_type: vk::StructureType::SUBMIT_INFO,
         command_buffer_count: 1,
@@ -1595,152 +5165,272 @@ fn copy_buffers(
     };
 
     unsafe {
-        device.begin_command_buffer(cmd_buffer, &begin_info).check_err("begin cmd buffer");
-
-        device.cmd_copy_buffer(cmd_buffer, src, dst, &[copy_region]);
-
-        device.end_command_buffer(cmd_buffer).check_err("end cmd buffer");
-
-        device.queue_submit(queue, &[submit_info], vk::Fence::null()).check_err("submit to queue");
-
-        device.queue_wait_idle(queue).check_err("wait for queue");
+        let result = device
+            .end_command_buffer(cmd_buffer)
+            .map_err(resource_err("end command buffer"))
+            .and_then(|_| {
+                device
+                    .queue_submit(queue, &[submit_info], vk::Fence::null())
+                    .map_err(resource_err("submit to queue"))
+            })
+            .and_then(|_| device.queue_wait_idle(queue).map_err(resource_err("wait for queue")));
 
         device.free_command_buffers(command_pool, &[cmd_buffer]);
+
+        result
     }
 }
 
-fn create_uniform_buffers(
+// One mapped buffer for each frame in flight
+fn create_uniform_buffers<T>(
     device: &ash::Device,
     device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
-) -> (Vec<vk::Buffer>, Vec<vk::DeviceMemory>, Vec<*mut UniformBufferObject>) {
-    let mut uniform_buffers = Vec::with_capacity(FRAMES_IN_FLIGHT);
-    let mut uniform_buffers_memories = Vec::with_capacity(FRAMES_IN_FLIGHT);
-    let mut uniform_buffers_mappings = Vec::with_capacity(FRAMES_IN_FLIGHT);
+    frames_in_flight: usize,
+) -> Result<(Vec<vk::Buffer>, Vec<vk::DeviceMemory>, Vec<*mut T>), RendererError> {
+    let buf_size = size_of::<T>() as u64;
 
-    let buf_size = size_of::<UniformBufferObject>() as u64;
-
-    for _ in 0..FRAMES_IN_FLIGHT {
-        unsafe {
+    let created = create_each(
+        0..frames_in_flight,
+        |_| unsafe {
             let (buffer, memory) = create_buffer(
                 device,
                 device_mem_properties,
                 buf_size,
                 vk::BufferUsageFlags::UNIFORM_BUFFER,
                 vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            );
-            let mapping = device
-                .map_memory(memory, 0, buf_size, vk::MemoryMapFlags::empty())
-                .check_err("map memory")
-                .cast::<UniformBufferObject>();
+            )?;
 
-            uniform_buffers.push(buffer);
-            uniform_buffers_memories.push(memory);
-            uniform_buffers_mappings.push(mapping);
-        }
+            match device.map_memory(memory, 0, buf_size, vk::MemoryMapFlags::empty()) {
+                Ok(mapping) => Ok((buffer, memory, mapping.cast::<T>())),
+                Err(e) => {
+                    device.destroy_buffer(buffer, None);
+                    device.free_memory(memory, None);
+
+                    Err(RendererError::Resource("map uniform buffer memory", e))
+                }
+            }
+        },
+        |(buffer, memory, _)| unsafe {
+            device.destroy_buffer(buffer, None);
+            device.free_memory(memory, None);
+        },
+    )?;
+
+    let mut uniform_buffers = Vec::with_capacity(frames_in_flight);
+    let mut uniform_buffers_memories = Vec::with_capacity(frames_in_flight);
+    let mut uniform_buffers_mappings = Vec::with_capacity(frames_in_flight);
+
+    for (buffer, memory, mapping) in created {
+        uniform_buffers.push(buffer);
+        uniform_buffers_memories.push(memory);
+        uniform_buffers_mappings.push(mapping);
     }
 
-    (uniform_buffers, uniform_buffers_memories, uniform_buffers_mappings)
+    Ok((uniform_buffers, uniform_buffers_memories, uniform_buffers_mappings))
 }
 
-fn create_desc_pool(device: &ash::Device) -> vk::DescriptorPool {
-    let pool_size = vk::DescriptorPoolSize {
-        ty: vk::DescriptorType::UNIFORM_BUFFER,
-        descriptor_count: FRAMES_IN_FLIGHT as u32,
-    };
+fn create_desc_pool(
+    device: &ash::Device,
+    frames_in_flight: usize,
+) -> Result<vk::DescriptorPool, RendererError> {
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 2 * frames_in_flight as u32,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: (MAX_SHADOW_LIGHTS * frames_in_flight) as u32,
+        },
+    ];
 
     let create_info = vk::DescriptorPoolCreateInfo {
         s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
-        max_sets: FRAMES_IN_FLIGHT as u32,
-        pool_size_count: 1,
-        p_pool_sizes: &pool_size,
+        max_sets: frames_in_flight as u32,
+        pool_size_count: pool_sizes.len() as u32,
+        p_pool_sizes: pool_sizes.as_ptr(),
         ..Default::default()
     };
 
-    unsafe { device.create_descriptor_pool(&create_info, None) }.check_err("create descriptor pool")
+    unsafe { device.create_descriptor_pool(&create_info, None) }
+        .map_err(resource_err("create descriptor pool"))
 }
 
 fn create_desc_sets(
     device: &ash::Device,
     desc_set_layout: vk::DescriptorSetLayout,
     descriptor_pool: vk::DescriptorPool,
-) -> Vec<vk::DescriptorSet> {
-    let mut layouts = Vec::with_capacity(FRAMES_IN_FLIGHT);
-    layouts.resize(FRAMES_IN_FLIGHT, desc_set_layout);
+    frames_in_flight: usize,
+) -> Result<Vec<vk::DescriptorSet>, RendererError> {
+    let mut layouts = Vec::with_capacity(frames_in_flight);
+    layouts.resize(frames_in_flight, desc_set_layout);
 
     let alloc_info = vk::DescriptorSetAllocateInfo {
         s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
         descriptor_pool,
-        descriptor_set_count: FRAMES_IN_FLIGHT as u32,
+        descriptor_set_count: frames_in_flight as u32,
         p_set_layouts: layouts.as_ptr(),
         ..Default::default()
     };
 
-    unsafe { device.allocate_descriptor_sets(&alloc_info) }.check_err("allocate descriptor sets")
-}
-
-fn create_push_const_range<T>(stage_flags: vk::ShaderStageFlags) -> vk::PushConstantRange {
-    vk::PushConstantRange {
-        stage_flags,
-        offset: 0,
-        size: size_of::<T>().try_into().unwrap(),
-    }
+    unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .map_err(resource_err("allocate descriptor sets"))
 }
 
 fn fill_desc_sets(
     device: &ash::Device,
     uniform_buffers: &[vk::Buffer],
+    lights_buffers: &[vk::Buffer],
     desc_sets: &[vk::DescriptorSet],
 ) {
-    for i in 0..FRAMES_IN_FLIGHT {
+    for (i, &desc_set) in desc_sets.iter().enumerate() {
         let buffer_info = vk::DescriptorBufferInfo {
             buffer: uniform_buffers[i],
             offset: 0,
             range: size_of::<UniformBufferObject>() as u64,
         };
 
-        let desc_write = vk::WriteDescriptorSet {
-            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
-            dst_set: desc_sets[i],
-            dst_binding: 0,
-            dst_array_element: 0,
-            descriptor_count: 1,
-            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-            p_buffer_info: &buffer_info,
-            ..Default::default()
+        let lights_buffer_info = vk::DescriptorBufferInfo {
+            buffer: lights_buffers[i],
+            offset: 0,
+            range: size_of::<LightsUniform>() as u64,
         };
 
+        let desc_writes = [
+            vk::WriteDescriptorSet {
+                s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                dst_set: desc_set,
+                dst_binding: 0,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                p_buffer_info: &buffer_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                dst_set: desc_set,
+                dst_binding: 1,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                p_buffer_info: &lights_buffer_info,
+                ..Default::default()
+            },
+        ];
+
         unsafe {
-            device.update_descriptor_sets(&[desc_write], &[]);
+            device.update_descriptor_sets(&desc_writes, &[]);
         }
     }
 }
 
+// Slots without a shadow map get the placeholder. The sets must not be in use by any frame in
+// flight
+fn write_shadow_map_descs(
+    device: &ash::Device,
+    desc_sets: &[vk::DescriptorSet],
+    shadow_maps: &ShadowMaps,
+    placeholder: &ShadowMap,
+    sampler: vk::Sampler,
+) {
+    let image_infos: Vec<_> = (0..MAX_SHADOW_LIGHTS)
+        .map(|idx| {
+            let shadow_map = shadow_maps.get(ShadowLightHandle(idx)).unwrap_or(placeholder);
+
+            vk::DescriptorImageInfo {
+                sampler,
+                image_view: shadow_map.cube_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        })
+        .collect();
+
+    let desc_writes: Vec<_> = desc_sets
+        .iter()
+        .map(|&desc_set| vk::WriteDescriptorSet {
+            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+            dst_set: desc_set,
+            dst_binding: 2,
+            dst_array_element: 0,
+            descriptor_count: image_infos.len() as u32,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            p_image_info: image_infos.as_ptr(),
+            ..Default::default()
+        })
+        .collect();
+
+    unsafe {
+        device.update_descriptor_sets(&desc_writes, &[]);
+    }
+}
+
 fn create_sync_objects(
     device: &ash::Device,
-) -> (Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>) {
-    let mut image_available = Vec::with_capacity(FRAMES_IN_FLIGHT);
-    let mut render_finished = Vec::with_capacity(FRAMES_IN_FLIGHT);
-    let mut is_rendering = Vec::with_capacity(FRAMES_IN_FLIGHT);
+    frames_in_flight: usize,
+) -> Result<(Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>), RendererError> {
+    let destroy_semaphore = |sem| unsafe { device.destroy_semaphore(sem, None) };
+    let destroy_semaphores = |sems: Vec<_>| sems.into_iter().for_each(destroy_semaphore);
+
+    let image_available =
+        create_each(0..frames_in_flight, |_| create_semaphore(device), destroy_semaphore)?;
+
+    let render_finished =
+        match create_each(0..frames_in_flight, |_| create_semaphore(device), destroy_semaphore) {
+            Ok(sems) => sems,
+            Err(e) => {
+                destroy_semaphores(image_available);
+                return Err(e);
+            }
+        };
 
-    for _ in 0..FRAMES_IN_FLIGHT {
-        image_available.push(create_semaphore(device));
-        render_finished.push(create_semaphore(device));
-        is_rendering.push(create_fence(device, true));
+    let is_rendering = match create_each(
+        0..frames_in_flight,
+        |_| create_fence(device, true),
+        |fence| unsafe { device.destroy_fence(fence, None) },
+    ) {
+        Ok(fences) => fences,
+        Err(e) => {
+            destroy_semaphores(image_available);
+            destroy_semaphores(render_finished);
+            return Err(e);
+        }
+    };
+
+    Ok((image_available, render_finished, is_rendering))
+}
+
+// Creates an object for each item, destroying the ones created so far if one of them fails
+fn create_each<I, T>(
+    items: impl IntoIterator<Item = I>,
+    mut create: impl FnMut(I) -> Result<T, RendererError>,
+    destroy: impl FnMut(T),
+) -> Result<Vec<T>, RendererError> {
+    let mut created = Vec::new();
+
+    for item in items {
+        match create(item) {
+            Ok(object) => created.push(object),
+            Err(e) => {
+                created.into_iter().for_each(destroy);
+                return Err(e);
+            }
+        }
     }
 
-    (image_available, render_finished, is_rendering)
+    Ok(created)
 }
 
-fn create_semaphore(device: &ash::Device) -> vk::Semaphore {
+fn create_semaphore(device: &ash::Device) -> Result<vk::Semaphore, RendererError> {
     let create_info = vk::SemaphoreCreateInfo {
         s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
         ..Default::default()
     };
 
-    unsafe { device.create_semaphore(&create_info, None) }.check_err("create semaphore")
+    unsafe { device.create_semaphore(&create_info, None) }.map_err(resource_err("create semaphore"))
 }
 
-fn create_fence(device: &ash::Device, signaled: bool) -> vk::Fence {
+fn create_fence(device: &ash::Device, signaled: bool) -> Result<vk::Fence, RendererError> {
     let flags = if signaled {
         vk::FenceCreateFlags::SIGNALED
     } else {
@@ -1753,14 +5443,11 @@ fn create_fence(device: &ash::Device, signaled: bool) -> vk::Fence {
         ..Default::default()
     };
 
-    unsafe { device.create_fence(&create_info, None) }.check_err("create fence")
+    unsafe { device.create_fence(&create_info, None) }.map_err(resource_err("create fence"))
 }
 
 fn create_skybox_mesh() -> Mesh {
-    Mesh {
-        vertices: vec![-1.0, 1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0],
-        indices: vec![0, 1, 2, 2, 3, 0],
-    }
+    Mesh::pos2(&[-1.0, 1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0], vec![0, 1, 2, 2, 3, 0])
 }
 
 fn create_grid_mesh(res: f32, cells: usize) -> Mesh {
@@ -1799,13 +5486,43 @@ fn create_grid_mesh(res: f32, cells: usize) -> Mesh {
         x_off += res;
     }
 
-    Mesh { vertices, indices }
+    Mesh::pos2(&vertices, indices)
+}
+
+// Unit square with the bottom left corner at the origin, scaled and moved by its projection
+// Unit cube from 0 to 1, scaled to bounding boxes for occlusion queries. Its faces are drawn from
+// both sides, so winding doesn't matter
+fn create_occlusion_box_mesh() -> Mesh {
+    let vertices: Vec<Pos3Vertex> = (0..8)
+        .map(|corner| Pos3Vertex {
+            position: Vec3::new(
+                (corner & 1) as f32,
+                ((corner >> 1) & 1) as f32,
+                ((corner >> 2) & 1) as f32,
+            ),
+        })
+        .collect();
+
+    let indices = vec![
+        0, 2, 1, 1, 2, 3, // -Z
+        4, 5, 6, 5, 7, 6, // +Z
+        0, 1, 4, 1, 5, 4, // -Y
+        2, 6, 3, 3, 6, 7, // +Y
+        0, 4, 2, 2, 4, 6, // -X
+        1, 3, 5, 3, 7, 5, // +X
+    ];
+
+    Mesh::new(&vertices, indices)
+}
+
+fn create_hud_box_mesh() -> Mesh {
+    Mesh::pos2(&[0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0], vec![0, 1, 2, 2, 3, 0])
 }
 
-fn create_crosshair_mesh(length: f32, thickness: f32, window: &Window) -> Mesh {
-    // Center X and Y
-    let cx = window.width() as f32 / 2.0;
-    let cy = window.height() as f32 / 2.0;
+// Built around the origin, moved to the center of the screen by its projection
+fn create_crosshair_mesh(length: f32, thickness: f32) -> Mesh {
+    let cx = 0.0;
+    let cy = 0.0;
 
     let near = thickness;
     let far = near + length;
@@ -1837,5 +5554,5 @@ fn create_crosshair_mesh(length: f32, thickness: f32, window: &Window) -> Mesh {
         indices.push(i);
     }
 
-    Mesh { vertices, indices }
+    Mesh::pos2(&vertices, indices)
 }
@@ -0,0 +1,45 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::broadphase::Aabb;
+
+// How many scene meshes the last frame drew, and how many it skipped for being out of view or,
+// with occlusion culling, hidden behind others
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CullStats {
+    pub visible: usize,
+    pub culled: usize,
+    pub occluded: usize,
+}
+
+// Planes facing inwards, as normal and distance, so points inside are in front of all of them
+pub(super) struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Planes of the clip volume, which is 0 <= z <= w in Vulkan. That holds for reversed-Z as well,
+    // only with the near and far planes swapped, so they need no special case
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|idx| view_proj.row(idx));
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z],
+        }
+    }
+
+    // Conservative, boxes near the frustum's corners may pass without actually being in it
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+
+            // Corner furthest along the normal
+            let corner = Vec3::new(
+                if normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
@@ -0,0 +1,196 @@
+use std::borrow::Cow;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::{ptr, slice};
+
+use ash::extensions::ext::DebugUtils;
+use ash::vk;
+
+use super::{resource_err, CheckVkError, RendererError};
+
+// Least severe validation layer messages that get printed
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum ValidationSeverity {
+    Verbose,
+    Info,
+    #[default]
+    Warning,
+    Error,
+}
+
+// Object names and command buffer labels for tools like RenderDoc and the validation layers.
+// Everything is a no-op when the debug utils extension isn't available.
+pub(super) struct DebugMarkers {
+    loader: Option<DebugUtils>,
+    device: vk::Device,
+}
+
+impl DebugMarkers {
+    pub fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        device: &ash::Device,
+        enabled: bool,
+    ) -> Self {
+        Self {
+            loader: enabled.then(|| DebugUtils::new(entry, instance)),
+            device: device.handle(),
+        }
+    }
+
+    pub fn name<T: vk::Handle>(&self, object: T, name: &str) {
+        let loader = match &self.loader {
+            Some(loader) => loader,
+            None => return,
+        };
+
+        let name = CString::new(name).check_err("convert debug name to CString");
+
+        let name_info = vk::DebugUtilsObjectNameInfoEXT {
+            s_type: vk::StructureType::DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+            object_type: T::TYPE,
+            object_handle: object.as_raw(),
+            p_object_name: name.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe { loader.set_debug_utils_object_name(self.device, &name_info) }
+            .check_err("set debug object name");
+    }
+
+    pub fn begin_label(&self, cmd_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        let loader = match &self.loader {
+            Some(loader) => loader,
+            None => return,
+        };
+
+        let name = CString::new(name).check_err("convert debug label to CString");
+
+        let label = vk::DebugUtilsLabelEXT {
+            s_type: vk::StructureType::DEBUG_UTILS_LABEL_EXT,
+            p_label_name: name.as_ptr(),
+            color,
+            ..Default::default()
+        };
+
+        unsafe {
+            loader.cmd_begin_debug_utils_label(cmd_buffer, &label);
+        }
+    }
+
+    pub fn end_label(&self, cmd_buffer: vk::CommandBuffer) {
+        if let Some(loader) = &self.loader {
+            unsafe {
+                loader.cmd_end_debug_utils_label(cmd_buffer);
+            }
+        }
+    }
+}
+
+// Prints validation layer messages to stderr along with the names of the objects they're about.
+// Layers stop printing to stdout on their own once a messenger exists
+pub(super) struct DebugMessenger {
+    loader: DebugUtils,
+    messenger: vk::DebugUtilsMessengerEXT,
+}
+
+impl DebugMessenger {
+    pub fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        severity: ValidationSeverity,
+    ) -> Result<Self, RendererError> {
+        let loader = DebugUtils::new(entry, instance);
+        let create_info = messenger_create_info(severity);
+
+        let messenger = unsafe { loader.create_debug_utils_messenger(&create_info, None) }
+            .map_err(resource_err("create debug messenger"))?;
+
+        Ok(Self { loader, messenger })
+    }
+}
+
+impl Drop for DebugMessenger {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_debug_utils_messenger(self.messenger, None);
+        }
+    }
+}
+
+// Also chained to the instance create info, for messages from creating and destroying it
+pub(super) fn messenger_create_info(
+    severity: ValidationSeverity,
+) -> vk::DebugUtilsMessengerCreateInfoEXT {
+    let all = [
+        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+    ];
+
+    let message_severity = all[severity as usize..]
+        .iter()
+        .fold(vk::DebugUtilsMessageSeverityFlagsEXT::empty(), |acc, &flag| acc | flag);
+
+    vk::DebugUtilsMessengerCreateInfoEXT {
+        s_type: vk::StructureType::DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
+        message_severity,
+        message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+            | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+            | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        pfn_user_callback: Some(print_message),
+        p_user_data: ptr::null_mut(),
+        ..Default::default()
+    }
+}
+
+unsafe extern "system" fn print_message(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    _user_data: *mut c_void,
+) -> vk::Bool32 {
+    let data = &*data;
+
+    let severity = match severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => "error",
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "warning",
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "info",
+        _ => "verbose",
+    };
+
+    let kind = match message_type {
+        vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "validation",
+        vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "performance",
+        _ => "general",
+    };
+
+    let message = c_str_or_empty(data.p_message);
+    let id = c_str_or_empty(data.p_message_id_name);
+
+    eprintln!("Vulkan {} {} [{}]: {}", kind, severity, id, message);
+
+    if !data.p_objects.is_null() {
+        let objects = slice::from_raw_parts(data.p_objects, data.object_count as usize);
+
+        for object in objects.iter().filter(|object| !object.p_object_name.is_null()) {
+            eprintln!(
+                "    {:?} {:#x} \"{}\"",
+                object.object_type,
+                object.object_handle,
+                c_str_or_empty(object.p_object_name)
+            );
+        }
+    }
+
+    // Has to be false, true aborts the call that caused the message
+    vk::FALSE
+}
+
+unsafe fn c_str_or_empty<'a>(ptr: *const c_char) -> Cow<'a, str> {
+    if ptr.is_null() {
+        "".into()
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy()
+    }
+}
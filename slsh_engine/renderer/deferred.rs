@@ -0,0 +1,425 @@
+use std::ptr;
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec4};
+
+use super::debug::DebugMarkers;
+use super::material::MaterialData;
+use super::push_consts::PushConstants;
+use super::tonemap::HDR_FORMAT;
+use super::vertex::{Pos2Vertex, VertexLayout};
+use super::{
+    create_framebuffer, create_fullscreen_render_pass, resource_err, sampled_output_dependency,
+    PipelineDesc, RenderTarget, RendererError,
+};
+
+// Fullscreen quad and the lighting shader
+const LIGHTING_SHADERS: [&str; 2] = ["skybox.vert", "deferred_lighting.frag"];
+
+// Color attachments of the G-buffer pass, in the order of the shaders' outputs:
+//
+//     albedo     linear color, stored as sRGB to keep precision in the darks
+//     normal     world space
+//     depth      copy of the depth buffer, which can't be sampled while forward meshes are drawn
+//                against it
+//     material   metallic, roughness, occlusion and shading model: 0 for lit.frag's, 1 for PBR
+pub(super) const GBUFFER_FORMATS: [vk::Format; 4] = [
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R32_SFLOAT,
+    vk::Format::R8G8B8A8_UNORM,
+];
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(super) struct LightingPushConstants {
+    // For getting world positions back from depth
+    pub inv_view_proj: Mat4,
+    pub camera_position: Vec4,
+}
+
+// Lit and PBR meshes are drawn into the G-buffer first, then a single fullscreen pass lights all of
+// it and writes the result to the HDR target. The scene pass loads that along with the depth
+// buffer and draws everything else over it, so light count costs per pixel rather than per mesh
+pub(super) struct DeferredPath {
+    device: ash::Device,
+    pub gbuffer_render_pass: vk::RenderPass,
+    pub lighting_render_pass: vk::RenderPass,
+    pub desc_set_layout: vk::DescriptorSetLayout,
+    sampler: vk::Sampler,
+    pub lighting_material: MaterialData,
+    pub push_consts: PushConstants<LightingPushConstants>,
+}
+
+// G-buffer images of one set of scene targets, with the descriptor set the lighting pass reads
+// them through
+pub(super) struct GBuffer {
+    device: ash::Device,
+    targets: Vec<RenderTarget>,
+    pub framebuffer: vk::Framebuffer,
+    desc_pool: vk::DescriptorPool,
+    pub desc_set: vk::DescriptorSet,
+}
+
+impl DeferredPath {
+    // The lighting pipeline reads lights at set 0, like scene meshes do, and the G-buffer at set 1.
+    // Shaders are the compiled LIGHTING_SHADERS
+    pub fn new(
+        device: &ash::Device,
+        depth_format: vk::Format,
+        scene_desc_set_layout: vk::DescriptorSetLayout,
+        lighting_shaders: [&[u8]; 2],
+        pipeline_cache: vk::PipelineCache,
+        max_push_consts_size: u32,
+    ) -> Result<Self, RendererError> {
+        let gbuffer_render_pass = create_gbuffer_render_pass(device, depth_format)?;
+        let lighting_render_pass = create_fullscreen_render_pass(
+            device,
+            HDR_FORMAT,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        )?;
+        let desc_set_layout = create_gbuffer_desc_set_layout(device)?;
+        let sampler = create_gbuffer_sampler(device)?;
+
+        let push_consts = PushConstants::new(
+            LightingPushConstants {
+                inv_view_proj: Mat4::IDENTITY,
+                camera_position: Vec4::ZERO,
+            },
+            vk::ShaderStageFlags::FRAGMENT,
+        );
+
+        let [vert_shader, frag_shader] = lighting_shaders;
+
+        let lighting_material = MaterialData::new(
+            device.clone(),
+            PipelineDesc {
+                layout: VertexLayout::of::<Pos2Vertex>(),
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                push_const_range: Some(push_consts.range(max_push_consts_size)),
+                shader_names: Some(LIGHTING_SHADERS),
+                color_attachments: 1,
+                depth_test_only: false,
+            },
+            &[scene_desc_set_layout, desc_set_layout],
+            vert_shader,
+            frag_shader,
+            pipeline_cache,
+            lighting_render_pass,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+
+        Ok(Self {
+            device: device.clone(),
+            gbuffer_render_pass,
+            lighting_render_pass,
+            desc_set_layout,
+            sampler,
+            lighting_material,
+            push_consts,
+        })
+    }
+
+    // The depth target is shared with the scene pass
+    pub fn create_gbuffer(
+        &self,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        extent: vk::Extent2D,
+        depth_target: &RenderTarget,
+    ) -> Result<GBuffer, RendererError> {
+        // Filled in one by one, so that a failure destroys what was created with the G-buffer
+        let mut gbuffer = GBuffer {
+            device: self.device.clone(),
+            targets: Vec::with_capacity(GBUFFER_FORMATS.len()),
+            framebuffer: vk::Framebuffer::null(),
+            desc_pool: vk::DescriptorPool::null(),
+            desc_set: vk::DescriptorSet::null(),
+        };
+
+        for format in GBUFFER_FORMATS {
+            gbuffer.targets.push(RenderTarget::new(
+                &self.device,
+                device_mem_properties,
+                format,
+                extent,
+                vk::SampleCountFlags::TYPE_1,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::ImageAspectFlags::COLOR,
+            )?);
+        }
+
+        let attachments: Vec<vk::ImageView> =
+            gbuffer.targets.iter().map(|target| target.view).chain([depth_target.view]).collect();
+
+        gbuffer.framebuffer =
+            create_framebuffer(&self.device, &attachments, extent, self.gbuffer_render_pass)?;
+        gbuffer.desc_pool = create_gbuffer_desc_pool(&self.device)?;
+        gbuffer.desc_set =
+            allocate_gbuffer_desc_set(&self.device, gbuffer.desc_pool, self.desc_set_layout)?;
+
+        gbuffer.update_desc_set(self.sampler);
+
+        Ok(gbuffer)
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers) {
+        debug.name(self.gbuffer_render_pass, "G-buffer render pass");
+        debug.name(self.lighting_render_pass, "deferred lighting render pass");
+        debug.name(self.desc_set_layout, "G-buffer descriptor set layout");
+        debug.name(self.sampler, "G-buffer sampler");
+        self.lighting_material.set_debug_names(debug, "deferred lighting");
+    }
+}
+
+impl Drop for DeferredPath {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_descriptor_set_layout(self.desc_set_layout, None);
+            self.device.destroy_render_pass(self.lighting_render_pass, None);
+            self.device.destroy_render_pass(self.gbuffer_render_pass, None);
+        }
+    }
+}
+
+impl GBuffer {
+    fn update_desc_set(&self, sampler: vk::Sampler) {
+        let image_infos: Vec<vk::DescriptorImageInfo> = self
+            .targets
+            .iter()
+            .map(|target| vk::DescriptorImageInfo {
+                sampler,
+                image_view: target.view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            })
+            .collect();
+
+        let desc_writes: Vec<vk::WriteDescriptorSet> = image_infos
+            .iter()
+            .zip(0..)
+            .map(|(image_info, binding)| vk::WriteDescriptorSet {
+                s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                dst_set: self.desc_set,
+                dst_binding: binding,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                p_image_info: image_info,
+                ..Default::default()
+            })
+            .collect();
+
+        unsafe {
+            self.device.update_descriptor_sets(&desc_writes, &[]);
+        }
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers, name: &str) {
+        let target_names = ["albedo", "normal", "depth", "material"];
+
+        for (target, target_name) in self.targets.iter().zip(target_names) {
+            target.set_debug_names(debug, &format!("{} G-buffer {}", name, target_name));
+        }
+
+        debug.name(self.framebuffer, &format!("{} G-buffer framebuffer", name));
+        debug.name(self.desc_pool, &format!("{} G-buffer descriptor pool", name));
+        debug.name(self.desc_set, &format!("{} G-buffer descriptor set", name));
+    }
+}
+
+// Nothing may be using it
+impl Drop for GBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_framebuffer(self.framebuffer, None);
+            self.device.destroy_descriptor_pool(self.desc_pool, None);
+        }
+    }
+}
+
+// Everything is cleared to 0, so depth is at the far plane and shading model 0 where nothing was
+// drawn. Color attachments are left ready to be sampled by the lighting pass, depth to be drawn
+// against by the scene pass
+fn create_gbuffer_render_pass(
+    device: &ash::Device,
+    depth_format: vk::Format,
+) -> Result<vk::RenderPass, RendererError> {
+    let color_attachments = GBUFFER_FORMATS.map(|format| vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    });
+
+    let depth_attachment = vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format: depth_format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let attachments: Vec<vk::AttachmentDescription> =
+        color_attachments.into_iter().chain([depth_attachment]).collect();
+
+    let color_attachment_refs: Vec<vk::AttachmentReference> = (0..GBUFFER_FORMATS.len() as u32)
+        .map(|attachment| vk::AttachmentReference {
+            attachment,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        })
+        .collect();
+
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: GBUFFER_FORMATS.len() as u32,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let subpass = vk::SubpassDescription {
+        pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+        color_attachment_count: color_attachment_refs.len() as u32,
+        p_color_attachments: color_attachment_refs.as_ptr(),
+        p_depth_stencil_attachment: &depth_attachment_ref,
+        ..Default::default()
+    };
+
+    let attachment_stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS;
+    let depth_stages =
+        vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+
+    // The G-buffer may still be sampled by the previous frame's lighting pass, and the depth
+    // buffer drawn against by its scene pass
+    let subpass_dependencies = [
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: attachment_stages | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_stage_mask: attachment_stages,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+        sampled_output_dependency(),
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_stage_mask: depth_stages,
+            dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+    ];
+
+    let create_info = vk::RenderPassCreateInfo {
+        s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
+        attachment_count: attachments.len() as u32,
+        p_attachments: attachments.as_ptr(),
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        dependency_count: subpass_dependencies.len() as u32,
+        p_dependencies: subpass_dependencies.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe { device.create_render_pass(&create_info, None) }
+        .map_err(resource_err("create G-buffer render pass"))
+}
+
+fn create_gbuffer_desc_set_layout(
+    device: &ash::Device,
+) -> Result<vk::DescriptorSetLayout, RendererError> {
+    let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..GBUFFER_FORMATS.len() as u32)
+        .map(|binding| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            p_immutable_samplers: ptr::null(),
+        })
+        .collect();
+
+    let create_info = vk::DescriptorSetLayoutCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        binding_count: bindings.len() as u32,
+        p_bindings: bindings.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None) }
+        .map_err(resource_err("create G-buffer descriptor set layout"))
+}
+
+fn create_gbuffer_desc_pool(device: &ash::Device) -> Result<vk::DescriptorPool, RendererError> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: GBUFFER_FORMATS.len() as u32,
+    };
+
+    let create_info = vk::DescriptorPoolCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+        max_sets: 1,
+        pool_size_count: 1,
+        p_pool_sizes: &pool_size,
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_pool(&create_info, None) }
+        .map_err(resource_err("create G-buffer descriptor pool"))
+}
+
+fn allocate_gbuffer_desc_set(
+    device: &ash::Device,
+    desc_pool: vk::DescriptorPool,
+    desc_set_layout: vk::DescriptorSetLayout,
+) -> Result<vk::DescriptorSet, RendererError> {
+    let alloc_info = vk::DescriptorSetAllocateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+        descriptor_pool: desc_pool,
+        descriptor_set_count: 1,
+        p_set_layouts: &desc_set_layout,
+        ..Default::default()
+    };
+
+    let desc_sets = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .map_err(resource_err("allocate G-buffer descriptor set"))?;
+
+    Ok(desc_sets[0])
+}
+
+// The lighting pass reads exactly one texel per pixel, and float formats may not support filtering
+fn create_gbuffer_sampler(device: &ash::Device) -> Result<vk::Sampler, RendererError> {
+    let create_info = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        mag_filter: vk::Filter::NEAREST,
+        min_filter: vk::Filter::NEAREST,
+        mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+        address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        anisotropy_enable: vk::FALSE,
+        max_anisotropy: 1.0,
+        compare_enable: vk::FALSE,
+        compare_op: vk::CompareOp::ALWAYS,
+        min_lod: 0.0,
+        max_lod: 0.0,
+        border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+        unnormalized_coordinates: vk::FALSE,
+        ..Default::default()
+    };
+
+    unsafe { device.create_sampler(&create_info, None) }
+        .map_err(resource_err("create G-buffer sampler"))
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders");
+const POLL_INTERVAL: f64 = 0.5;
+
+// Polls the modification times of the shader sources. Cheaper than it sounds with a handful of
+// files, and doesn't need a platform-specific file watcher
+pub(super) struct ShaderWatcher {
+    modified: HashMap<String, SystemTime>,
+    next_poll: f64,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        Self {
+            modified: scan_shader_dir(),
+            next_poll: 0.0,
+        }
+    }
+
+    // File names of shaders changed since the last poll
+    pub fn poll(&mut self, time: f64) -> Vec<String> {
+        if time < self.next_poll {
+            return Vec::new();
+        }
+
+        self.next_poll = time + POLL_INTERVAL;
+
+        let current = scan_shader_dir();
+
+        let changed = current
+            .iter()
+            .filter(|(name, modified)| self.modified.get(*name) != Some(modified))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        self.modified = current;
+
+        changed
+    }
+}
+
+pub(super) fn shader_path(name: &str) -> PathBuf {
+    Path::new(SHADER_DIR).join(name)
+}
+
+fn scan_shader_dir() -> HashMap<String, SystemTime> {
+    let entries = match fs::read_dir(SHADER_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return HashMap::new(),
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|metadata| metadata.modified()).ok()?;
+            let name = entry.file_name().into_string().ok()?;
+
+            Some((name, modified))
+        })
+        .collect()
+}
@@ -0,0 +1,97 @@
+use std::mem::size_of;
+
+use ash::vk;
+
+use super::{create_buffer, resource_err, RendererError};
+
+const STRIDE: usize = size_of::<vk::DrawIndexedIndirectCommand>();
+
+// Draw parameters in a mapped buffer, read by vkCmdDrawIndexedIndirect. The CPU fills it for now,
+// later a compute culling pass can write the same layout. There's one per frame in flight, so it
+// can only be written after that frame's fence has been waited on.
+pub(super) struct IndirectBuffer {
+    device: ash::Device,
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    mapping: *mut vk::DrawIndexedIndirectCommand,
+    capacity: usize,
+    len: usize,
+}
+
+impl IndirectBuffer {
+    pub unsafe fn new(
+        device: ash::Device,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        capacity: usize,
+    ) -> Result<Self, RendererError> {
+        let capacity = capacity.max(1);
+        let size = (capacity * STRIDE) as u64;
+
+        let (buffer, memory) = create_buffer(
+            &device,
+            device_mem_properties,
+            size,
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        let mapping = match device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty()) {
+            Ok(mapping) => mapping.cast::<vk::DrawIndexedIndirectCommand>(),
+            Err(e) => {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+
+                return Err(resource_err("map indirect buffer memory")(e));
+            }
+        };
+
+        Ok(Self {
+            device,
+            buffer,
+            memory,
+            mapping,
+            capacity,
+            len: 0,
+        })
+    }
+
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn push(&mut self, command: vk::DrawIndexedIndirectCommand) {
+        assert!(self.len < self.capacity, "Indirect buffer is full");
+
+        unsafe {
+            self.mapping.add(self.len).write(command);
+        }
+
+        self.len += 1;
+    }
+
+    // Byte offset of the command pushed at the given index, to be passed to the draw call
+    pub fn offset(idx: usize) -> vk::DeviceSize {
+        (idx * STRIDE) as vk::DeviceSize
+    }
+
+    pub fn stride() -> u32 {
+        STRIDE as u32
+    }
+}
+
+impl Drop for IndirectBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_buffer(self.buffer, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
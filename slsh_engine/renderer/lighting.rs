@@ -0,0 +1,149 @@
+use glam::Vec3;
+
+use super::shadow::{ShadowLightHandle, ShadowMaps, MAX_SHADOW_LIGHTS};
+
+// Has to match lit.frag
+pub const MAX_POINT_LIGHTS: usize = 32;
+
+const DEFAULT_AMBIENT: Vec3 = Vec3::new(0.1, 0.1, 0.1);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PointLightHandle(usize);
+
+// Light from infinitely far away, like the sun. Direction is the way the light travels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub color: Vec3,
+}
+
+// Fades out towards radius and has no effect past it. Colors are linear and may go above 1
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub radius: f32,
+    // Shadow map to test against, usually of a shadow light at the same position
+    pub shadow: Option<ShadowLightHandle>,
+}
+
+// Uniform buffer read by lit.frag, laid out as its std140 block
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct LightsUniform {
+    sun_direction: Vec3,
+    point_count: u32,
+    sun_color: Vec3,
+    _pad0: f32,
+    ambient: Vec3,
+    _pad1: f32,
+    shadows: [ShadowUniform; MAX_SHADOW_LIGHTS],
+    points: [PointLightUniform; MAX_POINT_LIGHTS],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct ShadowUniform {
+    position: Vec3,
+    radius: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct PointLightUniform {
+    position: Vec3,
+    radius: f32,
+    color: Vec3,
+    // Index into the shadow map array, -1 for none
+    shadow_map: i32,
+}
+
+// Lights are kept in slots, so that handles stay valid when others are removed
+pub(super) struct Lights {
+    pub sun: Option<DirectionalLight>,
+    pub ambient: Vec3,
+    points: Vec<Option<PointLight>>,
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Self {
+            sun: None,
+            ambient: DEFAULT_AMBIENT,
+            points: Vec::new(),
+        }
+    }
+
+    pub fn add_point(&mut self, light: PointLight) -> PointLightHandle {
+        assert!(self.points.iter().flatten().count() < MAX_POINT_LIGHTS, "Too many point lights");
+
+        let idx = match self.points.iter().position(Option::is_none) {
+            Some(idx) => idx,
+            None => {
+                self.points.push(None);
+                self.points.len() - 1
+            }
+        };
+
+        self.points[idx] = Some(light);
+
+        PointLightHandle(idx)
+    }
+
+    pub fn point_mut(&mut self, handle: PointLightHandle) -> Option<&mut PointLight> {
+        self.points.get_mut(handle.0).and_then(Option::as_mut)
+    }
+
+    pub fn remove_point(&mut self, handle: PointLightHandle) {
+        if let Some(slot) = self.points.get_mut(handle.0) {
+            *slot = None;
+        }
+    }
+
+    pub fn to_uniform(&self, shadow_maps: &ShadowMaps) -> LightsUniform {
+        let (sun_direction, sun_color) = match self.sun {
+            Some(sun) => (sun.direction.normalize_or_zero(), sun.color),
+            None => (Vec3::NEG_Y, Vec3::ZERO),
+        };
+
+        let mut shadows = [ShadowUniform::default(); MAX_SHADOW_LIGHTS];
+
+        for (idx, shadow) in shadows.iter_mut().enumerate() {
+            if let Some(shadow_map) = shadow_maps.get(ShadowLightHandle(idx)) {
+                shadow.position = shadow_map.light.position;
+                shadow.radius = shadow_map.light.radius;
+            }
+        }
+
+        let mut points = [PointLightUniform::default(); MAX_POINT_LIGHTS];
+        let mut point_count = 0;
+
+        for (light, uniform) in self.points.iter().flatten().zip(&mut points) {
+            // Shadow maps that were removed are ignored rather than sampled
+            let shadow_map = light
+                .shadow
+                .filter(|&handle| shadow_maps.get(handle).is_some())
+                .map_or(-1, |handle| handle.0 as i32);
+
+            *uniform = PointLightUniform {
+                position: light.position,
+                radius: light.radius,
+                color: light.color,
+                shadow_map,
+            };
+
+            point_count += 1;
+        }
+
+        LightsUniform {
+            sun_direction,
+            point_count,
+            sun_color,
+            _pad0: 0.0,
+            ambient: self.ambient,
+            _pad1: 0.0,
+            shadows,
+            points,
+        }
+    }
+}
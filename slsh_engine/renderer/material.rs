@@ -0,0 +1,129 @@
+use ash::vk;
+
+#[cfg(feature = "shaderc")]
+use super::CheckVkError;
+use super::{
+    create_graphics_pipeline, create_pipeline_layout, DebugMarkers, PipelineDesc, RendererError,
+    VertexLayout,
+};
+
+// Pipeline and pipeline layout of a shader pair, shared by every mesh drawn with it. Scene meshes
+// are drawn sorted by material so that the pipeline is only bound when it changes
+pub(super) struct MaterialData {
+    device: ash::Device,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    #[cfg_attr(not(feature = "shaderc"), allow(dead_code))]
+    pub desc: PipelineDesc,
+    // Render passes outlive swapchain recreation, so rebuilt pipelines can target the same one
+    #[cfg_attr(not(feature = "shaderc"), allow(dead_code))]
+    render_pass: vk::RenderPass,
+    #[cfg_attr(not(feature = "shaderc"), allow(dead_code))]
+    samples: vk::SampleCountFlags,
+}
+
+// What makes two scene meshes able to share a material. The descriptor set layout is that of set
+// 1, if the material has one
+#[derive(PartialEq, Eq, Hash)]
+pub(super) enum MaterialKey {
+    // Built-in shaders, by file name so that hot reloaded versions still match
+    Named([&'static str; 2], VertexLayout, Option<vk::DescriptorSetLayout>),
+    // Custom shaders, by their SPIR-V
+    Code(Vec<u8>, Vec<u8>, VertexLayout, Option<vk::DescriptorSetLayout>),
+}
+
+impl MaterialData {
+    pub fn new(
+        device: ash::Device,
+        desc: PipelineDesc,
+        desc_set_layouts: &[vk::DescriptorSetLayout],
+        vert_shader_compiled: &[u8],
+        frag_shader_compiled: &[u8],
+        pipeline_cache: vk::PipelineCache,
+        render_pass: vk::RenderPass,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self, RendererError> {
+        let pipeline_layout =
+            create_pipeline_layout(&device, desc.push_const_range.as_ref(), desc_set_layouts)?;
+
+        let pipeline = create_graphics_pipeline(
+            &device,
+            desc.layout,
+            vert_shader_compiled,
+            frag_shader_compiled,
+            desc.topology,
+            pipeline_cache,
+            render_pass,
+            samples,
+            desc.color_attachments,
+            desc.depth_test_only,
+            pipeline_layout,
+            desc.push_const_range.as_ref(),
+        );
+
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            device,
+            pipeline_layout,
+            pipeline,
+            desc,
+            render_pass,
+            samples,
+        })
+    }
+
+    pub unsafe fn bind(&self, cmd_buffer: vk::CommandBuffer) {
+        self.device.cmd_bind_pipeline(cmd_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+    }
+
+    // The old pipeline must not be in use by any frame in flight
+    #[cfg(feature = "shaderc")]
+    pub unsafe fn rebuild_pipeline(
+        &mut self,
+        vert_shader_compiled: &[u8],
+        frag_shader_compiled: &[u8],
+        pipeline_cache: vk::PipelineCache,
+    ) {
+        let desc = self.desc;
+
+        let pipeline = create_graphics_pipeline(
+            &self.device,
+            desc.layout,
+            vert_shader_compiled,
+            frag_shader_compiled,
+            desc.topology,
+            pipeline_cache,
+            self.render_pass,
+            self.samples,
+            desc.color_attachments,
+            desc.depth_test_only,
+            self.pipeline_layout,
+            desc.push_const_range.as_ref(),
+        )
+        .check_err("rebuild pipeline");
+
+        self.device.destroy_pipeline(self.pipeline, None);
+        self.pipeline = pipeline;
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers, name: &str) {
+        debug.name(self.pipeline_layout, &format!("{} pipeline layout", name));
+        debug.name(self.pipeline, &format!("{} pipeline", name));
+    }
+}
+
+impl Drop for MaterialData {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+
+use super::{resource_err, CheckVkError, DebugMarkers, RendererError};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(super) struct OcclusionPushConstants {
+    pub mvp: Mat4,
+}
+
+// Occlusion queries of the scene meshes, with a pool for each frame in flight. Query i belongs to
+// the i-th draw of the frame, like its indirect command. Results are read once the frame's fence
+// has been waited on, so they lag behind by the number of frames in flight. Meshes that were found
+// hidden have their bounding box drawn in their place until a query sees it again
+pub(super) struct OcclusionQueries {
+    device: ash::Device,
+    pools: Vec<vk::QueryPool>,
+    capacities: Vec<u32>,
+    // Query and scene mesh slot of every query each frame issued
+    issued: Vec<Vec<(u32, usize)>>,
+}
+
+impl OcclusionQueries {
+    pub fn new(device: ash::Device, frames: usize, capacity: u32) -> Result<Self, RendererError> {
+        let capacity = capacity.max(1);
+
+        let mut queries = Self {
+            device,
+            pools: Vec::with_capacity(frames),
+            capacities: vec![capacity; frames],
+            issued: vec![Vec::new(); frames],
+        };
+
+        // Pools created before a failed one are destroyed along with the queries
+        for _ in 0..frames {
+            queries.pools.push(create_query_pool(&queries.device, capacity)?);
+        }
+
+        Ok(queries)
+    }
+
+    pub fn pool(&self, frame: usize) -> vk::QueryPool {
+        self.pools[frame]
+    }
+
+    // Whether any samples of each mesh the frame queried passed the depth test, by scene mesh slot
+    pub fn results(&self, frame: usize) -> Vec<(usize, bool)> {
+        let count = match self.issued[frame].iter().map(|&(query, _)| query + 1).max() {
+            Some(count) => count,
+            None => return Vec::new(),
+        };
+
+        // Sample count and availability, queries of culled meshes were never written
+        let mut data = vec![[0_u32; 2]; count as usize];
+
+        let result = unsafe {
+            self.device.get_query_pool_results(
+                self.pools[frame],
+                0,
+                count,
+                &mut data,
+                vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+
+        // Not ready only means that some weren't available, the rest are still written
+        match result {
+            Ok(()) | Err(vk::Result::NOT_READY) => (),
+            Err(e) => panic!("failed to get occlusion query results: {}", e),
+        }
+
+        self.issued[frame]
+            .iter()
+            .filter(|&&(query, _)| data[query as usize][1] != 0)
+            .map(|&(query, slot)| (slot, data[query as usize][0] != 0))
+            .collect()
+    }
+
+    // Only after the frame's fence has been waited on. Pools are grown to fit every draw
+    pub fn prepare(&mut self, frame: usize, draw_count: usize, issued: Vec<(u32, usize)>) {
+        let draw_count = draw_count as u32;
+
+        if draw_count > self.capacities[frame] {
+            let capacity = draw_count.next_power_of_two();
+
+            unsafe {
+                self.device.destroy_query_pool(self.pools[frame], None);
+            }
+
+            self.pools[frame] =
+                create_query_pool(&self.device, capacity).check_err("grow occlusion query pool");
+            self.capacities[frame] = capacity;
+        }
+
+        self.issued[frame] = issued;
+    }
+
+    // Outside of render passes, before any query of the frame begins
+    pub unsafe fn record_reset(&self, cmd_buffer: vk::CommandBuffer, frame: usize) {
+        self.device.cmd_reset_query_pool(cmd_buffer, self.pools[frame], 0, self.capacities[frame]);
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers) {
+        for (i, pool) in self.pools.iter().enumerate() {
+            debug.name(*pool, &format!("frame {} occlusion query pool", i));
+        }
+    }
+}
+
+impl Drop for OcclusionQueries {
+    fn drop(&mut self) {
+        unsafe {
+            for pool in &self.pools {
+                self.device.destroy_query_pool(*pool, None);
+            }
+        }
+    }
+}
+
+fn create_query_pool(
+    device: &ash::Device,
+    query_count: u32,
+) -> Result<vk::QueryPool, RendererError> {
+    let create_info = vk::QueryPoolCreateInfo {
+        s_type: vk::StructureType::QUERY_POOL_CREATE_INFO,
+        query_type: vk::QueryType::OCCLUSION,
+        query_count,
+        ..Default::default()
+    };
+
+    unsafe { device.create_query_pool(&create_info, None) }
+        .map_err(resource_err("create occlusion query pool"))
+}
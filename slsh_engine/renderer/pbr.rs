@@ -0,0 +1,158 @@
+use std::ptr;
+
+use ash::vk;
+use glam::Vec4;
+
+use super::{resource_err, CheckVkError, RendererError, TextureHandle};
+
+pub(super) const MAX_PBR_MATERIALS: u32 = 64;
+
+// Base color, normal, metallic-roughness and occlusion, in the order of pbr.frag's bindings
+const PBR_TEXTURES: u32 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PbrMaterialHandle(pub(super) usize);
+
+// Metallic-roughness material as glTF defines it. Textures that are left out act as white, or as
+// a flat normal map, so the factors alone describe the surface. Base color textures are sRGB, the
+// rest hold linear data and have to be made with Renderer::create_linear_texture:
+//
+//     normal               tangent space, XYZ in RGB
+//     metallic_roughness   roughness in G, metalness in B
+//     occlusion            in R
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PbrMaterial {
+    pub base_color: Option<TextureHandle>,
+    pub normal: Option<TextureHandle>,
+    pub metallic_roughness: Option<TextureHandle>,
+    pub occlusion: Option<TextureHandle>,
+    pub base_color_factor: Vec4,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+}
+
+pub(super) struct PbrMaterialData {
+    // None if one of the textures doesn't exist
+    pub desc_set: Option<vk::DescriptorSet>,
+    pub base_color_factor: Vec4,
+    // Passed to pbr.frag as MeshPushConstants::params
+    pub params: Vec4,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: None,
+            normal: None,
+            metallic_roughness: None,
+            occlusion: None,
+            base_color_factor: Vec4::ONE,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+        }
+    }
+}
+
+impl PbrMaterialData {
+    pub fn new(desc_set: Option<vk::DescriptorSet>, material: &PbrMaterial) -> Self {
+        Self {
+            desc_set,
+            base_color_factor: material.base_color_factor,
+            params: Vec4::new(
+                material.metallic_factor.clamp(0.0, 1.0),
+                material.roughness_factor.clamp(0.0, 1.0),
+                material.normal_scale,
+                material.occlusion_strength.clamp(0.0, 1.0),
+            ),
+        }
+    }
+}
+
+pub(super) fn create_pbr_desc_set_layout(
+    device: &ash::Device,
+) -> Result<vk::DescriptorSetLayout, RendererError> {
+    let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..PBR_TEXTURES)
+        .map(|binding| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            p_immutable_samplers: ptr::null(),
+        })
+        .collect();
+
+    let create_info = vk::DescriptorSetLayoutCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        binding_count: bindings.len() as u32,
+        p_bindings: bindings.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None) }
+        .map_err(resource_err("create PBR descriptor set layout"))
+}
+
+pub(super) fn create_pbr_desc_pool(
+    device: &ash::Device,
+) -> Result<vk::DescriptorPool, RendererError> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: MAX_PBR_MATERIALS * PBR_TEXTURES,
+    };
+
+    let create_info = vk::DescriptorPoolCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+        max_sets: MAX_PBR_MATERIALS,
+        pool_size_count: 1,
+        p_pool_sizes: &pool_size,
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_pool(&create_info, None) }
+        .map_err(resource_err("create PBR descriptor pool"))
+}
+
+pub(super) fn create_pbr_desc_set(
+    device: &ash::Device,
+    desc_pool: vk::DescriptorPool,
+    desc_set_layout: vk::DescriptorSetLayout,
+    textures: &[vk::DescriptorImageInfo],
+) -> vk::DescriptorSet {
+    assert_eq!(textures.len(), PBR_TEXTURES as usize, "PBR materials have four textures");
+
+    let alloc_info = vk::DescriptorSetAllocateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+        descriptor_pool: desc_pool,
+        descriptor_set_count: 1,
+        p_set_layouts: &desc_set_layout,
+        ..Default::default()
+    };
+
+    let desc_set = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .check_err("allocate PBR descriptor set")[0];
+
+    let desc_writes: Vec<vk::WriteDescriptorSet> = textures
+        .iter()
+        .zip(0..)
+        .map(|(image_info, binding)| vk::WriteDescriptorSet {
+            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+            dst_set: desc_set,
+            dst_binding: binding,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            p_image_info: image_info,
+            ..Default::default()
+        })
+        .collect();
+
+    unsafe {
+        device.update_descriptor_sets(&desc_writes, &[]);
+    }
+
+    desc_set
+}
@@ -0,0 +1,76 @@
+use std::fs;
+
+use ash::vk;
+
+use super::{resource_err, RendererError};
+
+const CACHE_DIR: &str = "cache";
+const CACHE_FILE: &str = "cache/pipelines.bin";
+
+// Header layout for VK_PIPELINE_CACHE_HEADER_VERSION_ONE
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+// Loads the cache saved by a previous run. Data from a different driver or GPU is discarded
+// instead of being handed to the driver, which is not required to reject it gracefully.
+pub(super) fn load(
+    device: &ash::Device,
+    properties: &vk::PhysicalDeviceProperties,
+) -> Result<vk::PipelineCache, RendererError> {
+    let data = match fs::read(CACHE_FILE) {
+        Ok(data) if is_compatible(&data, properties) => data,
+        Ok(_) => {
+            eprintln!("Pipeline cache is from a different device or driver, ignoring it");
+            Vec::new()
+        }
+        Err(_) => Vec::new(),
+    };
+
+    let create_info = vk::PipelineCacheCreateInfo {
+        s_type: vk::StructureType::PIPELINE_CACHE_CREATE_INFO,
+        initial_data_size: data.len(),
+        p_initial_data: data.as_ptr().cast(),
+        ..Default::default()
+    };
+
+    unsafe { device.create_pipeline_cache(&create_info, None) }
+        .map_err(resource_err("create pipeline cache"))
+}
+
+pub(super) fn save(device: &ash::Device, cache: vk::PipelineCache) {
+    let data = match unsafe { device.get_pipeline_cache_data(cache) } {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to get pipeline cache data: {}", e);
+            return;
+        }
+    };
+
+    let result = fs::create_dir_all(CACHE_DIR).and_then(|_| fs::write(CACHE_FILE, data));
+
+    if let Err(e) = result {
+        eprintln!("Failed to write {}: {}", CACHE_FILE, e);
+    }
+}
+
+fn is_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset: usize| {
+        let bytes = data[offset..offset + 4].try_into().unwrap();
+        u32::from_ne_bytes(bytes)
+    };
+
+    let header_size = read_u32(0);
+    let header_version = read_u32(4);
+    let vendor_id = read_u32(8);
+    let device_id = read_u32(12);
+    let uuid = &data[16..HEADER_SIZE];
+
+    header_size as usize >= HEADER_SIZE
+        && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && vendor_id == properties.vendor_id
+        && device_id == properties.device_id
+        && uuid == properties.pipeline_cache_uuid
+}
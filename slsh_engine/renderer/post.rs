@@ -0,0 +1,162 @@
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec4};
+
+use super::material::MaterialData;
+use super::push_consts::PushConstants;
+use super::{resource_err, RendererError};
+
+pub const POST_EFFECT_PARAMS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PostEffectHandle(u32);
+
+// Push constants block shared by every post effect. Fragment shaders declare it as
+//
+//     layout(push_constant) uniform PushConstants {
+//         vec2 res;
+//         float time;
+//         vec4 params[4];
+//     } consts;
+//
+// and sample the output of the previous pass from a sampler2D at set 0, binding 0
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(super) struct PostPushConstants {
+    pub res: Vec2,
+    pub time: f32,
+    pub _pad: f32,
+    pub params: [Vec4; POST_EFFECT_PARAMS],
+}
+
+pub(super) struct PostEffect {
+    pub handle: PostEffectHandle,
+    pub material: MaterialData,
+    pub push_consts: PushConstants<PostPushConstants>,
+    pub enabled: bool,
+}
+
+// Fullscreen passes run in order between drawing the scene and tonemapping it. Each one reads the
+// HDR image written by the one before and writes the other, so two targets are enough for any
+// number of passes
+#[derive(Default)]
+pub(super) struct PostProcessChain {
+    effects: Vec<PostEffect>,
+    next_id: u32,
+}
+
+impl PostProcessChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(
+        &mut self,
+        material: MaterialData,
+        push_consts: PushConstants<PostPushConstants>,
+    ) -> PostEffectHandle {
+        let handle = PostEffectHandle(self.next_id);
+
+        self.next_id += 1;
+
+        self.effects.push(PostEffect {
+            handle,
+            material,
+            push_consts,
+            enabled: true,
+        });
+
+        handle
+    }
+
+    // The effect must not be in use by any frame in flight
+    pub fn remove(&mut self, handle: PostEffectHandle) {
+        self.effects.retain(|effect| effect.handle != handle);
+    }
+
+    pub fn get_mut(&mut self, handle: PostEffectHandle) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|effect| effect.handle == handle)
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &PostEffect> {
+        self.effects.iter().filter(|effect| effect.enabled)
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+}
+
+// Post effects sample neighbouring pixels, so reads outside the image are clamped to its edges
+pub(super) fn create_target_sampler(device: &ash::Device) -> Result<vk::Sampler, RendererError> {
+    let create_info = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        mag_filter: vk::Filter::LINEAR,
+        min_filter: vk::Filter::LINEAR,
+        mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+        address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        anisotropy_enable: vk::FALSE,
+        max_anisotropy: 1.0,
+        compare_enable: vk::FALSE,
+        compare_op: vk::CompareOp::ALWAYS,
+        min_lod: 0.0,
+        max_lod: 0.0,
+        border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+        unnormalized_coordinates: vk::FALSE,
+        ..Default::default()
+    };
+
+    unsafe { device.create_sampler(&create_info, None) }
+        .map_err(resource_err("create render target sampler"))
+}
+
+pub(super) fn allocate_target_desc_sets(
+    device: &ash::Device,
+    desc_pool: vk::DescriptorPool,
+    desc_set_layout: vk::DescriptorSetLayout,
+    count: usize,
+) -> Result<Vec<vk::DescriptorSet>, RendererError> {
+    let layouts = vec![desc_set_layout; count];
+
+    let alloc_info = vk::DescriptorSetAllocateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+        descriptor_pool: desc_pool,
+        descriptor_set_count: layouts.len() as u32,
+        p_set_layouts: layouts.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .map_err(resource_err("allocate render target descriptor sets"))
+}
+
+// Scene targets get new sets whenever they're recreated, pointed at their HDR targets
+pub(super) fn update_target_desc_set(
+    device: &ash::Device,
+    desc_set: vk::DescriptorSet,
+    image_view: vk::ImageView,
+    sampler: vk::Sampler,
+) {
+    let image_info = vk::DescriptorImageInfo {
+        sampler,
+        image_view,
+        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    };
+
+    let desc_write = vk::WriteDescriptorSet {
+        s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: desc_set,
+        dst_binding: 0,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        p_image_info: &image_info,
+        ..Default::default()
+    };
+
+    unsafe {
+        device.update_descriptor_sets(&[desc_write], &[]);
+    }
+}
@@ -0,0 +1,57 @@
+use std::mem::size_of;
+use std::ops::{Deref, DerefMut};
+
+use ash::vk;
+use bytemuck::Pod;
+
+// Push constants block of a pipeline along with the stages that read it. Derefs to the value
+#[derive(Clone, Copy)]
+pub(super) struct PushConstants<T> {
+    value: T,
+    stage_flags: vk::ShaderStageFlags,
+}
+
+impl<T: Pod> PushConstants<T> {
+    pub fn new(value: T, stage_flags: vk::ShaderStageFlags) -> Self {
+        Self { value, stage_flags }
+    }
+
+    // Range to create the pipeline layout with. The spec only guarantees 128 bytes, so the size is
+    // checked against what the device supports
+    pub fn range(&self, max_size: u32) -> vk::PushConstantRange {
+        let size = size_of::<T>();
+
+        assert!(size % 4 == 0, "Push constants size must be a multiple of 4");
+        assert!(
+            size <= max_size as usize,
+            "Push constants of {} bytes exceed device limit of {} bytes",
+            size,
+            max_size
+        );
+
+        vk::PushConstantRange {
+            stage_flags: self.stage_flags,
+            offset: 0,
+            size: size as u32,
+        }
+    }
+
+    // Stages and bytes, as taken by MeshData::record_draw_commands
+    pub fn as_push(&self) -> (vk::ShaderStageFlags, &[u8]) {
+        (self.stage_flags, bytemuck::bytes_of(&self.value))
+    }
+}
+
+impl<T> Deref for PushConstants<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for PushConstants<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_LEN: usize = 5;
+
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+
+enum Type {
+    Scalar(u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Array(u32, u32),
+    Struct(Vec<u32>),
+    Pointer(u32, u32),
+}
+
+#[derive(Default)]
+struct Module {
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    array_strides: HashMap<u32, u32>,
+    member_offsets: HashMap<(u32, u32), u32>,
+    matrix_strides: HashMap<(u32, u32), u32>,
+    push_constant_ptr: Option<u32>,
+}
+
+pub fn push_constant_block_size(code: &[u32]) -> Option<u32> {
+    let module = Module::parse(code);
+    let ptr_type = module.push_constant_ptr?;
+
+    match module.types.get(&ptr_type)? {
+        Type::Pointer(_, pointee) => module.type_size(*pointee, None),
+        _ => None,
+    }
+}
+
+impl Module {
+    fn parse(code: &[u32]) -> Self {
+        assert!(
+            code.len() >= HEADER_LEN && code[0] == MAGIC,
+            "shader code is not a valid SPIR-V module"
+        );
+
+        let mut module = Self::default();
+        let mut i = HEADER_LEN;
+
+        while i < code.len() {
+            let word_count = (code[i] >> 16) as usize;
+            let opcode = code[i] & 0xffff;
+
+            assert!(word_count != 0 && i + word_count <= code.len(), "malformed SPIR-V");
+
+            module.parse_instruction(opcode, &code[i + 1..i + word_count]);
+
+            i += word_count;
+        }
+
+        module
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, ops: &[u32]) {
+        match opcode {
+            OP_DECORATE if ops[1] == DECORATION_ARRAY_STRIDE => {
+                self.array_strides.insert(ops[0], ops[2]);
+            }
+            OP_MEMBER_DECORATE if ops[2] == DECORATION_OFFSET => {
+                self.member_offsets.insert((ops[0], ops[1]), ops[3]);
+            }
+            OP_MEMBER_DECORATE if ops[2] == DECORATION_MATRIX_STRIDE => {
+                self.matrix_strides.insert((ops[0], ops[1]), ops[3]);
+            }
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                self.types.insert(ops[0], Type::Scalar(ops[1] / 8));
+            }
+            OP_TYPE_VECTOR => {
+                self.types.insert(ops[0], Type::Vector(ops[1], ops[2]));
+            }
+            OP_TYPE_MATRIX => {
+                self.types.insert(ops[0], Type::Matrix(ops[1], ops[2]));
+            }
+            OP_TYPE_ARRAY => {
+                self.types.insert(ops[0], Type::Array(ops[1], ops[2]));
+            }
+            OP_TYPE_STRUCT => {
+                self.types.insert(ops[0], Type::Struct(ops[1..].to_vec()));
+            }
+            OP_TYPE_POINTER => {
+                self.types.insert(ops[0], Type::Pointer(ops[1], ops[2]));
+            }
+            OP_CONSTANT => {
+                self.constants.insert(ops[1], ops[2]);
+            }
+            OP_VARIABLE if ops[2] == STORAGE_CLASS_PUSH_CONSTANT => {
+                self.push_constant_ptr = Some(ops[0]);
+            }
+            _ => (),
+        }
+    }
+
+    fn type_size(&self, id: u32, matrix_stride: Option<u32>) -> Option<u32> {
+        match self.types.get(&id)? {
+            Type::Scalar(size) => Some(*size),
+            Type::Vector(component, count) => Some(self.type_size(*component, None)? * count),
+            Type::Matrix(column, count) => {
+                let stride = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.type_size(*column, None)?,
+                };
+
+                Some(stride * count)
+            }
+            Type::Array(element, length_id) => {
+                let length = *self.constants.get(length_id)?;
+                let stride = match self.array_strides.get(&id) {
+                    Some(stride) => *stride,
+                    None => self.type_size(*element, None)?,
+                };
+
+                Some(stride * length)
+            }
+            Type::Struct(members) => {
+                let mut size = 0;
+
+                for (i, member) in members.iter().enumerate() {
+                    let i = i as u32;
+                    let offset = self.member_offsets.get(&(id, i)).copied().unwrap_or(size);
+                    let stride = self.matrix_strides.get(&(id, i)).copied();
+                    let member_size = self.type_size(*member, stride)?;
+
+                    size = size.max(offset + member_size);
+                }
+
+                Some(size)
+            }
+            Type::Pointer(..) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INT: u32 = 1;
+    const FLOAT: u32 = 2;
+    const VEC4: u32 = 3;
+    const MAT4: u32 = 4;
+    const BLOCK: u32 = 5;
+    const BLOCK_PTR: u32 = 6;
+    const VARIABLE: u32 = 7;
+    const LENGTH: u32 = 8;
+    const ARRAY: u32 = 9;
+
+    fn module(instructions: &[(u32, &[u32])]) -> Vec<u32> {
+        let mut code = vec![MAGIC, 0x0001_0000, 0, 16, 0];
+
+        for (opcode, ops) in instructions {
+            code.push(((ops.len() as u32 + 1) << 16) | opcode);
+            code.extend_from_slice(ops);
+        }
+
+        code
+    }
+
+    // layout(push_constant) uniform Block { mat4 mvp; vec4 color; float params[3]; }
+    fn push_constant_module() -> Vec<u32> {
+        module(&[
+            (OP_DECORATE, &[ARRAY, DECORATION_ARRAY_STRIDE, 16]),
+            (OP_MEMBER_DECORATE, &[BLOCK, 0, DECORATION_OFFSET, 0]),
+            (OP_MEMBER_DECORATE, &[BLOCK, 0, DECORATION_MATRIX_STRIDE, 16]),
+            (OP_MEMBER_DECORATE, &[BLOCK, 1, DECORATION_OFFSET, 64]),
+            (OP_MEMBER_DECORATE, &[BLOCK, 2, DECORATION_OFFSET, 80]),
+            (OP_TYPE_INT, &[INT, 32, 0]),
+            (OP_TYPE_FLOAT, &[FLOAT, 32]),
+            (OP_TYPE_VECTOR, &[VEC4, FLOAT, 4]),
+            (OP_TYPE_MATRIX, &[MAT4, VEC4, 4]),
+            (OP_CONSTANT, &[INT, LENGTH, 3]),
+            (OP_TYPE_ARRAY, &[ARRAY, FLOAT, LENGTH]),
+            (OP_TYPE_STRUCT, &[BLOCK, MAT4, VEC4, ARRAY]),
+            (OP_TYPE_POINTER, &[BLOCK_PTR, STORAGE_CLASS_PUSH_CONSTANT, BLOCK]),
+            (OP_VARIABLE, &[BLOCK_PTR, VARIABLE, STORAGE_CLASS_PUSH_CONSTANT]),
+        ])
+    }
+
+    #[test]
+    fn push_constant_block() {
+        assert_eq!(push_constant_block_size(&push_constant_module()), Some(128));
+    }
+
+    #[test]
+    fn no_push_constants() {
+        let code = module(&[
+            (OP_TYPE_FLOAT, &[FLOAT, 32]),
+            (OP_TYPE_VECTOR, &[VEC4, FLOAT, 4]),
+        ]);
+
+        assert_eq!(push_constant_block_size(&code), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid SPIR-V module")]
+    fn truncated_header() {
+        push_constant_block_size(&push_constant_module()[..3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "malformed SPIR-V")]
+    fn truncated_instruction() {
+        let code = push_constant_module();
+
+        push_constant_block_size(&code[..code.len() - 2]);
+    }
+}
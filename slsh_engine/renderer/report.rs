@@ -0,0 +1,122 @@
+use std::ffi::CStr;
+use std::fmt::Write;
+
+use ash::extensions::khr::Surface;
+use ash::vk;
+
+use super::{enabled_device_features, CheckVkError, REQ_DEVICE_EXTENSIONS};
+
+pub(super) fn gpu_report(
+    instance: &ash::Instance,
+    phys_device: vk::PhysicalDevice,
+    surface_loader: &Surface,
+    surface: vk::SurfaceKHR,
+) -> String {
+    let mut out = String::new();
+
+    unsafe {
+        let properties = instance.get_physical_device_properties(phys_device);
+        let queue_families = instance.get_physical_device_queue_family_properties(phys_device);
+        let mem_properties = instance.get_physical_device_memory_properties(phys_device);
+        let features = instance.get_physical_device_features(phys_device);
+
+        // Headless renderers have no surface to query
+        let (formats, present_modes) = if surface == vk::SurfaceKHR::null() {
+            (Vec::new(), Vec::new())
+        } else {
+            let formats = surface_loader
+                .get_physical_device_surface_formats(phys_device, surface)
+                .check_err("get surface formats");
+
+            let present_modes = surface_loader
+                .get_physical_device_surface_present_modes(phys_device, surface)
+                .check_err("get present modes");
+
+            (formats, present_modes)
+        };
+
+        write_properties(&mut out, &properties);
+        write_queue_families(&mut out, &queue_families);
+        write_memory(&mut out, &mem_properties);
+
+        let _ = writeln!(out, "\nEnabled extensions:");
+
+        for ext in REQ_DEVICE_EXTENSIONS {
+            let _ = writeln!(out, "  {}", ext);
+        }
+
+        let _ = writeln!(out, "\nEnabled features: {:?}", enabled_device_features(&features));
+
+        let _ = writeln!(out, "\nSurface formats:");
+
+        for format in formats {
+            let _ = writeln!(out, "  {:?} {:?}", format.format, format.color_space);
+        }
+
+        let _ = writeln!(out, "\nPresent modes:");
+
+        for mode in present_modes {
+            let _ = writeln!(out, "  {:?}", mode);
+        }
+    }
+
+    out
+}
+
+fn write_properties(out: &mut String, properties: &vk::PhysicalDeviceProperties) {
+    let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) };
+    let limits = &properties.limits;
+
+    let _ = writeln!(out, "Device: {}", name.to_string_lossy());
+    let _ = writeln!(out, "Type: {:?}", properties.device_type);
+    let _ = writeln!(
+        out,
+        "Vendor/device ID: {:#06x}/{:#06x}",
+        properties.vendor_id, properties.device_id
+    );
+    let _ = writeln!(out, "Driver version: {:#x}", properties.driver_version);
+    let _ = writeln!(
+        out,
+        "API version: {}.{}.{}",
+        vk::api_version_major(properties.api_version),
+        vk::api_version_minor(properties.api_version),
+        vk::api_version_patch(properties.api_version)
+    );
+    let _ = writeln!(out, "Max image dimension 2D: {}", limits.max_image_dimension2_d);
+    let _ = writeln!(out, "Max push constants size: {}", limits.max_push_constants_size);
+    let _ = writeln!(out, "Max sampler anisotropy: {}", limits.max_sampler_anisotropy);
+    let _ = writeln!(
+        out,
+        "Framebuffer color sample counts: {:?}",
+        limits.framebuffer_color_sample_counts
+    );
+}
+
+fn write_queue_families(out: &mut String, queue_families: &[vk::QueueFamilyProperties]) {
+    let _ = writeln!(out, "\nQueue families:");
+
+    for (i, family) in queue_families.iter().enumerate() {
+        let _ = writeln!(out, "  {}: {} queue(s), {:?}", i, family.queue_count, family.queue_flags);
+    }
+}
+
+fn write_memory(out: &mut String, mem_properties: &vk::PhysicalDeviceMemoryProperties) {
+    let heap_count = mem_properties.memory_heap_count as usize;
+    let type_count = mem_properties.memory_type_count as usize;
+
+    let _ = writeln!(out, "\nMemory heaps:");
+
+    for (i, heap) in mem_properties.memory_heaps[..heap_count].iter().enumerate() {
+        let _ = writeln!(out, "  {}: {} MiB, {:?}", i, heap.size / (1024 * 1024), heap.flags);
+    }
+
+    let _ = writeln!(out, "\nMemory types:");
+
+    for (i, memory_type) in mem_properties.memory_types[..type_count].iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "  {}: heap {}, {:?}",
+            i, memory_type.heap_index, memory_type.property_flags
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+
+use super::{create_buffer, CheckVkError};
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    // HDR swapchains and ones without transfer support can't be saved as 8-bit PNGs
+    UnsupportedSwapchain(vk::Format),
+    // The window is minimized, its swapchain had to be recreated, or a headless renderer was
+    // resized to zero
+    NotPresented,
+    Io(PathBuf, io::Error),
+    Encode(png::EncodingError),
+}
+
+// A frame read back from the GPU, as 8-bit RGBA rows from top to bottom
+#[derive(Clone, Debug)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// Copy of a presented image in a host-visible buffer, recorded into the frame that renders it
+pub(super) struct ScreenshotReadback {
+    device: ash::Device,
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    size: u64,
+    image: vk::Image,
+    // What the present render pass leaves the image in, restored after copying
+    layout: vk::ImageLayout,
+    extent: vk::Extent2D,
+    // Whether the red and blue channels are swapped compared to RGBA
+    bgra: bool,
+}
+
+impl ScreenshotReadback {
+    pub fn new(
+        device: ash::Device,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        bgra: bool,
+        extent: vk::Extent2D,
+    ) -> Self {
+        let size = u64::from(extent.width) * u64::from(extent.height) * 4;
+
+        let (buffer, memory) = unsafe {
+            create_buffer(
+                &device,
+                device_mem_properties,
+                size,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+            .check_err("create screenshot buffer")
+        };
+
+        Self {
+            device,
+            buffer,
+            memory,
+            size,
+            image,
+            layout,
+            extent,
+            bgra,
+        }
+    }
+
+    // After the present render pass
+    pub unsafe fn record_copy(&self, cmd_buffer: vk::CommandBuffer) {
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let to_transfer = vk::ImageMemoryBarrier {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            old_layout: self.layout,
+            new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range,
+            ..Default::default()
+        };
+
+        self.device.cmd_pipeline_barrier(
+            cmd_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_transfer],
+        );
+
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            },
+        };
+
+        self.device.cmd_copy_image_to_buffer(
+            cmd_buffer,
+            self.image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            self.buffer,
+            &[region],
+        );
+
+        let to_present = vk::ImageMemoryBarrier {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+            src_access_mask: vk::AccessFlags::TRANSFER_READ,
+            dst_access_mask: vk::AccessFlags::empty(),
+            old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: self.layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range,
+            ..Default::default()
+        };
+
+        let to_host = vk::BufferMemoryBarrier {
+            s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::HOST_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: self.buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+
+        self.device.cmd_pipeline_barrier(
+            cmd_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[to_host],
+            &[to_present],
+        );
+    }
+
+    // Only once the frame the copy was recorded into has finished executing
+    pub fn read(&self) -> Screenshot {
+        let mut pixels = vec![0_u8; self.size as usize];
+
+        unsafe {
+            let mapping = self
+                .device
+                .map_memory(self.memory, 0, self.size, vk::MemoryMapFlags::empty())
+                .check_err("map screenshot memory");
+
+            pixels.copy_from_slice(std::slice::from_raw_parts(
+                mapping.cast::<u8>(),
+                self.size as usize,
+            ));
+
+            self.device.unmap_memory(self.memory);
+        }
+
+        // Swapchain alpha is meaningless with opaque compositing
+        for pixel in pixels.chunks_exact_mut(4) {
+            if self.bgra {
+                pixel.swap(0, 2);
+            }
+
+            pixel[3] = u8::MAX;
+        }
+
+        Screenshot {
+            width: self.extent.width,
+            height: self.extent.height,
+            pixels,
+        }
+    }
+}
+
+impl Screenshot {
+    // Creates the directories leading up to the path if they don't exist yet
+    pub fn save_png(&self, path: &Path) -> Result<(), ScreenshotError> {
+        let io_err = |e| ScreenshotError::Io(path.to_owned(), e);
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io_err)?;
+        }
+
+        let file = File::create(path).map_err(io_err)?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(ScreenshotError::Encode)?;
+
+        writer.write_image_data(&self.pixels).map_err(ScreenshotError::Encode)
+    }
+}
+
+impl Drop for ScreenshotReadback {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_buffer(self.buffer, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+impl Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScreenshotError::UnsupportedSwapchain(format) => {
+                write!(f, "can't read back swapchain images of format {:?}", format)
+            }
+            ScreenshotError::NotPresented => write!(f, "no frame was rendered"),
+            ScreenshotError::Io(path, e) => write!(f, "failed to write {}: {}", path.display(), e),
+            ScreenshotError::Encode(e) => write!(f, "failed to encode PNG: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScreenshotError {}
+
+// Whether swapchain images of the format come with red and blue swapped
+pub(super) fn is_bgra(format: vk::Format) -> Result<bool, ScreenshotError> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Ok(true),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(false),
+        _ => Err(ScreenshotError::UnsupportedSwapchain(format)),
+    }
+}
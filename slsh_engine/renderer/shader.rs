@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+#[cfg(feature = "shaderc")]
+use std::fs;
+#[cfg(feature = "shaderc")]
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ShaderSource<'a> {
+    // Precompiled SPIR-V, e.g. from include_shader!
+    Spirv(&'a [u8]),
+    // GLSL compiled at runtime, so user shaders don't have to go through the build system
+    #[cfg(feature = "shaderc")]
+    Glsl(&'a Path),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl<'a> ShaderSource<'a> {
+    // Only GLSL can fail, the error has the compiler output
+    #[cfg_attr(not(feature = "shaderc"), allow(unused_variables))]
+    pub(super) fn to_spirv(self, stage: ShaderStage) -> Result<Cow<'a, [u8]>, String> {
+        match self {
+            ShaderSource::Spirv(code) => Ok(Cow::Borrowed(code)),
+            #[cfg(feature = "shaderc")]
+            ShaderSource::Glsl(path) => try_compile_glsl(path, stage).map(Cow::Owned),
+        }
+    }
+}
+
+#[cfg(feature = "shaderc")]
+impl ShaderStage {
+    pub(super) fn from_file_name(name: &str) -> Option<Self> {
+        match name.rsplit('.').next() {
+            Some("vert") => Some(ShaderStage::Vertex),
+            Some("frag") => Some(ShaderStage::Fragment),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "shaderc")]
+pub(super) fn try_compile_glsl(path: &Path, stage: ShaderStage) -> Result<Vec<u8>, String> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read shader {}: {}", path.display(), e))?;
+
+    let kind = match stage {
+        ShaderStage::Vertex => shaderc::ShaderKind::Vertex,
+        ShaderStage::Fragment => shaderc::ShaderKind::Fragment,
+    };
+
+    let compiler = shaderc::Compiler::new().expect("failed to initialize shaderc");
+    let mut options = shaderc::CompileOptions::new().expect("failed to create shaderc options");
+
+    options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+
+    let file_name = path.to_string_lossy();
+
+    match compiler.compile_into_spirv(&source, kind, &file_name, "main", Some(&options)) {
+        Ok(artifact) => {
+            if artifact.get_num_warnings() > 0 {
+                eprintln!("{}", artifact.get_warning_messages());
+            }
+
+            Ok(artifact.as_binary_u8().to_vec())
+        }
+        Err(e) => Err(format!("failed to compile shader {}:\n{}", path.display(), e)),
+    }
+}
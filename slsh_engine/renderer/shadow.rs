@@ -0,0 +1,438 @@
+use std::f32::consts::FRAC_PI_2;
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+
+use super::debug::DebugMarkers;
+use super::texture::CUBE_FACES;
+use super::{
+    begin_one_time_commands, create_framebuffer, create_image_with_info, end_one_time_commands,
+    resource_err, CheckVkError, RendererError,
+};
+
+// Each light renders the scene meshes six more times a frame
+pub const MAX_SHADOW_LIGHTS: usize = 4;
+
+// Geometry closer to the light than this doesn't cast shadows
+const NEAR_PLANE: f32 = 0.05;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ShadowLightHandle(pub(super) usize);
+
+// Point light casting shadows in every direction, up to radius away from it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ShadowLight {
+    pub position: Vec3,
+    pub radius: f32,
+    // Width and height of each cube face in texels, clamped to what the device supports
+    pub resolution: u32,
+}
+
+// Shadow maps store distance to the light rather than projected depth, so the vertex shader also
+// outputs the position relative to the light in units of its radius
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(super) struct ShadowPushConstants {
+    pub mvp: Mat4,
+    pub light_space: Mat4,
+}
+
+// Cube depth image of a single light, with a view and framebuffer for each face to render into
+// and a cube view to sample from
+pub(super) struct ShadowMap {
+    device: ash::Device,
+    pub light: ShadowLight,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    face_views: Vec<vk::ImageView>,
+    pub cube_view: vk::ImageView,
+    // Same order as the faces
+    pub framebuffers: Vec<vk::Framebuffer>,
+}
+
+// Lights are kept in slots, so that handles stay valid when others are removed
+#[derive(Default)]
+pub(super) struct ShadowMaps {
+    slots: Vec<Option<ShadowMap>>,
+}
+
+impl ShadowLight {
+    // Same conventions as the camera's view and projection, which puts every face in the
+    // orientation that sampling a cube map expects
+    pub(super) fn face_view_projs(&self) -> [Mat4; CUBE_FACES as usize] {
+        let mut proj = Mat4::perspective_lh(FRAC_PI_2, 1.0, self.radius, NEAR_PLANE);
+
+        proj.y_axis.y *= -1.0;
+
+        let faces = [
+            (Vec3::X, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Y),
+            (Vec3::Y, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::Z),
+            (Vec3::Z, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::Y),
+        ];
+
+        faces.map(|(dir, up)| proj * Mat4::look_at_lh(self.position, self.position + dir, up))
+    }
+
+    pub(super) fn light_space(&self) -> Mat4 {
+        Mat4::from_scale(Vec3::splat(1.0 / self.radius)) * Mat4::from_translation(-self.position)
+    }
+}
+
+impl ShadowMap {
+    pub fn new(
+        device: ash::Device,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        format: vk::Format,
+        render_pass: vk::RenderPass,
+        light: ShadowLight,
+    ) -> Result<Self, RendererError> {
+        let size = light.resolution;
+
+        let create_info = vk::ImageCreateInfo {
+            s_type: vk::StructureType::IMAGE_CREATE_INFO,
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: CUBE_FACES,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+
+        let (image, memory) =
+            unsafe { create_image_with_info(&device, device_mem_properties, &create_info)? };
+
+        // Filled in one by one, so that a failure destroys what was created with the map
+        let mut shadow_map = Self {
+            device,
+            light,
+            image,
+            memory,
+            face_views: Vec::with_capacity(CUBE_FACES as usize),
+            cube_view: vk::ImageView::null(),
+            framebuffers: Vec::with_capacity(CUBE_FACES as usize),
+        };
+
+        let device = &shadow_map.device;
+
+        for face in 0..CUBE_FACES {
+            let view = create_depth_view(device, image, format, vk::ImageViewType::TYPE_2D, face)?;
+
+            shadow_map.face_views.push(view);
+        }
+
+        shadow_map.cube_view =
+            create_depth_view(device, image, format, vk::ImageViewType::CUBE, 0)?;
+
+        let extent = vk::Extent2D {
+            width: size,
+            height: size,
+        };
+
+        for &view in &shadow_map.face_views {
+            let framebuffer = create_framebuffer(device, &[view], extent, render_pass)?;
+
+            shadow_map.framebuffers.push(framebuffer);
+        }
+
+        Ok(shadow_map)
+    }
+
+    // Bound in place of missing shadow maps, as every element of a descriptor array has to be
+    // valid. Cleared once, so that it's never sampled in an undefined layout
+    pub fn placeholder(
+        device: ash::Device,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        format: vk::Format,
+        render_pass: vk::RenderPass,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+    ) -> Result<Self, RendererError> {
+        let light = ShadowLight {
+            position: Vec3::ZERO,
+            radius: 1.0,
+            resolution: 1,
+        };
+
+        let shadow_map = Self::new(device, device_mem_properties, format, render_pass, light)?;
+        let device = &shadow_map.device;
+
+        let clear_depth = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 0.0,
+                stencil: 0,
+            },
+        };
+
+        let cmd_buffer = begin_one_time_commands(device, command_pool)?;
+
+        for &framebuffer in &shadow_map.framebuffers {
+            let render_pass_info = vk::RenderPassBeginInfo {
+                s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+                render_pass,
+                framebuffer,
+                render_area: shadow_map.render_area(),
+                clear_value_count: 1,
+                p_clear_values: &clear_depth,
+                ..Default::default()
+            };
+
+            unsafe {
+                device.cmd_begin_render_pass(
+                    cmd_buffer,
+                    &render_pass_info,
+                    vk::SubpassContents::INLINE,
+                );
+                device.cmd_end_render_pass(cmd_buffer);
+            }
+        }
+
+        end_one_time_commands(device, command_pool, queue, cmd_buffer)?;
+
+        Ok(shadow_map)
+    }
+
+    pub fn render_area(&self) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: vk::Extent2D {
+                width: self.light.resolution,
+                height: self.light.resolution,
+            },
+        }
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers, name: &str) {
+        debug.name(self.image, &format!("{} image", name));
+        debug.name(self.memory, &format!("{} memory", name));
+        debug.name(self.cube_view, &format!("{} cube view", name));
+
+        for (i, (view, framebuffer)) in self.face_views.iter().zip(&self.framebuffers).enumerate() {
+            debug.name(*view, &format!("{} face {} view", name, i));
+            debug.name(*framebuffer, &format!("{} face {} framebuffer", name, i));
+        }
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            for framebuffer in self.framebuffers.drain(..) {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+
+            for view in self.face_views.drain(..) {
+                self.device.destroy_image_view(view, None);
+            }
+
+            self.device.destroy_image_view(self.cube_view, None);
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+impl ShadowMaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, shadow_map: ShadowMap) -> ShadowLightHandle {
+        assert!(self.iter().count() < MAX_SHADOW_LIGHTS, "Too many shadow lights");
+
+        let idx = match self.slots.iter().position(Option::is_none) {
+            Some(idx) => idx,
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+        };
+
+        self.slots[idx] = Some(shadow_map);
+
+        ShadowLightHandle(idx)
+    }
+
+    // The shadow map must not be in use by any frame in flight
+    pub fn remove(&mut self, handle: ShadowLightHandle) {
+        if let Some(slot) = self.slots.get_mut(handle.0) {
+            *slot = None;
+        }
+    }
+
+    pub fn get(&self, handle: ShadowLightHandle) -> Option<&ShadowMap> {
+        self.slots.get(handle.0).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, handle: ShadowLightHandle) -> Option<&mut ShadowMap> {
+        self.slots.get_mut(handle.0).and_then(Option::as_mut)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ShadowMap> {
+        self.slots.iter().flatten()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+    }
+}
+
+// Shadow maps are sampled, which not every depth format supports. One of these always is
+pub(super) fn choose_shadow_format(
+    instance: &ash::Instance,
+    phys_device: vk::PhysicalDevice,
+) -> vk::Format {
+    let required =
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+
+    [vk::Format::D32_SFLOAT, vk::Format::D16_UNORM]
+        .into_iter()
+        .find(|&format| {
+            unsafe { instance.get_physical_device_format_properties(phys_device, format) }
+                .optimal_tiling_features
+                .contains(required)
+        })
+        .check_err("find supported shadow map format")
+}
+
+// Depth formats don't have to support linear filtering, and lit.frag compares single texels anyway
+pub(super) fn create_shadow_sampler(device: &ash::Device) -> Result<vk::Sampler, RendererError> {
+    let create_info = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        mag_filter: vk::Filter::NEAREST,
+        min_filter: vk::Filter::NEAREST,
+        mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+        address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        anisotropy_enable: vk::FALSE,
+        max_anisotropy: 1.0,
+        compare_enable: vk::FALSE,
+        compare_op: vk::CompareOp::ALWAYS,
+        min_lod: 0.0,
+        max_lod: 0.0,
+        border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+        unnormalized_coordinates: vk::FALSE,
+        ..Default::default()
+    };
+
+    unsafe { device.create_sampler(&create_info, None) }
+        .map_err(resource_err("create shadow map sampler"))
+}
+
+// Depth-only pass into one face of a shadow map. Depth is cleared to 0 like the scene's, and left
+// ready to be sampled by the scene pass after it
+pub(super) fn create_shadow_render_pass(
+    device: &ash::Device,
+    format: vk::Format,
+) -> Result<vk::RenderPass, RendererError> {
+    let depth_attachment = vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    };
+
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let subpass = vk::SubpassDescription {
+        pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+        p_depth_stencil_attachment: &depth_attachment_ref,
+        ..Default::default()
+    };
+
+    // Depth is written by the fragment shader, so tests happen late
+    let depth_stages =
+        vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+
+    // The previous frame may still be sampling the map
+    let subpass_dependencies = [
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_stage_mask: depth_stages,
+            dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+    ];
+
+    let create_info = vk::RenderPassCreateInfo {
+        s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
+        attachment_count: 1,
+        p_attachments: &depth_attachment,
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        dependency_count: subpass_dependencies.len() as u32,
+        p_dependencies: subpass_dependencies.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe { device.create_render_pass(&create_info, None) }
+        .map_err(resource_err("create shadow render pass"))
+}
+
+// A single face to render into, or all six as a cube
+fn create_depth_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    view_type: vk::ImageViewType,
+    face: u32,
+) -> Result<vk::ImageView, RendererError> {
+    let layer_count = if view_type == vk::ImageViewType::CUBE {
+        CUBE_FACES
+    } else {
+        1
+    };
+
+    let create_info = vk::ImageViewCreateInfo {
+        s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+        view_type,
+        format,
+        components: vk::ComponentMapping::default(),
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: face,
+            layer_count,
+        },
+        image,
+        ..Default::default()
+    };
+
+    unsafe { device.create_image_view(&create_info, None) }
+        .map_err(resource_err("create shadow map view"))
+}
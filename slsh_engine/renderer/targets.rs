@@ -0,0 +1,225 @@
+use ash::vk;
+
+use super::debug::DebugMarkers;
+use super::deferred::{DeferredPath, GBuffer};
+use super::post::{allocate_target_desc_sets, update_target_desc_set};
+use super::texture::create_texture_desc_pool;
+use super::tonemap::HDR_FORMAT;
+use super::{create_framebuffer, create_framebuffers, RenderTarget, RendererError};
+
+// What the targets are created for, the same for the renderer's own and those of its views
+pub(super) struct TargetConfig<'a> {
+    pub device_mem_properties: &'a vk::PhysicalDeviceMemoryProperties,
+    pub depth_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+    pub scene_render_pass: vk::RenderPass,
+    pub post_render_pass: vk::RenderPass,
+    // For sampling the HDR targets in post effects and tonemapping
+    pub desc_set_layout: vk::DescriptorSetLayout,
+    pub sampler: vk::Sampler,
+    pub deferred: Option<&'a DeferredPath>,
+}
+
+// Everything the passes before the present pass draw into, at the size of the image it's presented
+// to. The renderer has one set for its swapchain, and every view one for its own
+pub(super) struct SceneTargets {
+    device: ash::Device,
+    pub extent: vk::Extent2D,
+    // Resolved into the first HDR target, None without MSAA
+    pub color_target: Option<RenderTarget>,
+    // Post effects read from one and write to the other
+    pub hdr_targets: Vec<RenderTarget>,
+    pub depth_target: RenderTarget,
+    // Only with the deferred path
+    pub gbuffer: Option<GBuffer>,
+    pub scene_framebuffer: vk::Framebuffer,
+    // One for each HDR target
+    pub post_framebuffers: Vec<vk::Framebuffer>,
+    desc_pool: vk::DescriptorPool,
+    // One for each HDR target
+    pub desc_sets: Vec<vk::DescriptorSet>,
+}
+
+impl SceneTargets {
+    pub fn new(
+        device: &ash::Device,
+        extent: vk::Extent2D,
+        config: &TargetConfig,
+    ) -> Result<Self, RendererError> {
+        let (color_target, hdr_targets, depth_target) = create_render_targets(
+            device,
+            config.device_mem_properties,
+            config.depth_format,
+            extent,
+            config.samples,
+        )?;
+
+        // Filled in one by one, so that a failure destroys what was created with the targets
+        let mut targets = Self {
+            device: device.clone(),
+            extent,
+            color_target,
+            hdr_targets,
+            depth_target,
+            gbuffer: None,
+            scene_framebuffer: vk::Framebuffer::null(),
+            post_framebuffers: Vec::new(),
+            desc_pool: vk::DescriptorPool::null(),
+            desc_sets: Vec::new(),
+        };
+
+        if let Some(deferred) = config.deferred {
+            let gbuffer = deferred.create_gbuffer(
+                config.device_mem_properties,
+                extent,
+                &targets.depth_target,
+            )?;
+
+            targets.gbuffer = Some(gbuffer);
+        }
+
+        targets.scene_framebuffer = create_scene_framebuffer(
+            device,
+            targets.color_target.as_ref(),
+            &targets.hdr_targets[0],
+            &targets.depth_target,
+            extent,
+            config.scene_render_pass,
+        )?;
+
+        let hdr_target_views: Vec<_> =
+            targets.hdr_targets.iter().map(|target| target.view).collect();
+
+        targets.post_framebuffers =
+            create_framebuffers(device, &hdr_target_views, extent, config.post_render_pass)?;
+        targets.desc_pool = create_texture_desc_pool(device, hdr_target_views.len() as u32)?;
+        targets.desc_sets = allocate_target_desc_sets(
+            device,
+            targets.desc_pool,
+            config.desc_set_layout,
+            hdr_target_views.len(),
+        )?;
+
+        for (&view, &desc_set) in hdr_target_views.iter().zip(&targets.desc_sets) {
+            update_target_desc_set(device, desc_set, view, config.sampler);
+        }
+
+        Ok(targets)
+    }
+
+    pub fn render_area(&self) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.extent,
+        }
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers, name: &str) {
+        if let Some(color_target) = &self.color_target {
+            color_target.set_debug_names(debug, &format!("{} msaa color target", name));
+        }
+
+        for (i, hdr_target) in self.hdr_targets.iter().enumerate() {
+            hdr_target.set_debug_names(debug, &format!("{} hdr color target {}", name, i));
+        }
+
+        self.depth_target.set_debug_names(debug, &format!("{} depth target", name));
+
+        if let Some(gbuffer) = &self.gbuffer {
+            gbuffer.set_debug_names(debug, name);
+        }
+
+        debug.name(self.scene_framebuffer, &format!("{} scene framebuffer", name));
+
+        for (i, framebuffer) in self.post_framebuffers.iter().enumerate() {
+            debug.name(*framebuffer, &format!("{} post-processing framebuffer {}", name, i));
+        }
+
+        debug.name(self.desc_pool, &format!("{} render target descriptor pool", name));
+
+        for (i, desc_set) in self.desc_sets.iter().enumerate() {
+            debug.name(*desc_set, &format!("{} hdr target {} descriptor set", name, i));
+        }
+    }
+}
+
+// Nothing may be using them
+impl Drop for SceneTargets {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_framebuffer(self.scene_framebuffer, None);
+
+            for framebuffer in self.post_framebuffers.drain(..) {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+
+            self.device.destroy_descriptor_pool(self.desc_pool, None);
+        }
+    }
+}
+
+// Multisampled color target if MSAA is enabled, the two HDR targets and depth target
+fn create_render_targets(
+    device: &ash::Device,
+    device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+    depth_format: vk::Format,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+) -> Result<(Option<RenderTarget>, Vec<RenderTarget>, RenderTarget), RendererError> {
+    let color_target = if samples == vk::SampleCountFlags::TYPE_1 {
+        None
+    } else {
+        Some(RenderTarget::new(
+            device,
+            device_mem_properties,
+            HDR_FORMAT,
+            extent,
+            samples,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+            vk::ImageAspectFlags::COLOR,
+        )?)
+    };
+
+    let hdr_targets = (0..2)
+        .map(|_| {
+            RenderTarget::new(
+                device,
+                device_mem_properties,
+                HDR_FORMAT,
+                extent,
+                vk::SampleCountFlags::TYPE_1,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::ImageAspectFlags::COLOR,
+            )
+        })
+        .collect::<Result<_, _>>()?;
+
+    let depth_target = RenderTarget::new(
+        device,
+        device_mem_properties,
+        depth_format,
+        extent,
+        samples,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::ImageAspectFlags::DEPTH,
+    )?;
+
+    Ok((color_target, hdr_targets, depth_target))
+}
+
+fn create_scene_framebuffer(
+    device: &ash::Device,
+    color_target: Option<&RenderTarget>,
+    hdr_target: &RenderTarget,
+    depth_target: &RenderTarget,
+    extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Framebuffer, RendererError> {
+    // Order matches attachments of the render pass
+    let attachments = match color_target {
+        Some(color_target) => vec![color_target.view, depth_target.view, hdr_target.view],
+        None => vec![hdr_target.view, depth_target.view],
+    };
+
+    create_framebuffer(device, &attachments, extent, render_pass)
+}
@@ -0,0 +1,689 @@
+use std::f32::consts::PI;
+use std::ptr;
+
+use ash::vk;
+use glam::{Vec2, Vec3};
+
+use super::debug::DebugMarkers;
+use super::{
+    begin_one_time_commands, create_buffer, create_image, create_image_view,
+    create_image_with_info, end_one_time_commands, resource_err, upload_to_buffer_memory,
+    CheckVkError, RendererError,
+};
+use crate::math;
+
+pub(super) const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+// For normal maps and other data that isn't color
+pub(super) const LINEAR_TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+pub(super) const MAX_TEXTURES: u32 = 64;
+
+// Layers of a cube image, in the order Vulkan expects them: +X, -X, +Y, -Y, +Z, -Z
+pub(super) const CUBE_FACES: u32 = 6;
+
+#[derive(Clone, Copy)]
+pub(super) struct SamplerSettings {
+    pub mip_lod_bias: f32,
+    pub max_anisotropy: Option<f32>,
+}
+
+pub(super) struct Texture {
+    device: ash::Device,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    sampler: vk::Sampler,
+    pub desc_set: vk::DescriptorSet,
+}
+
+impl Texture {
+    pub fn from_rgba(
+        device: ash::Device,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        desc_pool: vk::DescriptorPool,
+        desc_set_layout: vk::DescriptorSetLayout,
+        sampler_settings: SamplerSettings,
+        generate_mips: bool,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Self {
+        assert!(width > 0 && height > 0, "texture must not be empty");
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * 4,
+            "texture data must be tightly packed RGBA8"
+        );
+
+        // Down to 1x1, each level is made by blitting from the previous one
+        let mip_levels = if generate_mips {
+            32 - width.max(height).leading_zeros()
+        } else {
+            1
+        };
+
+        let usage = vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | vk::ImageUsageFlags::SAMPLED;
+
+        let (image, memory) = unsafe {
+            create_image(
+                &device,
+                device_mem_properties,
+                width,
+                height,
+                mip_levels,
+                format,
+                vk::SampleCountFlags::TYPE_1,
+                usage,
+            )
+            .check_err("create texture image")
+        };
+
+        upload_pixels(
+            &device,
+            device_mem_properties,
+            command_pool,
+            queue,
+            image,
+            width,
+            height,
+            mip_levels,
+            1,
+            pixels,
+        );
+
+        let view =
+            create_image_view(&device, image, format, vk::ImageAspectFlags::COLOR, mip_levels)
+                .check_err("create texture view");
+        let sampler = create_sampler(&device, sampler_settings, mip_levels);
+        let desc_set = create_texture_desc_set(&device, desc_pool, desc_set_layout, view, sampler);
+
+        Self {
+            device,
+            image,
+            memory,
+            view,
+            sampler,
+            desc_set,
+        }
+    }
+
+    // Faces are square, tightly packed RGBA8 and follow each other in CUBE_FACES order. Skyboxes
+    // are mostly magnified, so there are no mip levels
+    pub fn cube_from_rgba(
+        device: ash::Device,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        desc_pool: vk::DescriptorPool,
+        desc_set_layout: vk::DescriptorSetLayout,
+        sampler_settings: SamplerSettings,
+        size: u32,
+        faces: &[u8],
+    ) -> Self {
+        assert!(size > 0, "cube faces must not be empty");
+        assert_eq!(
+            faces.len(),
+            size as usize * size as usize * 4 * CUBE_FACES as usize,
+            "cube data must be six tightly packed RGBA8 faces"
+        );
+
+        let usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+
+        let create_info = vk::ImageCreateInfo {
+            s_type: vk::StructureType::IMAGE_CREATE_INFO,
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: vk::ImageType::TYPE_2D,
+            format: TEXTURE_FORMAT,
+            extent: vk::Extent3D {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: CUBE_FACES,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+
+        let (image, memory) =
+            unsafe { create_image_with_info(&device, device_mem_properties, &create_info) }
+                .check_err("create cube texture image");
+
+        upload_pixels(
+            &device,
+            device_mem_properties,
+            command_pool,
+            queue,
+            image,
+            size,
+            size,
+            1,
+            CUBE_FACES,
+            faces,
+        );
+
+        let view = create_cube_image_view(&device, image);
+        let sampler = create_sampler(&device, sampler_settings, 1);
+        let desc_set = create_texture_desc_set(&device, desc_pool, desc_set_layout, view, sampler);
+
+        Self {
+            device,
+            image,
+            memory,
+            view,
+            sampler,
+            desc_set,
+        }
+    }
+
+    // For descriptor sets other than its own
+    pub fn image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers, name: &str) {
+        debug.name(self.image, &format!("{} image", name));
+        debug.name(self.memory, &format!("{} memory", name));
+        debug.name(self.view, &format!("{} view", name));
+        debug.name(self.sampler, &format!("{} sampler", name));
+        debug.name(self.desc_set, &format!("{} descriptor set", name));
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+pub(super) fn supports_mipmap_generation(
+    instance: &ash::Instance,
+    phys_device: vk::PhysicalDevice,
+) -> bool {
+    [TEXTURE_FORMAT, LINEAR_TEXTURE_FORMAT].into_iter().all(|format| {
+        let properties =
+            unsafe { instance.get_physical_device_format_properties(phys_device, format) };
+
+        properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+    })
+}
+
+pub(super) fn create_texture_desc_set_layout(
+    device: &ash::Device,
+) -> Result<vk::DescriptorSetLayout, RendererError> {
+    let binding = vk::DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 1,
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        p_immutable_samplers: ptr::null(),
+    };
+
+    let create_info = vk::DescriptorSetLayoutCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        binding_count: 1,
+        p_bindings: &binding,
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None) }
+        .map_err(resource_err("create texture descriptor set layout"))
+}
+
+pub(super) fn create_texture_desc_pool(
+    device: &ash::Device,
+    max_sets: u32,
+) -> Result<vk::DescriptorPool, RendererError> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: max_sets,
+    };
+
+    let create_info = vk::DescriptorPoolCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+        max_sets,
+        pool_size_count: 1,
+        p_pool_sizes: &pool_size,
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_pool(&create_info, None) }
+        .map_err(resource_err("create texture descriptor pool"))
+}
+
+fn upload_pixels(
+    device: &ash::Device,
+    device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    image: vk::Image,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+    layers: u32,
+    pixels: &[u8],
+) {
+    let size_bytes = pixels.len() as u64;
+
+    let (staging_buffer, staging_memory) = unsafe {
+        create_buffer(
+            device,
+            device_mem_properties,
+            size_bytes,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )
+        .check_err("create texture staging buffer")
+    };
+
+    upload_to_buffer_memory(device, staging_memory, pixels).check_err("upload texture pixels");
+
+    // Layers follow each other in the buffer
+    let region = vk::BufferImageCopy {
+        buffer_offset: 0,
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: vk::ImageSubresourceLayers {
+            layer_count: layers,
+            ..color_subresource_layers(0)
+        },
+        image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+        image_extent: vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        },
+    };
+
+    let cmd_buffer =
+        begin_one_time_commands(device, command_pool).check_err("begin texture upload");
+
+    unsafe {
+        transition_image_layout(
+            device,
+            cmd_buffer,
+            image,
+            0,
+            mip_levels,
+            layers,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+
+        device.cmd_copy_buffer_to_image(
+            cmd_buffer,
+            staging_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        );
+
+        if layers == 1 {
+            generate_mipmaps(device, cmd_buffer, image, width, height, mip_levels);
+        } else {
+            transition_image_layout(
+                device,
+                cmd_buffer,
+                image,
+                0,
+                1,
+                layers,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        }
+    }
+
+    end_one_time_commands(device, command_pool, queue, cmd_buffer).check_err("upload texture");
+
+    unsafe {
+        device.destroy_buffer(staging_buffer, None);
+        device.free_memory(staging_memory, None);
+    }
+}
+
+// Leaves every level in shader read layout. Level 0 is expected to be filled already
+unsafe fn generate_mipmaps(
+    device: &ash::Device,
+    cmd_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+) {
+    let mut mip_width = width as i32;
+    let mut mip_height = height as i32;
+
+    for level in 1..mip_levels {
+        let next_width = (mip_width / 2).max(1);
+        let next_height = (mip_height / 2).max(1);
+
+        transition_image_layout(
+            device,
+            cmd_buffer,
+            image,
+            level - 1,
+            1,
+            1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+
+        let blit = vk::ImageBlit {
+            src_subresource: color_subresource_layers(level - 1),
+            src_offsets: [
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: mip_width,
+                    y: mip_height,
+                    z: 1,
+                },
+            ],
+            dst_subresource: color_subresource_layers(level),
+            dst_offsets: [
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: next_width,
+                    y: next_height,
+                    z: 1,
+                },
+            ],
+        };
+
+        device.cmd_blit_image(
+            cmd_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[blit],
+            vk::Filter::LINEAR,
+        );
+
+        transition_image_layout(
+            device,
+            cmd_buffer,
+            image,
+            level - 1,
+            1,
+            1,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        mip_width = next_width;
+        mip_height = next_height;
+    }
+
+    transition_image_layout(
+        device,
+        cmd_buffer,
+        image,
+        mip_levels - 1,
+        1,
+        1,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+}
+
+fn color_subresource_layers(mip_level: u32) -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+unsafe fn transition_image_layout(
+    device: &ash::Device,
+    cmd_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    base_mip_level: u32,
+    level_count: u32,
+    layer_count: u32,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) {
+    let (src_access_mask, dst_access_mask, src_stage, dst_stage) = match (old_layout, new_layout) {
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => (
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        ),
+        (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        ),
+        _ => panic!("Unsupported layout transition: {:?} -> {:?}", old_layout, new_layout),
+    };
+
+    let barrier = vk::ImageMemoryBarrier {
+        s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+        src_access_mask,
+        dst_access_mask,
+        old_layout,
+        new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level,
+            level_count,
+            base_array_layer: 0,
+            layer_count,
+        },
+        ..Default::default()
+    };
+
+    device.cmd_pipeline_barrier(
+        cmd_buffer,
+        src_stage,
+        dst_stage,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[barrier],
+    );
+}
+
+fn create_sampler(device: &ash::Device, settings: SamplerSettings, mip_levels: u32) -> vk::Sampler {
+    let create_info = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        mag_filter: vk::Filter::LINEAR,
+        min_filter: vk::Filter::LINEAR,
+        mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+        address_mode_u: vk::SamplerAddressMode::REPEAT,
+        address_mode_v: vk::SamplerAddressMode::REPEAT,
+        address_mode_w: vk::SamplerAddressMode::REPEAT,
+        mip_lod_bias: settings.mip_lod_bias,
+        anisotropy_enable: settings.max_anisotropy.is_some().into(),
+        max_anisotropy: settings.max_anisotropy.unwrap_or(1.0),
+        compare_enable: vk::FALSE,
+        compare_op: vk::CompareOp::ALWAYS,
+        min_lod: 0.0,
+        max_lod: mip_levels as f32,
+        border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+        unnormalized_coordinates: vk::FALSE,
+        ..Default::default()
+    };
+
+    unsafe { device.create_sampler(&create_info, None) }.check_err("create sampler")
+}
+
+fn create_texture_desc_set(
+    device: &ash::Device,
+    desc_pool: vk::DescriptorPool,
+    desc_set_layout: vk::DescriptorSetLayout,
+    image_view: vk::ImageView,
+    sampler: vk::Sampler,
+) -> vk::DescriptorSet {
+    let alloc_info = vk::DescriptorSetAllocateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+        descriptor_pool: desc_pool,
+        descriptor_set_count: 1,
+        p_set_layouts: &desc_set_layout,
+        ..Default::default()
+    };
+
+    let desc_set = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .check_err("allocate texture descriptor set")[0];
+
+    let image_info = vk::DescriptorImageInfo {
+        sampler,
+        image_view,
+        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    };
+
+    let desc_write = vk::WriteDescriptorSet {
+        s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: desc_set,
+        dst_binding: 0,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        p_image_info: &image_info,
+        ..Default::default()
+    };
+
+    unsafe {
+        device.update_descriptor_sets(&[desc_write], &[]);
+    }
+
+    desc_set
+}
+
+fn create_cube_image_view(device: &ash::Device, image: vk::Image) -> vk::ImageView {
+    let create_info = vk::ImageViewCreateInfo {
+        s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+        view_type: vk::ImageViewType::CUBE,
+        format: TEXTURE_FORMAT,
+        components: vk::ComponentMapping::default(),
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: CUBE_FACES,
+        },
+        image,
+        ..Default::default()
+    };
+
+    unsafe { device.create_image_view(&create_info, None) }.check_err("create cube image view")
+}
+
+// Resamples a panorama covering 360 degrees horizontally and 180 vertically into cube faces as
+// taken by Texture::cube_from_rgba. The panorama's center looks along +Z
+pub(super) fn equirect_to_cube_faces(
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    face_size: u32,
+) -> Vec<u8> {
+    assert_eq!(
+        pixels.len(),
+        width as usize * height as usize * 4,
+        "panorama data must be tightly packed RGBA8"
+    );
+
+    let mut faces = Vec::with_capacity(face_size as usize * face_size as usize * 4 * 6);
+
+    for face in 0..CUBE_FACES {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let u = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+
+                let dir = cube_face_direction(face, u, v).normalize();
+
+                let longitude = math::atan2(dir.x, dir.z);
+                let latitude = dir.y.clamp(-1.0, 1.0).asin();
+
+                let uv = Vec2::new(longitude / (2.0 * PI) + 0.5, 0.5 - latitude / PI);
+
+                faces.extend_from_slice(&sample_bilinear(width, height, pixels, uv));
+            }
+        }
+    }
+
+    faces
+}
+
+// Direction that texel coordinates in [-1, 1], from the top left of a face, point in
+fn cube_face_direction(face: u32, u: f32, v: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    }
+}
+
+// Wraps around horizontally and clamps vertically
+fn sample_bilinear(width: u32, height: u32, pixels: &[u8], uv: Vec2) -> [u8; 4] {
+    let x = uv.x * width as f32 - 0.5;
+    let y = (uv.y * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let tx = x - x0;
+    let ty = y - y0;
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(i64::from(width)) as usize;
+        let y = (y as u32).min(height - 1) as usize;
+        let idx = (y * width as usize + x) * 4;
+
+        &pixels[idx..idx + 4]
+    };
+
+    let (a, b) = (texel(x0, y0), texel(x0 + 1.0, y0));
+    let (c, d) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+
+    std::array::from_fn(|i| {
+        let top = f32::from(a[i]) * (1.0 - tx) + f32::from(b[i]) * tx;
+        let bottom = f32::from(c[i]) * (1.0 - tx) + f32::from(d[i]) * tx;
+
+        (top * (1.0 - ty) + bottom * ty).round() as u8
+    })
+}
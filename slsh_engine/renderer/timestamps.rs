@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use ash::vk;
+
+use super::{resource_err, DebugMarkers, RendererError};
+
+// Parts of a frame that are timed on the GPU, in the order they're recorded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GpuPass {
+    ShadowMaps,
+    // G-buffer and lighting, zero without the deferred path
+    Deferred,
+    Scene,
+    PostEffects,
+    Present,
+}
+
+// GPU time spent on a frame and on each of its passes. Passes that didn't run take no time
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct GpuStats {
+    pub frame: Duration,
+    passes: [Duration; GpuPass::ALL.len()],
+}
+
+// A timestamp at the start of the frame, then one at the end of each pass
+const QUERIES: u32 = GpuPass::ALL.len() as u32 + 1;
+
+pub(super) struct GpuTimestamps {
+    device: ash::Device,
+    pools: Vec<vk::QueryPool>,
+    // Whether the frame slot's pool has been recorded to, queries can't be read before that
+    recorded: Vec<bool>,
+    // Nanoseconds per tick
+    period: f64,
+    valid_mask: u64,
+    stats: Option<GpuStats>,
+}
+
+impl GpuPass {
+    pub const ALL: [GpuPass; 5] = [
+        GpuPass::ShadowMaps,
+        GpuPass::Deferred,
+        GpuPass::Scene,
+        GpuPass::PostEffects,
+        GpuPass::Present,
+    ];
+}
+
+impl GpuStats {
+    pub fn pass(&self, pass: GpuPass) -> Duration {
+        self.passes[pass as usize]
+    }
+}
+
+impl GpuTimestamps {
+    // None if the queue can't write timestamps
+    pub fn new(
+        device: ash::Device,
+        frames: usize,
+        timestamp_period: f32,
+        timestamp_valid_bits: u32,
+    ) -> Result<Option<Self>, RendererError> {
+        if timestamp_valid_bits == 0 {
+            return Ok(None);
+        }
+
+        let valid_mask = if timestamp_valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1 << timestamp_valid_bits) - 1
+        };
+
+        let mut timestamps = Self {
+            device,
+            pools: Vec::with_capacity(frames),
+            recorded: vec![false; frames],
+            period: f64::from(timestamp_period),
+            valid_mask,
+            stats: None,
+        };
+
+        for _ in 0..frames {
+            timestamps.pools.push(create_query_pool(&timestamps.device)?);
+        }
+
+        Ok(Some(timestamps))
+    }
+
+    pub fn stats(&self) -> Option<GpuStats> {
+        self.stats
+    }
+
+    // Only after the frame's fence has been waited on, and right before it's recorded again
+    pub fn read_results(&mut self, frame: usize) {
+        if !self.recorded[frame] {
+            self.recorded[frame] = true;
+            return;
+        }
+
+        // Timestamp and availability of each query
+        let mut data = [[0_u64; 2]; QUERIES as usize];
+
+        let result = unsafe {
+            self.device.get_query_pool_results(
+                self.pools[frame],
+                0,
+                QUERIES,
+                &mut data,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+
+        match result {
+            Ok(()) => (),
+            Err(vk::Result::NOT_READY) => return,
+            Err(e) => panic!("failed to get timestamp query results: {}", e),
+        }
+
+        let ticks_to_duration = |start: u64, end: u64| {
+            let ticks = end.wrapping_sub(start) & self.valid_mask;
+
+            Duration::from_nanos((ticks as f64 * self.period) as u64)
+        };
+
+        let mut stats = GpuStats {
+            frame: ticks_to_duration(data[0][0], data[QUERIES as usize - 1][0]),
+            ..GpuStats::default()
+        };
+
+        for (pass, bounds) in stats.passes.iter_mut().zip(data.windows(2)) {
+            *pass = ticks_to_duration(bounds[0][0], bounds[1][0]);
+        }
+
+        self.stats = Some(stats);
+    }
+
+    // First thing in the frame's command buffer
+    pub unsafe fn record_frame_start(&self, cmd_buffer: vk::CommandBuffer, frame: usize) {
+        let pool = self.pools[frame];
+
+        self.device.cmd_reset_query_pool(cmd_buffer, pool, 0, QUERIES);
+        self.device.cmd_write_timestamp(cmd_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, pool, 0);
+    }
+
+    // Every pass has to be ended in the order of GpuPass, whether it ran or not
+    pub unsafe fn record_pass_end(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        frame: usize,
+        pass: GpuPass,
+    ) {
+        self.device.cmd_write_timestamp(
+            cmd_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.pools[frame],
+            pass as u32 + 1,
+        );
+    }
+
+    pub fn set_debug_names(&self, debug: &DebugMarkers) {
+        for (i, pool) in self.pools.iter().enumerate() {
+            debug.name(*pool, &format!("frame {} timestamp query pool", i));
+        }
+    }
+}
+
+impl Drop for GpuTimestamps {
+    fn drop(&mut self) {
+        unsafe {
+            for pool in &self.pools {
+                self.device.destroy_query_pool(*pool, None);
+            }
+        }
+    }
+}
+
+fn create_query_pool(device: &ash::Device) -> Result<vk::QueryPool, RendererError> {
+    let create_info = vk::QueryPoolCreateInfo {
+        s_type: vk::StructureType::QUERY_POOL_CREATE_INFO,
+        query_type: vk::QueryType::TIMESTAMP,
+        query_count: QUERIES,
+        ..Default::default()
+    };
+
+    unsafe { device.create_query_pool(&create_info, None) }
+        .map_err(resource_err("create timestamp query pool"))
+}
@@ -0,0 +1,70 @@
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+// The scene is drawn in linear color to this format, so that values above 1 survive until the
+// tonemap pass maps them to what the swapchain can show
+pub(super) const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Tonemapper {
+    // Filmic curve, adds contrast and desaturates highlights
+    #[default]
+    Aces,
+    // Keeps hues, but flattens bright areas
+    Reinhard,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(super) struct TonemapPushConstants {
+    pub exposure: f32,
+    // Tonemapper as numbered in tonemap.frag
+    pub operator: u32,
+    // Transfer function the shader has to apply, see output_encoding
+    pub encoding: u32,
+}
+
+// Surface formats for HDR output, by preference. Their color spaces are only reported with the
+// swapchain colorspace extension enabled
+pub(super) const HDR_SURFACE_FORMATS: [vk::SurfaceFormatKHR; 2] = [
+    // HDR10, Rec. 2020 primaries with the PQ curve
+    vk::SurfaceFormatKHR {
+        format: vk::Format::A2B10G10R10_UNORM_PACK32,
+        color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+    },
+    // scRGB, sRGB primaries but linear and unbounded
+    vk::SurfaceFormatKHR {
+        format: vk::Format::R16G16B16A16_SFLOAT,
+        color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+    },
+];
+
+impl Tonemapper {
+    pub(super) fn shader_index(self) -> u32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::Reinhard => 1,
+        }
+    }
+}
+
+// Output encodings as numbered in tonemap.frag. Scene colors are linear, but UNORM swapchain
+// formats don't convert them to sRGB on write, and HDR color spaces need their own curves
+pub(super) fn output_encoding(swapchain_format: vk::SurfaceFormatKHR) -> u32 {
+    match swapchain_format.color_space {
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT => 2,
+        vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => 3,
+        _ => match swapchain_format.format {
+            vk::Format::B8G8R8A8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32 => 0,
+            _ => 1,
+        },
+    }
+}
+
+pub(super) fn is_hdr(swapchain_format: vk::SurfaceFormatKHR) -> bool {
+    HDR_SURFACE_FORMATS.iter().any(|hdr| {
+        hdr.format == swapchain_format.format && hdr.color_space == swapchain_format.color_space
+    })
+}
@@ -0,0 +1,280 @@
+use std::mem::size_of;
+
+use ash::vk;
+
+use super::{
+    create_buffer, create_command_buffers, create_command_pool, create_semaphore, resource_err,
+    upload_to_buffer_memory, CheckVkError, RendererError,
+};
+
+// Records staging copies on the transfer queue without waiting for them. Copies are batched until
+// the next frame, which waits on the batch's semaphore before using the buffers. When the
+// transfer queue belongs to another family, buffer ownership is released here and acquired on
+// the graphics queue at the start of the frame.
+pub(super) struct Uploader {
+    device: ash::Device,
+    queue: vk::Queue,
+    transfer_family: u32,
+    graphics_family: u32,
+    command_pool: vk::CommandPool,
+    recording: Option<UploadBatch>,
+}
+
+pub(super) struct UploadBatch {
+    device: ash::Device,
+    command_pool: vk::CommandPool,
+    cmd_buffer: vk::CommandBuffer,
+    pub semaphore: vk::Semaphore,
+    staging: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    // Acquire halves of the ownership transfers, empty when both queues are in the same family
+    pub acquire_barriers: Vec<vk::BufferMemoryBarrier>,
+}
+
+impl Uploader {
+    pub fn new(
+        device: ash::Device,
+        queue: vk::Queue,
+        transfer_family: u32,
+        graphics_family: u32,
+    ) -> Result<Self, RendererError> {
+        let command_pool = create_command_pool(&device, transfer_family, false)?;
+
+        Ok(Self {
+            device,
+            queue,
+            transfer_family,
+            graphics_family,
+            command_pool,
+            recording: None,
+        })
+    }
+
+    pub fn command_pool(&self) -> vk::CommandPool {
+        self.command_pool
+    }
+
+    // The returned buffer can't be used until the batch it was recorded in is waited on
+    pub fn upload_buffer<T: Copy>(
+        &mut self,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        usage: vk::BufferUsageFlags,
+        dst_access: vk::AccessFlags,
+        data: &[T],
+    ) -> Result<(vk::Buffer, vk::DeviceMemory), RendererError> {
+        let size_bytes: u64 = (data.len() * size_of::<T>()).try_into().unwrap();
+
+        let (staging_buffer, staging_memory) = unsafe {
+            create_buffer(
+                &self.device,
+                device_mem_properties,
+                size_bytes,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                vk::MemoryPropertyFlags::HOST_VISIBLE,
+            )?
+        };
+
+        let created = self.create_destination(device_mem_properties, usage, staging_memory, data);
+
+        let (buffer, memory) = match created {
+            Ok(created) => created,
+            Err(e) => {
+                unsafe {
+                    self.device.destroy_buffer(staging_buffer, None);
+                    self.device.free_memory(staging_memory, None);
+                }
+
+                return Err(e);
+            }
+        };
+
+        let ownership_transfer = self.transfer_family != self.graphics_family;
+        let transfer_family = self.transfer_family;
+        let graphics_family = self.graphics_family;
+
+        // Started by create_destination
+        let batch = self.recording.as_mut().unwrap();
+        let copy_region = vk::BufferCopy {
+            size: size_bytes,
+            ..Default::default()
+        };
+
+        unsafe {
+            batch.device.cmd_copy_buffer(batch.cmd_buffer, staging_buffer, buffer, &[copy_region]);
+        }
+
+        if ownership_transfer {
+            let release = vk::BufferMemoryBarrier {
+                s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::empty(),
+                src_queue_family_index: transfer_family,
+                dst_queue_family_index: graphics_family,
+                buffer,
+                offset: 0,
+                size: vk::WHOLE_SIZE,
+                ..Default::default()
+            };
+
+            unsafe {
+                batch.device.cmd_pipeline_barrier(
+                    batch.cmd_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[release],
+                    &[],
+                );
+            }
+
+            batch.acquire_barriers.push(vk::BufferMemoryBarrier {
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_access_mask: dst_access,
+                ..release
+            });
+        }
+
+        batch.staging.push((staging_buffer, staging_memory));
+
+        Ok((buffer, memory))
+    }
+
+    // Fills the staging memory and creates the buffer it's copied to, along with the batch to
+    // record the copy in
+    fn create_destination<T: Copy>(
+        &mut self,
+        device_mem_properties: &vk::PhysicalDeviceMemoryProperties,
+        usage: vk::BufferUsageFlags,
+        staging_memory: vk::DeviceMemory,
+        data: &[T],
+    ) -> Result<(vk::Buffer, vk::DeviceMemory), RendererError> {
+        let size_bytes: u64 = (data.len() * size_of::<T>()).try_into().unwrap();
+
+        upload_to_buffer_memory(&self.device, staging_memory, data)?;
+
+        let (buffer, memory) = unsafe {
+            create_buffer(
+                &self.device,
+                device_mem_properties,
+                size_bytes,
+                usage | vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )?
+        };
+
+        if let Err(e) = self.start_batch() {
+            unsafe {
+                self.device.destroy_buffer(buffer, None);
+                self.device.free_memory(memory, None);
+            }
+
+            return Err(e);
+        }
+
+        Ok((buffer, memory))
+    }
+
+    // For a buffer from upload_buffer that won't be used after all. The copy into it is already
+    // recorded, so it's freed along with the batch's staging buffers
+    pub fn discard_buffer(&mut self, buffer: vk::Buffer, memory: vk::DeviceMemory) {
+        if let Some(batch) = &mut self.recording {
+            batch.forget_buffers(&[buffer]);
+            batch.staging.push((buffer, memory));
+        }
+    }
+
+    // Submits everything recorded since the last flush
+    pub fn flush(&mut self) -> Option<UploadBatch> {
+        let batch = self.recording.take()?;
+
+        let submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            command_buffer_count: 1,
+            p_command_buffers: &batch.cmd_buffer,
+            signal_semaphore_count: 1,
+            p_signal_semaphores: &batch.semaphore,
+            ..Default::default()
+        };
+
+        unsafe {
+            self.device.end_command_buffer(batch.cmd_buffer).check_err("end upload cmd buffer");
+
+            self.device
+                .queue_submit(self.queue, &[submit_info], vk::Fence::null())
+                .check_err("submit to transfer queue");
+        }
+
+        Some(batch)
+    }
+
+    // Batches have to be dropped before this
+    pub unsafe fn destroy(&mut self) {
+        self.recording = None;
+        self.device.destroy_command_pool(self.command_pool, None);
+    }
+
+    fn start_batch(&mut self) -> Result<(), RendererError> {
+        if self.recording.is_none() {
+            self.recording = Some(self.begin_batch()?);
+        }
+
+        Ok(())
+    }
+
+    fn begin_batch(&self) -> Result<UploadBatch, RendererError> {
+        let cmd_buffer = create_command_buffers(&self.device, self.command_pool, 1)?[0];
+
+        let semaphore = match create_semaphore(&self.device) {
+            Ok(semaphore) => semaphore,
+            Err(e) => {
+                unsafe {
+                    self.device.free_command_buffers(self.command_pool, &[cmd_buffer]);
+                }
+
+                return Err(e);
+            }
+        };
+
+        let batch = UploadBatch {
+            device: self.device.clone(),
+            command_pool: self.command_pool,
+            cmd_buffer,
+            semaphore,
+            staging: Vec::new(),
+            acquire_barriers: Vec::new(),
+        };
+
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+
+        unsafe { self.device.begin_command_buffer(cmd_buffer, &begin_info) }
+            .map_err(resource_err("begin upload command buffer"))?;
+
+        Ok(batch)
+    }
+}
+
+impl UploadBatch {
+    // For buffers destroyed before the batch was waited on
+    pub fn forget_buffers(&mut self, buffers: &[vk::Buffer]) {
+        self.acquire_barriers.retain(|barrier| !buffers.contains(&barrier.buffer));
+    }
+}
+
+// Only safe once the batch has finished executing, which is after the frame waiting on it
+impl Drop for UploadBatch {
+    fn drop(&mut self) {
+        unsafe {
+            for (buffer, memory) in &self.staging {
+                self.device.destroy_buffer(*buffer, None);
+                self.device.free_memory(*memory, None);
+            }
+
+            self.device.destroy_semaphore(self.semaphore, None);
+            self.device.free_command_buffers(self.command_pool, &[self.cmd_buffer]);
+        }
+    }
+}
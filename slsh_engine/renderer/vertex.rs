@@ -0,0 +1,174 @@
+use std::mem::size_of;
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec3, Vec4};
+
+// Type of a single vertex shader input. Attributes take consecutive locations starting from 0
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VertexAttribute {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    // Four normalized bytes, read as a vec4 in the shader
+    Rgba8,
+}
+
+// Vertex types that can be uploaded as they are. Attributes are listed in field order and have to
+// be tightly packed, which #[repr(C)] structs of f32 and glam vectors are
+pub trait Vertex: Pod {
+    const ATTRIBUTES: &'static [VertexAttribute];
+}
+
+// What mesh.vert expects, the layout of Renderer::add_mesh
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct TexturedVertex {
+    pub position: Vec3,
+    pub uv: Vec2,
+}
+
+// What lit.vert expects, the layout of Renderer::add_lit_mesh
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct LitVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+
+// What pbr.vert expects, the layout of Renderer::add_pbr_mesh. Tangents are glTF's: XYZ points
+// along increasing U and W is the handedness of the bitangent, 1 or -1
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PbrVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub tangent: Vec4,
+    pub uv: Vec2,
+}
+
+// Used by the skybox, grid and crosshair
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(super) struct Pos2Vertex {
+    pub position: Vec2,
+}
+
+// Used by the occlusion query box
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(super) struct Pos3Vertex {
+    pub position: Vec3,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct VertexLayout {
+    stride: u32,
+    attributes: &'static [VertexAttribute],
+}
+
+impl Vertex for TexturedVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute::Vec3, VertexAttribute::Vec2];
+}
+
+impl Vertex for LitVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::Vec3,
+        VertexAttribute::Vec3,
+        VertexAttribute::Vec2,
+    ];
+}
+
+impl Vertex for PbrVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::Vec3,
+        VertexAttribute::Vec3,
+        VertexAttribute::Vec4,
+        VertexAttribute::Vec2,
+    ];
+}
+
+impl Vertex for Pos2Vertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute::Vec2];
+}
+
+impl Vertex for Pos3Vertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute::Vec3];
+}
+
+impl VertexAttribute {
+    fn format(self) -> vk::Format {
+        match self {
+            VertexAttribute::Float => vk::Format::R32_SFLOAT,
+            VertexAttribute::Vec2 => vk::Format::R32G32_SFLOAT,
+            VertexAttribute::Vec3 => vk::Format::R32G32B32_SFLOAT,
+            VertexAttribute::Vec4 => vk::Format::R32G32B32A32_SFLOAT,
+            VertexAttribute::Rgba8 => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+
+    fn size(self) -> u32 {
+        let size_f32 = size_of::<f32>() as u32;
+
+        match self {
+            VertexAttribute::Float => size_f32,
+            VertexAttribute::Vec2 => size_f32 * 2,
+            VertexAttribute::Vec3 => size_f32 * 3,
+            VertexAttribute::Vec4 => size_f32 * 4,
+            VertexAttribute::Rgba8 => 4,
+        }
+    }
+}
+
+impl VertexLayout {
+    pub fn of<V: Vertex>() -> Self {
+        let stride = size_of::<V>() as u32;
+        let packed_size: u32 = V::ATTRIBUTES.iter().map(|attr| attr.size()).sum();
+
+        assert_eq!(stride, packed_size, "Vertex attributes don't match the vertex size");
+
+        Self {
+            stride,
+            attributes: V::ATTRIBUTES,
+        }
+    }
+
+    pub fn stride(self) -> u32 {
+        self.stride
+    }
+
+    pub fn first_attribute(self) -> Option<VertexAttribute> {
+        self.attributes.first().copied()
+    }
+
+    pub fn binding_desc(self) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: self.stride,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    pub fn attribute_descs(self) -> Vec<vk::VertexInputAttributeDescription> {
+        let mut offset = 0;
+
+        self.attributes
+            .iter()
+            .zip(0..)
+            .map(|(attr, location)| {
+                let desc = vk::VertexInputAttributeDescription {
+                    binding: 0,
+                    location,
+                    format: attr.format(),
+                    offset,
+                };
+
+                offset += attr.size();
+
+                desc
+            })
+            .collect()
+    }
+}
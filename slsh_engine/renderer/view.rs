@@ -0,0 +1,158 @@
+use ash::extensions::khr::{Surface, Swapchain};
+use ash::vk;
+
+use super::targets::SceneTargets;
+use super::{
+    choose_swapchain_extent, create_framebuffers, create_image_views, create_semaphore,
+    create_swapchain, get_surface_capabilities, get_swapchain_images, QueueFamilyIndices,
+    RendererError, VSyncMode,
+};
+
+// What view swapchains are created with, the same as the renderer's own swapchain so that they
+// work with its present render pass
+pub(super) struct ViewConfig {
+    pub phys_device: vk::PhysicalDevice,
+    pub queue_family_indices: QueueFamilyIndices,
+    pub format: vk::SurfaceFormatKHR,
+    pub vsync: VSyncMode,
+    pub requested_images: Option<u32>,
+    pub render_pass: vk::RenderPass,
+}
+
+// Another window showing the renderer's scene from a camera of its own, drawn into targets of its
+// own at the size of its swapchain
+pub(super) struct WindowView {
+    device: ash::Device,
+    surface_loader: Surface,
+    swapchain_loader: Swapchain,
+    pub surface: vk::SurfaceKHR,
+    pub swapchain: vk::SwapchainKHR,
+    pub window_extent: vk::Extent2D,
+    pub extent: vk::Extent2D,
+    image_views: Vec<vk::ImageView>,
+    // One for each swapchain image
+    pub framebuffers: Vec<vk::Framebuffer>,
+    // Created by the renderer after each recreate, as it has the render passes and layouts
+    pub targets: Option<SceneTargets>,
+    // One for each frame in flight, like the renderer's own
+    pub image_available: Vec<vk::Semaphore>,
+    pub render_finished: Vec<vk::Semaphore>,
+    // The swapchain is created by the first recreate
+    pub outdated: bool,
+}
+
+impl WindowView {
+    // Owns the surface from then on, destroying it along with the view even if this fails
+    pub fn new(
+        device: ash::Device,
+        surface_loader: Surface,
+        swapchain_loader: Swapchain,
+        surface: vk::SurfaceKHR,
+        window_extent: vk::Extent2D,
+        frames_in_flight: usize,
+    ) -> Result<Self, RendererError> {
+        let mut view = Self {
+            device,
+            surface_loader,
+            swapchain_loader,
+            surface,
+            swapchain: vk::SwapchainKHR::null(),
+            window_extent,
+            extent: window_extent,
+            image_views: Vec::new(),
+            framebuffers: Vec::new(),
+            targets: None,
+            image_available: Vec::with_capacity(frames_in_flight),
+            render_finished: Vec::with_capacity(frames_in_flight),
+            outdated: true,
+        };
+
+        for _ in 0..frames_in_flight {
+            view.image_available.push(create_semaphore(&view.device)?);
+            view.render_finished.push(create_semaphore(&view.device)?);
+        }
+
+        Ok(view)
+    }
+
+    // Whether the surface can show images in the renderer's swapchain format
+    pub fn supports_format(
+        &self,
+        phys_device: vk::PhysicalDevice,
+        format: vk::SurfaceFormatKHR,
+    ) -> Result<bool, RendererError> {
+        let formats = unsafe {
+            self.surface_loader.get_physical_device_surface_formats(phys_device, self.surface)
+        }
+        .map_err(RendererError::Swapchain)?;
+
+        Ok(formats.iter().any(|f| f.format == format.format && f.color_space == format.color_space))
+    }
+
+    // Stays outdated while the window is minimized
+    pub unsafe fn recreate(&mut self, config: &ViewConfig) -> Result<(), RendererError> {
+        let capabilities =
+            get_surface_capabilities(config.phys_device, &self.surface_loader, self.surface)?;
+        let extent = choose_swapchain_extent(self.window_extent, &capabilities);
+
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(());
+        }
+
+        self.destroy_swapchain();
+
+        self.extent = extent;
+        self.swapchain = create_swapchain(
+            config.phys_device,
+            self.surface,
+            &self.surface_loader,
+            &capabilities,
+            config.format,
+            extent,
+            config.vsync,
+            config.requested_images,
+            &self.swapchain_loader,
+            &config.queue_family_indices,
+        )?;
+
+        let images = get_swapchain_images(&self.swapchain_loader, self.swapchain)?;
+
+        self.image_views = create_image_views(&self.device, config.format, &images)?;
+        self.framebuffers =
+            create_framebuffers(&self.device, &self.image_views, extent, config.render_pass)?;
+        self.outdated = false;
+
+        Ok(())
+    }
+
+    unsafe fn destroy_swapchain(&mut self) {
+        self.device.device_wait_idle().unwrap();
+
+        self.targets = None;
+
+        for fb in self.framebuffers.drain(..) {
+            self.device.destroy_framebuffer(fb, None);
+        }
+
+        for image_view in self.image_views.drain(..) {
+            self.device.destroy_image_view(image_view, None);
+        }
+
+        self.swapchain_loader.destroy_swapchain(self.swapchain, None);
+        self.swapchain = vk::SwapchainKHR::null();
+    }
+}
+
+impl Drop for WindowView {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy_swapchain();
+
+            for &sem in self.image_available.iter().chain(&self.render_finished) {
+                self.device.destroy_semaphore(sem, None);
+            }
+
+            self.surface_loader.destroy_surface(self.surface, None);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RewindConfig {
+    pub seconds: f32,
+    // Upper bound on the memory taken by stored states, which can shorten the rewind window. Only
+    // their own size counts, not what they keep on the heap
+    pub memory_budget: usize,
+}
+
+// Rolling history of simulation states, one per tick. Once full, the oldest state is dropped for
+// every new one, so rewinding can go back at most the configured number of seconds.
+pub struct RewindBuffer<T> {
+    states: VecDeque<T>,
+    capacity: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self {
+            seconds: 10.0,
+            memory_budget: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl<T> RewindBuffer<T> {
+    pub fn new(config: &RewindConfig, ticks_per_second: u32) -> Self {
+        let by_time = (config.seconds * ticks_per_second as f32) as usize;
+        let by_memory = config.memory_budget / size_of::<T>().max(1);
+        let capacity = by_time.min(by_memory);
+
+        Self {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, state: T) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+
+        self.states.push_back(state);
+    }
+
+    // Steps one tick back in time. The returned state is removed, so that simulating onwards from
+    // it overwrites the rewound future
+    pub fn step_back(&mut self) -> Option<T> {
+        self.states.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(seconds: f32, memory_budget: usize) -> RewindConfig {
+        RewindConfig {
+            seconds,
+            memory_budget,
+        }
+    }
+
+    #[test]
+    fn capacity_by_time_and_memory() {
+        assert_eq!(RewindBuffer::<u64>::new(&config(2.0, 1024), 60).capacity(), 120);
+        // 64 states of 8 bytes fit in the budget, fewer than 2 seconds of them
+        assert_eq!(RewindBuffer::<u64>::new(&config(2.0, 512), 60).capacity(), 64);
+        assert_eq!(RewindBuffer::<u64>::new(&config(0.0, 512), 60).capacity(), 0);
+    }
+
+    #[test]
+    fn oldest_states_are_evicted() {
+        let mut buffer = RewindBuffer::new(&config(1.0, 1024), 3);
+
+        for state in 0..5u64 {
+            buffer.push(state);
+        }
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.step_back(), Some(4));
+        assert_eq!(buffer.step_back(), Some(3));
+        assert_eq!(buffer.step_back(), Some(2));
+        assert_eq!(buffer.step_back(), None);
+    }
+
+    #[test]
+    fn step_back_removes_the_future() {
+        let mut buffer = RewindBuffer::new(&config(1.0, 1024), 10);
+
+        for state in 0..4u64 {
+            buffer.push(state);
+        }
+
+        assert_eq!(buffer.step_back(), Some(3));
+        assert_eq!(buffer.step_back(), Some(2));
+
+        buffer.push(10);
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.step_back(), Some(10));
+        assert_eq!(buffer.step_back(), Some(1));
+    }
+
+    #[test]
+    fn nothing_is_kept_without_capacity() {
+        let mut buffer = RewindBuffer::new(&config(0.0, 1024), 60);
+
+        buffer.push(1u64);
+
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.step_back(), None);
+    }
+
+    #[test]
+    fn clear() {
+        let mut buffer = RewindBuffer::new(&config(1.0, 1024), 10);
+
+        buffer.push(1u64);
+        buffer.push(2);
+        buffer.clear();
+
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.step_back(), None);
+    }
+}
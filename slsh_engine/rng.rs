@@ -0,0 +1,161 @@
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use glam::Vec3;
+
+use crate::{crash, math};
+
+// Independent random streams, one per system. Drawing more numbers in one of them doesn't shift
+// the sequences the others get, so e.g. adding particles doesn't change gameplay outcomes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stream {
+    Gameplay,
+    Particles,
+    Procedural,
+}
+
+// Engine-wide seed that all streams are derived from. Recording it is enough to reproduce a run
+pub struct RngService {
+    seed: u64,
+}
+
+// PCG-XSH-RR 32-bit generator
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+impl RngService {
+    pub fn new(seed: u64) -> Self {
+        crash::set_context("rng seed", seed.to_string());
+
+        Self { seed }
+    }
+
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+
+        Self::new(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&self, stream: Stream) -> Rng {
+        Rng::new(self.seed, stream as u64)
+    }
+}
+
+impl Rng {
+    const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(splitmix64(seed));
+        rng.next_u32();
+
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+
+        self.state = old.wrapping_mul(Self::MULTIPLIER).wrapping_add(self.inc);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+
+        xorshifted.rotate_right(rot)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        (u64::from(self.next_u32()) << 32) | u64::from(self.next_u32())
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub fn range_f32(&mut self, range: Range<f32>) -> f32 {
+        range.start + (range.end - range.start) * self.next_f32()
+    }
+
+    // Lemire's multiply-shift, rejecting the values that would bias the result
+    pub fn range_u32(&mut self, range: Range<u32>) -> u32 {
+        assert!(range.start < range.end, "Empty range");
+
+        let span = range.end - range.start;
+        let threshold = span.wrapping_neg() % span;
+
+        loop {
+            let product = u64::from(self.next_u32()) * u64::from(span);
+
+            if product as u32 >= threshold {
+                return range.start + (product >> 32) as u32;
+            }
+        }
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn unit_vec3(&mut self) -> Vec3 {
+        let z = self.range_f32(-1.0..1.0);
+        let angle = self.range_f32(0.0..std::f32::consts::TAU);
+        let r = (1.0 - z * z).sqrt();
+        let (sin, cos) = math::sin_cos(angle);
+
+        Vec3::new(r * cos, r * sin, z)
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_vec3_is_normalized() {
+        let mut rng = Rng::new(1234, 0);
+
+        for _ in 0..1000 {
+            let v = rng.unit_vec3();
+
+            assert!((v.length() - 1.0).abs() < 1e-5, "{:?}", v);
+        }
+    }
+
+    fn take(rng: &mut Rng, count: usize) -> Vec<u32> {
+        (0..count).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let service = RngService { seed: 42 };
+        let mut gameplay = service.stream(Stream::Gameplay);
+        let mut particles = service.stream(Stream::Particles);
+
+        let expected = take(&mut service.stream(Stream::Gameplay), 16);
+
+        for _ in 0..100 {
+            particles.unit_vec3();
+        }
+
+        assert_eq!(take(&mut gameplay, 16), expected);
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt::{self, Display, Write as _};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use crate::camera::{ClipPlanes, MotionSettings};
+use crate::config::{self, parse_bool, parse_float};
+
+// User options that persist between runs, in the same TOML subset as the HUD layout:
+//
+//     [motion]
+//     shake = false
+//     roll = false
+//     max_fov_speed = 90 # degrees per second
+//
+//     [view]
+//     near = 0.05
+//     far = 4096
+//
+// Missing keys keep their defaults
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    pub motion: MotionSettings,
+    pub clip_planes: ClipPlanes,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(PathBuf, io::Error),
+    Parse(usize, String),
+    // Values that are fine on their own but not together
+    Invalid(String),
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        let src = fs::read_to_string(path).map_err(|e| SettingsError::Io(path.to_owned(), e))?;
+
+        Self::parse(&src)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SettingsError> {
+        fs::write(path, self.serialize()).map_err(|e| SettingsError::Io(path.to_owned(), e))
+    }
+
+    pub fn parse(src: &str) -> Result<Self, SettingsError> {
+        let mut settings = Self::default();
+
+        config::parse(src, |section, key, value| match section {
+            "motion" => set_motion(&mut settings.motion, key, value),
+            "view" => set_view(&mut settings.clip_planes, key, value),
+            _ => Err(format!("unknown section {}", section)),
+        })
+        .map_err(|(line, msg)| SettingsError::Parse(line, msg))?;
+
+        let planes = settings.clip_planes;
+
+        if planes.far <= planes.near {
+            let msg = format!("far plane {} must be beyond near plane {}", planes.far, planes.near);
+            return Err(SettingsError::Invalid(msg));
+        }
+
+        Ok(settings)
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+        let motion = &self.motion;
+
+        let _ = writeln!(out, "[motion]");
+        let _ = writeln!(out, "shake = {}", motion.shake);
+        let _ = writeln!(out, "roll = {}", motion.roll);
+
+        if let Some(speed) = motion.max_fov_speed {
+            let _ = writeln!(out, "max_fov_speed = {}", speed.to_degrees());
+        }
+
+        let _ = writeln!(out, "\n[view]");
+        let _ = writeln!(out, "near = {}", self.clip_planes.near);
+        let _ = writeln!(out, "far = {}", self.clip_planes.far);
+
+        out
+    }
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(path, e) => write!(f, "failed to access {}: {}", path.display(), e),
+            SettingsError::Parse(line, msg) => write!(f, "line {}: {}", line, msg),
+            SettingsError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+fn set_view(planes: &mut ClipPlanes, key: &str, value: &str) -> Result<(), String> {
+    let distance = parse_float(value)?;
+
+    if distance <= 0.0 {
+        return Err(format!("{} must be positive, got {}", key, value));
+    }
+
+    match key {
+        "near" => planes.near = distance,
+        "far" => planes.far = distance,
+        _ => return Err(format!("unknown key {}", key)),
+    }
+
+    Ok(())
+}
+
+fn set_motion(motion: &mut MotionSettings, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "shake" => motion.shake = parse_bool(value)?,
+        "roll" => motion.roll = parse_bool(value)?,
+        "max_fov_speed" => {
+            let speed = parse_float(value)?;
+
+            if speed <= 0.0 {
+                return Err(format!("max_fov_speed must be positive, got {}", value));
+            }
+
+            motion.max_fov_speed = Some(speed.to_radians());
+        }
+        _ => return Err(format!("unknown key {}", key)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(src: &str) -> String {
+        match Settings::parse(src) {
+            Err(SettingsError::Invalid(msg)) => msg,
+            other => panic!("expected invalid settings, got {:?}", other),
+        }
+    }
+
+    fn parse_error(src: &str) -> (usize, String) {
+        match Settings::parse(src) {
+            Err(SettingsError::Parse(line, msg)) => (line, msg),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let settings = Settings::parse("").unwrap();
+
+        assert!(settings.motion.shake);
+        assert!(settings.motion.roll);
+        assert_eq!(settings.motion.max_fov_speed, None);
+        assert_eq!(settings.clip_planes.near, 0.05);
+        assert_eq!(settings.clip_planes.far, 4096.0);
+    }
+
+    #[test]
+    fn motion() {
+        let src = "\
+[motion]
+shake = false
+roll = false
+max_fov_speed = 90
+";
+        let motion = Settings::parse(src).unwrap().motion;
+
+        assert!(!motion.shake);
+        assert!(!motion.roll);
+        assert_eq!(motion.max_fov_speed, Some(90f32.to_radians()));
+
+        assert_eq!(parse_error("[motion]\nshake = 1").0, 2);
+        assert_eq!(parse_error("[motion]\nmax_fov_speed = 0").0, 2);
+        assert_eq!(parse_error("[motion]\nmax_fov_speed = -90").0, 2);
+        assert_eq!(parse_error("[motion]\nmax_fov_speed = inf").0, 2);
+        assert_eq!(parse_error("[motion]\nblur = true").1, "unknown key blur");
+    }
+
+    #[test]
+    fn clip_planes() {
+        let planes = Settings::parse("[view]\nnear = 0.1\nfar = 100").unwrap().clip_planes;
+
+        assert_eq!((planes.near, planes.far), (0.1, 100.0));
+
+        // Only one of them set is checked against the default of the other
+        assert!(Settings::parse("[view]\nfar = 1").is_ok());
+        assert_eq!(invalid("[view]\nfar = 0.01"), "far plane 0.01 must be beyond near plane 0.05");
+        assert_eq!(
+            invalid("[view]\nnear = 10\nfar = 10"),
+            "far plane 10 must be beyond near plane 10"
+        );
+
+        assert_eq!(parse_error("[view]\nnear = 0").1, "near must be positive, got 0");
+        assert_eq!(parse_error("[view]\nnear = -1").0, 2);
+        assert_eq!(parse_error("[view]\nfar = NaN").1, "expected a number, got NaN");
+        assert_eq!(parse_error("[view]\nfar = inf").0, 2);
+        assert_eq!(parse_error("[view]\nfov = 90").1, "unknown key fov");
+        assert_eq!(parse_error("[audio]\nvolume = 1").1, "unknown section audio");
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        let mut settings = Settings::default();
+
+        settings.motion.shake = false;
+        settings.motion.max_fov_speed = Some(45f32.to_radians());
+        settings.clip_planes.near = 0.25;
+
+        let parsed = Settings::parse(&settings.serialize()).unwrap();
+
+        assert!(!parsed.motion.shake);
+        assert!(parsed.motion.roll);
+        assert!((parsed.motion.max_fov_speed.unwrap() - 45f32.to_radians()).abs() < 1e-6);
+        assert_eq!(parsed.clip_planes.near, 0.25);
+        assert_eq!(parsed.clip_planes.far, 4096.0);
+    }
+}
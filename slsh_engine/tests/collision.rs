@@ -0,0 +1,210 @@
+// Swept movement against colliders at speeds that would tunnel through thin walls if entities
+// were only moved to their end position each tick, and collisions between entities
+
+use glam::Vec3;
+use slsh_engine::broadphase::Aabb;
+use slsh_engine::camera::Camera;
+use slsh_engine::input::InputHandler;
+use slsh_engine::physics::{self, CollisionWorld, Entity, EntityCollision};
+
+const DT: f64 = 1.0 / 60.0;
+const FAST: f32 = 1000.0;
+const WALL_X: f32 = 10.0;
+
+// Floor split in two along x, and a wall thinner than a tick of fast movement
+fn test_world() -> CollisionWorld {
+    let mut world = CollisionWorld::new();
+
+    world.add_box(Aabb::new(Vec3::new(-50.0, -1.0, -50.0), Vec3::new(5.0, 0.0, 50.0)));
+    world.add_box(Aabb::new(Vec3::new(5.0, -1.0, -50.0), Vec3::new(50.0, 0.0, 50.0)));
+    world.add_box(Aabb::new(Vec3::new(WALL_X, 0.0, -20.0), Vec3::new(WALL_X + 0.05, 8.0, 20.0)));
+
+    world
+}
+
+fn unit_box() -> Aabb {
+    Aabb::from_center(Vec3::ZERO, Vec3::splat(0.5))
+}
+
+fn thin_wall() -> Aabb {
+    Aabb::new(Vec3::new(2.0, -1.0, -1.0), Vec3::new(2.1, 1.0, 1.0))
+}
+
+fn run(entity: &mut Entity, world: &CollisionWorld, velocity: Vec3, ticks: u32) {
+    let mut camera = Camera::new(1.0);
+    let input = InputHandler::new(0, 0);
+
+    for tick in 0..ticks {
+        entity.set_velocity(velocity);
+        entity.update(&input, &mut camera, world, DT, f64::from(tick) * DT);
+    }
+}
+
+#[test]
+fn fast_entity_stops_at_thin_wall() {
+    let world = test_world();
+    let mut entity = Entity::new(0.0, 0.0, 0.0);
+
+    // A single tick moves further than from the start to past the wall
+    assert!(FAST * DT as f32 > WALL_X + 1.0);
+
+    run(&mut entity, &world, Vec3::new(FAST, 0.0, 0.0), 1);
+
+    let bounds = entity.bounds();
+
+    assert!(bounds.max.x <= WALL_X, "went through the wall to {}", bounds.max.x);
+    assert!(bounds.max.x > WALL_X - 0.01, "stopped short at {}", bounds.max.x);
+    assert_eq!(entity.velocity().x, 0.0);
+
+    run(&mut entity, &world, Vec3::new(FAST, 0.0, 0.0), 30);
+
+    assert!(entity.bounds().max.x <= WALL_X);
+}
+
+#[test]
+fn fast_entity_slides_along_thin_wall() {
+    let world = test_world();
+    let mut entity = Entity::new(0.0, 0.0, 0.0);
+
+    run(&mut entity, &world, Vec3::new(FAST, 0.0, 60.0), 1);
+
+    assert!(entity.bounds().max.x <= WALL_X);
+    assert!(entity.position().z > 0.5, "didn't slide, z = {}", entity.position().z);
+}
+
+#[test]
+fn world_sweep_reports_wall_contact() {
+    let world = test_world();
+    let entity = Entity::new(0.0, 0.0, 0.0);
+    let motion = Vec3::new(FAST * DT as f32, 0.0, 0.0);
+    let hit = world.sweep(&entity.bounds(), motion).expect("missed the wall");
+
+    assert_eq!(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+    assert!((entity.bounds().max.x + motion.x * hit.time - WALL_X).abs() < 1e-4);
+}
+
+#[test]
+fn sweep_with_motion_along_one_axis() {
+    let (time, normal) = unit_box().sweep(Vec3::new(4.0, 0.0, 0.0), &thin_wall()).unwrap();
+
+    assert_eq!(time, 0.375);
+    assert_eq!(normal, Vec3::new(-1.0, 0.0, 0.0));
+}
+
+#[test]
+fn sweep_with_motion_along_two_axes() {
+    let (time, normal) = unit_box().sweep(Vec3::new(4.0, 0.0, 0.4), &thin_wall()).unwrap();
+
+    assert_eq!(time, 0.375);
+    assert_eq!(normal, Vec3::new(-1.0, 0.0, 0.0));
+
+    // Passes above the wall, the axis without motion never overlaps
+    let above = unit_box().translated(Vec3::new(0.0, 3.0, 0.0));
+
+    assert_eq!(above.sweep(Vec3::new(4.0, 0.0, 0.4), &thin_wall()), None);
+}
+
+#[test]
+fn sweep_without_motion() {
+    assert_eq!(unit_box().sweep(Vec3::ZERO, &thin_wall()), None);
+    assert!(CollisionWorld::new().sweep(&unit_box(), Vec3::ZERO).is_none());
+}
+
+// Resting exactly on a slab boundary of an axis without motion used to compute 0 * inf
+#[test]
+fn sweep_flush_with_face_slides_past() {
+    let on_top = unit_box().translated(Vec3::new(0.0, 1.5, 0.0));
+
+    assert_eq!(on_top.sweep(Vec3::new(4.0, 0.0, 0.0), &thin_wall()), None);
+
+    // Walking across the seam between the two floor boxes
+    let world = test_world();
+    let mut entity = Entity::new(3.0, 0.0, 0.0);
+
+    run(&mut entity, &world, Vec3::new(20.0, 0.0, 0.0), 10);
+
+    assert!(entity.position().x > 5.0, "stuck at x = {}", entity.position().x);
+}
+
+#[test]
+fn sweep_starting_in_contact() {
+    let touching = unit_box().translated(Vec3::new(1.5, 0.0, 0.0));
+
+    assert_eq!(
+        touching.sweep(Vec3::new(1.0, 0.0, 0.0), &thin_wall()),
+        Some((0.0, Vec3::new(-1.0, 0.0, 0.0)))
+    );
+    assert_eq!(touching.sweep(Vec3::new(-1.0, 0.0, 0.0), &thin_wall()), None);
+    assert_eq!(touching.sweep(Vec3::new(0.0, 0.0, 1.0), &thin_wall()), None);
+}
+
+fn overlapping_pair() -> (Entity, Entity) {
+    (Entity::new(0.0, 0.0, 0.0), Entity::new(0.5, 0.0, 0.0))
+}
+
+#[test]
+fn pass_through_leaves_entities_overlapping() {
+    let world = CollisionWorld::new();
+    let (mut a, mut b) = overlapping_pair();
+
+    physics::resolve_entity_collisions(
+        &mut [&mut a, &mut b],
+        &world,
+        EntityCollision::PassThrough,
+        DT,
+    );
+
+    assert!(a.bounds().intersects(&b.bounds()));
+    assert_eq!(a.velocity(), Vec3::ZERO);
+    assert_eq!(b.velocity(), Vec3::ZERO);
+}
+
+#[test]
+fn colliding_entities_are_separated() {
+    let world = CollisionWorld::new();
+    let (mut a, mut b) = overlapping_pair();
+
+    physics::resolve_entity_collisions(&mut [&mut a, &mut b], &world, EntityCollision::Collide, DT);
+
+    assert!((a.bounds().max.x - b.bounds().min.x).abs() < 1e-5);
+    assert!((a.position().x + b.position().x - 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn separation_stops_at_walls() {
+    let world = test_world();
+    let mut a = Entity::new(WALL_X - 0.81, 0.0, 0.0);
+    let mut b = Entity::new(WALL_X - 1.31, 0.0, 0.0);
+
+    physics::resolve_entity_collisions(&mut [&mut a, &mut b], &world, EntityCollision::Collide, DT);
+
+    assert!(a.bounds().max.x <= WALL_X);
+    assert!(a.position().x > WALL_X - 0.81);
+    assert!(b.position().x < WALL_X - 1.31);
+}
+
+// Two ticks at 120 Hz push as hard as one at 60 Hz
+#[test]
+fn push_is_independent_of_tick_rate() {
+    let world = CollisionWorld::new();
+    let (mut a, mut b) = overlapping_pair();
+
+    physics::resolve_entity_collisions(&mut [&mut a, &mut b], &world, EntityCollision::Push, DT);
+
+    assert!(a.velocity().x < 0.0);
+    assert_eq!(a.velocity(), -b.velocity());
+    assert_eq!(a.position(), Vec3::ZERO);
+
+    let (mut c, mut d) = overlapping_pair();
+
+    for _ in 0..2 {
+        physics::resolve_entity_collisions(
+            &mut [&mut c, &mut d],
+            &world,
+            EntityCollision::Push,
+            DT / 2.0,
+        );
+    }
+
+    assert!((c.velocity().x - a.velocity().x).abs() < 1e-4);
+}
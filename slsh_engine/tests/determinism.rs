@@ -0,0 +1,188 @@
+// Runs scripted input through the simulation and hashes every tick's state. The hash has to be
+// the same in every build, which is what replays, leaderboards and lockstep rely on, so it's checked
+// against REFERENCE_CHECKSUM. Changes to the simulation that are meant to change it have to update
+// that too, with the checksum printed by:
+//
+//     cargo test --test determinism -- --nocapture
+//
+// Checksums only match across operating systems and CPUs without the native-math feature. To
+// compare builds with it, pass the checksum printed by one to the other:
+//
+//     SLSH_DETERMINISM_CHECKSUM=<checksum> cargo test --release --test determinism
+
+use std::env;
+
+use glam::Vec3;
+use slsh_engine::bot::Bot;
+use slsh_engine::broadphase::Aabb;
+use slsh_engine::camera::Camera;
+use slsh_engine::input::InputHandler;
+use slsh_engine::math;
+use slsh_engine::nav::NavGraph;
+use slsh_engine::physics::{CollisionWorld, Entity};
+use slsh_engine::rng::Rng;
+
+const DT: f64 = 1.0 / 60.0;
+const SEED: u64 = 0x5151_d00d;
+const BOT_TICKS: u32 = 1200;
+const REFERENCE_CHECKSUM: u64 = 0x9f32_a030_4fa0_eddc;
+
+// Ticks to hold for, forward, right, jump, and turn rate in radians per tick
+const SCRIPT: &[(u32, i8, i8, bool, f32)] = &[
+    (60, 1, 0, false, 0.0),
+    (30, 1, 1, true, 0.02),
+    (90, 1, -1, true, -0.035),
+    (45, 0, 1, false, 0.1),
+    (120, 1, 0, true, 0.013),
+    (60, -1, 0, false, -0.07),
+    (90, 0, 0, false, 0.0),
+];
+
+// FNV-1a over the bit patterns, so that differences in the last bit count too
+struct Checksum(u64);
+
+impl Checksum {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn add_vec(&mut self, v: Vec3) {
+        for component in v.to_array() {
+            for byte in component.to_bits().to_le_bytes() {
+                self.0 ^= u64::from(byte);
+                self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+    }
+
+    fn add_entity(&mut self, entity: &Entity) {
+        self.add_vec(entity.position());
+        self.add_vec(entity.velocity());
+    }
+}
+
+// Floor, walls to slide along and steps to land on
+fn test_world() -> CollisionWorld {
+    let mut world = CollisionWorld::new();
+
+    world.add_box(Aabb::new(Vec3::new(-50.0, -1.0, -50.0), Vec3::new(50.0, 0.0, 50.0)));
+    world.add_box(Aabb::new(Vec3::new(10.0, 0.0, -20.0), Vec3::new(11.0, 8.0, 20.0)));
+    world.add_box(Aabb::new(Vec3::new(-20.0, 0.0, 15.0), Vec3::new(20.0, 8.0, 16.0)));
+    world.add_box(Aabb::new(Vec3::new(-6.0, 0.0, 4.0), Vec3::new(-2.0, 1.0, 8.0)));
+    world.add_box(Aabb::new(Vec3::new(3.0, 0.0, -9.0), Vec3::new(7.0, 2.0, -5.0)));
+
+    world
+}
+
+fn test_nav() -> NavGraph {
+    let mut nav = NavGraph::new();
+
+    let corners = [
+        Vec3::new(-15.0, 0.0, -15.0),
+        Vec3::new(5.0, 0.0, -15.0),
+        Vec3::new(5.0, 0.0, 10.0),
+        Vec3::new(-15.0, 0.0, 10.0),
+    ];
+
+    let nodes: Vec<_> = corners.into_iter().map(|corner| nav.add_node(corner)).collect();
+
+    for (i, &node) in nodes.iter().enumerate() {
+        nav.link(node, nodes[(i + 1) % nodes.len()]);
+    }
+
+    nav
+}
+
+fn run_player_script(world: &CollisionWorld) -> u64 {
+    let mut checksum = Checksum::new();
+    let mut player = Entity::new(0.0, 0.0, 0.0);
+    let mut camera = Camera::new(1.0);
+    let mut input = InputHandler::new(0, 0);
+    let mut yaw = 0.0;
+    let mut tick = 0;
+
+    for &(ticks, forward, right, jump, turn) in SCRIPT {
+        input.forward = forward;
+        input.right = right;
+        input.up = i8::from(jump);
+
+        for _ in 0..ticks {
+            yaw += turn;
+            camera.set_orientation(0.0, yaw, 0.0);
+
+            player.update(&input, &mut camera, world, DT, f64::from(tick) * DT);
+            checksum.add_entity(&player);
+
+            tick += 1;
+        }
+    }
+
+    checksum.0
+}
+
+fn run_bots(world: &CollisionWorld) -> u64 {
+    let mut checksum = Checksum::new();
+    let nav = test_nav();
+    let mut rng = Rng::new(SEED, 0);
+    let mut bots = [
+        Bot::new(Vec3::new(-15.0, 0.0, -15.0)),
+        Bot::new(Vec3::new(5.0, 0.0, 10.0)),
+    ];
+
+    for tick in 0..BOT_TICKS {
+        for bot in &mut bots {
+            bot.update(&nav, world, &mut rng, DT, f64::from(tick) * DT);
+            checksum.add_entity(bot.entity());
+        }
+    }
+
+    checksum.0
+}
+
+fn simulation_checksum() -> u64 {
+    let world = test_world();
+
+    run_player_script(&world) ^ run_bots(&world).rotate_left(1)
+}
+
+#[test]
+fn repeated_runs_match() {
+    assert_eq!(simulation_checksum(), simulation_checksum());
+}
+
+#[test]
+fn matches_reference_checksum() {
+    let checksum = simulation_checksum();
+
+    println!("simulation checksum: {:016x}", checksum);
+
+    let reference = match env::var("SLSH_DETERMINISM_CHECKSUM") {
+        Ok(reference) => u64::from_str_radix(reference.trim(), 16)
+            .expect("SLSH_DETERMINISM_CHECKSUM must be a hexadecimal number"),
+        // Native math differs between machines, so there's nothing to compare to by default
+        Err(_) if cfg!(feature = "native-math") => return,
+        Err(_) => REFERENCE_CHECKSUM,
+    };
+
+    assert_eq!(checksum, reference, "simulation diverged from the reference build");
+}
+
+// The portable versions only have to be close to std, but exactly the same everywhere
+#[test]
+fn portable_trig_is_accurate() {
+    for i in -2000..2000 {
+        let angle = i as f32 * 0.0137;
+        let (sin, cos) = math::sin_cos(angle);
+
+        assert!((sin - angle.sin()).abs() < 1e-6, "sin({})", angle);
+        assert!((cos - angle.cos()).abs() < 1e-6, "cos({})", angle);
+
+        let y = (i as f32 * 0.31).sin() * 5.0;
+        let x = (i as f32 * 0.17).cos() * 5.0;
+
+        assert!((math::atan2(y, x) - y.atan2(x)).abs() < 1e-6, "atan2({}, {})", y, x);
+    }
+
+    assert_eq!(math::atan2(0.0, 0.0), 0.0);
+    assert_eq!(math::atan2(1.0, 0.0), std::f32::consts::FRAC_PI_2);
+}
@@ -0,0 +1,83 @@
+// Renders offscreen without a window. Machines without a Vulkan driver or device, like most CI
+// runners, skip the test instead of failing it
+
+#![cfg(feature = "render")]
+
+use ash::vk;
+use slsh_engine::camera::Camera;
+use slsh_engine::renderer::{PresentTarget, Renderer, RendererConfig};
+use slsh_engine::ui::UserInterface;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+// Stands in for a window, without a surface to present to
+struct NoSurface;
+
+impl PresentTarget for NoSurface {
+    fn required_extensions(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn create_surface(&self, _instance: &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result> {
+        Err(vk::Result::ERROR_INITIALIZATION_FAILED)
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (WIDTH, HEIGHT)
+    }
+}
+
+fn headless_renderer(width: u32, height: u32) -> Option<Renderer> {
+    let config = RendererConfig::default();
+
+    match unsafe { Renderer::new_headless("headless test", width, height, &config) } {
+        Ok(renderer) => Some(renderer),
+        Err(e) => {
+            eprintln!("Skipping, no headless renderer: {}", e);
+            None
+        }
+    }
+}
+
+#[test]
+fn renders_a_frame_of_the_requested_size() {
+    let Some(mut renderer) = headless_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    let mut camera = Camera::new(WIDTH as f32 / HEIGHT as f32);
+    let mut ui = UserInterface::new(WIDTH, HEIGHT);
+
+    renderer.update_data(&mut ui, &mut camera);
+    renderer.present();
+
+    let frame = renderer.read_frame().expect("headless frames can be read back");
+
+    assert_eq!(frame.width, WIDTH);
+    assert_eq!(frame.height, HEIGHT);
+    assert_eq!(frame.pixels.len(), (WIDTH * HEIGHT * 4) as usize);
+}
+
+#[test]
+fn resized_frames_match_the_new_size() {
+    let Some(mut renderer) = headless_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    renderer.resize(HEIGHT, WIDTH);
+    renderer.present();
+
+    let frame = renderer.read_frame().expect("headless frames can be read back");
+
+    assert_eq!((frame.width, frame.height), (HEIGHT, WIDTH));
+}
+
+#[test]
+fn headless_renderers_have_no_views() {
+    let Some(mut renderer) = headless_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    assert!(unsafe { renderer.add_view(&NoSurface) }.is_err());
+}
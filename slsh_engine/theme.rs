@@ -0,0 +1,61 @@
+use glam::Vec3;
+
+// Color presets for common types of color blindness, picked from the Okabe-Ito palette so that
+// the crosshair stays distinct from the world and from the other HUD colors
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Palette {
+    #[default]
+    Default,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+// Colors shared by HUD elements that don't set their own
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub crosshair: Vec3,
+    pub accent: Vec3,
+}
+
+const YELLOW: Vec3 = Vec3::new(0.94, 0.89, 0.26);
+const SKY_BLUE: Vec3 = Vec3::new(0.34, 0.71, 0.91);
+const REDDISH_PURPLE: Vec3 = Vec3::new(0.8, 0.47, 0.65);
+const BLUISH_GREEN: Vec3 = Vec3::new(0.0, 0.62, 0.45);
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Palette::Default),
+            "protanopia" => Some(Palette::Protanopia),
+            "deuteranopia" => Some(Palette::Deuteranopia),
+            "tritanopia" => Some(Palette::Tritanopia),
+            _ => None,
+        }
+    }
+}
+
+impl Theme {
+    pub fn new(palette: Palette) -> Self {
+        match palette {
+            Palette::Default => Self {
+                crosshair: Vec3::new(0.0, 1.0, 0.0),
+                accent: Vec3::ONE,
+            },
+            Palette::Protanopia | Palette::Deuteranopia => Self {
+                crosshair: YELLOW,
+                accent: SKY_BLUE,
+            },
+            Palette::Tritanopia => Self {
+                crosshair: REDDISH_PURPLE,
+                accent: BLUISH_GREEN,
+            },
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new(Palette::Default)
+    }
+}
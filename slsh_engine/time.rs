@@ -0,0 +1,204 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Time as gameplay sees it. It only advances with simulation ticks, so it stands still while the
+// game is paused and is the same on every run of a replay. Timers carry a value of the caller's
+// choosing, e.g. an event or a boxed callback, and hand it back on the tick they're due.
+#[derive(Clone)]
+pub struct GameTime<T> {
+    tick: u64,
+    ticks_per_second: u32,
+    // In the order they were scheduled, which is also the order timers due on one tick fire in
+    timers: Vec<Timer<T>>,
+    next_timer: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TimerHandle(u64);
+
+#[derive(Clone)]
+struct Timer<T> {
+    handle: TimerHandle,
+    due_tick: u64,
+    value: T,
+}
+
+impl<T> GameTime<T> {
+    pub fn new(ticks_per_second: u32) -> Self {
+        assert!(ticks_per_second > 0, "Tick rate has to be positive");
+
+        Self {
+            tick: 0,
+            ticks_per_second,
+            timers: Vec::new(),
+            next_timer: 0,
+        }
+    }
+
+    // Number of ticks simulated so far
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn ticks_per_second(&self) -> u32 {
+        self.ticks_per_second
+    }
+
+    // Seconds of game time since the start
+    pub fn seconds(&self) -> f64 {
+        self.tick as f64 / f64::from(self.ticks_per_second)
+    }
+
+    pub fn dt(&self) -> f64 {
+        1.0 / f64::from(self.ticks_per_second)
+    }
+
+    // Rounded up, so waiting that many ticks takes at least as long
+    pub fn to_ticks(&self, seconds: f64) -> u64 {
+        (seconds.max(0.0) * f64::from(self.ticks_per_second)).ceil() as u64
+    }
+
+    // Fires once the given time has passed, and on the next tick at the earliest
+    pub fn after(&mut self, seconds: f64, value: T) -> TimerHandle {
+        let due_tick = self.tick + self.to_ticks(seconds).max(1);
+
+        self.at_tick(due_tick, value)
+    }
+
+    // Ticks that have already passed fire on the next one
+    pub fn at_tick(&mut self, tick: u64, value: T) -> TimerHandle {
+        let handle = TimerHandle(self.next_timer);
+
+        self.next_timer += 1;
+
+        self.timers.push(Timer {
+            handle,
+            due_tick: tick,
+            value,
+        });
+
+        handle
+    }
+
+    // Gives the value back if the timer hasn't fired yet
+    pub fn cancel(&mut self, handle: TimerHandle) -> Option<T> {
+        let idx = self.timers.iter().position(|timer| timer.handle == handle)?;
+
+        Some(self.timers.remove(idx).value)
+    }
+
+    // Seconds left until the timer fires, None if it already has or was cancelled
+    pub fn remaining(&self, handle: TimerHandle) -> Option<f64> {
+        self.timers.iter().find(|timer| timer.handle == handle).map(|timer| {
+            timer.due_tick.saturating_sub(self.tick) as f64 / f64::from(self.ticks_per_second)
+        })
+    }
+
+    // Moves on to the next tick and returns the values of the timers due on it
+    pub fn advance(&mut self) -> Vec<T> {
+        self.tick += 1;
+
+        let tick = self.tick;
+
+        // Most ticks have nothing due, those don't allocate
+        if !self.timers.iter().any(|timer| timer.due_tick <= tick) {
+            return Vec::new();
+        }
+
+        let (due, pending): (Vec<_>, Vec<_>) =
+            self.timers.drain(..).partition(|timer| timer.due_tick <= tick);
+
+        self.timers = pending;
+
+        due.into_iter().map(|timer| timer.value).collect()
+    }
+}
+
+// Real time since the Unix epoch, for timestamps and the like. It can jump when the system clock
+// is changed, so durations in gameplay have to be measured with GameTime
+pub fn wall_clock() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_delay_fires_on_the_next_tick() {
+        let mut time = GameTime::new(60);
+
+        time.after(0.0, 'a');
+
+        assert_eq!(time.advance(), ['a']);
+        assert!(time.advance().is_empty());
+    }
+
+    #[test]
+    fn delays_round_up_to_whole_ticks() {
+        let mut time = GameTime::new(10);
+
+        time.after(0.25, 'a');
+
+        assert!(time.advance().is_empty());
+        assert!(time.advance().is_empty());
+        assert_eq!(time.advance(), ['a']);
+    }
+
+    #[test]
+    fn past_ticks_fire_on_the_next_one() {
+        let mut time = GameTime::new(60);
+
+        for _ in 0..5 {
+            time.advance();
+        }
+
+        time.at_tick(2, 'a');
+
+        assert_eq!(time.advance(), ['a']);
+        assert_eq!(time.tick(), 6);
+    }
+
+    #[test]
+    fn cancelled_timers_never_fire() {
+        let mut time = GameTime::new(60);
+
+        let a = time.after(0.0, 'a');
+        time.after(0.0, 'b');
+
+        assert_eq!(time.cancel(a), Some('a'));
+        assert_eq!(time.cancel(a), None);
+        assert_eq!(time.advance(), ['b']);
+    }
+
+    #[test]
+    fn remaining() {
+        let mut time = GameTime::new(10);
+
+        let a = time.after(0.5, 'a');
+
+        assert_eq!(time.remaining(a), Some(0.5));
+
+        time.advance();
+
+        assert_eq!(time.remaining(a), Some(0.4));
+
+        for _ in 0..4 {
+            time.advance();
+        }
+
+        assert_eq!(time.remaining(a), None);
+    }
+
+    #[test]
+    fn same_tick_fires_in_scheduling_order() {
+        let mut time = GameTime::new(60);
+
+        time.at_tick(1, 'a');
+        time.after(0.0, 'b');
+        time.at_tick(0, 'c');
+        time.at_tick(2, 'd');
+
+        assert_eq!(time.advance(), ['a', 'b', 'c']);
+        assert_eq!(time.advance(), ['d']);
+    }
+}
@@ -1,10 +1,46 @@
-use glam::Mat4;
+use std::path::{Path, PathBuf};
+
+use glam::{Mat4, Vec2};
+
+use crate::hud::{HudError, HudLayout};
+use crate::input::InputHandler;
+use crate::theme::{Palette, Theme};
+
+const SHOWKEYS_KEY_SIZE: f32 = 24.0;
+const SHOWKEYS_GAP: f32 = 4.0;
+// Pixels of yaw bar per radian turned in a tick
+const SHOWKEYS_YAW_SCALE: f32 = 400.0;
 
 pub struct UserInterface {
     win_width: u32,
     win_height: u32,
     proj: Mat4,
     proj_needs_recalc: bool,
+    hud: HudLayout,
+    hud_path: Option<PathBuf>,
+    showkeys: ShowKeys,
+    // Hides every widget, for screenshots
+    hidden: bool,
+}
+
+// Held movement keys and mouse buttons, for the showkeys widget
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ShowKeys {
+    pub forward: i8,
+    pub right: i8,
+    pub jump: bool,
+    pub mouse_left: bool,
+    pub mouse_right: bool,
+    // Radians turned during the last tick, positive to the right
+    pub yaw_delta: f32,
+}
+
+// Rectangle in pixels relative to the widget's position, growing up and to the right from pos
+#[derive(Clone, Copy, Debug)]
+pub struct HudBox {
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub lit: bool,
 }
 
 impl UserInterface {
@@ -14,9 +50,81 @@ impl UserInterface {
             win_height,
             proj: Mat4::IDENTITY,
             proj_needs_recalc: true,
+            hud: HudLayout::default(),
+            hud_path: None,
+            showkeys: ShowKeys::default(),
+            hidden: false,
         }
     }
 
+    pub fn update_showkeys(&mut self, input: &InputHandler, yaw_delta: f32) {
+        self.showkeys = ShowKeys {
+            forward: input.forward,
+            right: input.right,
+            jump: input.up == 1,
+            mouse_left: input.mouse_left,
+            mouse_right: input.mouse_right,
+            yaw_delta,
+        };
+    }
+
+    pub fn showkeys(&self) -> &ShowKeys {
+        &self.showkeys
+    }
+
+    pub fn showkeys_position(&self) -> Vec2 {
+        self.hud.showkeys.position(self.win_width, self.win_height)
+    }
+
+    pub fn hud(&self) -> &HudLayout {
+        &self.hud
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    // The layout is kept as is when the file fails to load
+    pub fn load_hud(&mut self, path: &Path) -> Result<(), HudError> {
+        self.hud_path = Some(path.to_owned());
+        self.hud = HudLayout::load(path)?;
+
+        Ok(())
+    }
+
+    // Replaces the theme colors, including custom ones from the HUD layout file
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.hud.theme = Theme::new(palette);
+    }
+
+    pub fn reload_hud(&mut self) -> Result<(), HudError> {
+        match &self.hud_path {
+            Some(path) => {
+                self.hud = HudLayout::load(path)?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn resize(&mut self, win_width: u32, win_height: u32) {
+        self.win_width = win_width;
+        self.win_height = win_height;
+        self.proj_needs_recalc = true;
+    }
+
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(self.win_width as f32 / 2.0, self.win_height as f32 / 2.0)
+    }
+
+    pub fn crosshair_position(&self) -> Vec2 {
+        self.hud.crosshair.position(self.win_width, self.win_height)
+    }
+
     pub fn proj(&mut self) -> &Mat4 {
         if self.proj_needs_recalc {
             self.recalc_proj_matrix();
@@ -31,11 +139,53 @@ impl UserInterface {
         let right = self.win_width as f32;
         let bottom = self.win_height as f32;
         let top = 0.0;
+        // Puts z = 0 at depth 1, in front of everything with reversed-Z
         let near = -1.0;
-        let far = 1.0;
+        let far = 0.0;
 
         self.proj = Mat4::orthographic_rh_gl(left, right, bottom, top, near, far);
 
         self.proj_needs_recalc = false;
     }
 }
+
+impl ShowKeys {
+    // W above A, S and D, jump below them, mouse buttons to the right and the yaw bar at the
+    // bottom. Horizontally centered on the widget's position, with the top row's bottom edge on it
+    pub fn boxes(&self) -> [HudBox; 8] {
+        let key = SHOWKEYS_KEY_SIZE;
+        let gap = SHOWKEYS_GAP;
+        let step = key + gap;
+        let width = step * 6.0 - gap;
+        let left = -width / 2.0;
+
+        let key_box = |col: f32, row: f32, lit: bool| HudBox {
+            pos: Vec2::new(left + col * step, -row * step),
+            size: Vec2::splat(key),
+            lit,
+        };
+
+        let jump_y = -step - gap - key / 2.0;
+        let yaw_y = jump_y - gap * 2.0;
+        let yaw_len = (self.yaw_delta * SHOWKEYS_YAW_SCALE).clamp(-width / 2.0, width / 2.0);
+
+        [
+            key_box(1.0, 0.0, self.forward == 1),
+            key_box(0.0, 1.0, self.right == -1),
+            key_box(1.0, 1.0, self.forward == -1),
+            key_box(2.0, 1.0, self.right == 1),
+            HudBox {
+                pos: Vec2::new(left, jump_y),
+                size: Vec2::new(step * 3.0 - gap, key / 2.0),
+                lit: self.jump,
+            },
+            key_box(4.0, 0.0, self.mouse_left),
+            key_box(5.0, 0.0, self.mouse_right),
+            HudBox {
+                pos: Vec2::new(yaw_len.min(0.0), yaw_y),
+                size: Vec2::new(yaw_len.abs(), gap),
+                lit: true,
+            },
+        ]
+    }
+}
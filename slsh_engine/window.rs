@@ -4,6 +4,9 @@ use std::sync::mpsc::Receiver;
 
 use ash::vk;
 
+pub use crate::keys::{Key, Modifiers, MouseButton, Scancode};
+use crate::renderer::PresentTarget;
+
 pub struct Window {
     glfw: glfw::Glfw,
     handle: glfw::Window,
@@ -12,27 +15,46 @@ pub struct Window {
     height: u32,
 }
 
+pub struct WindowManager {
+    windows: Vec<Option<Window>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WindowId(usize);
+
 pub enum Resolution {
     Windowed(u32, u32),
     Fullscreen,
     FullscreenWithRes(u32, u32),
+    // Exclusive fullscreen on the monitor at that index in Window::monitors
+    FullscreenWithMode(usize, DisplayMode),
 }
 
-pub enum Event {
-    KeyPress(Key),
-    KeyRelease(Key),
-    MouseMove(f64, f64),
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    // In Hz
+    pub refresh_rate: u32,
 }
 
-#[repr(i32)]
-pub enum Key {
-    Escape = glfw::Key::Escape as i32,
-    Space = glfw::Key::Space as i32,
-    W = glfw::Key::W as i32,
-    A = glfw::Key::A as i32,
-    S = glfw::Key::S as i32,
-    D = glfw::Key::D as i32,
-    Unknown = glfw::Key::Unknown as i32,
+#[derive(Clone, Debug)]
+pub struct MonitorInfo {
+    pub name: String,
+    // Smallest and slowest first
+    pub modes: Vec<DisplayMode>,
+    // Mode of the desktop, or of a fullscreen window on the monitor
+    pub current: Option<DisplayMode>,
+}
+
+pub enum Event {
+    KeyPress(Key, Scancode, Modifiers),
+    KeyRelease(Key, Scancode, Modifiers),
+    MouseMove(f64, f64),
+    MouseButtonPress(MouseButton),
+    MouseButtonRelease(MouseButton),
+    Focus(bool),
+    Resize(u32, u32),
 }
 
 impl Window {
@@ -43,36 +65,12 @@ impl Window {
         glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
         glfw.window_hint(glfw::WindowHint::CenterCursor(true));
 
-        let (width, height, create_result) = match *res {
-            Resolution::Windowed(width, height) => {
-                let res = glfw.create_window(width, height, title, glfw::WindowMode::Windowed);
+        let (width, height, create_result) =
+            with_window_mode(&mut glfw, res, |glfw, mode, width, height, refresh_rate| {
+                glfw.window_hint(glfw::WindowHint::RefreshRate(refresh_rate));
 
-                (width, height, res)
-            }
-            Resolution::Fullscreen => glfw.with_primary_monitor(|glfw, monitor| {
-                let monitor = monitor.expect("No monitors found");
-                let mode = monitor.get_video_mode().expect("Failed to get video mode");
-                let width = mode.width;
-                let height = mode.height;
-                let res =
-                    glfw.create_window(width, height, title, glfw::WindowMode::FullScreen(monitor));
-
-                (width, height, res)
-            }),
-            Resolution::FullscreenWithRes(width, height) => {
-                glfw.with_primary_monitor(|glfw, monitor| {
-                    let monitor = monitor.expect("No monitors found");
-                    let res = glfw.create_window(
-                        width,
-                        height,
-                        title,
-                        glfw::WindowMode::FullScreen(monitor),
-                    );
-
-                    (width, height, res)
-                })
-            }
-        };
+                (width, height, glfw.create_window(width, height, title, mode))
+            });
 
         let (mut handle, events) = create_result.expect("Failed to create GLFW window");
 
@@ -82,6 +80,9 @@ impl Window {
 
         handle.set_key_polling(true);
         handle.set_cursor_pos_polling(true);
+        handle.set_mouse_button_polling(true);
+        handle.set_focus_polling(true);
+        handle.set_framebuffer_size_polling(true);
 
         handle.set_cursor_mode(glfw::CursorMode::Disabled);
 
@@ -100,19 +101,58 @@ impl Window {
         }
     }
 
-    pub fn get_required_extensions(&self) -> Vec<String> {
-        self.glfw.get_required_instance_extensions().expect("Vulkan API unavaliable")
+    pub fn create_tool_window(&self, width: u32, height: u32, title: &str) -> Self {
+        let mut glfw = self.glfw.clone();
+
+        let (mut handle, events) = glfw
+            .create_window(width, height, title, glfw::WindowMode::Windowed)
+            .expect("Failed to create GLFW window");
+
+        handle.set_key_polling(true);
+        handle.set_framebuffer_size_polling(true);
+
+        Self {
+            glfw,
+            handle,
+            events,
+            width,
+            height,
+        }
     }
 
-    pub fn create_surface(&self, instance: &ash::Instance) -> vk::SurfaceKHR {
-        let mut surface = MaybeUninit::uninit();
+    // Switches between windowed and fullscreen, or to another display mode. GLFW puts the
+    // desktop mode back when the window leaves fullscreen, gets minimized or is destroyed, which
+    // includes unwinding from a panic
+    pub fn set_resolution(&mut self, res: &Resolution) {
+        let handle = &mut self.handle;
 
-        self.handle
-            .create_window_surface(instance.handle(), ptr::null(), surface.as_mut_ptr())
-            .result()
-            .expect("Failed to create window surface");
+        with_window_mode(&mut self.glfw, res, |_, mode, width, height, refresh_rate| {
+            handle.set_monitor(mode, 0, 0, width, height, refresh_rate);
+        });
 
-        unsafe { surface.assume_init() }
+        center_window(res, &mut self.glfw, &mut self.handle);
+    }
+
+    pub fn monitors(&mut self) -> Vec<MonitorInfo> {
+        self.glfw.with_connected_monitors(|_, monitors| {
+            monitors
+                .iter()
+                .map(|monitor| {
+                    let mut modes: Vec<_> =
+                        monitor.get_video_modes().iter().map(DisplayMode::from_glfw).collect();
+
+                    // Modes that only differ in bit depth look the same here
+                    modes.sort_by_key(|mode| (mode.width, mode.height, mode.refresh_rate));
+                    modes.dedup();
+
+                    MonitorInfo {
+                        name: monitor.get_name().unwrap_or_default(),
+                        modes,
+                        current: monitor.get_video_mode().as_ref().map(DisplayMode::from_glfw),
+                    }
+                })
+                .collect()
+        })
     }
 
     pub fn current_time(&self) -> f64 {
@@ -131,6 +171,14 @@ impl Window {
         self.handle.get_cursor_pos()
     }
 
+    pub fn capture_cursor(&mut self) {
+        self.handle.set_cursor_mode(glfw::CursorMode::Disabled);
+    }
+
+    pub fn release_cursor(&mut self) {
+        self.handle.set_cursor_mode(glfw::CursorMode::Normal);
+    }
+
     pub fn should_close(&self) -> bool {
         self.handle.should_close()
     }
@@ -143,37 +191,212 @@ impl Window {
         self.height
     }
 
-    pub fn poll_events(&mut self, mut handle_cb: impl FnMut(Event)) {
+    pub fn poll_events(&mut self, handle_cb: impl FnMut(Event)) {
         self.glfw.poll_events();
+        self.flush_events(handle_cb);
+    }
+
+    fn flush_events(&mut self, mut handle_cb: impl FnMut(Event)) {
         for (_, glfw_event) in glfw::flush_messages(&self.events) {
             match glfw_event {
-                glfw::WindowEvent::Key(key, _scancode, action, _modifiers) => {
+                glfw::WindowEvent::Key(key, scancode, action, modifiers) => {
+                    let key = Key::from_glfw(key);
+                    let modifiers = Modifiers::from_glfw(modifiers);
+
                     if action == glfw::Action::Press {
-                        let event = Event::KeyPress(Key::from_glfw(key));
+                        let event = Event::KeyPress(key, scancode, modifiers);
                         handle_cb(event);
                     }
                     if action == glfw::Action::Release {
-                        let event = Event::KeyRelease(Key::from_glfw(key));
+                        let event = Event::KeyRelease(key, scancode, modifiers);
                         handle_cb(event);
                     }
                 }
                 glfw::WindowEvent::CursorPos(x, y) => handle_cb(Event::MouseMove(x, y)),
+                glfw::WindowEvent::MouseButton(button, action, _) => {
+                    let button = MouseButton::from_glfw(button);
+
+                    if action == glfw::Action::Press {
+                        handle_cb(Event::MouseButtonPress(button));
+                    }
+                    if action == glfw::Action::Release {
+                        handle_cb(Event::MouseButtonRelease(button));
+                    }
+                }
+                glfw::WindowEvent::Focus(focused) => handle_cb(Event::Focus(focused)),
+                glfw::WindowEvent::FramebufferSize(width, height) => {
+                    self.width = width.try_into().unwrap_or(0);
+                    self.height = height.try_into().unwrap_or(0);
+                    handle_cb(Event::Resize(self.width, self.height));
+                }
                 _ => (),
             }
         }
     }
 }
 
+impl PresentTarget for Window {
+    fn required_extensions(&self) -> Vec<String> {
+        self.glfw.get_required_instance_extensions().expect("Vulkan API unavaliable")
+    }
+
+    fn create_surface(&self, instance: &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result> {
+        let mut surface = MaybeUninit::uninit();
+
+        self.handle
+            .create_window_surface(instance.handle(), ptr::null(), surface.as_mut_ptr())
+            .result()?;
+
+        Ok(unsafe { surface.assume_init() })
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl WindowManager {
+    pub const PRIMARY: WindowId = WindowId(0);
+
+    pub fn new(res: &Resolution, title: &str) -> Self {
+        let primary = Window::new(res, title);
+
+        Self {
+            windows: vec![Some(primary)],
+        }
+    }
+
+    pub fn open_tool_window(&mut self, width: u32, height: u32, title: &str) -> WindowId {
+        let window = self.primary().create_tool_window(width, height, title);
+
+        if let Some(idx) = self.windows.iter().position(Option::is_none) {
+            self.windows[idx] = Some(window);
+            return WindowId(idx);
+        }
+
+        self.windows.push(Some(window));
+
+        WindowId(self.windows.len() - 1)
+    }
+
+    // Ids of windows that are already closed, or that never existed, are ignored
+    pub fn close(&mut self, id: WindowId) {
+        assert!(id != Self::PRIMARY, "Primary window can't be closed");
+
+        if let Some(window) = self.windows.get_mut(id.0) {
+            *window = None;
+        }
+    }
+
+    pub fn get(&self, id: WindowId) -> Option<&Window> {
+        self.windows.get(id.0).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, id: WindowId) -> Option<&mut Window> {
+        self.windows.get_mut(id.0).and_then(Option::as_mut)
+    }
+
+    pub fn primary(&self) -> &Window {
+        self.get(Self::PRIMARY).unwrap()
+    }
+
+    pub fn primary_mut(&mut self) -> &mut Window {
+        self.get_mut(Self::PRIMARY).unwrap()
+    }
+
+    pub fn poll_events(&mut self, mut handle_cb: impl FnMut(WindowId, Event)) {
+        self.primary_mut().glfw.poll_events();
+
+        for (idx, window) in self.windows.iter_mut().enumerate() {
+            if let Some(window) = window {
+                window.flush_events(|event| handle_cb(WindowId(idx), event));
+            }
+        }
+    }
+}
+
 impl Key {
-    fn from_glfw(key: glfw::Key) -> Self {
-        match key {
-            glfw::Key::Escape => Key::Escape,
-            glfw::Key::Space => Key::Space,
-            glfw::Key::W => Key::W,
-            glfw::Key::A => Key::A,
-            glfw::Key::S => Key::S,
-            glfw::Key::D => Key::D,
-            _ => Key::Unknown,
+    pub fn scancode(self) -> Option<Scancode> {
+        glfw::get_key_scancode(Some(self.to_glfw()))
+    }
+}
+
+// Name of the key in the current keyboard layout. Only printable keys have one, so callers need a
+// fallback for keys like Space or the arrows
+pub fn scancode_name(scancode: Scancode) -> Option<String> {
+    glfw::get_key_name(None, Some(scancode))
+}
+
+impl Modifiers {
+    fn from_glfw(modifiers: glfw::Modifiers) -> Self {
+        Self {
+            shift: modifiers.contains(glfw::Modifiers::Shift),
+            control: modifiers.contains(glfw::Modifiers::Control),
+            alt: modifiers.contains(glfw::Modifiers::Alt),
+            super_: modifiers.contains(glfw::Modifiers::Super),
+            caps_lock: modifiers.contains(glfw::Modifiers::CapsLock),
+            num_lock: modifiers.contains(glfw::Modifiers::NumLock),
+        }
+    }
+}
+
+impl DisplayMode {
+    fn from_glfw(mode: &glfw::VidMode) -> Self {
+        Self {
+            width: mode.width,
+            height: mode.height,
+            refresh_rate: mode.refresh_rate,
+        }
+    }
+}
+
+impl MouseButton {
+    fn from_glfw(button: glfw::MouseButton) -> Self {
+        match button {
+            glfw::MouseButton::Button1 => MouseButton::Left,
+            glfw::MouseButton::Button2 => MouseButton::Right,
+            glfw::MouseButton::Button3 => MouseButton::Middle,
+            other => MouseButton::Other(other as u8),
+        }
+    }
+}
+
+// Calls f with the window mode, size and refresh rate the resolution stands for. Refresh rate is
+// None where any will do
+fn with_window_mode<T>(
+    glfw: &mut glfw::Glfw,
+    res: &Resolution,
+    f: impl FnOnce(&mut glfw::Glfw, glfw::WindowMode, u32, u32, Option<u32>) -> T,
+) -> T {
+    match *res {
+        Resolution::Windowed(width, height) => {
+            f(glfw, glfw::WindowMode::Windowed, width, height, None)
+        }
+        Resolution::Fullscreen => glfw.with_primary_monitor(|glfw, monitor| {
+            let monitor = monitor.expect("No monitors found");
+            let mode = monitor.get_video_mode().expect("Failed to get video mode");
+
+            f(glfw, glfw::WindowMode::FullScreen(monitor), mode.width, mode.height, None)
+        }),
+        Resolution::FullscreenWithRes(width, height) => {
+            glfw.with_primary_monitor(|glfw, monitor| {
+                let monitor = monitor.expect("No monitors found");
+
+                f(glfw, glfw::WindowMode::FullScreen(monitor), width, height, None)
+            })
+        }
+        Resolution::FullscreenWithMode(idx, mode) => {
+            glfw.with_connected_monitors(|glfw, monitors| {
+                let monitor = monitors.get(idx).expect("No monitor with that index");
+
+                f(
+                    glfw,
+                    glfw::WindowMode::FullScreen(monitor),
+                    mode.width,
+                    mode.height,
+                    Some(mode.refresh_rate),
+                )
+            })
         }
     }
 }
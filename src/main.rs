@@ -1,8 +1,114 @@
+use std::path::Path;
+use std::{env, process};
+
+use slsh_engine::crash;
 use slsh_engine::main_loop::MainLoop;
+use slsh_engine::remote::RemoteControl;
+use slsh_engine::renderer::{Renderer, RendererConfig};
+use slsh_engine::settings::Settings;
 use slsh_engine::window::Resolution;
 
+const USAGE: &str = "usage: slsh [--gpuinfo] [--seed <u64>] [--remote <port>]";
+
+#[derive(Default)]
+struct Args {
+    gpuinfo: bool,
+    seed: Option<u64>,
+    remote: Option<u16>,
+}
+
+// All of them before anything opens a window, so that mistakes are reported right away
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gpuinfo" => parsed.gpuinfo = true,
+            "--seed" => {
+                let seed = args.next().and_then(|seed| seed.parse().ok());
+                parsed.seed = Some(seed.ok_or("--seed expects an unsigned integer")?);
+            }
+            "--remote" => {
+                let port = args.next().and_then(|port| port.parse().ok());
+                parsed.remote = Some(port.ok_or("--remote expects a port number")?);
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+// Offscreen, so it works without a display. Surface formats and present modes are left out
+fn print_gpu_report() {
+    match unsafe { Renderer::new_headless("slsh", 1, 1, &RendererConfig::default()) } {
+        Ok(renderer) => print!("{}", renderer.gpu_report()),
+        Err(e) => {
+            eprintln!("Failed to initialize the renderer: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
-    let mut main_loop = MainLoop::new(&Resolution::Windowed(1024, 768), "slsh");
+    crash::install("slsh");
+
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if args.gpuinfo {
+        print_gpu_report();
+        return;
+    }
+
+    // Local connections only, there is no authentication
+    let remote = args.remote.and_then(|port| match RemoteControl::bind(("127.0.0.1", port)) {
+        Ok(remote) => Some(remote),
+        Err(e) => {
+            eprintln!("Failed to start remote control on port {}: {}", port, e);
+            None
+        }
+    });
+
+    let mut main_loop = match MainLoop::new(&Resolution::Windowed(1024, 768), "slsh") {
+        Ok(main_loop) => main_loop,
+        Err(e) => {
+            eprintln!("Failed to initialize the renderer: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Some(seed) = args.seed {
+        main_loop.set_seed(seed);
+    }
+
+    println!("RNG seed: {}", main_loop.rng().seed());
+
+    if let Some(remote) = remote {
+        main_loop.enable_remote_control(remote);
+    }
+
+    let settings_path = Path::new("settings.toml");
+
+    if settings_path.exists() {
+        match Settings::load(settings_path) {
+            Ok(settings) => main_loop.apply_settings(&settings),
+            Err(e) => eprintln!("Failed to load settings: {}", e),
+        }
+    }
+
+    let hud_path = Path::new("hud.toml");
+
+    if hud_path.exists() {
+        if let Err(e) = main_loop.load_hud(hud_path) {
+            eprintln!("Failed to load HUD layout: {}", e);
+        }
+    }
 
     main_loop.run();
 }